@@ -0,0 +1,159 @@
+//! Allowlist of gateway endpoints the proxy forwards
+//!
+//! Defense in depth for hardened and kiosk deployments: with an allowlist
+//! set, `proxy_request` and `proxy_stream` only forward calls matching one
+//! of its method and path-prefix pairs, so a compromised webview can't reach
+//! arbitrary gateway endpoints. Anything else is refused with a `forbidden`
+//! error before it is sent, and logged. Without an allowlist (the
+//! default) every endpoint is forwarded.
+//!
+//! Paths are matched as they will be in the URL the request goes to: after
+//! protocol adaptation moves them to a legacy endpoint, and with dot
+//! segments resolved, so `/models/../admin` counts as `/admin`.
+
+use reqwest::{Method, Url};
+use serde::{Deserialize, Serialize};
+
+/// Method matching any method
+const ANY_METHOD: &str = "*";
+
+/// A gateway endpoint the proxy may forward to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AllowedEndpoint {
+    /// HTTP method, or `*` for any
+    pub method: String,
+
+    /// Path prefix, matched on whole segments: `/models` allows
+    /// `/models/abc` but not `/models-admin`
+    pub path_prefix: String,
+}
+
+impl AllowedEndpoint {
+    fn allows(&self, method: &Method, path: &str) -> bool {
+        let method_ok =
+            self.method == ANY_METHOD || self.method.eq_ignore_ascii_case(method.as_str());
+        let prefix = self.path_prefix.trim_end_matches('/');
+        method_ok
+            && path
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+/// Check an allowlist is well formed
+pub fn validate(allowlist: &[AllowedEndpoint]) -> Result<(), String> {
+    for endpoint in allowlist {
+        if endpoint.method != ANY_METHOD && Method::from_bytes(endpoint.method.as_bytes()).is_err()
+        {
+            return Err(format!(
+                "invalid method `{}` in proxy allowlist",
+                endpoint.method
+            ));
+        }
+        if !endpoint.path_prefix.starts_with('/') {
+            return Err(format!(
+                "proxy allowlist path `{}` must start with `/`",
+                endpoint.path_prefix
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Fail with a `forbidden` error unless `allowlist` (if set) allows the
+/// call
+pub fn check(
+    allowlist: Option<&[AllowedEndpoint]>,
+    method: &Method,
+    path: &str,
+) -> Result<(), String> {
+    let Some(allowlist) = allowlist else {
+        return Ok(());
+    };
+
+    let allowed = resolve(path).is_some_and(|resolved| {
+        allowlist
+            .iter()
+            .any(|endpoint| endpoint.allows(method, &resolved))
+    });
+    if allowed {
+        return Ok(());
+    }
+
+    tracing::warn!(method = %method, path, "blocked proxied request not in the allowlist");
+    Err(format!(
+        "forbidden: {method} {path} is not in the proxy allowlist"
+    ))
+}
+
+/// Path the request will go to, dot segments resolved and the query left
+/// off, or `None` if it isn't an absolute path
+fn resolve(path: &str) -> Option<String> {
+    if !path.starts_with('/') || path.starts_with("//") {
+        return None;
+    }
+    let url = Url::parse(&format!("http://gateway{path}")).ok()?;
+    Some(url.path().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(method: &str, path_prefix: &str) -> AllowedEndpoint {
+        AllowedEndpoint {
+            method: method.to_string(),
+            path_prefix: path_prefix.to_string(),
+        }
+    }
+
+    fn allowed(allowlist: &[AllowedEndpoint], method: Method, path: &str) -> bool {
+        check(Some(allowlist), &method, path).is_ok()
+    }
+
+    #[test]
+    fn prefixes_match_whole_segments() {
+        let allowlist = [endpoint("GET", "/models")];
+        assert!(allowed(&allowlist, Method::GET, "/models"));
+        assert!(allowed(&allowlist, Method::GET, "/models/x"));
+        assert!(allowed(&allowlist, Method::GET, "/models?page=2"));
+        assert!(!allowed(&allowlist, Method::GET, "/models-admin"));
+    }
+
+    #[test]
+    fn dot_segments_are_resolved_first() {
+        let allowlist = [endpoint("*", "/models")];
+        assert!(allowed(&allowlist, Method::GET, "/models/./x"));
+        assert!(!allowed(&allowlist, Method::GET, "/models/../admin"));
+        assert!(!allowed(&allowlist, Method::GET, "/models/%2e%2e/admin"));
+        assert!(!allowed(&allowlist, Method::GET, "/models/%2E./admin"));
+    }
+
+    #[test]
+    fn non_absolute_paths_are_refused() {
+        let allowlist = [endpoint("*", "/")];
+        assert!(!allowed(&allowlist, Method::GET, "//evil.example/models"));
+        assert!(!allowed(&allowlist, Method::GET, "models"));
+    }
+
+    #[test]
+    fn methods_match_exactly_unless_any() {
+        let allowlist = [endpoint("get", "/models"), endpoint("*", "/chat")];
+        assert!(allowed(&allowlist, Method::GET, "/models"));
+        assert!(!allowed(&allowlist, Method::POST, "/models"));
+        assert!(allowed(&allowlist, Method::POST, "/chat"));
+        assert!(allowed(&allowlist, Method::DELETE, "/chat/1"));
+    }
+
+    #[test]
+    fn no_allowlist_allows_everything() {
+        assert!(check(None, &Method::DELETE, "/admin/../anything").is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_bad_entries() {
+        assert!(validate(&[endpoint("GET", "/models")]).is_ok());
+        assert!(validate(&[endpoint("G ET", "/models")]).is_err());
+        assert!(validate(&[endpoint("GET", "models")]).is_err());
+    }
+}
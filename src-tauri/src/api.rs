@@ -0,0 +1,180 @@
+//! Gateway HTTP API helpers
+//!
+//! Thin wrappers for calling the connected gateway from Rust, using the
+//! shared client (so profile auth headers apply).
+
+use std::sync::atomic::AtomicU64;
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+
+use crate::features::Feature;
+use crate::throttle::{self, Throttle};
+use crate::{gateway, pinning, AppState, GatewayState};
+
+/// Header a traced request's ID is sent in
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Header the gateway's device ID is sent in on traced requests
+const DEVICE_ID_HEADER: &str = "X-Device-Id";
+
+/// Base URL of the connected gateway
+pub async fn base_url(state: &AppState) -> Result<String, String> {
+    match &*state.gateway_state.read().await {
+        GatewayState::Connected { url, .. } => Ok(url.clone()),
+        _ => Err("not connected to a gateway".to_string()),
+    }
+}
+
+/// Send a request to the connected gateway, returning the raw response
+///
+/// Fails immediately in offline mode rather than waiting to time out. With
+/// the `request_logging` feature, every request is logged with its outcome.
+/// Bodies are sent at the upload bandwidth limit, if one is set.
+pub async fn request(
+    state: &AppState,
+    method: Method,
+    path: &str,
+    body: Option<&serde_json::Value>,
+) -> Result<reqwest::Response, String> {
+    send(state, method, path, body, None).await
+}
+
+/// New ID to tag a proxied request with, if request tracing is on
+pub async fn trace_id(state: &AppState) -> Option<String> {
+    let enabled = state.settings.read().await.request_tracing;
+    enabled.then(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Like [`request`], tagged with `request_id` (from [`trace_id`]) if given
+///
+/// A tagged request carries it as `X-Request-Id`, along with the gateway's
+/// device ID as `X-Device-Id` when known, and is always logged.
+pub async fn send(
+    state: &AppState,
+    method: Method,
+    path: &str,
+    body: Option<&serde_json::Value>,
+    request_id: Option<&str>,
+) -> Result<reqwest::Response, String> {
+    if state.settings.read().await.offline_mode {
+        return Err("offline mode is on, not contacting the gateway".to_string());
+    }
+
+    let base = base_url(state).await?;
+    let url = format!("{base}{path}");
+    let device_id = match request_id {
+        Some(_) => saved_device_id(state, &base).await,
+        None => None,
+    };
+    let client = state.client.read().await.clone();
+
+    let mut request = client.request(method.clone(), &url);
+    if let Some(request_id) = request_id {
+        request = request.header(REQUEST_ID_HEADER, request_id);
+    }
+    if let Some(device_id) = &device_id {
+        request = request.header(DEVICE_ID_HEADER, device_id);
+    }
+    if let Some(body) = body {
+        let data = serde_json::to_vec(body)
+            .map_err(|e| format!("failed to serialize request body: {e}"))?;
+        request = request.header(CONTENT_TYPE, "application/json");
+        request = match state.upload_throttle.limit() {
+            Some(_) => request.body(throttle::body(data, state.upload_throttle.clone())),
+            None => {
+                state.upload_throttle.count(data.len());
+                request.body(data)
+            }
+        };
+    }
+
+    let started = std::time::Instant::now();
+    let result = request.send().await.map_err(|e| {
+        if pinning::is_pin_mismatch(&e) {
+            format!("{method} {path} failed: certificate pin mismatch")
+        } else if gateway::is_tls_version_error(&e) {
+            format!("{method} {path} failed: TLS version too low for the gateway")
+        } else {
+            format!("{method} {path} failed: {e}")
+        }
+    });
+    if let Ok(resp) = &result {
+        state.pool_tracker.observe(resp);
+    }
+
+    if request_id.is_some() || state.features.is_enabled(Feature::RequestLogging) {
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        let device_id = device_id.as_deref();
+        match &result {
+            Ok(resp) => tracing::info!(
+                %method,
+                path,
+                status = resp.status().as_u16(),
+                elapsed_ms,
+                request_id,
+                device_id,
+                "gateway request"
+            ),
+            Err(e) => tracing::info!(
+                %method,
+                path,
+                error = %e,
+                elapsed_ms,
+                request_id,
+                device_id,
+                "gateway request failed"
+            ),
+        }
+    }
+
+    result
+}
+
+/// Device ID of the gateway at `url`, if it's the saved one
+async fn saved_device_id(state: &AppState, url: &str) -> Option<String> {
+    let settings = state.settings.read().await;
+    let saved = settings.saved_gateway.as_ref()?;
+    if saved.url != url {
+        return None;
+    }
+    saved.device_id.clone()
+}
+
+/// Decode a successful JSON response, turning error statuses into messages
+pub async fn json<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T, String> {
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("gateway returned {status}: {body}"));
+    }
+
+    resp.json()
+        .await
+        .map_err(|e| format!("invalid gateway response: {e}"))
+}
+
+/// Like [`json`], but reading the body no faster than `throttle` allows and
+/// counting the bytes read in `progress`
+pub async fn json_throttled<T: DeserializeOwned>(
+    mut resp: reqwest::Response,
+    throttle: &Throttle,
+    progress: &AtomicU64,
+) -> Result<T, String> {
+    let status = resp.status();
+    let body = throttle::read(&mut resp, throttle, progress)
+        .await
+        .map_err(|e| format!("failed to read gateway response: {e}"))?;
+    if !status.is_success() {
+        let body = String::from_utf8_lossy(&body);
+        return Err(format!("gateway returned {status}: {body}"));
+    }
+
+    serde_json::from_slice(&body).map_err(|e| format!("invalid gateway response: {e}"))
+}
+
+/// GET a JSON resource from the connected gateway
+pub async fn get<T: DeserializeOwned>(state: &AppState, path: &str) -> Result<T, String> {
+    json(request(state, Method::GET, path, None).await?).await
+}
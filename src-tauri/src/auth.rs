@@ -0,0 +1,142 @@
+//! Gateway token refresh
+//!
+//! Remote gateways may hand out short-lived tokens. When a proxied request
+//! comes back 401, the connected profile's token is refreshed, either from
+//! the profile's refresh endpoint or by re-reading a token rotated into
+//! secure storage, and the live client switches over to it. Idempotent
+//! requests are then retried once; others are left for the caller to resend.
+//!
+//! If the refresh fails (or the gateway rejects the new token too), the
+//! gateway goes `Failed` with an "auth expired" error and a
+//! `gateway-auth-expired` event prompts the user to sign in again.
+
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use zeroize::Zeroize;
+
+use crate::errors::ErrorKind;
+use crate::{api, gateway, profiles, storage, AppState, GatewayState};
+
+/// Payload of `gateway-auth-expired` events
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthExpiredEvent {
+    /// Profile whose token expired, if connected through one
+    pub profile: Option<String>,
+
+    pub error: String,
+}
+
+/// Refresh endpoint response
+#[derive(Deserialize)]
+struct RefreshResponse {
+    #[serde(alias = "access_token")]
+    token: String,
+}
+
+/// Send a request to the connected gateway, refreshing an expired token once
+///
+/// `request_id` tags the request (and its retry) as in [`api::send`].
+pub async fn request(
+    app: &AppHandle,
+    state: &AppState,
+    method: Method,
+    path: &str,
+    body: Option<&serde_json::Value>,
+    request_id: Option<&str>,
+) -> Result<reqwest::Response, String> {
+    let resp = api::send(state, method.clone(), path, body, request_id).await?;
+    if resp.status() != StatusCode::UNAUTHORIZED {
+        return Ok(resp);
+    }
+
+    tracing::info!(%method, path, "gateway returned 401, refreshing token");
+    if let Err(e) = refresh(state).await {
+        expire(app, state, &e).await;
+        return Ok(resp);
+    }
+    if !method.is_idempotent() {
+        return Ok(resp);
+    }
+
+    let retried = api::send(state, method, path, body, request_id).await?;
+    if retried.status() == StatusCode::UNAUTHORIZED {
+        expire(app, state, "gateway rejected the refreshed token").await;
+    }
+    Ok(retried)
+}
+
+/// Fetch a fresh token for the connected profile and apply it to the client
+async fn refresh(state: &AppState) -> Result<(), String> {
+    let name = state
+        .active_profile
+        .read()
+        .await
+        .clone()
+        .ok_or_else(|| "not connected through a profile, no token to refresh".to_string())?;
+    let profile = profiles::find(&state.data_dir, &name)
+        .ok_or_else(|| format!("profile not found: {name}"))?;
+
+    let key = profiles::token_key(&name);
+    let mut token = storage::get(&key)?;
+
+    if let Some(url) = &profile.token_refresh_url {
+        let fresh = fetch_token(url, token.as_deref()).await?;
+        if let Some(mut previous) = storage::set(&key, fresh.clone())? {
+            previous.zeroize();
+        }
+        token.zeroize();
+        token = Some(fresh);
+    }
+
+    let Some(mut token) = token else {
+        return Err(format!("no token stored for profile {name}"));
+    };
+    let client = profiles::client(&profile, Some(&token));
+    token.zeroize();
+
+    *state.client.write().await = client?;
+    tracing::info!(profile = %name, "gateway token refreshed");
+    Ok(())
+}
+
+/// Ask a refresh endpoint for a new token, authenticating with the old one
+async fn fetch_token(url: &str, current: Option<&str>) -> Result<String, String> {
+    let mut request = gateway::default_client()
+        .post(url)
+        .timeout(gateway::PROBE_TIMEOUT);
+    if let Some(current) = current {
+        request = request.bearer_auth(current);
+    }
+
+    let resp = request
+        .send()
+        .await
+        .map_err(|e| format!("token refresh failed: {e}"))?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(format!("token refresh endpoint returned {status}"));
+    }
+
+    let body: RefreshResponse = resp
+        .json()
+        .await
+        .map_err(|e| format!("invalid token refresh response: {e}"))?;
+    Ok(body.token)
+}
+
+/// Mark the gateway as failed with expired auth and prompt re-authentication
+async fn expire(app: &AppHandle, state: &AppState, error: &str) {
+    let error = format!("gateway auth expired: {error}");
+    tracing::warn!("{error}");
+    state.recent_errors.record(ErrorKind::Auth, &error);
+
+    state
+        .set_gateway_state(GatewayState::Failed {
+            error: error.clone(),
+        })
+        .await;
+
+    let profile = state.active_profile.read().await.clone();
+    let _ = app.emit("gateway-auth-expired", AuthExpiredEvent { profile, error });
+}
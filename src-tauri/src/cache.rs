@@ -0,0 +1,101 @@
+//! Gateway response caches
+//!
+//! The app keeps a few gateway responses around: the API schema (per gateway
+//! version), health details (for a couple of seconds), feature support
+//! (until the next connect) and the active persona's profile. They are
+//! reported on and reset together here, since a model, persona or gateway
+//! change can leave any of them stale.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+use crate::AppState;
+
+/// Hit and miss counts across the response caches
+#[derive(Default)]
+pub struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheCounters {
+    /// Count a response served from a cache
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a response that had to be fetched from the gateway
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Response cache usage
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    /// Cached responses
+    pub entries: usize,
+
+    /// Responses served from a cache since launch
+    pub hits: u64,
+
+    /// Responses fetched from the gateway since launch
+    pub misses: u64,
+
+    /// Approximate size of the cached responses, as JSON (bytes)
+    pub total_bytes: usize,
+}
+
+/// Report what the response caches hold
+pub async fn stats(state: &AppState) -> CacheStats {
+    let mut entries = 0;
+    let mut total_bytes = 0;
+
+    if let Some((_, schema)) = &*state.schema_cache.read().await {
+        entries += 1;
+        total_bytes += json_len(schema);
+    }
+    if let Some((_, _, detail)) = &*state.health_cache.read().await {
+        entries += 1;
+        total_bytes += json_len(detail);
+    }
+    if let Some((_, support)) = &*state.feature_support_cache.read().await {
+        entries += 1;
+        total_bytes += json_len(support);
+    }
+    if let Some((_, profile)) = &*state.persona_profile.read().await {
+        entries += 1;
+        total_bytes += json_len(profile);
+    }
+
+    CacheStats {
+        entries,
+        hits: state.cache_counters.hits.load(Ordering::Relaxed),
+        misses: state.cache_counters.misses.load(Ordering::Relaxed),
+        total_bytes,
+    }
+}
+
+/// Empty every response cache, returning how many entries were dropped
+///
+/// Hit and miss counts are kept, so they still cover the whole session.
+pub async fn clear(state: &AppState) -> usize {
+    let schema = state.schema_cache.write().await.take();
+    let health = state.health_cache.write().await.take();
+    let support = state.feature_support_cache.write().await.take();
+    let persona = state.persona_profile.write().await.take();
+    let cleared = usize::from(schema.is_some())
+        + usize::from(health.is_some())
+        + usize::from(support.is_some())
+        + usize::from(persona.is_some());
+
+    if cleared > 0 {
+        tracing::debug!(cleared, "response caches cleared");
+    }
+    cleared
+}
+
+fn json_len(value: &impl Serialize) -> usize {
+    serde_json::to_vec(value).map_or(0, |json| json.len())
+}
@@ -0,0 +1,191 @@
+//! Gateway feature support
+//!
+//! Gateways describe what they can do at `/capabilities`, as a list of names
+//! or an object of flags, possibly nested under `capabilities` or `features`.
+//! That is boiled down to the booleans the UI needs. Gateways from before the
+//! endpoint get an estimate from their version instead.
+
+use std::collections::HashSet;
+
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::features::Feature;
+use crate::{api, gateway, AppState};
+
+/// Capability names, in their accepted spellings, behind each feature
+const VISION: &[&str] = &["vision", "images", "image_input"];
+const TOOLS: &[&str] = &["tools", "tool_calls", "function_calling"];
+const VOICE: &[&str] = &["voice", "audio", "speech", "tts", "stt"];
+const STREAMING: &[&str] = &["streaming", "stream", "sse"];
+const PERSONA_SWITCH: &[&str] = &["persona_switch", "personas"];
+const MODEL_SWITCH: &[&str] = &["model_switch", "models"];
+const DEVICE_REGISTRATION: &[&str] = &["device_registration", "devices", "pairing"];
+
+/// First versions with tool calls, vision and voice, for gateways that
+/// don't report capabilities
+const TOOLS_SINCE: (u64, u64) = (0, 2);
+const VISION_SINCE: (u64, u64) = (0, 3);
+const VOICE_SINCE: (u64, u64) = (0, 4);
+
+/// How the feature support was worked out
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SupportSource {
+    /// From the gateway's `/capabilities`
+    Capabilities,
+
+    /// Estimated from the gateway version
+    Version,
+
+    /// Gateway reports neither; only the baseline is assumed
+    Baseline,
+}
+
+/// Features the connected gateway supports
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureSupport {
+    pub vision: bool,
+    pub tools: bool,
+    pub voice: bool,
+    pub streaming: bool,
+    pub persona_switch: bool,
+    pub model_switch: bool,
+    pub device_registration: bool,
+
+    pub source: SupportSource,
+    pub version: Option<String>,
+}
+
+impl FeatureSupport {
+    fn from_capabilities(names: &HashSet<String>, version: Option<String>) -> Self {
+        let has = |spellings: &[&str]| spellings.iter().any(|s| names.contains(*s));
+        Self {
+            vision: has(VISION),
+            tools: has(TOOLS),
+            voice: has(VOICE),
+            streaming: has(STREAMING),
+            persona_switch: has(PERSONA_SWITCH),
+            model_switch: has(MODEL_SWITCH),
+            device_registration: has(DEVICE_REGISTRATION),
+            source: SupportSource::Capabilities,
+            version,
+        }
+    }
+
+    /// Estimate for a gateway without `/capabilities`
+    ///
+    /// Streaming, model and persona switching and pairing predate the
+    /// endpoint, so every gateway is assumed to have them.
+    fn from_version(version: Option<String>) -> Self {
+        let parsed = version.as_deref().and_then(gateway::parse_version);
+        let since = |first: (u64, u64)| parsed.is_some_and(|v| v >= first);
+        Self {
+            vision: since(VISION_SINCE),
+            tools: since(TOOLS_SINCE),
+            voice: since(VOICE_SINCE),
+            streaming: true,
+            persona_switch: true,
+            model_switch: true,
+            device_registration: true,
+            source: if parsed.is_some() {
+                SupportSource::Version
+            } else {
+                SupportSource::Baseline
+            },
+            version,
+        }
+    }
+}
+
+/// Work out what the connected gateway supports, cached until reconnect
+pub async fn feature_support(state: &AppState) -> Result<FeatureSupport, String> {
+    let url = api::base_url(state).await?;
+
+    if let Some((cached_url, support)) = &*state.feature_support_cache.read().await {
+        if *cached_url == url {
+            state.cache_counters.hit();
+            return Ok(support.clone());
+        }
+    }
+    state.cache_counters.miss();
+
+    let client = state.client.read().await.clone();
+    let version = gateway::fetch_gateway_info(&client, &url)
+        .await
+        .and_then(|info| info.version);
+
+    let resp = api::request(state, Method::GET, "/capabilities", None).await?;
+    let status = resp.status();
+    let support = match status {
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => {
+            FeatureSupport::from_version(version)
+        }
+        status if status.is_success() => {
+            let body = resp.text().await.unwrap_or_default();
+            let value = serde_json::from_str::<Value>(&body)
+                .map_err(|e| format!("gateway capabilities are not valid JSON: {e}"))?;
+            FeatureSupport::from_capabilities(&names(&value), version)
+        }
+        status => return Err(format!("gateway returned {status} for its capabilities")),
+    };
+
+    tracing::debug!(?support, "gateway feature support");
+    if state.features.is_enabled(Feature::Cache) {
+        *state.feature_support_cache.write().await = Some((url, support.clone()));
+    }
+    Ok(support)
+}
+
+/// Whether a `/capabilities` body lists voice support
+pub fn has_voice(value: &Value) -> bool {
+    let names = names(value);
+    VOICE.iter().any(|s| names.contains(*s))
+}
+
+/// Largest request body a `/capabilities` (or error) body states, in bytes
+///
+/// Read from `max_request_bytes` at the top level or under `limits`,
+/// `capabilities` or `error`.
+pub fn max_request_bytes(value: &Value) -> Option<u64> {
+    [
+        "/max_request_bytes",
+        "/limits/max_request_bytes",
+        "/capabilities/max_request_bytes",
+        "/error/max_request_bytes",
+    ]
+    .iter()
+    .find_map(|p| value.pointer(p).and_then(Value::as_u64))
+}
+
+/// Names of the enabled capabilities, lowercased
+///
+/// Accepts `["vision", ...]`, `{"vision": true, ...}` (an object value other
+/// than `false` or `null` counts as enabled), either one under a
+/// `capabilities` or `features` key, and objects with a `name` in a list.
+fn names(value: &Value) -> HashSet<String> {
+    let value = ["/capabilities", "/features"]
+        .iter()
+        .find_map(|p| value.pointer(p))
+        .unwrap_or(value);
+
+    let enabled = |v: &Value| !matches!(v, Value::Bool(false) | Value::Null);
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                Value::String(name) => Some(name.as_str()),
+                Value::Object(_) => item.get("name").and_then(Value::as_str),
+                _ => None,
+            })
+            .map(str::to_lowercase)
+            .collect(),
+        Value::Object(flags) => flags
+            .iter()
+            .filter(|(_, v)| enabled(v))
+            .map(|(name, _)| name.to_lowercase())
+            .collect(),
+        _ => HashSet::new(),
+    }
+}
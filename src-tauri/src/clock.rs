@@ -0,0 +1,117 @@
+//! Clock skew against the gateway
+//!
+//! Token expiry and other time-based checks fail in confusing ways when this
+//! machine's clock is off. The gateway's idea of the time is read from its
+//! `/health` response (a time field in the body if it has one, otherwise the
+//! HTTP `Date` header) and compared with the local clock at the midpoint of
+//! the request.
+
+use std::time::Instant;
+
+use chrono::DateTime;
+use reqwest::header::DATE;
+use reqwest::Method;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{api, logs, AppState};
+
+/// Skew beyond which the clock is flagged (ms)
+pub const SKEW_WARNING_MS: u64 = 30_000;
+
+/// Where the gateway's time came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeSource {
+    /// A time field in the health body (ms precision)
+    HealthBody,
+
+    /// The HTTP `Date` header (second precision)
+    DateHeader,
+}
+
+/// Result of a clock skew check
+#[derive(Debug, Clone, Serialize)]
+pub struct SkewResult {
+    /// Local clock minus gateway clock (ms, positive when this machine is ahead)
+    pub skew_ms: i64,
+
+    /// How far off the estimate may be, from timing and precision (ms)
+    pub uncertainty_ms: u64,
+
+    pub round_trip_ms: u64,
+    pub source: TimeSource,
+
+    /// Whether the skew is past [`SKEW_WARNING_MS`]
+    pub warning: bool,
+
+    /// When the check ran (ms since Unix epoch)
+    pub checked_at_ms: u64,
+}
+
+/// Estimate the skew between this machine's clock and the gateway's
+pub async fn check(state: &AppState) -> Result<SkewResult, String> {
+    let sent_ms = logs::now_ms();
+    let started = Instant::now();
+    let resp = api::request(state, Method::GET, "/health", None).await?;
+    let round_trip_ms = started.elapsed().as_millis() as u64;
+
+    let date_header = resp
+        .headers()
+        .get(DATE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+        .map(|date| date.timestamp_millis());
+    let body = resp.text().await.unwrap_or_default();
+
+    let (gateway_ms, source, precision_ms) = match body_time(&body) {
+        Some(ms) => (ms, TimeSource::HealthBody, 0),
+        None => {
+            let ms = date_header.ok_or_else(|| {
+                "gateway reported no time (no Date header or time field)".to_string()
+            })?;
+            // Date truncates to the second, so the true time is up to 1s later
+            (ms + 500, TimeSource::DateHeader, 500)
+        }
+    };
+
+    let local_ms = (sent_ms + round_trip_ms / 2) as i64;
+    let skew_ms = local_ms - gateway_ms;
+    let result = SkewResult {
+        skew_ms,
+        uncertainty_ms: round_trip_ms / 2 + precision_ms,
+        round_trip_ms,
+        source,
+        warning: skew_ms.unsigned_abs() > SKEW_WARNING_MS,
+        checked_at_ms: logs::now_ms(),
+    };
+
+    if result.warning {
+        tracing::warn!(skew_ms, ?source, "local clock is off from the gateway's");
+    } else {
+        tracing::debug!(skew_ms, ?source, "checked clock skew");
+    }
+    Ok(result)
+}
+
+/// Gateway time from a health body, as ms since Unix epoch
+///
+/// Accepts epoch milliseconds or seconds, or an RFC 3339 string.
+fn body_time(body: &str) -> Option<i64> {
+    let value = serde_json::from_str::<Value>(body).ok()?;
+    let field = ["/time", "/server_time", "/timestamp", "/now"]
+        .iter()
+        .find_map(|p| value.pointer(p))?;
+
+    match field {
+        Value::Number(n) => {
+            let n = n.as_i64()?;
+            // Epoch seconds stay below 10^10 until the year 2286
+            Some(if n < 10_000_000_000 { n * 1000 } else { n })
+        }
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.timestamp_millis()),
+        _ => None,
+    }
+}
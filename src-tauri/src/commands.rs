@@ -6,13 +6,63 @@
 //! - Secure storage for device identity
 //! - Native OS integrations
 
-use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_opener::OpenerExt;
+use tokio::sync::Notify;
+use zeroize::Zeroize;
 
-use crate::{gateway, AppState, GatewayState};
+use crate::allowlist::{self, AllowedEndpoint};
+use crate::auth;
+use crate::cache::{self, CacheStats};
+use crate::capabilities::{self, FeatureSupport};
+use crate::clock::{self, SkewResult};
+use crate::config_blob;
+use crate::decision::DecisionLog;
+use crate::diagnostics::{self, Diagnostics, EarlierChecks};
+use crate::discovery::{self, DiscoveredGateway, MetadataRefresh};
+use crate::env_profiles;
+use crate::errors::{ErrorKind, RecentError};
+use crate::event_channel::{self, EventChannelResult};
+use crate::export::ExportFile;
+use crate::features::Feature;
+use crate::gateway::{
+    BinarySourceInfo, GatewayLifecycle, Ownership, RetryBudget, SidecarInfo, TlsVersion,
+};
+use crate::health::{self, HealthDetail};
+use crate::jobs::{self, JobStatus};
+use crate::load::GatewayLoad;
+use crate::logs::{self, LogLevel, LogLine};
+use crate::manifest::{self, Manifest};
+use crate::metrics_stream;
+use crate::models::{self, ModelInfo};
+use crate::opener::{self, OpenTarget};
+use crate::pairing;
+use crate::permissions::{self, PermissionKind, PermissionStates, PermissionStatus};
+use crate::personas::{self, PersonaProfile};
+use crate::pinning::{self, CertFingerprint};
+use crate::pool::{self, PoolConfig, PoolStats};
+use crate::profiles::{self, GatewayProfile};
+use crate::protocol::{self, NegotiatedProtocol};
+use crate::proxy::{self, ActiveRequest, DrainResult, QueueStats, RequestKind};
+use crate::reachability::{self, ReachabilityResult};
+use crate::reconnect::ReconnectState;
+use crate::recording::{self, RecordingSummary, ReplaySummary};
+use crate::request_size::{self, RequestLimit};
+use crate::settings::{self, Salvage, Settings};
+use crate::snapshot::{self, SnapshotImport};
+use crate::startup;
+use crate::storage::{MigrationResult, StorageTestResult};
+use crate::throttle;
+use crate::timing::{self, ConnectionTiming};
+use crate::validation::{self, ConnectionConfig, ValidationReport};
+use crate::verify::{self, VerifyResult};
+use crate::{api, download, gateway, storage, AppState, GatewayState, StartupPhase};
 
 // === Gateway Management ===
 
@@ -23,11 +73,37 @@ pub struct GatewayStatus {
     pub url: Option<String>,
     pub is_sidecar: bool,
     pub error: Option<String>,
+    pub safe_mode: bool,
+    pub log_level: Option<LogLevel>,
+
+    /// Restart attempt in progress (only while starting or failed)
+    pub retry: Option<RetryBudget>,
+
+    /// Startup step in progress (only while starting)
+    pub startup_phase: Option<StartupPhase>,
+
+    /// Time since startup began (ms, only while starting)
+    pub elapsed_ms: Option<u64>,
+
+    /// Expected downtime left of an announced restart (ms, only while
+    /// restarting)
+    pub restart_remaining_ms: Option<u64>,
+
+    /// Every sidecar this instance runs, the active one marked
+    pub sidecars: Vec<SidecarInfo>,
+
+    /// Whether the app manages the gateway process (lifecycle controls are
+    /// only meaningful when it does)
+    pub lifecycle: GatewayLifecycle,
 }
 
 /// Get current gateway connection status
 #[tauri::command]
 pub async fn get_gateway_status(state: State<'_, Arc<AppState>>) -> Result<GatewayStatus, String> {
+    let log_level = state.settings.read().await.gateway_log_level;
+    let lifecycle = gateway::lifecycle(&state).await;
+    let retry = *state.retry.read().await;
+    let sidecars = gateway::list_sidecars(&state).await;
     let gateway_state = state.gateway_state.read().await;
 
     Ok(match &*gateway_state {
@@ -36,28 +112,125 @@ pub async fn get_gateway_status(state: State<'_, Arc<AppState>>) -> Result<Gatew
             url: None,
             is_sidecar: false,
             error: None,
+            safe_mode: state.safe_mode,
+            log_level,
+            retry: None,
+            startup_phase: None,
+            elapsed_ms: None,
+            restart_remaining_ms: None,
+            sidecars,
+            lifecycle,
         },
-        GatewayState::Starting => GatewayStatus {
+        GatewayState::Starting { phase, started } => GatewayStatus {
             state: "starting".to_string(),
             url: None,
             is_sidecar: true,
             error: None,
+            safe_mode: state.safe_mode,
+            log_level,
+            retry,
+            startup_phase: Some(*phase),
+            elapsed_ms: Some(started.elapsed().as_millis() as u64),
+            restart_remaining_ms: None,
+            sidecars,
+            lifecycle,
         },
         GatewayState::Connected { url, is_sidecar } => GatewayStatus {
             state: "connected".to_string(),
             url: Some(url.clone()),
             is_sidecar: *is_sidecar,
             error: None,
+            safe_mode: state.safe_mode,
+            log_level,
+            retry: None,
+            startup_phase: None,
+            elapsed_ms: None,
+            restart_remaining_ms: None,
+            sidecars,
+            lifecycle,
+        },
+        GatewayState::Restarting {
+            url,
+            is_sidecar,
+            until,
+            ..
+        } => GatewayStatus {
+            state: "restarting".to_string(),
+            url: Some(url.clone()),
+            is_sidecar: *is_sidecar,
+            error: None,
+            safe_mode: state.safe_mode,
+            log_level,
+            retry: None,
+            startup_phase: None,
+            elapsed_ms: None,
+            restart_remaining_ms: Some(
+                until.saturating_duration_since(Instant::now()).as_millis() as u64
+            ),
+            sidecars,
+            lifecycle,
         },
         GatewayState::Failed { error } => GatewayStatus {
             state: "failed".to_string(),
             url: None,
             is_sidecar: false,
             error: Some(error.clone()),
+            safe_mode: state.safe_mode,
+            log_level,
+            retry,
+            startup_phase: None,
+            elapsed_ms: None,
+            restart_remaining_ms: None,
+            sidecars,
+            lifecycle,
         },
     })
 }
 
+/// Handle the gateway announcing a restart or shutdown (e.g. a
+/// `gateway-restarting` or `gateway-shutdown` message on its event channel)
+///
+/// The gateway goes `restarting` for up to `expected_downtime_ms` (30s if
+/// unset) instead of being reported as crashed: the sidecar monitor and its
+/// restart breaker stand down, and the app reconnects as soon as the gateway
+/// answers again. A sidecar that exits is started again. If it isn't back in
+/// time, a restart ends `failed` and a shutdown ends `disconnected`.
+#[tauri::command]
+pub async fn handle_gateway_shutdown(
+    state: State<'_, Arc<AppState>>,
+    restarting: bool,
+    expected_downtime_ms: Option<u64>,
+    reason: Option<String>,
+) -> Result<GatewayStatus, String> {
+    let downtime = expected_downtime_ms.map(Duration::from_millis);
+    gateway::expect_restart(state.inner().clone(), restarting, downtime, reason).await?;
+    get_gateway_status(state).await
+}
+
+/// Wait up to `timeout_ms` for the gateway to connect or fail, returning the
+/// status at that point
+///
+/// Returns right away if the gateway is already connected or failed, so the
+/// UI can start the gateway and then proceed once it's usable.
+#[tauri::command]
+pub async fn await_gateway_ready(
+    state: State<'_, Arc<AppState>>,
+    timeout_ms: u64,
+) -> Result<GatewayStatus, String> {
+    let mut changes = state.state_changes.subscribe();
+    let settled = |s: &GatewayState| {
+        matches!(
+            s,
+            GatewayState::Connected { .. } | GatewayState::Failed { .. }
+        )
+    };
+
+    let _ =
+        tokio::time::timeout(Duration::from_millis(timeout_ms), changes.wait_for(settled)).await;
+
+    get_gateway_status(state).await
+}
+
 /// Start gateway request
 #[derive(Debug, Deserialize)]
 pub struct StartGatewayRequest {
@@ -75,24 +248,30 @@ pub async fn start_gateway(
 
     if let Some(url) = request.url {
         // Connect to external gateway
+        let url = gateway::normalize_gateway_url(&url)?;
         tracing::info!(url = %url, "connecting to external gateway");
 
-        if gateway::probe_gateway(&url).await {
-            *state.gateway_state.write().await = GatewayState::Connected {
-                url: url.clone(),
-                is_sidecar: false,
-            };
-            *state.gateway_url.write().await = Some(url.clone());
+        let url = match gateway::canonical_url(&url).await {
+            Ok(url) => url,
+            Err(e) => {
+                let e = gateway::resolve_gateway_host(&url).await.err().unwrap_or(e);
+                state.recent_errors.record(ErrorKind::Probe, &e);
+                return Err(e);
+            }
+        };
 
-            Ok(GatewayStatus {
-                state: "connected".to_string(),
-                url: Some(url),
+        state
+            .set_gateway_state(GatewayState::Connected {
+                url: url.clone(),
                 is_sidecar: false,
-                error: None,
             })
-        } else {
-            Err(format!("failed to connect to gateway at {url}"))
-        }
+            .await;
+        *state.gateway_url.write().await = Some(url.clone());
+        *state.active_profile.write().await = None;
+        *state.client.write().await = gateway::default_client();
+        gateway::remember_gateway(&state, &url).await;
+
+        get_gateway_status(state).await
     } else {
         // Start sidecar
         gateway::start_sidecar(&state).await?;
@@ -100,37 +279,2273 @@ pub async fn start_gateway(
     }
 }
 
-/// Stop gateway (only affects sidecar)
+/// Select a sidecar env profile (or none) and run the sidecar with it
+///
+/// The selection is saved to settings. A running sidecar is restarted to
+/// pick it up (refused if another app instance owns it); otherwise one is
+/// started. See `env_profiles` for how profile variables combine with the
+/// ones the app manages.
+#[tauri::command]
+pub async fn connect_with_env_profile(
+    state: State<'_, Arc<AppState>>,
+    name: Option<String>,
+) -> Result<GatewayStatus, String> {
+    {
+        let mut settings = state.settings.write().await;
+        env_profiles::validate_all(&settings.env_profiles, name.as_deref())?;
+        settings.env_profile = name.clone();
+        settings.save(&state.data_dir)?;
+    }
+    tracing::info!(env_profile = ?name, "gateway env profile selected");
+
+    if gateway::sidecar_running(&state).await {
+        gateway::restart_sidecar(&state).await?;
+    } else {
+        gateway::start_sidecar(&state).await?;
+    }
+    get_gateway_status(state).await
+}
+
+/// Stop gateway (only affects sidecars, all of them)
+///
+/// Refused if another app instance owns the active sidecar. With the
+/// lifecycle attached, only disconnects.
 #[tauri::command]
 pub async fn stop_gateway(state: State<'_, Arc<AppState>>) -> Result<(), String> {
-    gateway::stop_sidecar(&state).await;
+    if gateway::lifecycle(&state).await == GatewayLifecycle::Attached {
+        state.set_gateway_state(GatewayState::Disconnected).await;
+        return Ok(());
+    }
+
+    gateway::ensure_sidecar_owned(&state).await?;
+    gateway::stop_all_sidecars(&state).await;
     Ok(())
 }
 
-// === Secure Storage ===
+/// Switch between `managed` (the app starts, monitors, restarts and stops
+/// the sidecar) and `attached` (a gateway started by hand is connected to
+/// and health-checked, but never restarted or stopped), persisting the mode
+///
+/// Going attached releases the running sidecars, which keep running. Going
+/// managed adopts a local gateway the app is connected to, writing its PID
+/// file; it fails, leaving the mode unchanged, if that gateway's process
+/// can't be found or another app instance owns it.
+#[tauri::command]
+pub async fn set_gateway_lifecycle(
+    state: State<'_, Arc<AppState>>,
+    mode: GatewayLifecycle,
+) -> Result<GatewayStatus, String> {
+    gateway::set_lifecycle(&state, mode).await?;
+    get_gateway_status(state).await
+}
 
-// Simple in-memory storage for now
-// In production, use the platform's keychain
-static SECURE_STORAGE: std::sync::LazyLock<std::sync::Mutex<HashMap<String, String>>> =
-    std::sync::LazyLock::new(|| std::sync::Mutex::new(HashMap::new()));
+/// Start a sidecar running `persona` alongside the running ones
+///
+/// It listens on `port`, or the first free port after the default one.
+/// With `activate`, the connection moves to it once healthy; otherwise it
+/// runs in the background until `switch_sidecar`.
+#[tauri::command]
+pub async fn start_persona_sidecar(
+    state: State<'_, Arc<AppState>>,
+    persona: String,
+    port: Option<u16>,
+    activate: Option<bool>,
+) -> Result<Vec<SidecarInfo>, String> {
+    gateway::start_sidecar_for(&state, &persona, port, activate.unwrap_or(false)).await?;
+    Ok(gateway::list_sidecars(&state).await)
+}
 
-/// Get a value from secure storage
+/// List the sidecars this instance runs, by port
 #[tauri::command]
-pub async fn get_secure_storage(key: String) -> Result<Option<String>, String> {
-    let storage = SECURE_STORAGE
-        .lock()
-        .map_err(|e| format!("storage lock failed: {e}"))?;
+pub async fn list_sidecars(state: State<'_, Arc<AppState>>) -> Result<Vec<SidecarInfo>, String> {
+    Ok(gateway::list_sidecars(&state).await)
+}
 
-    Ok(storage.get(&key).cloned())
+/// Point the connection at the running sidecar of `persona`, without
+/// stopping the others
+#[tauri::command]
+pub async fn switch_sidecar(
+    state: State<'_, Arc<AppState>>,
+    persona: String,
+) -> Result<GatewayStatus, String> {
+    gateway::switch_sidecar(&state, &persona).await?;
+    get_gateway_status(state).await
 }
 
-/// Set a value in secure storage
+/// Stop the sidecar of `persona`
+///
+/// Stopping the active one disconnects; the others keep running either way.
+/// Refused if another app instance owns it.
 #[tauri::command]
-pub async fn set_secure_storage(key: String, value: String) -> Result<(), String> {
-    let mut storage = SECURE_STORAGE
-        .lock()
-        .map_err(|e| format!("storage lock failed: {e}"))?;
+pub async fn stop_sidecar_by_persona(
+    state: State<'_, Arc<AppState>>,
+    persona: String,
+) -> Result<(), String> {
+    gateway::stop_sidecar_by_persona(&state, &persona).await
+}
+
+/// Stop new streams and let active ones finish (`drain`) or abort them now
+async fn wind_down_streams(state: &AppState, drain: bool, grace_ms: Option<u64>) -> DrainResult {
+    state.streams.close();
+
+    if drain {
+        let grace_ms = match grace_ms {
+            Some(ms) => ms,
+            None => state.settings.read().await.drain_grace_ms,
+        };
+        tracing::info!(
+            active = state.streams.active(),
+            grace_ms,
+            "draining streams"
+        );
+        state.streams.drain(Duration::from_millis(grace_ms)).await
+    } else {
+        DrainResult {
+            completed: 0,
+            cancelled: state.streams.abort_all(),
+        }
+    }
+}
+
+/// Disconnect from the gateway, stopping the sidecar if one is running
+///
+/// With `drain`, new streams are refused while active ones get up to
+/// `grace_ms` (default from settings) to finish before being aborted.
+/// Without it, active streams are aborted immediately.
+#[tauri::command]
+pub async fn disconnect_gateway(
+    state: State<'_, Arc<AppState>>,
+    drain: Option<bool>,
+    grace_ms: Option<u64>,
+) -> Result<DrainResult, String> {
+    let result = wind_down_streams(&state, drain.unwrap_or(false), grace_ms).await;
+
+    gateway::stop_all_sidecars(&state).await;
+    *state.active_profile.write().await = None;
+    *state.client.write().await = gateway::default_client();
+    state.streams.reopen();
+
+    tracing::info!(
+        completed = result.completed,
+        cancelled = result.cancelled,
+        "disconnected from gateway"
+    );
+    Ok(result)
+}
+
+/// Switch to the gateway of a saved profile, draining or aborting streams
+/// on the current one first (see `disconnect_gateway`)
+///
+/// The new gateway is found answering before anything is torn down, so an
+/// unreachable profile leaves the current connection and sidecars as they
+/// were.
+#[tauri::command]
+pub async fn switch_gateway(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    profile: String,
+    drain: Option<bool>,
+    grace_ms: Option<u64>,
+) -> Result<GatewayStatus, String> {
+    let reached = reach_profile(&state, &profile).await?;
+
+    wind_down_streams(&state, drain.unwrap_or(false), grace_ms).await;
+
+    gateway::stop_all_sidecars(&state).await;
+    state.streams.reopen();
+
+    use_profile(app, state, profile, reached).await
+}
+
+/// Get the connected gateway's health details (model, queue, memory, warnings)
+///
+/// Cached for a couple of seconds, so a status panel can poll freely.
+#[tauri::command]
+pub async fn get_gateway_health_detail(
+    state: State<'_, Arc<AppState>>,
+) -> Result<HealthDetail, String> {
+    health::detail(&state).await
+}
+
+/// Get which features the connected gateway supports (vision, tools, voice,
+/// streaming, persona and model switching, device registration)
+///
+/// Read from the gateway's capabilities, or estimated from its version for
+/// gateways that don't report them. Cached until the next connect.
+#[tauri::command]
+pub async fn get_feature_support(
+    state: State<'_, Arc<AppState>>,
+) -> Result<FeatureSupport, String> {
+    capabilities::feature_support(&state).await
+}
+
+/// Get the sidecar restart backoff: recent failure density, whether the
+/// gateway is considered unstable, and the current delays
+#[tauri::command]
+pub async fn get_reconnect_state(
+    state: State<'_, Arc<AppState>>,
+) -> Result<ReconnectState, String> {
+    Ok(state.reconnect.state())
+}
+
+/// What the latest automatic connect tried, in order, and how each attempt
+/// went
+#[tauri::command]
+pub async fn get_connection_decision(
+    state: State<'_, Arc<AppState>>,
+) -> Result<DecisionLog, String> {
+    Ok(state.connection_decision.read().await.clone())
+}
+
+/// Report whether this instance, another instance, or no one owns the
+/// sidecar of `persona` (the active one if unset)
+#[tauri::command]
+pub async fn get_sidecar_ownership(
+    state: State<'_, Arc<AppState>>,
+    persona: Option<String>,
+) -> Result<Ownership, String> {
+    Ok(match persona {
+        Some(persona) => gateway::persona_ownership(&state, &persona).await,
+        None => gateway::sidecar_ownership(&state).await,
+    })
+}
+
+/// Hard-kill every sidecar without a graceful shutdown
+///
+/// For recovering from a sidecar that won't stop; always leaves the gateway
+/// `Disconnected`.
+#[tauri::command]
+pub async fn force_stop_gateway(state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    state.streams.abort_all();
+    gateway::force_stop_sidecar(&state).await;
+    Ok(())
+}
+
+/// Check that a gateway binary runs on this machine before switching to it
+///
+/// Checks executability and architecture and runs `--version`; with `start`,
+/// also starts it on a temporary port and waits for a healthy response. The
+/// active connection is left alone and any test process is stopped.
+#[tauri::command]
+pub async fn verify_gateway_binary(
+    path: PathBuf,
+    start: Option<bool>,
+) -> Result<VerifyResult, String> {
+    Ok(verify::verify(&path, start.unwrap_or(false)).await)
+}
+
+/// Report which gateway binary the sidecar would launch, and where it came
+/// from (bundled, downloaded, env override, `PATH` or a dev build)
+#[tauri::command]
+pub async fn get_gateway_binary_source(
+    state: State<'_, Arc<AppState>>,
+) -> Result<BinarySourceInfo, String> {
+    gateway::find_gateway_binary(&state.data_dir)
+}
+
+/// When the sidecar will next be restarted by the schedule (ms since Unix
+/// epoch), or `None` if no restart is scheduled
+#[tauri::command]
+pub async fn get_next_scheduled_restart(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Option<i64>, String> {
+    Ok(*state.next_scheduled_restart.read().await)
+}
+
+/// Typical sidecar startup time on this machine (ms), or `None` until a few
+/// startups have been timed
+#[tauri::command]
+pub async fn get_startup_estimate(state: State<'_, Arc<AppState>>) -> Result<Option<u64>, String> {
+    let settings = state.settings.read().await;
+    Ok(startup::estimate(&settings).map(|estimate| estimate.as_millis() as u64))
+}
+
+/// Gateway API schema lookup result
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum GatewaySchema {
+    /// Schema fetched from the gateway (or cached for this version)
+    Available {
+        version: Option<String>,
+        schema: serde_json::Value,
+    },
+
+    /// Gateway doesn't publish a schema; the UI should use static assumptions
+    Unavailable,
+}
+
+/// Get the connected gateway's API schema
+///
+/// Cached per gateway version until the next connect, model or persona change
+/// (or `clear_response_cache`).
+#[tauri::command]
+pub async fn get_gateway_schema(state: State<'_, Arc<AppState>>) -> Result<GatewaySchema, String> {
+    let url = state
+        .gateway_url()
+        .await
+        .ok_or_else(|| "not connected to a gateway".to_string())?;
+    let client = state.client.read().await.clone();
+
+    let version = gateway::fetch_gateway_info(&client, &url)
+        .await
+        .and_then(|info| info.version);
+
+    if let (Some(version), Some((cached_version, schema))) =
+        (&version, &*state.schema_cache.read().await)
+    {
+        if version == cached_version {
+            state.cache_counters.hit();
+            return Ok(GatewaySchema::Available {
+                version: Some(version.clone()),
+                schema: schema.clone(),
+            });
+        }
+    }
+
+    state.cache_counters.miss();
+    let Some(schema) = gateway::fetch_schema(&client, &url).await else {
+        return Ok(GatewaySchema::Unavailable);
+    };
 
-    storage.insert(key, value);
+    let caching = state.features.is_enabled(Feature::Cache);
+    if let Some(version) = version.as_ref().filter(|_| caching) {
+        *state.schema_cache.write().await = Some((version.clone(), schema.clone()));
+    }
+
+    Ok(GatewaySchema::Available { version, schema })
+}
+
+/// Download the gateway binary for this platform (or `platform_target`)
+///
+/// The base URL comes from `BEACON_GATEWAY_DOWNLOAD_URL`, then settings, then
+/// the official releases. Emits `gateway-download-progress` while downloading.
+#[tauri::command]
+pub async fn download_gateway_binary(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    platform_target: Option<String>,
+) -> Result<PathBuf, String> {
+    let base_url = match std::env::var("BEACON_GATEWAY_DOWNLOAD_URL") {
+        Ok(url) => url,
+        Err(_) => state
+            .settings
+            .read()
+            .await
+            .gateway_download_url
+            .clone()
+            .unwrap_or_else(|| download::DEFAULT_BASE_URL.to_string()),
+    };
+    let target = platform_target.unwrap_or_else(download::current_target);
+
+    download::download_gateway(&app, &base_url, &target, &state.data_dir).await
+}
+
+// === Gateway Logs ===
+
+/// Default number of lines returned by `get_gateway_logs`
+const DEFAULT_LOG_LIMIT: usize = 200;
+
+/// Get the most recent sidecar log lines (oldest first)
+///
+/// Only sidecar output is captured; remote gateways serve their logs over
+/// their own HTTP endpoint.
+#[tauri::command]
+pub async fn get_gateway_logs(
+    state: State<'_, Arc<AppState>>,
+    limit: Option<usize>,
+) -> Result<Vec<LogLine>, String> {
+    Ok(state
+        .gateway_logs
+        .recent(limit.unwrap_or(DEFAULT_LOG_LIMIT)))
+}
+
+/// Write the captured sidecar output to a file under the data directory,
+/// returning its path
+///
+/// The file is gzip-compressed (`.log.gz`) unless `compress` is false.
+#[tauri::command]
+pub async fn export_gateway_log(
+    state: State<'_, Arc<AppState>>,
+    compress: Option<bool>,
+) -> Result<PathBuf, String> {
+    state
+        .gateway_logs
+        .export(&state.data_dir.join("exports"), compress.unwrap_or(true))
+}
+
+/// Start emitting batched `gateway-log-line` events for new sidecar output
+///
+/// Off by default to save IPC. Sidecar only: remote gateway logs aren't
+/// streamed, poll the gateway's logs endpoint instead.
+#[tauri::command]
+pub async fn start_gateway_log_stream(state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    state.gateway_logs.set_streaming(true);
+    Ok(())
+}
+
+/// Stop emitting `gateway-log-line` events
+#[tauri::command]
+pub async fn stop_gateway_log_stream(state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    state.gateway_logs.set_streaming(false);
+    Ok(())
+}
+
+/// Set the gateway's log verbosity, persisting it as the preference
+///
+/// A remote gateway is asked to switch at runtime. A sidecar reads its level
+/// from `BEACON_LOG` at launch, so it is restarted to apply the change.
+#[tauri::command]
+pub async fn set_gateway_log_level(
+    state: State<'_, Arc<AppState>>,
+    level: LogLevel,
+) -> Result<GatewayStatus, String> {
+    let is_sidecar = matches!(
+        &*state.gateway_state.read().await,
+        GatewayState::Connected {
+            is_sidecar: true,
+            ..
+        }
+    );
+
+    if state.is_connected().await && !is_sidecar {
+        let body = serde_json::json!({ "level": level.as_str() });
+        let resp = api::request(&state, reqwest::Method::POST, "/log-level", Some(&body)).await?;
+        if matches!(resp.status().as_u16(), 404 | 405) {
+            return Err("gateway does not support changing its log level at runtime".to_string());
+        }
+        api::json::<serde_json::Value>(resp).await?;
+    }
+
+    {
+        let mut settings = state.settings.write().await;
+        settings.gateway_log_level = Some(level);
+        settings.save(&state.data_dir)?;
+    }
+
+    if is_sidecar {
+        tracing::info!(
+            level = level.as_str(),
+            "restarting sidecar to apply log level"
+        );
+        gateway::restart_sidecar(&state).await?;
+    }
+
+    get_gateway_status(state).await
+}
+
+// === Proxy ===
+
+/// Parse an HTTP method name from the frontend
+fn parse_method(method: &str) -> Result<reqwest::Method, String> {
+    reqwest::Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+        .map_err(|_| format!("invalid HTTP method `{method}`"))
+}
+
+/// Response of a proxied request
+#[derive(Debug, Serialize)]
+pub struct ProxyResponse {
+    pub body: serde_json::Value,
+
+    /// `X-Request-Id` the request was sent with, if request tracing is on
+    pub request_id: Option<String>,
+}
+
+/// Hold a proxied request until the gateway is connected, if it is on its
+/// way: starting, restarting, or failed with a restart pending
+///
+/// Waits up to `timeout_ms` (default from settings). Otherwise the request
+/// goes ahead right away, and fails if the gateway isn't connected.
+async fn await_ready(state: &AppState, timeout_ms: Option<u64>) -> Result<(), String> {
+    let coming = match &*state.gateway_state.read().await {
+        GatewayState::Starting { .. } | GatewayState::Restarting { .. } => true,
+        GatewayState::Failed { .. } => state.retry.read().await.is_some(),
+        GatewayState::Connected { .. } | GatewayState::Disconnected => false,
+    };
+    if !coming {
+        return Ok(());
+    }
+
+    let (max_waiting, default_timeout_ms) = {
+        let settings = state.settings.read().await;
+        (
+            settings.max_queued_until_ready,
+            settings.queue_until_ready_timeout_ms,
+        )
+    };
+    let timeout = Duration::from_millis(timeout_ms.unwrap_or(default_timeout_ms));
+    tracing::debug!(
+        timeout_ms = timeout.as_millis() as u64,
+        "request waiting for the gateway to become ready"
+    );
+    state
+        .request_limiter
+        .wait_until_ready(state.state_changes.subscribe(), max_waiting, timeout)
+        .await
+}
+
+/// Send a request to the connected gateway and return its JSON response
+///
+/// Waits for a request-limiter slot first (see `max_concurrent_requests`).
+/// With `queue_until_ready`, a request made while the gateway is starting
+/// or reconnecting also waits for it to connect, for up to
+/// `queue_timeout_ms`, instead of failing right away.
+/// The request and response bodies respect the bandwidth limits.
+/// An expired token is refreshed, and idempotent requests retried once.
+/// Requests are adapted to an older gateway's protocol version (see
+/// `get_negotiated_protocol`).
+#[tauri::command]
+pub async fn proxy_request(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    method: String,
+    path: String,
+    body: Option<serde_json::Value>,
+    queue_until_ready: Option<bool>,
+    queue_timeout_ms: Option<u64>,
+) -> Result<ProxyResponse, String> {
+    let method = parse_method(&method)?;
+    let request = state
+        .streams
+        .register(RequestKind::Buffered, &method, &path)?;
+    let request_id = api::trace_id(&state).await;
+
+    let response = async {
+        if queue_until_ready.unwrap_or(false) {
+            await_ready(&state, queue_timeout_ms).await?;
+        }
+        // Only once connected is the protocol known to adapt to
+        let (path, body) = protocol::adapt(&app, &state, path, body).await;
+        check_allowed(&state, &method, &path).await?;
+        let size = request_size::check(&state, body.as_ref()).await?;
+        let _permit = state.request_limiter.acquire().await?;
+        let id = request_id.as_deref();
+        let resp = auth::request(&app, &state, method, &path, body.as_ref(), id).await?;
+        if resp.status() == reqwest::StatusCode::PAYLOAD_TOO_LARGE {
+            return Err(request_size::rejected(&state, resp, size).await);
+        }
+        api::json_throttled(resp, &state.download_throttle, &request.bytes).await
+    };
+    let result = tokio::select! {
+        result = response => result,
+        () = request.cancel.notified() => Err("request cancelled".to_string()),
+    };
+    state.metrics.record_request(result.is_ok());
+    Ok(ProxyResponse {
+        body: result?,
+        request_id,
+    })
+}
+
+/// Send a request to the connected gateway and stream its response body
+///
+/// Returns a stream ID immediately; the body arrives as `proxy-stream-chunk`
+/// events followed by one `proxy-stream-end`. The stream holds a
+/// request-limiter slot until it ends. Expired tokens, protocol adaptation and
+/// `queue_until_ready` are handled as in `proxy_request`.
+#[tauri::command]
+pub async fn proxy_stream(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    method: String,
+    path: String,
+    body: Option<serde_json::Value>,
+    queue_until_ready: Option<bool>,
+    queue_timeout_ms: Option<u64>,
+) -> Result<u64, String> {
+    let method = parse_method(&method)?;
+    let registration = state
+        .streams
+        .register(RequestKind::Stream, &method, &path)?;
+
+    let request_id = api::trace_id(&state).await;
+
+    let start = async {
+        if queue_until_ready.unwrap_or(false) {
+            await_ready(&state, queue_timeout_ms)
+                .await
+                .inspect_err(|_| state.metrics.record_request(false))?;
+        }
+        // Only once connected is the protocol known to adapt to
+        let (path, body) = protocol::adapt(&app, &state, path, body).await;
+        check_allowed(&state, &method, &path).await?;
+        let size = request_size::check(&state, body.as_ref()).await?;
+        let permit = state.request_limiter.acquire().await?;
+        let id = request_id.as_deref();
+        let resp = auth::request(&app, &state, method, &path, body.as_ref(), id)
+            .await
+            .inspect_err(|_| state.metrics.record_request(false))?;
+        Ok::<_, String>((permit, resp, size))
+    };
+    let (permit, resp, size) = tokio::select! {
+        started = start => started?,
+        () = registration.cancel.notified() => return Err("request cancelled".to_string()),
+    };
+    let status = resp.status();
+    state.metrics.record_request(status.is_success());
+    if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE {
+        return Err(request_size::rejected(&state, resp, size).await);
+    }
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("gateway returned {status}: {body}"));
+    }
+
+    let throttle = state.download_throttle.clone();
+    let recorder = state.recorder.clone();
+    Ok(proxy::spawn(
+        app,
+        registration,
+        permit,
+        throttle,
+        recorder,
+        resp,
+    ))
+}
+
+/// Start a long-running gateway operation with a POST to `path`, returning
+/// the job ID the gateway answers with
+///
+/// The job is polled until it finishes, across reconnects and relaunches,
+/// with each change emitted as a `job-update` event. The allowlist, request
+/// size limit and protocol adaptation apply as in `proxy_request`.
+#[tauri::command]
+pub async fn start_async_operation(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    body: Option<serde_json::Value>,
+) -> Result<String, String> {
+    let (path, body) = protocol::adapt(&app, &state, path, body).await;
+    check_allowed(&state, &reqwest::Method::POST, &path).await?;
+    request_size::check(&state, body.as_ref()).await?;
+    jobs::start(&app, &state, &path, body.as_ref()).await
+}
+
+/// Status of a job started with `start_async_operation`, with its result
+/// once done
+///
+/// The job is looked up on the gateway at `url` (a job's `url`), else the
+/// connected one, else any gateway. Finished jobs are kept for an hour.
+#[tauri::command]
+pub async fn get_async_operation(
+    state: State<'_, Arc<AppState>>,
+    job_id: String,
+    url: Option<String>,
+) -> Result<JobStatus, String> {
+    let job = match url {
+        Some(url) => state.jobs.get(Some(&url), &job_id),
+        None => {
+            let connected = api::base_url(&state).await.ok();
+            connected
+                .and_then(|url| state.jobs.get(Some(&url), &job_id))
+                .or_else(|| state.jobs.get(None, &job_id))
+        }
+    };
+    job.ok_or_else(|| format!("unknown job `{job_id}`"))
+}
+
+/// List in-flight proxied requests and streams (method, path, start time,
+/// bytes received so far), oldest first
+#[tauri::command]
+pub async fn list_active_requests(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<ActiveRequest>, String> {
+    Ok(state.streams.list())
+}
+
+/// Cancel one in-flight request or stream by ID, returning whether it was
+/// still running
+///
+/// A cancelled request fails with "request cancelled"; a cancelled stream
+/// ends with a `cancelled` outcome.
+#[tauri::command]
+pub async fn cancel_active_request(
+    state: State<'_, Arc<AppState>>,
+    id: u64,
+) -> Result<bool, String> {
+    Ok(state.streams.cancel(id))
+}
+
+/// Set the connection pool limits of gateway clients
+///
+/// The live client is rebuilt with them right away, which closes its
+/// current connections.
+#[tauri::command]
+pub async fn set_connection_pool(
+    state: State<'_, Arc<AppState>>,
+    max_idle_per_host: usize,
+    idle_timeout_secs: u64,
+) -> Result<PoolStats, String> {
+    let config = PoolConfig {
+        max_idle_per_host,
+        idle_timeout_secs,
+    };
+    config.validate()?;
+
+    {
+        let mut settings = state.settings.write().await;
+        settings.pool_max_idle_per_host = max_idle_per_host;
+        settings.pool_idle_timeout_secs = idle_timeout_secs;
+        settings.save(&state.data_dir)?;
+    }
+    apply_pool_config(&state, config).await?;
+
+    Ok(state.pool_tracker.stats())
+}
+
+/// Get the connection pool limits and estimated connection reuse
+#[tauri::command]
+pub async fn get_connection_pool_stats(
+    state: State<'_, Arc<AppState>>,
+) -> Result<PoolStats, String> {
+    Ok(state.pool_tracker.stats())
+}
+
+/// Turn request tracing on or off
+///
+/// While on, every proxied request is sent with a fresh `X-Request-Id` (and
+/// the gateway's device ID, when known) and logged, for correlating with the
+/// gateway's logs. `proxy_request` returns the ID it used.
+#[tauri::command]
+pub async fn set_request_tracing(
+    state: State<'_, Arc<AppState>>,
+    enabled: bool,
+) -> Result<(), String> {
+    let mut settings = state.settings.write().await;
+    settings.request_tracing = enabled;
+    settings.save(&state.data_dir)?;
+    tracing::info!(enabled, "request tracing changed");
+    Ok(())
+}
+
+/// Restrict proxied requests to `allowlist` (method and path-prefix pairs,
+/// `*` matching any method), or lift the restriction with none
+///
+/// Calls outside it are refused with a `forbidden` error before anything is
+/// sent, and logged.
+#[tauri::command]
+pub async fn set_proxy_allowlist(
+    state: State<'_, Arc<AppState>>,
+    allowlist: Option<Vec<AllowedEndpoint>>,
+) -> Result<(), String> {
+    if let Some(allowlist) = &allowlist {
+        allowlist::validate(allowlist)?;
+    }
+
+    let mut settings = state.settings.write().await;
+    settings.proxy_allowlist = allowlist;
+    settings.save(&state.data_dir)?;
+    tracing::info!(
+        endpoints = settings.proxy_allowlist.as_ref().map(Vec::len),
+        "proxy allowlist changed"
+    );
+    Ok(())
+}
+
+/// Request size limit of the connected gateway, from its capabilities or
+/// a request it turned down as too large
+///
+/// Larger bodies are refused by `proxy_request` and `proxy_stream` before
+/// they're sent.
+#[tauri::command]
+pub async fn get_max_request_size(state: State<'_, Arc<AppState>>) -> Result<RequestLimit, String> {
+    request_size::limit(&state).await
+}
+
+/// Protocol version in use with the connected gateway, negotiated from
+/// what it reports and what the app speaks
+///
+/// Below the app's version, proxied requests are adapted to the gateway
+/// and the features it lacks are listed.
+#[tauri::command]
+pub async fn get_negotiated_protocol(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+) -> Result<NegotiatedProtocol, String> {
+    protocol::negotiate(&app, &state).await
+}
+
+/// Refuse a proxied call the allowlist doesn't cover
+async fn check_allowed(
+    state: &AppState,
+    method: &reqwest::Method,
+    path: &str,
+) -> Result<(), String> {
+    let settings = state.settings.read().await;
+    allowlist::check(settings.proxy_allowlist.as_deref(), method, path)
+}
+
+/// Switch to new pool limits, rebuilding the live client if they changed
+///
+/// If the client can't be rebuilt, the previous limits are kept.
+async fn apply_pool_config(state: &AppState, config: PoolConfig) -> Result<(), String> {
+    let previous = pool::config();
+    if !pool::set_config(config) {
+        return Ok(());
+    }
+    if let Err(e) = gateway::rebuild_client(state).await {
+        pool::set_config(previous);
+        return Err(e);
+    }
+    tracing::info!(?config, "connection pool limits changed");
     Ok(())
 }
+
+/// Start recording proxied stream events to a newline-delimited JSON file
+///
+/// Only `proxy_stream` events are recorded; events the webview gets from
+/// the gateway's own WebSocket or SSE connections bypass the app and are
+/// out of scope. Writes to `path` under the data directory's `exports`, or a new file
+/// there if unset, and returns where; paths leading out of `exports` are
+/// refused. Credentials are redacted and the file is capped in size.
+/// Replaces any recording already running.
+#[tauri::command]
+pub async fn start_event_recording(
+    state: State<'_, Arc<AppState>>,
+    path: Option<PathBuf>,
+) -> Result<PathBuf, String> {
+    let path = path.unwrap_or_else(|| PathBuf::from(format!("events-{}.ndjson", logs::now_ms())));
+    let path = recording::confine(&state.data_dir.join("exports"), &path)?;
+    state.recorder.start(&path)?;
+    Ok(path)
+}
+
+/// Stop the event recording, returning what was recorded (`None` if no
+/// recording was running)
+#[tauri::command]
+pub async fn stop_event_recording(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Option<RecordingSummary>, String> {
+    Ok(state.recorder.stop())
+}
+
+/// Re-emit the events of a recording, for testing the UI against them
+///
+/// `path` is taken from the data directory's `exports`, as in
+/// `start_event_recording`. Events keep their recorded timing unless
+/// `realtime` is false, in which case they are sent as fast as possible.
+#[tauri::command]
+pub async fn replay_event_recording(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    path: PathBuf,
+    realtime: Option<bool>,
+) -> Result<ReplaySummary, String> {
+    let path = recording::confine(&state.data_dir.join("exports"), &path)?;
+    recording::replay(&app, &path, realtime.unwrap_or(true)).await
+}
+
+/// Bandwidth limits of gateway traffic
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BandwidthLimits {
+    /// Upload cap (bytes/sec, unlimited if unset)
+    pub upload_bps: Option<u64>,
+
+    /// Download cap (bytes/sec, unlimited if unset)
+    pub download_bps: Option<u64>,
+}
+
+/// Cap how fast the app sends to and reads from the gateway
+///
+/// Applies to proxied requests and streams right away, including ones
+/// already running. Unset limits are unlimited.
+#[tauri::command]
+pub async fn set_bandwidth_limits(
+    state: State<'_, Arc<AppState>>,
+    upload_bps: Option<u64>,
+    download_bps: Option<u64>,
+) -> Result<BandwidthLimits, String> {
+    throttle::check_limit(upload_bps)?;
+    throttle::check_limit(download_bps)?;
+
+    {
+        let mut settings = state.settings.write().await;
+        settings.upload_limit_bps = upload_bps;
+        settings.download_limit_bps = download_bps;
+        settings.save(&state.data_dir)?;
+    }
+    state.upload_throttle.set_limit(upload_bps);
+    state.download_throttle.set_limit(download_bps);
+
+    tracing::info!(?upload_bps, ?download_bps, "bandwidth limits changed");
+    get_bandwidth_limits(state).await
+}
+
+/// Get the current bandwidth limits
+#[tauri::command]
+pub async fn get_bandwidth_limits(
+    state: State<'_, Arc<AppState>>,
+) -> Result<BandwidthLimits, String> {
+    Ok(BandwidthLimits {
+        upload_bps: state.upload_throttle.limit(),
+        download_bps: state.download_throttle.limit(),
+    })
+}
+
+/// Get response cache usage (entries, hits, misses, size)
+#[tauri::command]
+pub async fn get_cache_stats(state: State<'_, Arc<AppState>>) -> Result<CacheStats, String> {
+    Ok(cache::stats(&state).await)
+}
+
+/// Empty the response caches, returning how many entries were dropped
+///
+/// They are also cleared on every connect and model or persona change.
+#[tauri::command]
+pub async fn clear_response_cache(state: State<'_, Arc<AppState>>) -> Result<usize, String> {
+    Ok(cache::clear(&state).await)
+}
+
+/// Get the gateway's last reported load and the request limit it implies
+///
+/// `None` until the first check after launch. Updates are also emitted as
+/// `gateway-load` events.
+#[tauri::command]
+pub async fn get_gateway_load(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Option<GatewayLoad>, String> {
+    Ok(state.gateway_load.read().await.clone())
+}
+
+/// Get queueing metrics for proxied requests
+#[tauri::command]
+pub async fn get_request_queue_stats(
+    state: State<'_, Arc<AppState>>,
+) -> Result<QueueStats, String> {
+    Ok(state.request_limiter.stats())
+}
+
+// === Discovery ===
+
+/// Scan the local network for gateways
+///
+/// Always performs a fresh browse. A scan already in progress is cancelled
+/// (and returns its partial results) when a new one starts.
+///
+/// Returns the discovery cache after merging in the scan, so gateways seen
+/// within the discovery TTL stay listed; `discovery-updated` is emitted when
+/// the cached set changes.
+#[tauri::command]
+pub async fn rescan_gateways(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    timeout_ms: u64,
+) -> Result<Vec<DiscoveredGateway>, String> {
+    if !state.features.is_enabled(Feature::Mdns) {
+        return Err("gateway discovery is disabled (feature `mdns`)".to_string());
+    }
+
+    let cancel = Arc::new(Notify::new());
+    if let Some(previous) = state.rescan_cancel.write().await.replace(cancel.clone()) {
+        previous.notify_one();
+    }
+
+    let result = discovery::scan(&app, Duration::from_millis(timeout_ms), &cancel).await;
+
+    let mut current = state.rescan_cancel.write().await;
+    if current.as_ref().is_some_and(|c| Arc::ptr_eq(c, &cancel)) {
+        *current = None;
+    }
+    drop(current);
+
+    let ttl = Duration::from_secs(state.settings.read().await.discovery_ttl_secs);
+    let (gateways, changed) = state.discovery_cache.update(result?, ttl);
+    if changed {
+        let _ = app.emit("discovery-updated", &gateways);
+    }
+
+    Ok(gateways)
+}
+
+/// Forget every gateway found by previous scans
+#[tauri::command]
+pub async fn clear_discovery_cache(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+) -> Result<(), String> {
+    if state.discovery_cache.clear() {
+        let _ = app.emit("discovery-updated", Vec::<DiscoveredGateway>::new());
+    }
+    Ok(())
+}
+
+/// Re-read a gateway's version, persona and voice support, e.g. after it was
+/// restarted with a new config
+///
+/// `target` is a gateway fingerprint (device ID) or URL. The discovery cache
+/// is updated and `discovery-updated` emitted. A gateway that can't be
+/// reached keeps its cached metadata, returned with `stale` set.
+#[tauri::command]
+pub async fn refresh_gateway_metadata(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    target: String,
+) -> Result<MetadataRefresh, String> {
+    let is_url = target.contains("://");
+    let profile = if is_url {
+        None
+    } else {
+        profiles::find_by_fingerprint(&state.data_dir, &target)
+    };
+    let (device_id, url) = match &profile {
+        _ if is_url => (None, Some(gateway::normalize_gateway_url(&target)?)),
+        Some(profile) => (Some(target.as_str()), Some(profile.url.clone())),
+        None => (Some(target.as_str()), None),
+    };
+
+    let client = match &profile {
+        Some(profile) => {
+            let token = storage::get(&profiles::token_key(&profile.name))?;
+            profiles::client(profile, token.as_deref())?
+        }
+        None => gateway::default_client(),
+    };
+    let browse = state.features.is_enabled(Feature::Mdns);
+    let refresh = discovery::refresh_metadata(
+        &state.discovery_cache,
+        device_id,
+        url.as_deref(),
+        &client,
+        browse,
+    )
+    .await
+    .inspect_err(|e| state.recent_errors.record(ErrorKind::Probe, e))?;
+
+    if !refresh.stale {
+        let _ = app.emit("discovery-updated", state.discovery_cache.list());
+    }
+    Ok(refresh)
+}
+
+// === Models ===
+
+/// List the models the connected gateway offers
+#[tauri::command]
+pub async fn get_available_models(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<ModelInfo>, String> {
+    models::available(&state).await
+}
+
+/// Get the model the connected gateway is running
+#[tauri::command]
+pub async fn get_active_model(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Option<ModelInfo>, String> {
+    models::active(&state).await
+}
+
+/// Switch the connected gateway's model (distinct from persona switching)
+///
+/// Emits `model-changed` with the new model on success.
+#[tauri::command]
+pub async fn set_active_model(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    model_id: String,
+) -> Result<ModelInfo, String> {
+    let model = models::set_active(&state, &model_id).await?;
+    let _ = app.emit("model-changed", &model);
+    Ok(model)
+}
+
+// === Personas ===
+
+/// Get the active persona's display name, accent color, description and
+/// greeting, with defaults for what the gateway doesn't provide
+///
+/// Emits `persona-profile-changed` when the persona changed since last seen.
+#[tauri::command]
+pub async fn get_persona_profile(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+) -> Result<PersonaProfile, String> {
+    personas::current_profile(&app, &state).await
+}
+
+// === Profiles ===
+
+/// List saved gateway profiles
+#[tauri::command]
+pub async fn list_profiles(state: State<'_, Arc<AppState>>) -> Result<Vec<GatewayProfile>, String> {
+    Ok(profiles::load(&state.data_dir))
+}
+
+/// Create or update a gateway profile
+///
+/// The token (if provided) is kept in secure storage, never in the profile file.
+#[tauri::command]
+pub async fn save_profile(
+    state: State<'_, Arc<AppState>>,
+    mut profile: GatewayProfile,
+    token: Option<String>,
+) -> Result<(), String> {
+    profile.url = gateway::normalize_gateway_url(&profile.url)?;
+    if let Some(addr) = profile.local_address {
+        gateway::check_local_address(addr)?;
+    }
+    if let Some(user_agent) = &profile.user_agent {
+        gateway::check_user_agent(user_agent)?;
+    }
+    if let Some(url) = &profile.token_refresh_url {
+        reqwest::Url::parse(url).map_err(|e| format!("invalid token refresh URL `{url}`: {e}"))?;
+    }
+    if let Some(pin) = &profile.pinned_cert_sha256 {
+        profile.pinned_cert_sha256 = Some(pinning::normalize_pin(pin)?);
+    }
+
+    if let Some(token) = token {
+        storage::set(&profiles::token_key(&profile.name), token)?;
+    }
+
+    let mut all = profiles::load(&state.data_dir);
+    all.retain(|p| p.name != profile.name);
+    all.push(profile);
+    profiles::save(&state.data_dir, &all)
+}
+
+/// Result of applying a gateway config blob
+#[derive(Debug, Serialize)]
+pub struct AppliedGatewayConfig {
+    /// Profile created (or replaced) from the blob
+    pub profile: GatewayProfile,
+
+    /// Connection status, only set when `connect` was requested
+    pub status: Option<GatewayStatus>,
+}
+
+/// Import a gateway from a shareable config blob (e.g. pasted or scanned)
+///
+/// Creates or replaces the profile named in the blob and stores its token and
+/// headers in secure storage. Nothing is connected unless `connect` is set,
+/// so the UI can ask the user to confirm first.
+#[tauri::command]
+pub async fn apply_gateway_config_blob(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    blob: String,
+    connect: Option<bool>,
+) -> Result<AppliedGatewayConfig, String> {
+    let config = config_blob::decode(&blob)?;
+
+    let name = config.name.as_deref().unwrap_or_default();
+    let profile = GatewayProfile {
+        ca_bundle: config.ca_bundle.clone(),
+        ..GatewayProfile::new(name.to_string(), config.url.clone())
+    }
+    .merged_with(profiles::find(&state.data_dir, name));
+
+    let token_key = profiles::token_key(&profile.name);
+    match config.token.clone() {
+        Some(token) => {
+            if let Some(mut previous) = storage::set(&token_key, token)? {
+                previous.zeroize();
+            }
+        }
+        None => storage::remove(&token_key)?,
+    }
+
+    let headers_key = profiles::headers_key(&profile.name);
+    if config.headers.is_empty() {
+        storage::remove(&headers_key)?;
+    } else {
+        let headers = serde_json::to_string(&config.headers)
+            .map_err(|e| format!("failed to serialize headers: {e}"))?;
+        if let Some(mut previous) = storage::set(&headers_key, headers)? {
+            previous.zeroize();
+        }
+    }
+
+    let mut all = profiles::load(&state.data_dir);
+    all.retain(|p| p.name != profile.name);
+    all.push(profile.clone());
+    profiles::save(&state.data_dir, &all)?;
+
+    tracing::info!(profile = %profile.name, url = %profile.url, "imported gateway config");
+
+    let status = if connect.unwrap_or(false) {
+        Some(connect_profile(app, state, profile.name.clone()).await?)
+    } else {
+        None
+    };
+
+    Ok(AppliedGatewayConfig { profile, status })
+}
+
+/// Pair with a gateway using the short code it displays, then connect
+///
+/// `host` is a gateway URL (or bare host) or the device ID of a discovered
+/// gateway. The code is exchanged for a token, which is stored in secure
+/// storage, and a profile is saved under `name` (the gateway's name if
+/// unset). Only a `name` passed explicitly replaces an existing profile;
+/// a gateway's own name that's already taken is refused. Wrong codes are
+/// limited per gateway to stop brute-forcing.
+#[tauri::command]
+pub async fn pair_with_code(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    host: String,
+    code: String,
+    name: Option<String>,
+) -> Result<GatewayStatus, String> {
+    let discovered = state.discovery_cache.get(&host);
+    let url = match &discovered {
+        Some(gateway) => gateway.url(),
+        None => gateway::normalize_gateway_url(&host)?,
+    };
+    let explicit = name.as_deref().map(str::trim).map(str::to_string);
+    if let Some(name) = &explicit {
+        profiles::validate_name(name)?;
+    }
+
+    let paired = pairing::pair(&state.pairing_attempts, &url, &code)
+        .await
+        .inspect_err(|e| state.recent_errors.record(ErrorKind::Auth, e))?;
+
+    let name = match explicit.clone() {
+        Some(name) => name,
+        None => {
+            let name = paired
+                .name
+                .or_else(|| discovered.as_ref().map(|g| g.name.clone()))
+                .unwrap_or_else(|| host.clone());
+            let name = name.trim().to_string();
+            profiles::validate_name(&name)
+                .map_err(|e| format!("gateway's name can't be used ({e}), pass a name"))?;
+            name
+        }
+    };
+    let existing = profiles::find(&state.data_dir, &name);
+    if existing.is_some() && explicit.is_none() {
+        return Err(format!(
+            "a profile named `{name}` already exists, pass a name to replace it or use another"
+        ));
+    }
+    let profile = GatewayProfile {
+        fingerprint: paired.device_id.or_else(|| discovered.map(|g| g.device_id)),
+        ..GatewayProfile::new(name.clone(), url.clone())
+    }
+    .merged_with(existing);
+
+    if let Some(mut previous) = storage::set(&profiles::token_key(&name), paired.token)? {
+        previous.zeroize();
+    }
+    let mut all = profiles::load(&state.data_dir);
+    all.retain(|p| p.name != name);
+    all.push(profile);
+    profiles::save(&state.data_dir, &all)?;
+
+    tracing::info!(profile = %name, url = %url, "paired with gateway");
+    connect_profile(app, state, name).await
+}
+
+/// Delete a gateway profile and its stored token
+#[tauri::command]
+pub async fn delete_profile(state: State<'_, Arc<AppState>>, name: String) -> Result<(), String> {
+    let mut all = profiles::load(&state.data_dir);
+    all.retain(|p| p.name != name);
+    profiles::save(&state.data_dir, &all)?;
+
+    storage::remove(&profiles::headers_key(&name))?;
+    storage::remove(&profiles::token_key(&name))
+}
+
+/// Payload of `profile-defaults-applied` events
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileDefaultsEvent {
+    pub profile: String,
+
+    /// Persona activated from the profile's default
+    pub persona: Option<String>,
+
+    /// Model activated from the profile's default
+    pub model: Option<ModelInfo>,
+
+    /// Defaults that couldn't be applied, and why
+    pub warnings: Vec<String>,
+}
+
+/// Activate a profile's default persona and model on the connected gateway
+///
+/// A default the gateway doesn't offer (or fails to switch to) is reported
+/// as a warning; the connection itself stays up either way.
+async fn apply_profile_defaults(
+    state: &AppState,
+    profile: &GatewayProfile,
+) -> ProfileDefaultsEvent {
+    let mut event = ProfileDefaultsEvent {
+        profile: profile.name.clone(),
+        persona: None,
+        model: None,
+        warnings: Vec::new(),
+    };
+
+    if let Some(persona) = &profile.default_persona {
+        match personas::activate(state, persona).await {
+            Ok(()) => event.persona = Some(persona.clone()),
+            Err(e) => event
+                .warnings
+                .push(format!("default persona not applied: {e}")),
+        }
+    }
+
+    if let Some(model) = &profile.default_model {
+        match models::set_active(state, model).await {
+            Ok(model) => event.model = Some(model),
+            Err(e) => event
+                .warnings
+                .push(format!("default model not applied: {e}")),
+        }
+    }
+
+    for warning in &event.warnings {
+        tracing::warn!(profile = %profile.name, "{warning}");
+    }
+
+    event
+}
+
+/// Connect to the gateway described by a saved profile
+///
+/// Once connected, the profile's default persona and model are applied and
+/// reported in a `profile-defaults-applied` event.
+#[tauri::command]
+pub async fn connect_profile(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    name: String,
+) -> Result<GatewayStatus, String> {
+    let reached = reach_profile(&state, &name).await?;
+    use_profile(app, state, name, reached).await
+}
+
+/// A profile's gateway, found answering
+struct ReachedProfile {
+    profile: GatewayProfile,
+    client: reqwest::Client,
+
+    /// Where it answered, after redirects or relocation
+    url: String,
+}
+
+/// Find the gateway of profile `name` answering, without touching the
+/// current connection
+async fn reach_profile(state: &AppState, name: &str) -> Result<ReachedProfile, String> {
+    let profile = profiles::find(&state.data_dir, name)
+        .ok_or_else(|| format!("profile not found: {name}"))?;
+
+    let token = storage::get(&profiles::token_key(name))?;
+    let client = profiles::client(&profile, token.as_deref())?;
+
+    tracing::info!(profile = %name, url = %profile.url, "connecting to profile");
+
+    let probed = gateway::probe_canonical(&client, &profile.url, gateway::PROBE_TIMEOUT).await;
+    let url = match probed {
+        Ok(url) => url,
+        // The gateway may have moved (e.g. new DHCP lease), look it up by fingerprint
+        Err(e) => match relocate_profile(state, &profile, &client).await {
+            Some(url) => url,
+            None => {
+                let e = gateway::resolve_gateway_host(&profile.url)
+                    .await
+                    .err()
+                    .unwrap_or(e);
+                state.recent_errors.record(ErrorKind::Probe, &e);
+                return Err(e);
+            }
+        },
+    };
+    Ok(ReachedProfile {
+        profile,
+        client,
+        url,
+    })
+}
+
+/// Connect to a profile's gateway found by [`reach_profile`]
+async fn use_profile(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    name: String,
+    reached: ReachedProfile,
+) -> Result<GatewayStatus, String> {
+    let ReachedProfile {
+        profile,
+        client,
+        url,
+    } = reached;
+
+    // Remember where the gateway redirected (or moved) to, so next time we go
+    // straight there
+    if url != profile.url {
+        let mut all = profiles::load(&state.data_dir);
+        for p in all.iter_mut().filter(|p| p.name == name) {
+            p.url = url.clone();
+        }
+        profiles::save(&state.data_dir, &all)?;
+    }
+
+    *state.client.write().await = client;
+    *state.active_profile.write().await = Some(name);
+    *state.gateway_url.write().await = Some(url.clone());
+    state
+        .set_gateway_state(GatewayState::Connected {
+            url: url.clone(),
+            is_sidecar: false,
+        })
+        .await;
+    gateway::remember_gateway(&state, &url).await;
+
+    if profile.fingerprint.is_none() {
+        capture_fingerprint(&state, &name).await;
+    }
+
+    if profile.default_persona.is_some() || profile.default_model.is_some() {
+        let applied = apply_profile_defaults(&state, &profile).await;
+        if let Some(model) = &applied.model {
+            let _ = app.emit("model-changed", model);
+        }
+        if applied.persona.is_some() {
+            if let Err(e) = personas::current_profile(&app, &state).await {
+                tracing::debug!(error = %e, "failed to fetch persona profile");
+            }
+        }
+        let _ = app.emit("profile-defaults-applied", applied);
+    }
+
+    get_gateway_status(state).await
+}
+
+/// Find a profile's gateway at a new address by its fingerprint
+async fn relocate_profile(
+    state: &AppState,
+    profile: &GatewayProfile,
+    client: &reqwest::Client,
+) -> Option<String> {
+    let fingerprint = profile.fingerprint.as_deref()?;
+    let browse = state.features.is_enabled(Feature::Mdns);
+
+    let located =
+        discovery::locate(&state.discovery_cache, fingerprint, None, client, browse).await;
+    let url = located
+        .inspect_err(
+            |e| tracing::info!(profile = %profile.name, error = %e, "gateway not relocated"),
+        )
+        .ok()?;
+    gateway::probe_canonical(client, &url, gateway::PROBE_TIMEOUT)
+        .await
+        .ok()
+}
+
+/// Store the connected gateway's device ID as the profile's fingerprint
+///
+/// Reuses the device ID `remember_gateway` just fetched; gateways that don't
+/// report one leave the profile URL-only.
+async fn capture_fingerprint(state: &AppState, name: &str) {
+    let device_id = state
+        .settings
+        .read()
+        .await
+        .saved_gateway
+        .as_ref()
+        .and_then(|saved| saved.device_id.clone());
+    let Some(device_id) = device_id else {
+        return;
+    };
+
+    let mut all = profiles::load(&state.data_dir);
+    for p in all.iter_mut().filter(|p| p.name == name) {
+        p.fingerprint = Some(device_id.clone());
+    }
+    match profiles::save(&state.data_dir, &all) {
+        Ok(()) => {
+            tracing::info!(profile = %name, fingerprint = %device_id, "captured gateway fingerprint")
+        }
+        Err(e) => tracing::warn!(profile = %name, error = %e, "failed to save gateway fingerprint"),
+    }
+}
+
+/// Connect to the gateway with this fingerprint (device ID), wherever it is now
+///
+/// A profile saved for that gateway is connected as with `connect_profile`.
+/// Otherwise the gateway's current address is taken from discovery (looking
+/// it up again over mDNS if the cached one is dead) and connected to by URL.
+/// If gateways on several hosts claim the fingerprint, `host` must say which
+/// one to connect to (a profile is only used if it's for that host).
+#[tauri::command]
+pub async fn connect_by_fingerprint(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    fingerprint: String,
+    host: Option<String>,
+) -> Result<GatewayStatus, String> {
+    let on_host = |profile: &GatewayProfile| {
+        let url = reqwest::Url::parse(&profile.url).ok();
+        host.is_none() || url.as_ref().and_then(|u| u.host_str()) == host.as_deref()
+    };
+    let profile = profiles::find_by_fingerprint(&state.data_dir, &fingerprint).filter(on_host);
+    if let Some(profile) = profile {
+        return connect_profile(app, state, profile.name).await;
+    }
+
+    let browse = state.features.is_enabled(Feature::Mdns);
+    let client = gateway::default_client();
+    let host = host.as_deref();
+    let url = discovery::locate(&state.discovery_cache, &fingerprint, host, &client, browse)
+        .await
+        .inspect_err(|e| state.recent_errors.record(ErrorKind::Probe, e))?;
+
+    start_gateway(state, Some(StartGatewayRequest { url: Some(url) })).await
+}
+
+/// Result of rotating a profile's gateway token
+#[derive(Debug, Serialize)]
+pub struct TokenRotationResult {
+    /// Whether the gateway accepted the new token
+    pub valid: bool,
+
+    /// Whether the token was applied to the live connection
+    pub applied: bool,
+}
+
+/// Replace a profile's stored gateway token
+///
+/// The new token is checked against an authenticated endpoint of the
+/// profile's gateway before being kept.
+/// On failure the previous token is restored; on success the old one is wiped
+/// and, if the profile is connected, the live client switches over immediately.
+#[tauri::command]
+pub async fn rotate_gateway_token(
+    state: State<'_, Arc<AppState>>,
+    profile_name: String,
+    new_token: String,
+) -> Result<TokenRotationResult, String> {
+    let profile = profiles::find(&state.data_dir, &profile_name)
+        .ok_or_else(|| format!("profile not found: {profile_name}"))?;
+
+    let key = profiles::token_key(&profile_name);
+    let client = profiles::client(&profile, Some(&new_token))?;
+    let previous = storage::set(&key, new_token)?;
+
+    // `/health` needs no auth, so it can't tell a bad token from a good one
+    if let Err(e) = validation::check_auth(&client, &profile.url, true).await {
+        tracing::warn!(
+            profile = %profile_name,
+            error = %e,
+            "new gateway token rejected, reverting"
+        );
+        state.recent_errors.record(
+            ErrorKind::Auth,
+            format!("gateway rejected the new token for profile {profile_name}: {e}"),
+        );
+
+        match previous {
+            Some(previous) => {
+                if let Some(mut rejected) = storage::set(&key, previous)? {
+                    rejected.zeroize();
+                }
+            }
+            None => storage::remove(&key)?,
+        }
+
+        return Ok(TokenRotationResult {
+            valid: false,
+            applied: false,
+        });
+    }
+
+    if let Some(mut previous) = previous {
+        previous.zeroize();
+    }
+
+    let is_active = state.active_profile.read().await.as_deref() == Some(profile_name.as_str());
+    let applied = is_active && state.is_connected().await;
+    if applied {
+        *state.client.write().await = client;
+    }
+
+    tracing::info!(profile = %profile_name, applied, "gateway token rotated");
+
+    Ok(TokenRotationResult {
+        valid: true,
+        applied,
+    })
+}
+
+/// Set or clear a profile's User-Agent override
+///
+/// Returns the User-Agent the profile now sends. If the profile is connected,
+/// the live client switches over immediately.
+#[tauri::command]
+pub async fn set_profile_user_agent(
+    state: State<'_, Arc<AppState>>,
+    profile_name: String,
+    user_agent: Option<String>,
+) -> Result<String, String> {
+    if let Some(user_agent) = &user_agent {
+        gateway::check_user_agent(user_agent)?;
+    }
+
+    let mut all = profiles::load(&state.data_dir);
+    let profile = all
+        .iter_mut()
+        .find(|p| p.name == profile_name)
+        .ok_or_else(|| format!("profile not found: {profile_name}"))?;
+    profile.user_agent = user_agent;
+    let profile = profile.clone();
+    profiles::save(&state.data_dir, &all)?;
+
+    let is_active = state.active_profile.read().await.as_deref() == Some(profile_name.as_str());
+    if is_active {
+        let token = storage::get(&profiles::token_key(&profile_name))?;
+        *state.client.write().await = profiles::client(&profile, token.as_deref())?;
+    }
+
+    let effective = profile
+        .user_agent
+        .unwrap_or_else(gateway::default_user_agent);
+    tracing::info!(profile = %profile_name, user_agent = %effective, "profile User-Agent set");
+
+    Ok(effective)
+}
+
+/// Set the oldest TLS version a profile's gateway connection may use
+///
+/// If the profile is connected, the live client switches over immediately,
+/// so a gateway that can't meet the minimum fails on its next request.
+#[tauri::command]
+pub async fn set_profile_min_tls_version(
+    state: State<'_, Arc<AppState>>,
+    profile_name: String,
+    version: TlsVersion,
+) -> Result<(), String> {
+    let mut all = profiles::load(&state.data_dir);
+    let profile = all
+        .iter_mut()
+        .find(|p| p.name == profile_name)
+        .ok_or_else(|| format!("profile not found: {profile_name}"))?;
+    profile.min_tls_version = version;
+    let profile = profile.clone();
+    profiles::save(&state.data_dir, &all)?;
+
+    let is_active = state.active_profile.read().await.as_deref() == Some(profile_name.as_str());
+    if is_active {
+        let token = storage::get(&profiles::token_key(&profile_name))?;
+        *state.client.write().await = profiles::client(&profile, token.as_deref())?;
+    }
+
+    tracing::info!(profile = %profile_name, %version, "profile minimum TLS version set");
+    Ok(())
+}
+
+/// Pin a profile's gateway certificate by its SHA-256 fingerprint, or clear
+/// the pin
+///
+/// A pinned profile trusts only that certificate, self-signed or not, and
+/// fails with "certificate pin mismatch" on any other. Capture the
+/// fingerprint with `get_gateway_cert_fingerprint`. If the profile is
+/// connected, the live client switches over immediately.
+#[tauri::command]
+pub async fn set_profile_cert_pin(
+    state: State<'_, Arc<AppState>>,
+    profile_name: String,
+    sha256: Option<String>,
+) -> Result<(), String> {
+    let pin = sha256.as_deref().map(pinning::normalize_pin).transpose()?;
+
+    let mut all = profiles::load(&state.data_dir);
+    let profile = all
+        .iter_mut()
+        .find(|p| p.name == profile_name)
+        .ok_or_else(|| format!("profile not found: {profile_name}"))?;
+    profile.pinned_cert_sha256 = pin;
+    let profile = profile.clone();
+    profiles::save(&state.data_dir, &all)?;
+
+    let is_active = state.active_profile.read().await.as_deref() == Some(profile_name.as_str());
+    if is_active {
+        let token = storage::get(&profiles::token_key(&profile_name))?;
+        *state.client.write().await = profiles::client(&profile, token.as_deref())?;
+    }
+
+    let pinned = profile.pinned_cert_sha256.is_some();
+    tracing::info!(profile = %profile_name, pinned, "profile certificate pin set");
+    Ok(())
+}
+
+/// Read the SHA-256 fingerprint of the certificate a gateway presents, to
+/// pin it with `set_profile_cert_pin`
+///
+/// The certificate isn't verified; compare the fingerprint with the one the
+/// gateway's host reports before pinning it.
+#[tauri::command]
+pub async fn get_gateway_cert_fingerprint(url: String) -> Result<CertFingerprint, String> {
+    pinning::fetch_fingerprint(&url).await
+}
+
+/// User-Agent the live gateway client sends
+async fn effective_user_agent(state: &AppState) -> String {
+    let active = state.active_profile.read().await.clone();
+    active
+        .and_then(|name| profiles::find(&state.data_dir, &name))
+        .and_then(|p| p.user_agent)
+        .unwrap_or_else(gateway::default_user_agent)
+}
+
+// === Settings ===
+
+/// Get current app settings
+#[tauri::command]
+pub async fn get_settings(state: State<'_, Arc<AppState>>) -> Result<Settings, String> {
+    Ok(state.settings.read().await.clone())
+}
+
+/// Set whether closing the window hides to the tray instead of quitting
+#[tauri::command]
+pub async fn set_close_to_tray(
+    state: State<'_, Arc<AppState>>,
+    enabled: bool,
+) -> Result<(), String> {
+    let mut settings = state.settings.write().await;
+    settings.close_to_tray = enabled;
+    settings.save(&state.data_dir)?;
+    state.close_to_tray.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Turn looking for the saved gateway over mDNS at startup on or off
+///
+/// With it off, startup connects only to the saved or configured URL, or
+/// the sidecar. `rescan_gateways` works either way.
+#[tauri::command]
+pub async fn set_discovery_enabled(
+    state: State<'_, Arc<AppState>>,
+    enabled: bool,
+) -> Result<(), String> {
+    let mut settings = state.settings.write().await;
+    settings.discovery_on_startup = enabled;
+    settings.save(&state.data_dir)?;
+    tracing::info!(enabled, "startup discovery changed");
+    Ok(())
+}
+
+/// Payload of `offline-mode-changed` events
+#[derive(Debug, Clone, Serialize)]
+pub struct OfflineModeEvent {
+    pub enabled: bool,
+}
+
+/// Turn offline mode on or off (persisted across restarts)
+///
+/// Turning it on aborts active streams, stops the sidecar and disconnects;
+/// gateway requests then fail fast until it's turned off, which immediately
+/// tries to reconnect.
+#[tauri::command]
+pub async fn set_offline_mode(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    enabled: bool,
+) -> Result<(), String> {
+    {
+        let mut settings = state.settings.write().await;
+        if settings.offline_mode == enabled {
+            return Ok(());
+        }
+        settings.offline_mode = enabled;
+        settings.save(&state.data_dir)?;
+    }
+
+    offline_mode_changed(&app, &state, enabled).await;
+    Ok(())
+}
+
+/// Act on offline mode having been turned on or off in the settings
+async fn offline_mode_changed(app: &AppHandle, state: &Arc<AppState>, enabled: bool) {
+    tracing::info!(enabled, "offline mode changed");
+    let _ = app.emit("offline-mode-changed", OfflineModeEvent { enabled });
+
+    if enabled {
+        state.streams.abort_all();
+        gateway::stop_all_sidecars(state).await;
+        *state.active_profile.write().await = None;
+        *state.client.write().await = gateway::default_client();
+    } else {
+        tauri::async_runtime::spawn(gateway::auto_connect(state.clone()));
+    }
+}
+
+/// Whether offline mode is on
+#[tauri::command]
+pub async fn is_offline_mode(state: State<'_, Arc<AppState>>) -> Result<bool, String> {
+    Ok(state.settings.read().await.offline_mode)
+}
+
+/// Replace app settings
+#[tauri::command]
+pub async fn update_settings(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    settings: Settings,
+) -> Result<Settings, String> {
+    apply_settings(&app, &state, settings).await
+}
+
+/// Salvage a corrupt settings file and apply what could be saved
+///
+/// Repairs `settings.json` if it doesn't parse, else the newest corrupt file
+/// set aside at startup (see `settings-recovered`). Every top-level field
+/// that is valid on its own is kept and the rest reset to defaults; if the
+/// result doesn't pass validation, defaults are applied instead.
+#[tauri::command]
+pub async fn repair_settings(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+) -> Result<Salvage, String> {
+    let mut salvage = settings::salvage(&state.data_dir)?;
+    let Some(source) = salvage.source.clone() else {
+        return Ok(salvage);
+    };
+
+    match apply_settings(&app, &state, salvage.settings.clone()).await {
+        Ok(settings) => salvage.settings = settings,
+        Err(e) => {
+            tracing::warn!(error = %e, "salvaged settings are invalid, using defaults");
+            salvage.dropped.append(&mut salvage.salvaged);
+            salvage.settings = apply_settings(&app, &state, Settings::default()).await?;
+        }
+    }
+    settings::mark_repaired(&source);
+    Ok(salvage)
+}
+
+/// Check settings are valid, without applying them
+fn validate_settings(settings: &Settings) -> Result<(), String> {
+    if let Some(schedule) = &settings.scheduled_restart {
+        schedule.validate()?;
+    }
+    throttle::check_limit(settings.upload_limit_bps)?;
+    throttle::check_limit(settings.download_limit_bps)?;
+    metrics_stream::check_interval(settings.metrics_stream_interval_ms)?;
+    if let Some(allowlist) = &settings.proxy_allowlist {
+        allowlist::validate(allowlist)?;
+    }
+    settings.pool().validate()?;
+    env_profiles::validate_all(&settings.env_profiles, settings.env_profile.as_deref())
+}
+
+/// Validate, apply and save settings
+///
+/// The changes that can fail (lifecycle, pool limits) are applied first and
+/// rolled back if a later step fails, so the file is only written once the
+/// app runs with the new settings. A change of offline mode takes effect as
+/// through `set_offline_mode`. The startup samples are the app's own record,
+/// so they're kept as they are.
+async fn apply_settings(
+    app: &AppHandle,
+    state: &Arc<AppState>,
+    mut settings: Settings,
+) -> Result<Settings, String> {
+    validate_settings(&settings)?;
+    let previous_lifecycle = {
+        let current = state.settings.read().await;
+        settings
+            .startup_samples_ms
+            .clone_from(&current.startup_samples_ms);
+        current.gateway_lifecycle
+    };
+    let previous_pool = pool::config();
+
+    gateway::set_lifecycle(state, settings.gateway_lifecycle).await?;
+    let applied = match apply_pool_config(state, settings.pool()).await {
+        Ok(()) => settings.save(&state.data_dir),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = applied {
+        if let Err(e) = apply_pool_config(state, previous_pool).await {
+            tracing::warn!(error = %e, "failed to restore connection pool limits");
+        }
+        if let Err(e) = gateway::set_lifecycle(state, previous_lifecycle).await {
+            tracing::warn!(error = %e, "failed to restore gateway lifecycle");
+        }
+        return Err(e);
+    }
+
+    state
+        .request_limiter
+        .set_limit(settings.max_concurrent_requests);
+    state.upload_throttle.set_limit(settings.upload_limit_bps);
+    state
+        .download_throttle
+        .set_limit(settings.download_limit_bps);
+    let previous = std::mem::replace(&mut *state.settings.write().await, settings.clone());
+    state
+        .close_to_tray
+        .store(settings.close_to_tray, Ordering::Relaxed);
+    if settings.offline_mode != previous.offline_mode {
+        offline_mode_changed(app, state, settings.offline_mode).await;
+    }
+    Ok(settings)
+}
+
+// === Permissions ===
+
+/// Get the status of every platform permission the app uses
+#[tauri::command]
+pub async fn get_permission_states(app: AppHandle) -> Result<PermissionStates, String> {
+    permissions::states(&app)
+}
+
+/// Show the platform prompt for a permission, returning its new status
+#[tauri::command]
+pub async fn request_permission(
+    app: AppHandle,
+    kind: PermissionKind,
+) -> Result<PermissionStatus, String> {
+    permissions::request(&app, kind)
+}
+
+// === Opener ===
+
+/// Open a link or file with the OS, after checking it against the allowlist
+///
+/// All "open link" actions go through here rather than the opener plugin,
+/// so URLs from assistant output can't launch arbitrary schemes or files.
+#[tauri::command]
+pub async fn open_external(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    target: String,
+) -> Result<(), String> {
+    let allowed_schemes = state.settings.read().await.allowed_open_schemes.clone();
+
+    let result = match opener::validate(&target, &allowed_schemes, &state.data_dir) {
+        Ok(OpenTarget::Url(url)) => app.opener().open_url(url, None::<&str>),
+        Ok(OpenTarget::Path(path)) => app.opener().open_path(path.to_string_lossy(), None::<&str>),
+        Err(e) => {
+            tracing::warn!(target = %target, error = %e, "blocked open request");
+            return Err(e);
+        }
+    };
+
+    result.map_err(|e| format!("failed to open `{target}`: {e}"))
+}
+
+/// Show the gateway binary the sidecar would launch in the file manager
+///
+/// Resolves the path the same way `start_sidecar` does, without launching
+/// anything, and returns it so the UI can display it too.
+#[tauri::command]
+pub async fn reveal_gateway_binary(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+) -> Result<String, String> {
+    let path = gateway::find_gateway_binary(&state.data_dir)?.path;
+
+    app.opener()
+        .reveal_item_in_dir(&path)
+        .map_err(|e| format!("failed to reveal {}: {e}", path.display()))?;
+
+    Ok(path.display().to_string())
+}
+
+// === Secure Storage ===
+
+/// Get a value from secure storage
+#[tauri::command]
+pub async fn get_secure_storage(key: String) -> Result<Option<String>, String> {
+    storage::get(&key)
+}
+
+/// Set a value in secure storage
+#[tauri::command]
+pub async fn set_secure_storage(key: String, value: String) -> Result<(), String> {
+    storage::set(&key, value)?;
+    Ok(())
+}
+
+/// Check that secure storage round-trips a value, and which backend is used
+#[tauri::command]
+pub async fn test_secure_storage() -> Result<StorageTestResult, String> {
+    Ok(storage::round_trip_test())
+}
+
+/// Move secrets kept in memory into the keychain, listing profiles whose
+/// token may need re-entering
+///
+/// Idempotent: calling it again only moves what is left in memory.
+#[tauri::command]
+pub async fn migrate_secure_storage(
+    state: State<'_, Arc<AppState>>,
+) -> Result<MigrationResult, String> {
+    storage::migrate(&state.data_dir)
+}
+
+// === Diagnostics ===
+
+/// Default number of errors returned by `get_recent_errors`
+const DEFAULT_ERROR_LIMIT: usize = 50;
+
+/// Recent errors included in a diagnostics report
+const DIAGNOSTICS_ERROR_LIMIT: usize = 20;
+
+/// Collect a diagnostics report (app info, gateway status, recent errors,
+/// self-test checklist)
+#[tauri::command]
+pub async fn get_diagnostics(state: State<'_, Arc<AppState>>) -> Result<Diagnostics, String> {
+    let recent_errors = state.recent_errors.recent(DIAGNOSTICS_ERROR_LIMIT);
+    let gateway_binary = gateway::find_gateway_binary(&state.data_dir);
+    let user_agent = effective_user_agent(&state).await;
+    let features = state.features.enabled();
+    let earlier = EarlierChecks {
+        clock_skew: state.clock_skew.read().await.clone(),
+        gateway_manifest: state.manifest_check.read().await.clone(),
+        event_channel: state.event_channel.read().await.clone(),
+        connection_decision: state.connection_decision.read().await.clone(),
+    };
+    let gateway = get_gateway_status(state).await?;
+    Ok(diagnostics::collect(
+        gateway,
+        gateway_binary,
+        recent_errors,
+        user_agent,
+        features,
+        earlier,
+    ))
+}
+
+/// Version manifest of the gateway binary bundled with the app, if any
+#[tauri::command]
+pub async fn get_bundled_gateway_manifest() -> Result<Option<Manifest>, String> {
+    manifest::bundled()
+}
+
+/// Compare this machine's clock with the connected gateway's
+///
+/// Emits `clock-skew-warning` when the skew is large enough to break token
+/// validation; the latest result is also included in diagnostics.
+#[tauri::command]
+pub async fn check_clock_skew(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+) -> Result<SkewResult, String> {
+    let result = clock::check(&state).await?;
+    *state.clock_skew.write().await = Some(result.clone());
+
+    if result.warning {
+        let _ = app.emit("clock-skew-warning", &result);
+    }
+    Ok(result)
+}
+
+/// Check that the gateway's event channel works both ways: open the
+/// WebSocket at `<url>/ws`, ping it and wait for the pong, then close it
+///
+/// Tells an upgrade turned down by a proxy apart from a refused connection,
+/// since features relying on push events won't work behind such a proxy.
+/// The latest result is also included in diagnostics. Refused with the
+/// `websocket` feature off.
+#[tauri::command]
+pub async fn test_event_channel(
+    state: State<'_, Arc<AppState>>,
+) -> Result<EventChannelResult, String> {
+    if !state.features.is_enabled(Feature::Websocket) {
+        return Err("the event channel is disabled (feature `websocket`)".to_string());
+    }
+
+    let result = event_channel::check(&state).await?;
+    *state.event_channel.write().await = Some(result.clone());
+    Ok(result)
+}
+
+/// Get the features enabled for this launch (see `BEACON_FEATURES`)
+#[tauri::command]
+pub async fn get_enabled_features(state: State<'_, Arc<AppState>>) -> Result<Vec<Feature>, String> {
+    Ok(state.features.enabled())
+}
+
+/// Write a diagnostics report as JSON to a file under the data directory,
+/// returning its path
+///
+/// The file is gzip-compressed (`.json.gz`) unless `compress` is false.
+#[tauri::command]
+pub async fn export_diagnostics(
+    state: State<'_, Arc<AppState>>,
+    compress: Option<bool>,
+) -> Result<PathBuf, String> {
+    let dir = state.data_dir.join("exports");
+    let diagnostics = get_diagnostics(state).await?;
+
+    let name = format!("diagnostics-{}.json", logs::now_ms());
+    let (path, mut file) = ExportFile::create(&dir, &name, compress.unwrap_or(true))?;
+    serde_json::to_writer_pretty(&mut file, &diagnostics)
+        .map_err(|e| format!("failed to write diagnostics: {e}"))?;
+    file.finish()?;
+
+    Ok(path)
+}
+
+/// Check whether a gateway's host resolves and accepts TCP connections on
+/// its port, separately from the HTTP health check
+#[tauri::command]
+pub async fn ping_gateway_host(url: String) -> Result<ReachabilityResult, String> {
+    reachability::check(&url).await
+}
+
+/// Time each phase of one `/health` probe (DNS, TCP connect, TLS handshake,
+/// first byte, total), to tell where a slow connection spends its time
+///
+/// A failed probe still returns the timings it got, and the phase that
+/// failed.
+#[tauri::command]
+pub async fn diagnose_connection(url: String) -> Result<ConnectionTiming, String> {
+    timing::diagnose(&url).await
+}
+
+/// Check a candidate connection end to end (URL, TCP, TLS, health, token,
+/// gateway version, event channel) before it is saved, without touching the
+/// active one
+#[tauri::command]
+pub async fn validate_connection(config: ConnectionConfig) -> Result<ValidationReport, String> {
+    Ok(validation::validate(config).await)
+}
+
+/// Export session metrics (latency samples, connection events, request
+/// counts) as a CSV file under the data directory, returning its path
+#[tauri::command]
+pub async fn export_metrics_csv(state: State<'_, Arc<AppState>>) -> Result<PathBuf, String> {
+    state.metrics.export_csv(&state.data_dir.join("exports"))
+}
+
+/// Start emitting a `gateway-metrics` event every `interval_ms` (default
+/// from settings) with latency, uptime, request counts, sidecar CPU and
+/// memory, and bandwidth
+///
+/// Replaces a stream already running. Ends by itself when the gateway
+/// disconnects.
+#[tauri::command]
+pub async fn start_metrics_stream(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    interval_ms: Option<u64>,
+) -> Result<(), String> {
+    let interval_ms = match interval_ms {
+        Some(ms) => ms,
+        None => state.settings.read().await.metrics_stream_interval_ms,
+    };
+    metrics_stream::check_interval(interval_ms)?;
+    if !state.is_connected().await {
+        return Err("not connected to a gateway".to_string());
+    }
+
+    let stop = Arc::new(Notify::new());
+    if let Some(previous) = state.metrics_stream.write().await.replace(stop.clone()) {
+        previous.notify_one();
+    }
+    tauri::async_runtime::spawn(metrics_stream::run(
+        app,
+        state.inner().clone(),
+        Duration::from_millis(interval_ms),
+        stop,
+    ));
+    Ok(())
+}
+
+/// Stop emitting `gateway-metrics` events
+#[tauri::command]
+pub async fn stop_metrics_stream(state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    if let Some(stop) = state.metrics_stream.write().await.take() {
+        stop.notify_one();
+    }
+    Ok(())
+}
+
+/// Export settings, profiles, connection history and metrics as a single
+/// snapshot file under the data directory, returning its path
+///
+/// Profile tokens and headers are only included, encrypted, when a
+/// passphrase is given.
+#[tauri::command]
+pub async fn export_state_snapshot(
+    state: State<'_, Arc<AppState>>,
+    passphrase: Option<String>,
+) -> Result<PathBuf, String> {
+    let settings = state.settings.read().await.clone();
+    let snapshot = snapshot::capture(
+        settings,
+        state.metrics.snapshot(),
+        &state.data_dir,
+        passphrase.as_deref(),
+    );
+    if let Some(mut passphrase) = passphrase {
+        passphrase.zeroize();
+    }
+
+    let snapshot = snapshot?;
+    let path = snapshot::write(&snapshot, &state.data_dir.join("exports"))?;
+    tracing::info!(
+        path = %path.display(),
+        secrets = snapshot.secrets.is_some(),
+        "exported state snapshot"
+    );
+    Ok(path)
+}
+
+/// Restore a snapshot written by `export_state_snapshot`
+///
+/// This replaces the current settings, profiles and metrics, so it is refused
+/// unless `overwrite` is set. Snapshots with secrets need the passphrase they
+/// were exported with. Restored profiles take effect on the next connect.
+#[tauri::command]
+pub async fn import_state_snapshot(
+    app: AppHandle,
+    state: State<'_, Arc<AppState>>,
+    path: PathBuf,
+    passphrase: Option<String>,
+    overwrite: Option<bool>,
+) -> Result<SnapshotImport, String> {
+    let snapshot = snapshot::read(&path)?;
+    if !overwrite.unwrap_or(false) {
+        return Err(
+            "importing a snapshot replaces current settings, profiles and metrics; \
+             set overwrite to confirm"
+                .to_string(),
+        );
+    }
+    validate_settings(&snapshot.settings)?;
+
+    let secrets = snapshot::unseal(&snapshot, passphrase.as_deref());
+    if let Some(mut passphrase) = passphrase {
+        passphrase.zeroize();
+    }
+    let secrets = secrets?;
+
+    // Nothing is restored until the settings, which can still fail, are in
+    apply_settings(&app, &state, snapshot.settings.clone()).await?;
+    let secrets_restored = snapshot::restore(&snapshot, &state.data_dir, secrets)?;
+    state.metrics.restore(snapshot.metrics);
+
+    tracing::info!(
+        path = %path.display(),
+        profiles = snapshot.profiles.len(),
+        secrets_restored,
+        "imported state snapshot"
+    );
+
+    Ok(SnapshotImport {
+        app_version: snapshot.app_version,
+        created_at_ms: snapshot.created_at_ms,
+        profiles: snapshot.profiles.len(),
+        secrets_restored,
+    })
+}
+
+/// Get recent gateway failures, newest first
+#[tauri::command]
+pub async fn get_recent_errors(
+    state: State<'_, Arc<AppState>>,
+    limit: Option<usize>,
+) -> Result<Vec<RecentError>, String> {
+    Ok(state
+        .recent_errors
+        .recent(limit.unwrap_or(DEFAULT_ERROR_LIMIT)))
+}
@@ -0,0 +1,240 @@
+//! Shareable gateway config blobs
+//!
+//! A config blob is base64-encoded JSON describing how to reach a gateway:
+//! its URL plus an optional token, CA bundle and extra headers. It is what
+//! the QR pairing flow encodes, and users can paste it on desktop too.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+use zeroize::Zeroize;
+
+use crate::{gateway, profiles};
+
+/// Largest accepted blob (encoded), generous for a URL, token and a few headers
+const MAX_BLOB_LEN: usize = 8 * 1024;
+
+/// Most extra headers a blob may carry
+const MAX_HEADERS: usize = 16;
+
+/// Headers a blob may not set, since the app manages them itself
+const RESERVED_HEADERS: &[&str] = &["authorization", "host", "content-length"];
+
+/// Decoded and validated gateway config
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GatewayConfig {
+    /// Profile name (derived from the URL's host if absent)
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Gateway URL
+    pub url: String,
+
+    /// Gateway auth token
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// Path to a PEM CA bundle for verifying the gateway's certificate
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Extra headers sent with every request
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Drop for GatewayConfig {
+    fn drop(&mut self) {
+        self.token.zeroize();
+        for value in self.headers.values_mut() {
+            value.zeroize();
+        }
+    }
+}
+
+/// Decode and validate a config blob
+///
+/// Both standard and URL-safe base64 are accepted, with or without padding.
+pub fn decode(blob: &str) -> Result<GatewayConfig, String> {
+    let blob = blob.trim();
+
+    if blob.is_empty() {
+        return Err("config blob is empty".to_string());
+    }
+    if blob.len() > MAX_BLOB_LEN {
+        return Err(format!(
+            "config blob is too large ({} bytes, max {MAX_BLOB_LEN})",
+            blob.len()
+        ));
+    }
+
+    let normalized: String = blob
+        .trim_end_matches('=')
+        .chars()
+        .map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c,
+        })
+        .collect();
+
+    let mut json = URL_SAFE_NO_PAD
+        .decode(normalized)
+        .map_err(|_| "config blob is not valid base64".to_string())?;
+
+    let parsed = serde_json::from_slice::<GatewayConfig>(&json)
+        .map_err(|e| format!("config blob is malformed: {e}"));
+    json.zeroize();
+
+    let mut config = parsed?;
+    validate(&mut config)?;
+
+    Ok(config)
+}
+
+/// Check fields and fill in defaults
+fn validate(config: &mut GatewayConfig) -> Result<(), String> {
+    config.url = gateway::normalize_gateway_url(&config.url)?;
+
+    let name = match config.name.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => reqwest::Url::parse(&config.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .ok_or_else(|| "config blob has no profile name".to_string())?,
+    };
+    profiles::validate_name(&name)?;
+    config.name = Some(name);
+
+    if config.token.as_deref().is_some_and(|t| t.trim().is_empty()) {
+        config.token = None;
+    }
+
+    if let Some(path) = &config.ca_bundle {
+        if !path.is_file() {
+            return Err(format!("CA bundle not found: {}", path.display()));
+        }
+    }
+
+    if config.headers.len() > MAX_HEADERS {
+        return Err(format!(
+            "config blob has too many headers (max {MAX_HEADERS})"
+        ));
+    }
+    if let Some(name) = config
+        .headers
+        .keys()
+        .find(|name| RESERVED_HEADERS.contains(&name.to_ascii_lowercase().as_str()))
+    {
+        return Err(format!("config blob may not set the `{name}` header"));
+    }
+
+    // Reject bad header names/values now rather than on first connect
+    gateway::build_client(
+        config.token.as_deref(),
+        &config.headers,
+        config.ca_bundle.as_deref(),
+        None,
+        None,
+        gateway::TlsVersion::default(),
+        None,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE};
+
+    /// Encodes with padding and both `+` and `/` in standard base64
+    const JSON: &str = r#"{"url":"http://gw.local:8080","token":"???>>>"}"#;
+
+    fn encode(json: &str) -> String {
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    fn error(json: &str) -> String {
+        match decode(&encode(json)) {
+            Ok(_) => panic!("blob should be rejected: {json}"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn any_base64_flavour_decodes() {
+        let standard = STANDARD.encode(JSON);
+        assert!(standard.contains('+') && standard.contains('/') && standard.ends_with('='));
+
+        for blob in [
+            standard,
+            STANDARD_NO_PAD.encode(JSON),
+            URL_SAFE.encode(JSON),
+            URL_SAFE_NO_PAD.encode(JSON),
+        ] {
+            let config = decode(&format!("  {blob}\n")).unwrap();
+            assert_eq!(config.url, "http://gw.local:8080");
+            assert_eq!(config.token.as_deref(), Some("???>>>"));
+        }
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert!(decode("   ").is_err());
+        assert!(decode("not base64!").is_err());
+        assert!(decode(&"A".repeat(MAX_BLOB_LEN + 4)).is_err());
+        assert!(error(r#"{"url":"http://gw","extra":1}"#).contains("malformed"));
+        assert!(error(r#"{"url":"ftp://gw"}"#).contains("scheme"));
+    }
+
+    #[test]
+    fn name_defaults_to_the_host() {
+        let config = decode(&encode(r#"{"url":"gw.local"}"#)).unwrap();
+        assert_eq!(config.name.as_deref(), Some("gw.local"));
+        assert_eq!(config.url, "http://gw.local");
+
+        let config = decode(&encode(r#"{"name":"  ","url":"gw.local"}"#)).unwrap();
+        assert_eq!(config.name.as_deref(), Some("gw.local"));
+
+        let config = decode(&encode(r#"{"name":" Home ","url":"gw.local"}"#)).unwrap();
+        assert_eq!(config.name.as_deref(), Some("Home"));
+    }
+
+    #[test]
+    fn bad_names_are_rejected() {
+        assert!(error(r#"{"name":"a\u0007b","url":"gw.local"}"#).contains("control"));
+    }
+
+    #[test]
+    fn blank_token_is_dropped() {
+        let config = decode(&encode(r#"{"url":"gw.local","token":"  "}"#)).unwrap();
+        assert_eq!(config.token, None);
+    }
+
+    #[test]
+    fn reserved_headers_are_rejected_in_any_case() {
+        for name in ["authorization", "Authorization", "HOST", "Content-Length"] {
+            let json = format!(r#"{{"url":"gw.local","headers":{{"{name}":"x"}}}}"#);
+            assert!(error(&json).contains(name), "{name}");
+        }
+
+        let json = r#"{"url":"gw.local","headers":{"X-Tenant":"acme"}}"#;
+        let config = decode(&encode(json)).unwrap();
+        assert_eq!(config.headers["X-Tenant"], "acme");
+    }
+
+    #[test]
+    fn header_count_is_capped() {
+        let headers: BTreeMap<_, _> = (0..=MAX_HEADERS)
+            .map(|i| (format!("X-H{i}"), "v".to_string()))
+            .collect();
+        let json = serde_json::json!({ "url": "gw.local", "headers": headers }).to_string();
+        assert!(error(&json).contains("too many headers"));
+    }
+}
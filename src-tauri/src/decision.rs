@@ -0,0 +1,116 @@
+//! Why `auto_connect` ended up on its connection
+//!
+//! Each connect cycle records what it tried, in order, and how each attempt
+//! went: the saved gateway, the configured URL, the saved gateway looked for
+//! over mDNS, a hand-started gateway when attached, and the sidecar. The log
+//! is reset at the start of every cycle, so "why did it start a sidecar when
+//! my remote was up?" can be answered from the last one.
+
+use serde::Serialize;
+
+use crate::{logs, AppState};
+
+/// A connection `auto_connect` can try
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Candidate {
+    /// Gateway connected to last time
+    SavedGateway,
+
+    /// Gateway URL from the app's configuration
+    ConfiguredUrl,
+
+    /// Saved gateway found over mDNS at a new address
+    Discovered,
+
+    /// Gateway started by hand on the default sidecar port
+    AttachedGateway,
+    Sidecar,
+}
+
+/// How an attempt went
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Connected,
+
+    /// Tried, but nothing answered
+    Unreachable,
+
+    /// Not tried (see the attempt's detail)
+    Skipped,
+
+    /// Tried and failed with an error
+    Failed,
+}
+
+/// One step of a connect cycle
+#[derive(Debug, Clone, Serialize)]
+pub struct Attempt {
+    pub candidate: Candidate,
+    pub url: Option<String>,
+    pub outcome: Outcome,
+    pub detail: Option<String>,
+
+    /// When the attempt finished (ms since Unix epoch)
+    pub at_ms: u64,
+}
+
+/// Attempts of the latest connect cycle, in order
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DecisionLog {
+    /// When the cycle started (ms since Unix epoch, 0 if none has)
+    pub started_at_ms: u64,
+
+    /// When it finished, if it has
+    pub finished_at_ms: Option<u64>,
+
+    /// Connection the cycle settled on, if any
+    pub chosen: Option<Candidate>,
+
+    pub attempts: Vec<Attempt>,
+
+    /// Why the cycle didn't try anything (e.g. offline mode)
+    pub skipped: Option<String>,
+}
+
+/// Start a new connect cycle, dropping the previous log
+pub async fn begin(state: &AppState) {
+    *state.connection_decision.write().await = DecisionLog {
+        started_at_ms: logs::now_ms(),
+        ..DecisionLog::default()
+    };
+}
+
+/// Record an attempt, finishing the cycle if it connected
+pub async fn record(
+    state: &AppState,
+    candidate: Candidate,
+    url: Option<&str>,
+    outcome: Outcome,
+    detail: Option<String>,
+) {
+    tracing::debug!(?candidate, url, ?outcome, ?detail, "connection attempt");
+    let now = logs::now_ms();
+    let mut log = state.connection_decision.write().await;
+    log.attempts.push(Attempt {
+        candidate,
+        url: url.map(str::to_string),
+        outcome,
+        detail,
+        at_ms: now,
+    });
+    if outcome == Outcome::Connected {
+        log.chosen = Some(candidate);
+        log.finished_at_ms = Some(now);
+    }
+}
+
+/// Finish the cycle without trying anything further
+pub async fn finish(state: &AppState, skipped: Option<&str>) {
+    let mut log = state.connection_decision.write().await;
+    log.finished_at_ms = Some(logs::now_ms());
+    if let Some(reason) = skipped {
+        log.skipped = Some(reason.to_string());
+    }
+}
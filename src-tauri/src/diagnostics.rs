@@ -0,0 +1,166 @@
+//! Diagnostics report
+//!
+//! Collects a snapshot of app and gateway state plus a checklist of
+//! self-tests, for support requests and the onboarding screen.
+
+use serde::Serialize;
+
+use crate::clock::SkewResult;
+use crate::commands::GatewayStatus;
+use crate::decision::DecisionLog;
+use crate::errors::RecentError;
+use crate::event_channel::EventChannelResult;
+use crate::features::Feature;
+use crate::gateway::BinarySourceInfo;
+use crate::manifest::ManifestCheck;
+use crate::storage::{self, StorageTestResult};
+
+/// Full diagnostics report
+#[derive(Debug, Serialize)]
+pub struct Diagnostics {
+    pub app_version: String,
+    pub os: String,
+    pub arch: String,
+    pub gateway: GatewayStatus,
+    pub user_agent: String,
+    pub features: Vec<Feature>,
+
+    /// Last clock skew check against the gateway, if one ran
+    pub clock_skew: Option<SkewResult>,
+    pub gateway_binary: Option<BinarySourceInfo>,
+
+    /// Bundled gateway manifest, checked against the binary at startup
+    pub gateway_manifest: Option<ManifestCheck>,
+
+    /// Last event channel test against the gateway, if one ran
+    pub event_channel: Option<EventChannelResult>,
+
+    /// What the latest automatic connect tried
+    pub connection_decision: DecisionLog,
+    pub recent_errors: Vec<RecentError>,
+    pub storage: StorageTestResult,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+/// A single pass/fail item in the diagnostics checklist
+#[derive(Debug, Serialize)]
+pub struct DiagnosticCheck {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Results of checks that ran earlier, reported as they were
+pub struct EarlierChecks {
+    pub clock_skew: Option<SkewResult>,
+    pub gateway_manifest: Option<ManifestCheck>,
+    pub event_channel: Option<EventChannelResult>,
+    pub connection_decision: DecisionLog,
+}
+
+/// Build the diagnostics report
+pub fn collect(
+    gateway: GatewayStatus,
+    gateway_binary: Result<BinarySourceInfo, String>,
+    recent_errors: Vec<RecentError>,
+    user_agent: String,
+    features: Vec<Feature>,
+    earlier: EarlierChecks,
+) -> Diagnostics {
+    let EarlierChecks {
+        clock_skew,
+        gateway_manifest,
+        event_channel,
+        connection_decision,
+    } = earlier;
+    let storage = storage::round_trip_test();
+
+    let mut checks = vec![
+        DiagnosticCheck {
+            name: "gateway_connected".to_string(),
+            passed: gateway.state == "connected",
+            detail: gateway
+                .error
+                .clone()
+                .unwrap_or_else(|| format!("gateway is {}", gateway.state)),
+        },
+        DiagnosticCheck {
+            name: "gateway_binary".to_string(),
+            passed: gateway_binary.is_ok(),
+            detail: match &gateway_binary {
+                Ok(binary) => format!("{:?} binary at {}", binary.source, binary.path.display()),
+                Err(e) => e.clone(),
+            },
+        },
+        DiagnosticCheck {
+            name: "secure_storage".to_string(),
+            passed: storage.success && storage.persistent,
+            detail: match (&storage.error, storage.persistent) {
+                (Some(e), _) => e.clone(),
+                (None, false) => "secrets are kept in memory and lost on restart".to_string(),
+                (None, true) => "secrets persist across restarts".to_string(),
+            },
+        },
+    ];
+
+    if let Some(skew) = &clock_skew {
+        let direction = if skew.skew_ms >= 0 {
+            "ahead of"
+        } else {
+            "behind"
+        };
+        checks.push(DiagnosticCheck {
+            name: "clock_skew".to_string(),
+            passed: !skew.warning,
+            detail: format!(
+                "local clock is {}ms {direction} the gateway's (±{}ms)",
+                skew.skew_ms.unsigned_abs(),
+                skew.uncertainty_ms
+            ),
+        });
+    }
+
+    if let Some(check) = &gateway_manifest {
+        let expected = &check.manifest.sha256;
+        checks.push(DiagnosticCheck {
+            name: "gateway_manifest".to_string(),
+            passed: check.matches,
+            detail: match (&check.actual_sha256, &check.error) {
+                _ if check.matches => format!(
+                    "bundled gateway {} matches its manifest",
+                    check.manifest.version
+                ),
+                (Some(actual), _) => {
+                    format!("bundled gateway binary is {actual}, its manifest says {expected}")
+                }
+                (None, Some(e)) => e.clone(),
+                (None, None) => "bundled gateway binary could not be checked".to_string(),
+            },
+        });
+    }
+
+    if let Some(result) = &event_channel {
+        checks.push(DiagnosticCheck {
+            name: "event_channel".to_string(),
+            passed: result.events_work,
+            detail: result.detail(),
+        });
+    }
+
+    Diagnostics {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        gateway,
+        user_agent,
+        features,
+        clock_skew,
+        gateway_binary: gateway_binary.ok(),
+        gateway_manifest,
+        event_channel,
+        connection_decision,
+        recent_errors,
+        storage,
+        checks,
+    }
+}
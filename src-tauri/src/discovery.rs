@@ -0,0 +1,566 @@
+//! Gateway discovery over mDNS
+//!
+//! Gateways on the LAN advertise themselves as `_beacon-gateway._tcp`
+//! services, with their device ID, version and persona in TXT records.
+//!
+//! Scan results are kept in a [`DiscoveryCache`] so a gateway missed by one
+//! scan isn't dropped from the picker right away, while one that went away
+//! expires once it hasn't been seen for the configured TTL. A cached
+//! gateway's metadata can be refreshed on its own, from the gateway itself,
+//! after it restarts with a new config (see [`refresh_metadata`]).
+//!
+//! Device IDs should be unique, but two machines restored from the same image
+//! share one. Gateways claiming the same device ID from different hosts are
+//! flagged as duplicates and reported in a `duplicate-gateways` event; a
+//! gateway is then only looked up by device ID together with its host.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::ops::ControlFlow;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use mdns_sd::{ResolvedService, ServiceDaemon, ServiceEvent};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+
+use crate::{capabilities, gateway, logs};
+
+/// mDNS service type advertised by beacon-gateway
+const SERVICE_TYPE: &str = "_beacon-gateway._tcp.local.";
+
+/// How long to browse when looking for one gateway by device ID
+const LOCATE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A gateway found on the local network
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredGateway {
+    pub device_id: String,
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub version: String,
+    pub persona: String,
+    pub voice: bool,
+    pub tls: bool,
+
+    /// Health probe round-trip time (None if unreachable or not measured)
+    pub latency_ms: Option<u64>,
+
+    /// When this gateway was last resolved (ms since Unix epoch)
+    pub last_seen_ms: u64,
+
+    /// Another gateway, on a different host, claims the same device ID
+    pub duplicate: bool,
+}
+
+/// Gateways claiming one device ID, as listed in `duplicate-gateways` events
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGateway {
+    pub device_id: String,
+    pub hosts: Vec<String>,
+}
+
+impl DiscoveredGateway {
+    /// Connection URL for this gateway
+    pub fn url(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{scheme}://{}:{}", self.host, self.port)
+    }
+
+    /// A gateway known only by URL, with its metadata still to be filled in
+    fn from_url(url: &str, device_id: &str) -> Option<Self> {
+        let parsed = reqwest::Url::parse(url).ok()?;
+        Some(Self {
+            device_id: device_id.to_string(),
+            name: device_id.to_string(),
+            host: parsed.host_str()?.to_string(),
+            port: parsed.port_or_known_default()?,
+            version: String::new(),
+            persona: String::new(),
+            voice: false,
+            tls: parsed.scheme() == "https",
+            latency_ms: None,
+            last_seen_ms: logs::now_ms(),
+            duplicate: false,
+        })
+    }
+
+    fn from_service(service: &ResolvedService) -> Option<Self> {
+        // Prefer IPv4 literals: `.local` hostnames aren't resolvable by every
+        // system resolver, and IPv6 link-local addresses need a zone id
+        let addrs: Vec<IpAddr> = service
+            .get_addresses()
+            .iter()
+            .map(|a| a.to_ip_addr())
+            .collect();
+        let host = match addrs.iter().find(|a| a.is_ipv4()).or(addrs.first())? {
+            IpAddr::V4(v4) => v4.to_string(),
+            IpAddr::V6(v6) => format!("[{v6}]"),
+        };
+
+        let txt = |key: &str| service.get_property_val_str(key).map(str::to_string);
+        let flag = |key: &str| txt(key).is_some_and(|v| v == "true" || v == "1");
+
+        let name = service
+            .get_fullname()
+            .strip_suffix(SERVICE_TYPE)
+            .unwrap_or(service.get_fullname())
+            .trim_end_matches('.')
+            .to_string();
+
+        Some(Self {
+            device_id: txt("device_id").unwrap_or_else(|| name.clone()),
+            name,
+            host,
+            port: service.get_port(),
+            version: txt("version").unwrap_or_default(),
+            persona: txt("persona").unwrap_or_default(),
+            voice: flag("voice"),
+            tls: flag("tls"),
+            latency_ms: None,
+            last_seen_ms: logs::now_ms(),
+            duplicate: false,
+        })
+    }
+}
+
+/// Browse for gateways until the timeout elapses, `cancel` is notified, or
+/// `on_found` breaks
+///
+/// Returns every gateway resolved during the browse.
+pub async fn browse(
+    timeout: Duration,
+    cancel: &Notify,
+    mut on_found: impl FnMut(&DiscoveredGateway) -> ControlFlow<()>,
+) -> Result<Vec<DiscoveredGateway>, String> {
+    // A fresh daemon per browse, so results never come from a stale cache
+    let daemon = ServiceDaemon::new().map_err(|e| format!("failed to start mDNS: {e}"))?;
+    let receiver = daemon
+        .browse(SERVICE_TYPE)
+        .map_err(|e| format!("failed to browse mDNS: {e}"))?;
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut found: Vec<DiscoveredGateway> = Vec::new();
+
+    loop {
+        let event = tokio::select! {
+            event = tokio::time::timeout_at(deadline, receiver.recv_async()) => event,
+            () = cancel.notified() => {
+                tracing::debug!("gateway discovery cancelled");
+                break;
+            }
+        };
+        let Ok(Ok(event)) = event else {
+            break;
+        };
+
+        let ServiceEvent::ServiceResolved(service) = event else {
+            continue;
+        };
+        let Some(gateway) = DiscoveredGateway::from_service(&service) else {
+            continue;
+        };
+
+        tracing::debug!(name = %gateway.name, url = %gateway.url(), "discovered gateway");
+
+        found.retain(|g| g.device_id != gateway.device_id || g.host != gateway.host);
+        let flow = on_found(&gateway);
+        found.push(gateway);
+
+        if flow.is_break() {
+            break;
+        }
+    }
+
+    let _ = daemon.shutdown();
+    mark_duplicates(&mut found);
+    Ok(found)
+}
+
+/// Flag gateways whose device ID is also claimed from another host
+fn mark_duplicates(gateways: &mut [DiscoveredGateway]) {
+    let mut claims: HashMap<String, usize> = HashMap::new();
+    for gateway in gateways.iter() {
+        *claims.entry(gateway.device_id.clone()).or_default() += 1;
+    }
+    for gateway in gateways.iter_mut() {
+        gateway.duplicate = claims[&gateway.device_id] > 1;
+    }
+}
+
+/// Device IDs claimed by more than one host, with those hosts
+pub fn duplicates(gateways: &[DiscoveredGateway]) -> Vec<DuplicateGateway> {
+    let mut hosts: HashMap<&str, Vec<String>> = HashMap::new();
+    for gateway in gateways.iter().filter(|g| g.duplicate) {
+        hosts
+            .entry(&gateway.device_id)
+            .or_default()
+            .push(gateway.host.clone());
+    }
+
+    let mut duplicates: Vec<_> = hosts
+        .into_iter()
+        .map(|(device_id, mut hosts)| {
+            hosts.sort();
+            DuplicateGateway {
+                device_id: device_id.to_string(),
+                hosts,
+            }
+        })
+        .collect();
+    duplicates.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+    duplicates
+}
+
+/// The gateway to use out of those claiming `device_id`: the one on `host`
+/// if given, otherwise the only one
+///
+/// Fails rather than guessing when several hosts claim the device ID.
+fn pick(
+    candidates: Vec<DiscoveredGateway>,
+    device_id: &str,
+    host: Option<&str>,
+) -> Result<Option<DiscoveredGateway>, String> {
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .filter(|g| g.device_id == device_id && host.is_none_or(|h| g.host == h))
+        .collect();
+    if candidates.len() > 1 {
+        let hosts: Vec<_> = candidates.iter().map(|g| g.host.as_str()).collect();
+        return Err(format!(
+            "gateways on several hosts claim device ID {device_id} ({}), pick one by host",
+            hosts.join(", ")
+        ));
+    }
+    Ok(candidates.pop())
+}
+
+/// Look for a specific gateway by device ID, and host if given
+///
+/// Without a host, the whole timeout is browsed so that a second gateway
+/// claiming the device ID is noticed; that fails rather than picking one.
+pub async fn find(
+    device_id: &str,
+    host: Option<&str>,
+    timeout: Duration,
+) -> Result<Option<DiscoveredGateway>, String> {
+    let found = browse(timeout, &Notify::new(), |g| {
+        let wanted = host.is_some_and(|h| g.device_id == device_id && g.host == h);
+        if wanted {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })
+    .await?;
+
+    pick(found, device_id, host)
+}
+
+/// Find the current URL of the gateway with this device ID
+///
+/// The cached address is tried first; if it doesn't answer (or the gateway
+/// isn't cached), the gateway is looked up again over mDNS, unless `browse`
+/// is off. Addresses are checked with `client`, so auth headers apply. If
+/// gateways on several hosts claim the device ID, `host` picks one.
+pub async fn locate(
+    cache: &DiscoveryCache,
+    device_id: &str,
+    host: Option<&str>,
+    client: &reqwest::Client,
+    browse: bool,
+) -> Result<String, String> {
+    if let Some(cached) = pick(cache.matching(device_id), device_id, host)? {
+        if gateway::probe_with_client(client, &cached.url()).await {
+            return Ok(cached.url());
+        }
+        tracing::info!(device_id, url = %cached.url(), "cached gateway address is dead");
+    }
+
+    if !browse {
+        return Err(format!(
+            "gateway {device_id} is not reachable at a known address"
+        ));
+    }
+
+    let found = find(device_id, host, LOCATE_TIMEOUT)
+        .await?
+        .ok_or_else(|| format!("gateway {device_id} not found on the network"))?;
+    let url = found.url();
+    if !gateway::probe_with_client(client, &url).await {
+        return Err(format!(
+            "gateway {device_id} found at {url} but not responding"
+        ));
+    }
+
+    tracing::info!(device_id, url = %url, "located gateway");
+    cache.insert(found);
+    Ok(url)
+}
+
+/// Result of refreshing a gateway's metadata
+#[derive(Debug, Clone, Serialize)]
+pub struct MetadataRefresh {
+    pub gateway: DiscoveredGateway,
+
+    /// The gateway couldn't be reached, so this is the metadata cached before
+    pub stale: bool,
+
+    /// Why the refresh failed, when stale
+    pub error: Option<String>,
+}
+
+/// Re-read a gateway's metadata (version, persona, voice) from the gateway
+/// itself, and its TXT records over mDNS if `browse` is on
+///
+/// The gateway is looked up by `device_id` if given, with `url` as the
+/// address to fall back on. The refreshed entry replaces the cached one. An
+/// unreachable gateway keeps its cached metadata, returned as stale.
+pub async fn refresh_metadata(
+    cache: &DiscoveryCache,
+    device_id: Option<&str>,
+    url: Option<&str>,
+    client: &reqwest::Client,
+    browse: bool,
+) -> Result<MetadataRefresh, String> {
+    let previous = match device_id {
+        Some(id) => pick(cache.matching(id), id, None)?,
+        None => None,
+    };
+    let advertised = match device_id.filter(|_| browse) {
+        Some(id) => find(id, None, LOCATE_TIMEOUT).await.unwrap_or_else(|e| {
+            tracing::debug!(device_id = id, error = %e, "mDNS lookup failed");
+            None
+        }),
+        None => None,
+    };
+    let url = advertised
+        .as_ref()
+        .or(previous.as_ref())
+        .map(DiscoveredGateway::url)
+        .or(url.map(str::to_string))
+        .ok_or_else(|| format!("gateway {} not found", device_id.unwrap_or_default()))?;
+
+    let Some(info) = gateway::fetch_gateway_info(client, &url).await else {
+        let error = format!("gateway at {url} is not reachable");
+        let gateway = previous
+            .or(advertised)
+            .ok_or_else(|| format!("{error} and has no cached metadata"))?;
+        tracing::info!(url = %url, "gateway unreachable, keeping cached metadata");
+        return Ok(MetadataRefresh {
+            gateway,
+            stale: true,
+            error: Some(error),
+        });
+    };
+
+    let previous = previous.or_else(|| cache.get(&info.device_id));
+    let mut gateway = match advertised.or(previous) {
+        Some(gateway) if gateway.device_id == info.device_id => gateway,
+        _ => DiscoveredGateway::from_url(&url, &info.device_id)
+            .ok_or_else(|| format!("invalid gateway URL: {url}"))?,
+    };
+    if let Some(version) = info.version {
+        gateway.version = version;
+    }
+
+    let get = |path: &str| {
+        let request = client
+            .get(format!("{url}{path}"))
+            .timeout(gateway::PROBE_TIMEOUT);
+        async move {
+            let resp = request.send().await.ok()?;
+            if !resp.status().is_success() {
+                return None;
+            }
+            resp.json::<Value>().await.ok()
+        }
+    };
+    let personas = get("/personas").await;
+    if let Some(persona) = personas
+        .as_ref()
+        .and_then(|p| p.get("active_id"))
+        .and_then(Value::as_str)
+    {
+        gateway.persona = persona.to_string();
+    }
+    if let Some(capabilities) = get("/capabilities").await {
+        gateway.voice = capabilities::has_voice(&capabilities);
+    }
+    gateway.last_seen_ms = logs::now_ms();
+
+    tracing::info!(
+        device_id = %gateway.device_id,
+        version = %gateway.version,
+        persona = %gateway.persona,
+        "refreshed gateway metadata"
+    );
+    cache.insert(gateway.clone());
+    Ok(MetadataRefresh {
+        gateway,
+        stale: false,
+        error: None,
+    })
+}
+
+/// Run a fresh scan, measuring each gateway's latency as it's found
+///
+/// Emits `mdns-gateway-found` for every gateway once its latency is known so
+/// the UI can populate incrementally. On timeout or cancellation, whatever was
+/// found so far is returned (with `latency_ms` unset if its probe didn't finish).
+pub async fn scan(
+    app: &AppHandle,
+    timeout: Duration,
+    cancel: &Notify,
+) -> Result<Vec<DiscoveredGateway>, String> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut probes = JoinSet::new();
+
+    let found = browse(timeout, cancel, |gateway| {
+        let mut gateway = gateway.clone();
+        let app = app.clone();
+
+        probes.spawn(async move {
+            let start = Instant::now();
+            if gateway::probe_gateway(&gateway.url()).await {
+                gateway.latency_ms = Some(start.elapsed().as_millis() as u64);
+            }
+
+            let _ = app.emit("mdns-gateway-found", &gateway);
+            gateway
+        });
+
+        ControlFlow::Continue(())
+    })
+    .await?;
+
+    let mut latencies: HashMap<String, u64> = HashMap::new();
+    while let Ok(Some(result)) = tokio::time::timeout_at(deadline, probes.join_next()).await {
+        let Ok(gateway) = result else {
+            continue;
+        };
+        if let Some(latency_ms) = gateway.latency_ms {
+            latencies.insert(gateway.url(), latency_ms);
+        }
+    }
+    probes.abort_all();
+
+    let duplicates = duplicates(&found);
+    if !duplicates.is_empty() {
+        tracing::warn!(?duplicates, "several gateways claim the same device ID");
+        let _ = app.emit("duplicate-gateways", &duplicates);
+    }
+
+    Ok(found
+        .into_iter()
+        .map(|mut g| {
+            g.latency_ms = latencies.remove(&g.url());
+            g
+        })
+        .collect())
+}
+
+/// Gateway in the cache: by device ID and host, since duplicates share the ID
+type Key = (String, String);
+
+fn key(gateway: &DiscoveredGateway) -> Key {
+    (gateway.device_id.clone(), gateway.host.clone())
+}
+
+/// Gateways found by recent scans, keyed by device ID and host
+#[derive(Default)]
+pub struct DiscoveryCache {
+    entries: Mutex<HashMap<Key, DiscoveredGateway>>,
+}
+
+impl DiscoveryCache {
+    /// Add or refresh `found`, then drop entries not seen within `ttl`
+    ///
+    /// A gateway in `found` replaces the cached entries for its device ID, so
+    /// one that moved to another host isn't taken for a duplicate.
+    ///
+    /// Returns the cached gateways (newest first) and whether the set changed.
+    pub fn update(
+        &self,
+        found: Vec<DiscoveredGateway>,
+        ttl: Duration,
+    ) -> (Vec<DiscoveredGateway>, bool) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let before = fingerprint(&entries);
+
+        entries.retain(|(device_id, _), _| found.iter().all(|g| g.device_id != *device_id));
+        for gateway in found {
+            entries.insert(key(&gateway), gateway);
+        }
+        let cutoff = logs::now_ms().saturating_sub(ttl.as_millis() as u64);
+        entries.retain(|_, g| g.last_seen_ms >= cutoff);
+
+        let changed = fingerprint(&entries) != before;
+        (sorted(&entries), changed)
+    }
+
+    /// Cached gateway with this device ID, unless several hosts claim it
+    pub fn get(&self, device_id: &str) -> Option<DiscoveredGateway> {
+        pick(self.matching(device_id), device_id, None)
+            .ok()
+            .flatten()
+    }
+
+    /// Cached gateways with this device ID (more than one for duplicates)
+    pub fn matching(&self, device_id: &str) -> Vec<DiscoveredGateway> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .values()
+            .filter(|g| g.device_id == device_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Cached gateways, newest first
+    pub fn list(&self) -> Vec<DiscoveredGateway> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        sorted(&entries)
+    }
+
+    /// Add or refresh a single gateway
+    ///
+    /// Replaces the entries for its device ID, other than known duplicates.
+    pub fn insert(&self, gateway: DiscoveredGateway) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, g| g.device_id != gateway.device_id || g.duplicate);
+        entries.insert(key(&gateway), gateway);
+    }
+
+    /// Forget every cached gateway, returning whether there were any
+    pub fn clear(&self) -> bool {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let had_entries = !entries.is_empty();
+        entries.clear();
+        had_entries
+    }
+}
+
+/// What the picker shows for each gateway, to tell real changes from
+/// refreshed timestamps and latencies
+fn fingerprint(entries: &HashMap<Key, DiscoveredGateway>) -> Vec<(String, String, String, bool)> {
+    let mut keys: Vec<_> = entries
+        .values()
+        .map(|g| (g.device_id.clone(), g.url(), g.name.clone(), g.duplicate))
+        .collect();
+    keys.sort();
+    keys
+}
+
+fn sorted(entries: &HashMap<Key, DiscoveredGateway>) -> Vec<DiscoveredGateway> {
+    let mut gateways: Vec<_> = entries.values().cloned().collect();
+    gateways.sort_by(|a, b| {
+        b.last_seen_ms
+            .cmp(&a.last_seen_ms)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    gateways
+}
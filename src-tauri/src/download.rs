@@ -0,0 +1,242 @@
+//! Gateway binary download
+//!
+//! Fetches a release build of beacon-gateway for first-run users who don't
+//! have one installed. Artifacts are downloaded into `data_dir/gateway/`,
+//! verified against their published SHA-256 checksum, and then picked up by
+//! `find_gateway_binary`.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter};
+use tokio::io::AsyncWriteExt;
+
+/// Default location of gateway release artifacts
+pub const DEFAULT_BASE_URL: &str =
+    "https://github.com/omnidotdev/beacon-gateway/releases/latest/download";
+
+/// Progress event emitted while downloading
+#[derive(Debug, Clone, Serialize)]
+struct DownloadProgress {
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+/// Directory downloaded gateway binaries live in
+pub fn gateway_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("gateway")
+}
+
+/// Path of the downloaded gateway binary
+pub fn binary_path(data_dir: &Path) -> PathBuf {
+    gateway_dir(data_dir).join(format!("beacon-gateway{}", std::env::consts::EXE_SUFFIX))
+}
+
+/// Rust target triple matching the running app
+pub fn current_target() -> String {
+    let arch = std::env::consts::ARCH;
+    let rest = match std::env::consts::OS {
+        "macos" => "apple-darwin",
+        "windows" => "pc-windows-msvc",
+        "linux" => "unknown-linux-gnu",
+        "android" => "linux-android",
+        "ios" => "apple-ios",
+        other => other,
+    };
+
+    format!("{arch}-{rest}")
+}
+
+/// Download, verify and install the gateway binary for `target`
+///
+/// An interrupted download is resumed from its `.part` file when the server
+/// supports range requests and the artifact is unchanged since (by its
+/// ETag), and restarted cleanly otherwise.
+pub async fn download_gateway(
+    app: &AppHandle,
+    base_url: &str,
+    target: &str,
+    data_dir: &Path,
+) -> Result<PathBuf, String> {
+    let suffix = if target.contains("windows") {
+        ".exe"
+    } else {
+        ""
+    };
+    let artifact = format!("beacon-gateway-{target}{suffix}");
+    let base_url = base_url.trim_end_matches('/');
+    let client = reqwest::Client::new();
+
+    let dir = gateway_dir(data_dir);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+
+    // Fetch the expected checksum first so a bad release fails fast
+    let checksum_url = format!("{base_url}/{artifact}.sha256");
+    let expected = client
+        .get(&checksum_url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| format!("failed to fetch checksum: {e}"))?
+        .text()
+        .await
+        .map_err(|e| format!("failed to read checksum: {e}"))?;
+    let expected = expected
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| "checksum file is empty".to_string())?
+        .to_ascii_lowercase();
+
+    let part = dir.join(format!("{artifact}.part"));
+    let etag_path = dir.join(format!("{artifact}.part.etag"));
+    let mut resume_from = tokio::fs::metadata(&part)
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+
+    let url = format!("{base_url}/{artifact}");
+    tracing::info!(url = %url, resume_from, "downloading gateway binary");
+
+    let mut resp = loop {
+        let mut request = client.get(&url);
+        if resume_from > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={resume_from}-"));
+            if let Ok(etag) = tokio::fs::read_to_string(&etag_path).await {
+                request = request.header(reqwest::header::IF_RANGE, etag.trim());
+            }
+        }
+
+        let resp = request
+            .send()
+            .await
+            .map_err(|e| format!("failed to download gateway: {e}"))?;
+        if resume_from == 0 || resp.status() != reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            break resp
+                .error_for_status()
+                .map_err(|e| format!("failed to download gateway: {e}"))?;
+        }
+
+        // Nothing left past the part: it's either the whole artifact already
+        // or not the artifact at all
+        if sha256_file(&part)? == expected {
+            tracing::info!("gateway download already complete");
+            let _ = tokio::fs::remove_file(&etag_path).await;
+            return install(&part, data_dir).await;
+        }
+        tracing::warn!(resume_from, "invalid partial gateway download, restarting");
+        let _ = tokio::fs::remove_file(&part).await;
+        let _ = tokio::fs::remove_file(&etag_path).await;
+        resume_from = 0;
+    };
+
+    // 206 means the server honored the range; anything else restarts from scratch
+    let resumed = resp.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    if !resumed {
+        // Only a strong ETag can vouch for the bytes on a later resume
+        let etag = resp
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .filter(|etag| !etag.starts_with("W/"));
+        let _ = match etag {
+            Some(etag) => tokio::fs::write(&etag_path, etag).await,
+            None => tokio::fs::remove_file(&etag_path).await,
+        };
+    }
+    let mut downloaded = if resumed { resume_from } else { 0 };
+    let total = resp.content_length().map(|len| len + downloaded);
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&part)
+        .await
+        .map_err(|e| format!("failed to open {}: {e}", part.display()))?;
+
+    while let Some(chunk) = resp
+        .chunk()
+        .await
+        .map_err(|e| format!("download interrupted: {e}"))?
+    {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("failed to write download: {e}"))?;
+
+        downloaded += chunk.len() as u64;
+        let _ = app.emit(
+            "gateway-download-progress",
+            DownloadProgress { downloaded, total },
+        );
+    }
+
+    file.flush()
+        .await
+        .map_err(|e| format!("failed to write download: {e}"))?;
+    drop(file);
+
+    let actual = sha256_file(&part)?;
+    let _ = tokio::fs::remove_file(&etag_path).await;
+    if actual != expected {
+        let _ = tokio::fs::remove_file(&part).await;
+        return Err(format!(
+            "gateway checksum mismatch (expected {expected}, got {actual})"
+        ));
+    }
+
+    install(&part, data_dir).await
+}
+
+/// Move a verified download into place as the gateway binary
+async fn install(part: &Path, data_dir: &Path) -> Result<PathBuf, String> {
+    let dest = binary_path(data_dir);
+    tokio::fs::rename(part, &dest)
+        .await
+        .map_err(|e| format!("failed to install gateway binary: {e}"))?;
+    make_executable(&dest)?;
+
+    tracing::info!(path = %dest.display(), "gateway binary installed");
+    Ok(dest)
+}
+
+/// Hex-encoded SHA-256 of a file, read in chunks
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file =
+        std::fs::File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .map_err(|e| format!("failed to make gateway executable: {e}"))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
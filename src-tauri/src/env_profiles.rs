@@ -0,0 +1,126 @@
+//! Sidecar environment profiles
+//!
+//! Gateway runtime configs (dev vs prod, GPU vs CPU, ...) are sets of
+//! environment variables. Settings can define them as named profiles, and
+//! the selected one is applied when the sidecar starts.
+//!
+//! Precedence, lowest first: the app's own environment (inherited by the
+//! sidecar), the variables the app manages from its settings (`BEACON_MODEL`,
+//! `BEACON_LOG`), then the profile. `BEACON_API_PORT` is the exception: the
+//! app connects to the sidecar on that port, so profiles can't set it.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+/// Variables only the app may set
+const RESERVED_VARS: &[&str] = &["BEACON_API_PORT"];
+
+/// How secret values appear in logs
+const REDACTED: &str = "[redacted]";
+
+/// A named set of sidecar environment variables
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvProfile {
+    /// Variables set to the given values
+    pub vars: BTreeMap<String, String>,
+
+    /// Variables whose values are kept in secure storage, under
+    /// [`secret_key`], rather than in settings
+    pub secrets: Vec<String>,
+}
+
+/// A resolved sidecar environment variable
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+    pub secret: bool,
+}
+
+impl EnvVar {
+    /// A variable that is fine to log
+    pub fn plain(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            secret: false,
+        }
+    }
+}
+
+/// Secure storage key holding a secret variable of an env profile
+pub fn secret_key(profile: &str, var: &str) -> String {
+    format!("gateway-env:{profile}:{var}")
+}
+
+impl EnvProfile {
+    /// Check that every variable name is usable and not reserved
+    pub fn validate(&self, name: &str) -> Result<(), String> {
+        for var in self.vars.keys().chain(&self.secrets) {
+            if var.is_empty() || var.contains(['=', '\0']) {
+                return Err(format!(
+                    "env profile `{name}`: invalid variable name `{var}`"
+                ));
+            }
+            if RESERVED_VARS.contains(&var.as_str()) {
+                return Err(format!(
+                    "env profile `{name}`: `{var}` is managed by the app and can't be set"
+                ));
+            }
+        }
+        if let Some(var) = self.secrets.iter().find(|v| self.vars.contains_key(*v)) {
+            return Err(format!(
+                "env profile `{name}`: `{var}` is both a plain and a secret variable"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Variables with their values, secrets read from secure storage
+    pub fn resolve(&self, name: &str) -> Result<Vec<EnvVar>, String> {
+        let mut vars: Vec<EnvVar> = self
+            .vars
+            .iter()
+            .map(|(var, value)| EnvVar::plain(var, value))
+            .collect();
+
+        for var in &self.secrets {
+            let value = storage::get(&secret_key(name, var))?.ok_or_else(|| {
+                format!("env profile `{name}`: no value stored for secret `{var}`")
+            })?;
+            vars.push(EnvVar {
+                name: var.clone(),
+                value,
+                secret: true,
+            });
+        }
+        Ok(vars)
+    }
+}
+
+/// Variables for logging, with secret values redacted
+pub fn redacted(vars: &[EnvVar]) -> BTreeMap<&str, &str> {
+    vars.iter()
+        .map(|var| {
+            let value = if var.secret { REDACTED } else { &var.value };
+            (var.name.as_str(), value)
+        })
+        .collect()
+}
+
+/// Check every profile in settings, and that the selected one exists
+pub fn validate_all(
+    profiles: &BTreeMap<String, EnvProfile>,
+    selected: Option<&str>,
+) -> Result<(), String> {
+    for (name, profile) in profiles {
+        profile.validate(name)?;
+    }
+    match selected {
+        Some(name) if !profiles.contains_key(name) => Err(format!("env profile not found: {name}")),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,74 @@
+//! Recent gateway errors
+//!
+//! Failures from the connection, sidecar and auth paths are recorded into a
+//! small ring buffer, independent of the current gateway state, so the UI can
+//! show a "recent problems" list even after a later reconnect succeeded.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::logs;
+
+/// Maximum errors kept in the ring buffer
+const MAX_ERRORS: usize = 100;
+
+/// What kind of failure an error was
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Gateway could not be reached or answered unhealthy
+    Probe,
+
+    /// Sidecar could not be started
+    Spawn,
+
+    /// Gateway rejected credentials
+    Auth,
+
+    /// Sidecar exited unexpectedly
+    Crash,
+
+    /// Sidecar stayed alive but stopped responding
+    Hang,
+}
+
+/// A recorded failure
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentError {
+    /// When it happened (ms since Unix epoch)
+    pub timestamp_ms: u64,
+
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+/// Bounded log of recent errors
+#[derive(Default)]
+pub struct ErrorLog {
+    errors: Mutex<VecDeque<RecentError>>,
+}
+
+impl ErrorLog {
+    /// Record a failure
+    pub fn record(&self, kind: ErrorKind, message: impl Into<String>) {
+        let entry = RecentError {
+            timestamp_ms: logs::now_ms(),
+            kind,
+            message: message.into(),
+        };
+
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        if errors.len() == MAX_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(entry);
+    }
+
+    /// Most recent errors, newest first
+    pub fn recent(&self, limit: usize) -> Vec<RecentError> {
+        let errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        errors.iter().rev().take(limit).cloned().collect()
+    }
+}
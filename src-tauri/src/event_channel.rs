@@ -0,0 +1,413 @@
+//! Two-way check of the gateway's event channel
+//!
+//! Push events reach the app over a WebSocket at `<url>/ws`, which a gateway
+//! reachable over plain HTTP can still be cut off from: proxies that don't
+//! pass `Upgrade` requests answer the handshake themselves, or mangle it.
+//! The check opens the socket with the connection's credentials, sends a
+//! ping and waits for the pong, then closes the socket again, telling a
+//! refused connection apart from an upgrade a proxy turned down.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio_native_tls::native_tls;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{header, HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::{Error, Message};
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+
+use crate::gateway::TlsVersion;
+use crate::{pinning, profiles, storage, AppState};
+
+/// Path of the event channel under the gateway URL
+const EVENTS_PATH: &str = "/ws";
+
+/// Timeout for opening the socket, handshake included
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the pong may take
+const PONG_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the closing handshake may take before the socket is dropped
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Payload of the test ping, to recognize its pong among other frames
+const PING_PAYLOAD: &[u8] = b"beacon-event-channel-test";
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Why the event channel doesn't work
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelFailure {
+    /// Nothing accepted the TCP connection
+    ConnectionRefused,
+
+    /// The upgrade was answered with plain HTTP or a broken handshake,
+    /// usually by a proxy that doesn't pass WebSocket traffic
+    UpgradeRejected,
+
+    /// The gateway turned down the credentials
+    Unauthorized,
+
+    /// The gateway has no event channel
+    NotFound,
+    Tls,
+
+    /// The socket opened but the ping went unanswered
+    NoPong,
+    Timeout,
+    Other,
+}
+
+/// Result of an event channel check
+#[derive(Debug, Clone, Serialize)]
+pub struct EventChannelResult {
+    /// WebSocket URL tried
+    pub url: String,
+
+    /// Whether push events will get through
+    pub events_work: bool,
+
+    /// Opening the socket, handshake included (ms)
+    pub handshake_ms: Option<u64>,
+
+    /// From the ping to its pong (ms)
+    pub round_trip_ms: Option<u64>,
+
+    /// HTTP status the upgrade was answered with instead of 101
+    pub status: Option<u16>,
+
+    pub failure: Option<ChannelFailure>,
+    pub error: Option<String>,
+}
+
+impl EventChannelResult {
+    /// One-line summary, for reports
+    pub fn detail(&self) -> String {
+        match (&self.error, self.round_trip_ms) {
+            (Some(e), _) => e.clone(),
+            (None, Some(rtt)) => format!("event channel answered a ping in {rtt}ms"),
+            (None, None) => "event channel works".to_string(),
+        }
+    }
+}
+
+/// A failed step, with what went wrong
+type Failure = (ChannelFailure, String, Option<u16>);
+
+/// Open the event channel of the gateway at `url`, ping it, and close it
+///
+/// Only an unusable URL or TLS setup is an error; a channel that doesn't
+/// work is reported in the result.
+pub async fn test(
+    url: &str,
+    token: Option<&str>,
+    headers: &BTreeMap<String, String>,
+    ca_bundle: Option<&Path>,
+    min_tls_version: TlsVersion,
+    pinned_cert_sha256: Option<&str>,
+) -> Result<EventChannelResult, String> {
+    let ws_url = ws_url(url)?;
+    let mut request = ws_url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| format!("invalid event channel URL `{ws_url}`: {e}"))?;
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("invalid header name `{name}`"))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| format!("header `{name}` contains invalid characters"))?;
+        request.headers_mut().insert(name, value);
+    }
+    if let Some(token) = token {
+        let value = HeaderValue::from_str(&format!("Bearer {token}"))
+            .map_err(|_| "gateway token contains invalid characters".to_string())?;
+        request.headers_mut().insert(header::AUTHORIZATION, value);
+    }
+    let connector = connector(&ws_url, ca_bundle, min_tls_version, pinned_cert_sha256)?;
+
+    let mut result = EventChannelResult {
+        url: ws_url,
+        events_work: false,
+        handshake_ms: None,
+        round_trip_ms: None,
+        status: None,
+        failure: None,
+        error: None,
+    };
+
+    let started = Instant::now();
+    let opened = tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        tokio_tungstenite::connect_async_tls_with_config(request, None, false, connector),
+    )
+    .await;
+    result.handshake_ms = Some(started.elapsed().as_millis() as u64);
+
+    let outcome = match opened {
+        Ok(Ok((mut socket, _))) => {
+            let pinged = ping(&mut socket).await;
+            close(socket).await;
+            pinged
+        }
+        Ok(Err(e)) => Err(classify(e)),
+        Err(_) => Err((
+            ChannelFailure::Timeout,
+            format!(
+                "event channel did not open within {}s",
+                CONNECT_TIMEOUT.as_secs()
+            ),
+            None,
+        )),
+    };
+
+    match outcome {
+        Ok(round_trip_ms) => {
+            result.events_work = true;
+            result.round_trip_ms = Some(round_trip_ms);
+        }
+        Err((failure, error, status)) => {
+            result.failure = Some(failure);
+            result.error = Some(error);
+            result.status = status;
+        }
+    }
+    tracing::info!(?result, "tested gateway event channel");
+    Ok(result)
+}
+
+/// Test the event channel of the connected gateway, with the active
+/// profile's credentials and TLS settings
+pub async fn check(state: &AppState) -> Result<EventChannelResult, String> {
+    let url = state
+        .gateway_url()
+        .await
+        .ok_or_else(|| "not connected to a gateway".to_string())?;
+    let active_profile = state.active_profile.read().await.clone();
+    let Some(profile) = active_profile.and_then(|name| profiles::find(&state.data_dir, &name))
+    else {
+        return test(
+            &url,
+            None,
+            &BTreeMap::new(),
+            None,
+            TlsVersion::default(),
+            None,
+        )
+        .await;
+    };
+
+    let token = storage::get(&profiles::token_key(&profile.name))?;
+    let headers = profiles::headers(&profile)?;
+    test(
+        &url,
+        token.as_deref(),
+        &headers,
+        profile.ca_bundle.as_deref(),
+        profile.min_tls_version,
+        profile.pinned_cert_sha256.as_deref(),
+    )
+    .await
+}
+
+/// Send a ping and wait for its pong, returning the round trip (ms)
+///
+/// Events the gateway pushes meanwhile are skipped.
+async fn ping(socket: &mut Socket) -> Result<u64, Failure> {
+    let started = Instant::now();
+    socket
+        .send(Message::Ping(PING_PAYLOAD.to_vec()))
+        .await
+        .map_err(classify)?;
+
+    let pong = tokio::time::timeout(PONG_TIMEOUT, async {
+        while let Some(message) = socket.next().await {
+            match message.map_err(classify)? {
+                Message::Pong(payload) if payload == PING_PAYLOAD => return Ok(true),
+                Message::Close(_) => return Ok(false),
+                _ => {}
+            }
+        }
+        Ok(false)
+    })
+    .await;
+
+    match pong {
+        Ok(Ok(true)) => Ok(started.elapsed().as_millis() as u64),
+        Ok(Ok(false)) => Err((
+            ChannelFailure::NoPong,
+            "gateway closed the event channel before answering the ping".to_string(),
+            None,
+        )),
+        Ok(Err(failure)) => Err(failure),
+        Err(_) => Err((
+            ChannelFailure::NoPong,
+            format!(
+                "no pong within {}s, a proxy may be holding back WebSocket traffic",
+                PONG_TIMEOUT.as_secs()
+            ),
+            None,
+        )),
+    }
+}
+
+/// Close the socket with a closing handshake, giving up after
+/// [`CLOSE_TIMEOUT`]
+async fn close(mut socket: Socket) {
+    let closed = tokio::time::timeout(CLOSE_TIMEOUT, async {
+        socket.close(None).await?;
+        while socket.next().await.transpose()?.is_some() {}
+        Ok::<_, Error>(())
+    })
+    .await;
+    if !matches!(closed, Ok(Ok(()))) {
+        tracing::debug!("event channel test socket did not close cleanly");
+    }
+}
+
+fn classify(error: Error) -> Failure {
+    match error {
+        Error::Http(response) => {
+            let status = response.status().as_u16();
+            let (failure, error) = match status {
+                401 | 403 => (
+                    ChannelFailure::Unauthorized,
+                    format!("gateway rejected the credentials for the event channel ({status})"),
+                ),
+                404 => (
+                    ChannelFailure::NotFound,
+                    format!("gateway has no event channel at {EVENTS_PATH}"),
+                ),
+                _ => {
+                    let via = response
+                        .headers()
+                        .get(header::VIA)
+                        .and_then(|via| via.to_str().ok())
+                        .map(|via| format!(" (via {via})"))
+                        .unwrap_or_default();
+                    (
+                        ChannelFailure::UpgradeRejected,
+                        format!(
+                            "WebSocket upgrade answered with HTTP {status}{via}, likely by a \
+                             proxy that doesn't pass WebSocket traffic"
+                        ),
+                    )
+                }
+            };
+            (failure, error, Some(status))
+        }
+        Error::Protocol(e) => (
+            ChannelFailure::UpgradeRejected,
+            format!("WebSocket handshake broken ({e}), likely by a proxy"),
+            None,
+        ),
+        Error::Io(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => (
+            ChannelFailure::ConnectionRefused,
+            format!("connection refused: {e}"),
+            None,
+        ),
+        Error::Io(e) if pinning::is_pin_mismatch(&e) => (
+            ChannelFailure::Tls,
+            "certificate pin mismatch: gateway presented another certificate".to_string(),
+            None,
+        ),
+        Error::Tls(e) => (
+            ChannelFailure::Tls,
+            format!("TLS handshake failed: {e}"),
+            None,
+        ),
+        e => (ChannelFailure::Other, e.to_string(), None),
+    }
+}
+
+/// Event channel URL of the gateway at `url`
+fn ws_url(url: &str) -> Result<String, String> {
+    let url = url.trim_end_matches('/');
+    if let Some(rest) = url.strip_prefix("https://") {
+        Ok(format!("wss://{rest}{EVENTS_PATH}"))
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        Ok(format!("ws://{rest}{EVENTS_PATH}"))
+    } else {
+        Err(format!("unsupported gateway URL `{url}`"))
+    }
+}
+
+/// TLS setup matching the connection's: the pinned certificate, or the
+/// platform roots plus the CA bundle
+///
+/// The platform TLS library can't require TLS 1.3, so a TLS 1.3 minimum
+/// uses rustls instead, as the connection's client does.
+fn connector(
+    ws_url: &str,
+    ca_bundle: Option<&Path>,
+    min_tls_version: TlsVersion,
+    pinned_cert_sha256: Option<&str>,
+) -> Result<Option<Connector>, String> {
+    if !ws_url.starts_with("wss://") {
+        return Ok(Some(Connector::Plain));
+    }
+    if let Some(pin) = pinned_cert_sha256 {
+        let config = pinning::client_config(pin, min_tls_version)?;
+        return Ok(Some(Connector::Rustls(Arc::new(config))));
+    }
+    if min_tls_version == TlsVersion::Tls13 {
+        let config = tls13_config(ca_bundle)?;
+        return Ok(Some(Connector::Rustls(Arc::new(config))));
+    }
+
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.min_protocol_version(Some(native_tls::Protocol::Tlsv12));
+    if let Some(path) = ca_bundle {
+        let pem = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read CA bundle {}: {e}", path.display()))?;
+        let blocks = pem
+            .split_inclusive("-----END CERTIFICATE-----")
+            .filter(|block| block.contains("-----BEGIN CERTIFICATE-----"));
+        for block in blocks {
+            let cert = native_tls::Certificate::from_pem(block.trim().as_bytes())
+                .map_err(|e| format!("invalid CA bundle {}: {e}", path.display()))?;
+            builder.add_root_certificate(cert);
+        }
+    }
+    let connector = builder
+        .build()
+        .map_err(|e| format!("failed to configure TLS: {e}"))?;
+    Ok(Some(Connector::NativeTls(connector)))
+}
+
+/// rustls config allowing only TLS 1.3, trusting the platform roots plus
+/// the CA bundle
+fn tls13_config(ca_bundle: Option<&Path>) -> Result<rustls::ClientConfig, String> {
+    let mut roots = rustls::RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for e in &native.errors {
+        tracing::debug!(error = %e, "failed to load a platform root certificate");
+    }
+    roots.add_parsable_certificates(native.certs);
+
+    if let Some(path) = ca_bundle {
+        let pem = std::fs::read(path)
+            .map_err(|e| format!("failed to read CA bundle {}: {e}", path.display()))?;
+        for cert in CertificateDer::pem_slice_iter(&pem) {
+            cert.map_err(|e| e.to_string())
+                .and_then(|cert| roots.add(cert).map_err(|e| e.to_string()))
+                .map_err(|e| format!("invalid CA bundle {}: {e}", path.display()))?;
+        }
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| format!("failed to configure TLS: {e}"))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(config)
+}
@@ -0,0 +1,71 @@
+//! Export files
+//!
+//! Exports meant to be attached to issues (gateway logs, diagnostics) are
+//! gzip-compressed by default to keep them small for slow uploads. Content is
+//! written through the encoder as it's produced, never buffered whole.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+/// An export file being written, optionally gzip-compressed
+pub enum ExportFile {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl ExportFile {
+    /// Create `name` in `dir` (with `.gz` appended when compressing),
+    /// returning the file's path
+    pub fn create(dir: &Path, name: &str, compress: bool) -> Result<(PathBuf, Self), String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+
+        let path = if compress {
+            dir.join(format!("{name}.gz"))
+        } else {
+            dir.join(name)
+        };
+        let file =
+            File::create(&path).map_err(|e| format!("failed to create {}: {e}", path.display()))?;
+        let file = BufWriter::new(file);
+
+        let export = if compress {
+            Self::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            Self::Plain(file)
+        };
+        Ok((path, export))
+    }
+
+    /// Flush everything to disk, writing the gzip trailer if compressing
+    pub fn finish(self) -> Result<(), String> {
+        let mut file = match self {
+            Self::Plain(file) => file,
+            Self::Gzip(encoder) => encoder
+                .finish()
+                .map_err(|e| format!("failed to compress export: {e}"))?,
+        };
+        file.flush()
+            .map_err(|e| format!("failed to write export: {e}"))
+    }
+}
+
+impl Write for ExportFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Feature flags
+//!
+//! Experimental or optional subsystems can be toggled at launch without a
+//! rebuild. `BEACON_FEATURES=mdns,cache` replaces the compiled-in defaults
+//! with exactly the listed features, and `BEACON_FEATURE_<NAME>=1` (or `0`)
+//! then turns single features on or off, e.g. `BEACON_FEATURE_REQUEST_LOGGING=1`.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+/// An optional subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// mDNS gateway discovery (rescans and rediscovering a moved gateway)
+    Mdns,
+
+    /// WebSocket transport, checked by the frontend before opening sockets
+    Websocket,
+
+    /// Caching of gateway responses (schema, health details)
+    Cache,
+
+    /// Logging every proxied gateway request
+    RequestLogging,
+}
+
+impl Feature {
+    /// Every feature
+    pub const ALL: [Self; 4] = [
+        Self::Mdns,
+        Self::Websocket,
+        Self::Cache,
+        Self::RequestLogging,
+    ];
+
+    /// Name used in `BEACON_FEATURES`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mdns => "mdns",
+            Self::Websocket => "websocket",
+            Self::Cache => "cache",
+            Self::RequestLogging => "request_logging",
+        }
+    }
+
+    /// Whether the feature is on when nothing is configured
+    fn default_enabled(self) -> bool {
+        !matches!(self, Self::RequestLogging)
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase().replace('-', "_");
+        Self::ALL.into_iter().find(|f| f.as_str() == name)
+    }
+}
+
+/// Features enabled for this launch
+#[derive(Debug, Clone)]
+pub struct FeatureSet {
+    enabled: BTreeSet<Feature>,
+}
+
+impl FeatureSet {
+    /// Resolve the enabled features from the environment
+    pub fn from_env() -> Self {
+        let mut enabled: BTreeSet<Feature> = match std::env::var("BEACON_FEATURES") {
+            Ok(list) => list
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .filter_map(|name| {
+                    let feature = Feature::parse(name);
+                    if feature.is_none() {
+                        tracing::warn!(feature = %name.trim(), "ignoring unknown feature");
+                    }
+                    feature
+                })
+                .collect(),
+            Err(_) => Feature::ALL
+                .into_iter()
+                .filter(|f| f.default_enabled())
+                .collect(),
+        };
+
+        for feature in Feature::ALL {
+            let var = format!("BEACON_FEATURE_{}", feature.as_str().to_ascii_uppercase());
+            let Ok(value) = std::env::var(&var) else {
+                continue;
+            };
+            match value.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => {
+                    enabled.insert(feature);
+                }
+                "0" | "false" | "no" | "off" => {
+                    enabled.remove(&feature);
+                }
+                _ => {
+                    tracing::warn!(var = %var, value = %value, "ignoring invalid feature override")
+                }
+            }
+        }
+
+        Self { enabled }
+    }
+
+    /// Whether `feature` is on
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.enabled.contains(&feature)
+    }
+
+    /// Enabled features, in a stable order
+    pub fn enabled(&self) -> Vec<Feature> {
+        self.enabled.iter().copied().collect()
+    }
+}
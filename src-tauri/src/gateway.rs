@@ -2,128 +2,1632 @@
 //!
 //! Handles starting, stopping, and monitoring the beacon-gateway sidecar
 
-use std::process::{Command, Stdio};
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, LazyLock, Mutex};
 use std::time::Duration;
 
-use crate::{AppState, GatewayState};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
 
-/// How long to wait for gateway to start
-const GATEWAY_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+use crate::decision::{self, Candidate, Outcome};
+use crate::env_profiles::{self, EnvVar};
+use crate::errors::ErrorKind;
+use crate::features::Feature;
+use crate::settings::SavedGateway;
+use crate::{
+    api, discovery, download, logs, pinning, pool, profiles, reconnect, startup, storage, AppState,
+    GatewayState, StartupPhase,
+};
+
+/// How long a sidecar gets to exit after being asked to stop
+#[cfg(unix)]
+const SIDECAR_STOP_GRACE: Duration = Duration::from_secs(2);
+
+/// How long to wait for a killed sidecar to be reaped
+const SIDECAR_KILL_WAIT: Duration = Duration::from_secs(2);
+
+/// How long to wait for gateway to start, until its startup time is known
+pub const GATEWAY_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for a health probe response
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Short probe for the saved URL, which may point at a gateway that moved
+const SAVED_URL_PROBE_TIMEOUT: Duration = Duration::from_millis(750);
+
+/// How long to browse mDNS for a saved gateway at a new address
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
 
 /// Try to connect to an existing gateway or start sidecar
+///
+/// Order: saved gateway URL, configured gateway URL, the saved gateway
+/// rediscovered over mDNS at a new address (unless `discovery_on_startup`
+/// is off), then the sidecar. Each step is recorded in the connection
+/// decision log.
 pub async fn auto_connect(state: Arc<AppState>) {
-    // First, try to connect to configured gateway URL
+    decision::begin(&state).await;
+    if state.settings.read().await.offline_mode {
+        tracing::info!("offline mode active, not connecting");
+        decision::finish(&state, Some("offline mode")).await;
+        return;
+    }
+
+    let saved = state.settings.read().await.saved_gateway.clone();
+
+    // First, try the gateway we last connected to
+    if let Some(saved) = &saved {
+        tracing::info!(url = %saved.url, "checking saved gateway");
+
+        match probe_canonical(&default_client(), &saved.url, SAVED_URL_PROBE_TIMEOUT).await {
+            Ok(url) => {
+                tracing::info!(url = %url, "connected to saved gateway");
+                let detail = (url != saved.url).then(|| format!("redirected to {url}"));
+                decision::record(
+                    &state,
+                    Candidate::SavedGateway,
+                    Some(&saved.url),
+                    Outcome::Connected,
+                    detail,
+                )
+                .await;
+                connect_external(&state, url).await;
+                return;
+            }
+            Err(e) => {
+                tracing::info!(url = %saved.url, "saved gateway unreachable");
+                decision::record(
+                    &state,
+                    Candidate::SavedGateway,
+                    Some(&saved.url),
+                    Outcome::Unreachable,
+                    Some(e),
+                )
+                .await;
+            }
+        }
+    } else {
+        let detail = Some("no saved gateway".to_string());
+        decision::record(
+            &state,
+            Candidate::SavedGateway,
+            None,
+            Outcome::Skipped,
+            detail,
+        )
+        .await;
+    }
+
+    // Then the configured gateway URL
     let url = state.gateway_url.read().await.clone();
 
-    if let Some(url) = url {
-        tracing::info!(url = %url, "checking for existing gateway");
+    match url.filter(|url| saved.as_ref().is_none_or(|s| &s.url != url)) {
+        Some(url) => {
+            tracing::info!(url = %url, "checking for existing gateway");
+
+            match canonical_url(&url).await {
+                Ok(canonical) => {
+                    tracing::info!(url = %canonical, "connected to existing gateway");
+                    let detail = (canonical != url).then(|| format!("redirected to {canonical}"));
+                    decision::record(
+                        &state,
+                        Candidate::ConfiguredUrl,
+                        Some(&url),
+                        Outcome::Connected,
+                        detail,
+                    )
+                    .await;
+                    connect_external(&state, canonical).await;
+                    return;
+                }
+                Err(e) => {
+                    decision::record(
+                        &state,
+                        Candidate::ConfiguredUrl,
+                        Some(&url),
+                        Outcome::Unreachable,
+                        Some(e),
+                    )
+                    .await;
+                }
+            }
+        }
+        None => {
+            let detail = Some("no configured URL other than the saved gateway".to_string());
+            decision::record(
+                &state,
+                Candidate::ConfiguredUrl,
+                None,
+                Outcome::Skipped,
+                detail,
+            )
+            .await;
+        }
+    }
+
+    // The saved gateway may have moved (e.g. new DHCP lease), look for it on the LAN
+    let device_id = saved.as_ref().and_then(|s| s.device_id.as_deref());
+    let mdns = state.features.is_enabled(Feature::Mdns);
+    let on_startup = state.settings.read().await.discovery_on_startup;
+    match device_id {
+        Some(device_id) if mdns && on_startup => {
+            match discovery::find(device_id, None, DISCOVERY_TIMEOUT).await {
+                Ok(Some(found)) => {
+                    let url = found.url();
+                    if probe_gateway(&url).await {
+                        tracing::info!(url = %url, "rediscovered saved gateway at new address");
+                        decision::record(
+                            &state,
+                            Candidate::Discovered,
+                            Some(&url),
+                            Outcome::Connected,
+                            None,
+                        )
+                        .await;
+                        connect_external(&state, url).await;
+                        return;
+                    }
+                    let detail = Some("found over mDNS, but not answering".to_string());
+                    decision::record(
+                        &state,
+                        Candidate::Discovered,
+                        Some(&url),
+                        Outcome::Unreachable,
+                        detail,
+                    )
+                    .await;
+                }
+                Ok(None) => {
+                    tracing::info!("saved gateway not found on the network");
+                    let detail = Some("saved gateway not found on the network".to_string());
+                    decision::record(
+                        &state,
+                        Candidate::Discovered,
+                        None,
+                        Outcome::Unreachable,
+                        detail,
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "gateway discovery failed");
+                    decision::record(
+                        &state,
+                        Candidate::Discovered,
+                        None,
+                        Outcome::Failed,
+                        Some(e),
+                    )
+                    .await;
+                }
+            }
+        }
+        _ => {
+            let detail = if device_id.is_none() {
+                "saved gateway has no device ID"
+            } else if !mdns {
+                "mDNS is disabled"
+            } else {
+                "discovery_on_startup is off"
+            };
+            decision::record(
+                &state,
+                Candidate::Discovered,
+                None,
+                Outcome::Skipped,
+                Some(detail.to_string()),
+            )
+            .await;
+        }
+    }
+
+    // A gateway started by hand is most likely on the default sidecar port
+    if lifecycle(&state).await == GatewayLifecycle::Attached {
+        let url = format!("http://localhost:{DEFAULT_SIDECAR_PORT}");
+        let candidate = Candidate::AttachedGateway;
+        if probe_gateway(&url).await {
+            tracing::info!(url = %url, "connected to attached gateway");
+            decision::record(&state, candidate, Some(&url), Outcome::Connected, None).await;
+            connect_external(&state, url).await;
+        } else {
+            tracing::info!("no gateway found, not starting one as the lifecycle is attached");
+            decision::record(&state, candidate, Some(&url), Outcome::Unreachable, None).await;
+            let detail = Some("the gateway lifecycle is attached".to_string());
+            decision::record(&state, Candidate::Sidecar, None, Outcome::Skipped, detail).await;
+            decision::finish(&state, None).await;
+        }
+        return;
+    }
+
+    // No existing gateway, try to start sidecar
+    tracing::info!("no existing gateway found, attempting to start sidecar");
+    match start_sidecar(&state).await {
+        Ok(()) => {
+            let url = api::base_url(&state).await.ok();
+            decision::record(
+                &state,
+                Candidate::Sidecar,
+                url.as_deref(),
+                Outcome::Connected,
+                None,
+            )
+            .await;
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to start sidecar gateway");
+            let detail = Some(e.to_string());
+            decision::record(&state, Candidate::Sidecar, None, Outcome::Failed, detail).await;
+            decision::finish(&state, None).await;
+            state
+                .set_gateway_state(GatewayState::Failed {
+                    error: e.to_string(),
+                })
+                .await;
+        }
+    }
+}
+
+/// Mark an external gateway as connected and remember it for next launch
+async fn connect_external(state: &AppState, url: String) {
+    state
+        .set_gateway_state(GatewayState::Connected {
+            url: url.clone(),
+            is_sidecar: false,
+        })
+        .await;
+
+    remember_gateway(state, &url).await;
+}
+
+/// Persist an external gateway as the saved gateway
+///
+/// The gateway's device ID is fetched (best effort) so it can be recognized
+/// over mDNS if its address changes.
+pub async fn remember_gateway(state: &AppState, url: &str) {
+    let client = state.client.read().await.clone();
+    let device_id = fetch_gateway_info(&client, url)
+        .await
+        .map(|info| info.device_id);
+
+    let mut settings = state.settings.write().await;
+    settings.saved_gateway = Some(SavedGateway {
+        url: url.to_string(),
+        device_id,
+    });
+
+    if let Err(e) = settings.save(&state.data_dir) {
+        tracing::warn!(error = %e, "failed to persist saved gateway");
+    }
+}
+
+/// Identity info a gateway reports about itself
+#[derive(Debug, Clone, Deserialize)]
+pub struct GatewayInfo {
+    pub device_id: String,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+/// Fetch the gateway's identity from its pairing info endpoint
+pub async fn fetch_gateway_info(client: &reqwest::Client, url: &str) -> Option<GatewayInfo> {
+    let resp = client
+        .get(format!("{url}/api/pair/gateway"))
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .ok()?;
+
+    if !resp.status().is_success() {
+        return None;
+    }
+
+    resp.json::<GatewayInfo>().await.ok()
+}
+
+/// Major and minor of a `major.minor[.patch]` version (a leading `v` is ok)
+pub fn parse_version(version: &str) -> Option<(u64, u64)> {
+    let mut parts = version
+        .trim()
+        .trim_start_matches('v')
+        .split(['.', '-', '+']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// Fetch the gateway's API schema, trying `/openapi.json` then `/schema`
+///
+/// Returns `None` when the gateway exposes neither.
+pub async fn fetch_schema(client: &reqwest::Client, url: &str) -> Option<serde_json::Value> {
+    for path in ["/openapi.json", "/schema"] {
+        let Ok(resp) = client
+            .get(format!("{url}{path}"))
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+        else {
+            continue;
+        };
+
+        if !resp.status().is_success() {
+            continue;
+        }
+
+        match resp.json::<serde_json::Value>().await {
+            Ok(schema) => return Some(schema),
+            Err(e) => tracing::debug!(path, error = %e, "gateway schema is not valid JSON"),
+        }
+    }
+
+    None
+}
+
+/// Persona a sidecar runs unless another one is started
+pub const DEFAULT_PERSONA: &str = "orin";
+
+/// API port of the default persona's sidecar; other personas get the next
+/// free ports after it
+pub const DEFAULT_SIDECAR_PORT: u16 = 18790;
+
+/// Who looks after the gateway process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GatewayLifecycle {
+    /// The app starts, monitors, restarts and stops the sidecar
+    #[default]
+    Managed,
+
+    /// The gateway is started by hand; the app connects to it and watches
+    /// its health, but never starts, restarts or stops the process
+    Attached,
+}
+
+/// A sidecar process this app instance runs
+pub struct Sidecar {
+    pub process: SidecarProcess,
+    pub port: u16,
+
+    /// When it was started (ms since Unix epoch)
+    pub started_ms: u64,
+}
+
+impl Sidecar {
+    pub fn url(&self) -> String {
+        format!("http://localhost:{}", self.port)
+    }
+}
+
+/// Handle on a sidecar process
+pub enum SidecarProcess {
+    /// Launched by this instance
+    Spawned(Child),
+
+    /// Started outside the app and adopted, known only by its PID
+    Adopted(u32),
+}
+
+impl SidecarProcess {
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::Spawned(child) => child.id(),
+            Self::Adopted(pid) => *pid,
+        }
+    }
+
+    /// Exit status, if the process has exited (unknown for an adopted one)
+    fn try_wait(&mut self) -> std::io::Result<Option<String>> {
+        match self {
+            Self::Spawned(child) => Ok(child.try_wait()?.map(|status| format!("{status:?}"))),
+            Self::Adopted(pid) => Ok((!process_alive(*pid)).then(|| "unknown".to_string())),
+        }
+    }
+
+    fn kill(&mut self) {
+        match self {
+            Self::Spawned(child) => {
+                let _ = child.kill();
+            }
+            Self::Adopted(pid) => kill_pid(*pid),
+        }
+    }
+}
+
+/// A running sidecar, as returned by `list_sidecars`
+#[derive(Debug, Clone, Serialize)]
+pub struct SidecarInfo {
+    pub persona: String,
+    pub port: u16,
+    pub url: String,
+    pub pid: u32,
+
+    /// Whether the connection points at this sidecar
+    pub active: bool,
+
+    /// When it was started (ms since Unix epoch)
+    pub started_ms: u64,
+}
+
+/// Start the gateway as a sidecar process for the active persona and
+/// connect to it
+pub async fn start_sidecar(state: &AppState) -> Result<(), String> {
+    let persona = state.active_sidecar.read().await.clone();
+    start_sidecar_for(state, &persona, None, true).await
+}
+
+/// Start a sidecar running `persona`, alongside any others already running
+///
+/// It listens on `port`, or on the default port for the default persona and
+/// the first free one after it for others. With `activate` the connection
+/// moves to it once healthy (the other sidecars keep running); without, it
+/// runs in the background until switched to. A persona that already has a
+/// sidecar isn't started twice, only switched to if `activate`.
+pub async fn start_sidecar_for(
+    state: &AppState,
+    persona: &str,
+    port: Option<u16>,
+    activate: bool,
+) -> Result<(), String> {
+    check_persona(persona)?;
+    ensure_managed(state).await?;
+    if state.sidecars.read().await.contains_key(persona) {
+        return if activate {
+            switch_sidecar(state, persona).await
+        } else {
+            Ok(())
+        };
+    }
+
+    let env_profile = selected_env_profile(state)
+        .await
+        .inspect_err(|e| state.recent_errors.record(ErrorKind::Spawn, e))?;
+    let port = sidecar_port(state, persona, port).await?;
+
+    let started = std::time::Instant::now();
+    let set_phase = |phase| async move {
+        if activate {
+            state
+                .set_gateway_state(GatewayState::Starting { phase, started })
+                .await;
+        }
+    };
+    set_phase(StartupPhase::LocatingBinary).await;
+
+    // Find the gateway binary
+    let binary = find_gateway_binary(&state.data_dir).inspect_err(|e| {
+        state.recent_errors.record(ErrorKind::Spawn, e);
+    })?;
+    let gateway_path = binary.path;
+    tracing::info!(
+        path = %gateway_path.display(),
+        source = ?binary.source,
+        persona,
+        port,
+        "starting gateway sidecar"
+    );
+
+    // Dev paths are relative to our cwd, which the child won't share
+    let gateway_path = std::path::absolute(&gateway_path).unwrap_or(gateway_path);
+
+    let configured_dir = state.settings.read().await.sidecar_working_dir.clone();
+    let working_dir = working_dir(
+        &gateway_path,
+        std::env::var_os("BEACON_GATEWAY_CWD").map(std::path::PathBuf::from),
+        configured_dir,
+    );
+
+    // Start the process
+    set_phase(StartupPhase::Spawning).await;
+    let mut command = Command::new(&gateway_path);
+    if let Some(dir) = &working_dir {
+        tracing::info!(cwd = %dir.display(), "gateway sidecar working directory");
+        command.current_dir(dir);
+    }
+    command
+        .args(["--persona", persona])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut env = vec![EnvVar::plain("BEACON_API_PORT", &port.to_string())];
+    if let Some(model) = &*state.sidecar_model.read().await {
+        env.push(EnvVar::plain("BEACON_MODEL", model));
+    }
+    if let Some(level) = state.settings.read().await.gateway_log_level {
+        env.push(EnvVar::plain("BEACON_LOG", level.as_str()));
+    }
+    // Later entries win, so the env profile overrides the managed variables
+    if let Some((name, vars)) = env_profile {
+        tracing::info!(env_profile = %name, "applying gateway env profile");
+        env.extend(vars);
+    }
+    tracing::debug!(env = ?env_profiles::redacted(&env), "gateway sidecar environment");
+    command.envs(env.iter().map(|var| (&var.name, &var.value)));
+
+    let mut child = command.spawn().map_err(|e| {
+        let error = format!("failed to start gateway: {e}");
+        state.recent_errors.record(ErrorKind::Spawn, &error);
+        error
+    })?;
+
+    let pid = child.id();
+    tracing::info!(pid, persona, "gateway process started");
+
+    // Drain output into the log buffer (also keeps the pipes from filling up)
+    if let Some(stdout) = child.stdout.take() {
+        logs::capture(state.gateway_logs.clone(), "stdout", stdout);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        logs::capture(state.gateway_logs.clone(), "stderr", stderr);
+    }
+
+    // Store the process handle
+    let sidecar = Sidecar {
+        process: SidecarProcess::Spawned(child),
+        port,
+        started_ms: logs::now_ms(),
+    };
+    let url = sidecar.url();
+    state
+        .sidecars
+        .write()
+        .await
+        .insert(persona.to_string(), sidecar);
+    write_pid_file(&state.data_dir, persona, pid, &state.instance_id);
+
+    // Wait for gateway to be ready
+    set_phase(StartupPhase::WaitingForHealth).await;
+    let timeout = startup::timeout(&state.settings.read().await);
+    let ready = wait_for_gateway(&url, timeout).await;
+
+    if ready {
+        let took = started.elapsed();
+        let took_ms = took.as_millis() as u64;
+        tracing::info!(url = %url, persona, took_ms, "gateway sidecar ready");
+        startup::record(state, took).await;
+        if activate {
+            *state.active_sidecar.write().await = persona.to_string();
+            state
+                .set_gateway_state(GatewayState::Connected {
+                    url,
+                    is_sidecar: true,
+                })
+                .await;
+        }
+        Ok(())
+    } else {
+        // Gateway failed to start, clean up
+        stop_process(state, persona).await;
+        let error = "gateway failed to start within timeout".to_string();
+        state.recent_errors.record(ErrorKind::Spawn, &error);
+        if activate {
+            state
+                .set_gateway_state(GatewayState::Failed {
+                    error: error.clone(),
+                })
+                .await;
+        }
+        Err(error)
+    }
+}
+
+/// Directory the sidecar runs in
+///
+/// The binary's own directory, so relative config/model paths resolve,
+/// unless overridden by `BEACON_GATEWAY_CWD` or, after it, the
+/// `sidecar_working_dir` setting.
+fn working_dir(
+    binary: &std::path::Path,
+    env_override: Option<std::path::PathBuf>,
+    configured: Option<std::path::PathBuf>,
+) -> Option<std::path::PathBuf> {
+    env_override
+        .or(configured)
+        .or_else(|| binary.parent().map(std::path::Path::to_path_buf))
+}
+
+/// Point the connection at the running sidecar of `persona`, leaving the
+/// others running
+pub async fn switch_sidecar(state: &AppState, persona: &str) -> Result<(), String> {
+    let url = state
+        .sidecars
+        .read()
+        .await
+        .get(persona)
+        .map(Sidecar::url)
+        .ok_or_else(|| format!("no sidecar running for persona `{persona}`"))?;
+    if !probe_gateway(&url).await {
+        return Err(format!("sidecar for persona `{persona}` is not answering"));
+    }
+
+    tracing::info!(url = %url, persona, "switching to gateway sidecar");
+    *state.active_sidecar.write().await = persona.to_string();
+    state
+        .set_gateway_state(GatewayState::Connected {
+            url,
+            is_sidecar: true,
+        })
+        .await;
+    Ok(())
+}
+
+/// The sidecars this instance runs, by port
+pub async fn list_sidecars(state: &AppState) -> Vec<SidecarInfo> {
+    let active_url = match &*state.gateway_state.read().await {
+        GatewayState::Connected {
+            url,
+            is_sidecar: true,
+        }
+        | GatewayState::Restarting {
+            url,
+            is_sidecar: true,
+            ..
+        } => Some(url.clone()),
+        _ => None,
+    };
+
+    let mut sidecars: Vec<SidecarInfo> = state
+        .sidecars
+        .read()
+        .await
+        .iter()
+        .map(|(persona, sidecar)| {
+            let url = sidecar.url();
+            SidecarInfo {
+                persona: persona.clone(),
+                port: sidecar.port,
+                active: active_url.as_deref() == Some(url.as_str()),
+                url,
+                pid: sidecar.process.id(),
+                started_ms: sidecar.started_ms,
+            }
+        })
+        .collect();
+    sidecars.sort_by_key(|sidecar| sidecar.port);
+    sidecars
+}
+
+/// Whether the active persona's sidecar is running
+pub async fn sidecar_running(state: &AppState) -> bool {
+    let persona = state.active_sidecar.read().await.clone();
+    state.sidecars.read().await.contains_key(&persona)
+}
+
+/// Persona names end up in PID file names, so only a safe set is allowed
+fn check_persona(persona: &str) -> Result<(), String> {
+    let valid = !persona.is_empty()
+        && persona.len() <= 64
+        && persona
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid persona name `{persona}`, use letters, digits, `-` and `_`"
+        ))
+    }
+}
+
+/// Port for a new sidecar of `persona`
+async fn sidecar_port(
+    state: &AppState,
+    persona: &str,
+    requested: Option<u16>,
+) -> Result<u16, String> {
+    let sidecars = state.sidecars.read().await;
+    let taken = |port: u16| sidecars.values().any(|sidecar| sidecar.port == port);
+
+    if let Some(port) = requested {
+        if taken(port) {
+            return Err(format!("port {port} is already used by another sidecar"));
+        }
+        return Ok(port);
+    }
+    if persona == DEFAULT_PERSONA && !taken(DEFAULT_SIDECAR_PORT) {
+        return Ok(DEFAULT_SIDECAR_PORT);
+    }
+
+    (DEFAULT_SIDECAR_PORT + 1..=u16::MAX)
+        .find(|port| !taken(*port) && std::net::TcpListener::bind(("127.0.0.1", *port)).is_ok())
+        .ok_or_else(|| "no free port for another sidecar".to_string())
+}
+
+/// Name and variables of the env profile selected in settings, if any
+async fn selected_env_profile(state: &AppState) -> Result<Option<(String, Vec<EnvVar>)>, String> {
+    let settings = state.settings.read().await;
+    let Some(name) = &settings.env_profile else {
+        return Ok(None);
+    };
+
+    let profile = settings
+        .env_profiles
+        .get(name)
+        .ok_or_else(|| format!("env profile not found: {name}"))?;
+    Ok(Some((name.clone(), profile.resolve(name)?)))
+}
+
+/// Stop the active persona's sidecar and disconnect
+///
+/// Sidecars of other personas keep running.
+pub async fn stop_sidecar(state: &AppState) {
+    let persona = state.active_sidecar.read().await.clone();
+    stop_process(state, &persona).await;
+    state.set_gateway_state(GatewayState::Disconnected).await;
+}
+
+/// Stop every sidecar and disconnect
+pub async fn stop_all_sidecars(state: &AppState) {
+    let personas: Vec<String> = state.sidecars.read().await.keys().cloned().collect();
+    for persona in personas {
+        stop_process(state, &persona).await;
+    }
+    // The active one may have no process left but still a PID file
+    let active = state.active_sidecar.read().await.clone();
+    remove_pid_file(&state.data_dir, &active, &state.instance_id);
+    state.set_gateway_state(GatewayState::Disconnected).await;
+}
+
+/// Stop the sidecar of `persona`
+///
+/// Stopping the active one disconnects; stopping another leaves the
+/// connection alone. Refused if another app instance owns it.
+pub async fn stop_sidecar_by_persona(state: &AppState, persona: &str) -> Result<(), String> {
+    ensure_persona_owned(state, persona).await?;
+    if !state.sidecars.read().await.contains_key(persona) {
+        return Err(format!("no sidecar running for persona `{persona}`"));
+    }
+
+    let active = list_sidecars(state)
+        .await
+        .iter()
+        .any(|sidecar| sidecar.active && sidecar.persona == persona);
+    if active {
+        stop_sidecar(state).await;
+    } else {
+        stop_process(state, persona).await;
+    }
+    Ok(())
+}
+
+/// Stop the sidecar process of `persona`, leaving the gateway state alone
+async fn stop_process(state: &AppState, persona: &str) {
+    let sidecar = state.sidecars.write().await.remove(persona);
+    if let Some(Sidecar { mut process, .. }) = sidecar {
+        tracing::info!(persona, "stopping gateway sidecar");
+        terminate(&mut process, persona).await;
+        tracing::info!(persona, "gateway sidecar stopped");
+    }
+
+    remove_pid_file(&state.data_dir, persona, &state.instance_id);
+}
+
+/// Ask a sidecar process to shut down, then kill it
+///
+/// Sends SIGTERM on Unix and waits up to [`SIDECAR_STOP_GRACE`] for it to
+/// exit, then kills it regardless, so a gateway that ignores the request
+/// can't leave us half-stopped.
+async fn terminate(process: &mut SidecarProcess, persona: &str) {
+    #[cfg(unix)]
+    {
+        let _ = Command::new("kill")
+            .args(["-TERM", &process.id().to_string()])
+            .status();
+        if !reap(process, SIDECAR_STOP_GRACE).await {
+            tracing::warn!(persona, "gateway sidecar ignored SIGTERM, killing it");
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = persona;
+
+    // Force kill if still running
+    kill_process(process).await;
+}
+
+/// Gracefully stop the active sidecar and start it again
+///
+/// Refused if another app instance owns the running sidecar.
+pub async fn restart_sidecar(state: &AppState) -> Result<(), String> {
+    ensure_managed(state).await?;
+    ensure_sidecar_owned(state).await?;
+    stop_sidecar(state).await;
+    start_sidecar(state).await
+}
+
+/// Kill every sidecar immediately, skipping graceful shutdown
+///
+/// The recovery path for a wedged sidecar: always ends `Disconnected` with
+/// the PID files gone, whatever state the processes were in.
+pub async fn force_stop_sidecar(state: &AppState) {
+    let sidecars: Vec<(String, Sidecar)> = state.sidecars.write().await.drain().collect();
+    for (persona, mut sidecar) in sidecars {
+        let pid = sidecar.process.id();
+        tracing::warn!(pid, persona, "force-stopping gateway sidecar");
+        kill_process(&mut sidecar.process).await;
+        remove_pid_file(&state.data_dir, &persona, &state.instance_id);
+    }
+
+    let active = state.active_sidecar.read().await.clone();
+    remove_pid_file(&state.data_dir, &active, &state.instance_id);
+    state.set_gateway_state(GatewayState::Disconnected).await;
+}
+
+/// Kill a sidecar process and wait (bounded) for it to be reaped
+async fn kill_process(process: &mut SidecarProcess) {
+    process.kill();
+    if !reap(process, SIDECAR_KILL_WAIT).await {
+        let pid = process.id();
+        tracing::error!(pid, "gateway sidecar did not exit after kill");
+    }
+}
+
+/// Wait up to `timeout` for a sidecar process to exit, returning whether it
+/// did
+///
+/// Polls instead of calling the blocking `Child::wait`, which could hang
+/// the caller forever on a process that won't die.
+async fn reap(process: &mut SidecarProcess, timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        match process.try_wait() {
+            Ok(Some(_)) | Err(_) => return true,
+            Ok(None) if tokio::time::Instant::now() >= deadline => return false,
+            Ok(None) => tokio::time::sleep(Duration::from_millis(50)).await,
+        }
+    }
+}
+
+/// Path of the file holding the PID of `persona`'s sidecar
+///
+/// The PID is followed on a second line by the ID of the app instance that
+/// launched it, so instances sharing a data directory can tell whose it is.
+/// The default persona keeps the original `gateway.pid`; each other persona
+/// gets its own file.
+fn pid_file_path(data_dir: &std::path::Path, persona: &str) -> std::path::PathBuf {
+    if persona == DEFAULT_PERSONA {
+        data_dir.join("gateway.pid")
+    } else {
+        data_dir.join(format!("gateway-{persona}.pid"))
+    }
+}
+
+fn write_pid_file(data_dir: &std::path::Path, persona: &str, pid: u32, instance_id: &str) {
+    let path = pid_file_path(data_dir, persona);
+    if let Err(e) = std::fs::write(path, format!("{pid}\n{instance_id}\n")) {
+        tracing::warn!(error = %e, persona, "failed to write gateway PID file");
+    }
+}
+
+/// Read the PID file's PID and owner marker (absent in files written
+/// before markers were added)
+fn read_pid_file(data_dir: &std::path::Path, persona: &str) -> Option<(u32, Option<String>)> {
+    let contents = std::fs::read_to_string(pid_file_path(data_dir, persona)).ok()?;
+    let mut lines = contents.lines().map(str::trim);
+    let pid = lines.next()?.parse().ok()?;
+    let owner = lines.next().filter(|id| !id.is_empty()).map(str::to_string);
+    Some((pid, owner))
+}
+
+/// Remove the PID file, unless it marks a sidecar of another instance
+fn remove_pid_file(data_dir: &std::path::Path, persona: &str, instance_id: &str) {
+    if let Some((_, Some(owner))) = read_pid_file(data_dir, persona) {
+        if owner != instance_id {
+            return;
+        }
+    }
+
+    match std::fs::remove_file(pid_file_path(data_dir, persona)) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!(error = %e, persona, "failed to remove gateway PID file"),
+    }
+}
+
+/// Whether a process with this PID is running
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Whether a process with this PID is running
+#[cfg(windows)]
+fn process_alive(pid: u32) -> bool {
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/NH"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+}
+
+/// Whether a process with this PID is running (assumed so where we can't check)
+#[cfg(not(any(unix, windows)))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+/// Kill a process known only by its PID
+#[cfg(unix)]
+fn kill_pid(pid: u32) {
+    let _ = Command::new("kill")
+        .args(["-KILL", &pid.to_string()])
+        .status();
+}
+
+/// Kill a process known only by its PID
+#[cfg(windows)]
+fn kill_pid(pid: u32) {
+    let _ = Command::new("taskkill")
+        .args(["/F", "/PID", &pid.to_string()])
+        .status();
+}
+
+/// Kill a process known only by its PID (not possible here)
+#[cfg(not(any(unix, windows)))]
+fn kill_pid(_pid: u32) {}
+
+/// PID of the process listening on TCP `port` on this machine
+#[cfg(unix)]
+fn listener_pid(port: u16) -> Option<u32> {
+    let output = Command::new("lsof")
+        .args(["-nP", "-t", &format!("-iTCP:{port}"), "-sTCP:LISTEN"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().parse().ok())
+}
+
+/// PID of the process listening on TCP `port` on this machine
+#[cfg(windows)]
+fn listener_pid(port: u16) -> Option<u32> {
+    let output = Command::new("netstat")
+        .args(["-ano", "-p", "TCP"])
+        .output()
+        .ok()?;
+    let suffix = format!(":{port}");
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [_, local, _, "LISTENING", pid] if local.ends_with(&suffix) => pid.parse().ok(),
+                _ => None,
+            }
+        })
+}
+
+/// PID of the process listening on TCP `port` (unknown here)
+#[cfg(not(any(unix, windows)))]
+fn listener_pid(_port: u16) -> Option<u32> {
+    None
+}
+
+/// Who owns the running sidecar
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SidecarOwner {
+    /// This app instance launched it
+    ThisInstance,
+
+    /// Another app instance launched it (or one that didn't mark ownership)
+    OtherInstance,
+
+    /// No sidecar is running
+    None,
+}
+
+/// Ownership of the running sidecar
+#[derive(Debug, Clone, Serialize)]
+pub struct Ownership {
+    /// Persona whose sidecar this is
+    pub persona: String,
+
+    pub owner: SidecarOwner,
+
+    /// Sidecar PID, if one is running
+    pub pid: Option<u32>,
+
+    /// ID of the owning instance, if known
+    pub instance_id: Option<String>,
+
+    /// ID of this app instance
+    pub this_instance_id: String,
+}
+
+/// Work out who owns the active persona's sidecar
+pub async fn sidecar_ownership(state: &AppState) -> Ownership {
+    let persona = state.active_sidecar.read().await.clone();
+    persona_ownership(state, &persona).await
+}
+
+/// Work out who owns the sidecar of `persona` from our process handles and
+/// its PID file, ignoring a stale PID file whose process has exited
+pub async fn persona_ownership(state: &AppState, persona: &str) -> Ownership {
+    let this_instance_id = state.instance_id.clone();
+
+    if let Some(sidecar) = state.sidecars.read().await.get(persona) {
+        return Ownership {
+            persona: persona.to_string(),
+            owner: SidecarOwner::ThisInstance,
+            pid: Some(sidecar.process.id()),
+            instance_id: Some(this_instance_id.clone()),
+            this_instance_id,
+        };
+    }
+
+    match read_pid_file(&state.data_dir, persona) {
+        Some((pid, instance_id)) if process_alive(pid) => {
+            let owner = if instance_id.as_deref() == Some(this_instance_id.as_str()) {
+                SidecarOwner::ThisInstance
+            } else {
+                SidecarOwner::OtherInstance
+            };
+            Ownership {
+                persona: persona.to_string(),
+                owner,
+                pid: Some(pid),
+                instance_id,
+                this_instance_id,
+            }
+        }
+        _ => Ownership {
+            persona: persona.to_string(),
+            owner: SidecarOwner::None,
+            pid: None,
+            instance_id: None,
+            this_instance_id,
+        },
+    }
+}
+
+/// Fail if the active persona's sidecar belongs to another app instance
+pub async fn ensure_sidecar_owned(state: &AppState) -> Result<(), String> {
+    let persona = state.active_sidecar.read().await.clone();
+    ensure_persona_owned(state, &persona).await
+}
+
+/// Fail if the sidecar of `persona` belongs to another app instance
+pub async fn ensure_persona_owned(state: &AppState, persona: &str) -> Result<(), String> {
+    let ownership = persona_ownership(state, persona).await;
+    if ownership.owner != SidecarOwner::OtherInstance {
+        return Ok(());
+    }
+
+    let pid = ownership.pid.unwrap_or_default();
+    let owner = ownership
+        .instance_id
+        .map(|id| format!(" ({id})"))
+        .unwrap_or_default();
+    Err(format!(
+        "gateway sidecar (pid {pid}) is owned by another app instance{owner}"
+    ))
+}
+
+/// Current gateway lifecycle mode
+pub async fn lifecycle(state: &AppState) -> GatewayLifecycle {
+    state.settings.read().await.gateway_lifecycle
+}
+
+/// Fail if the gateway lifecycle is attached, where the app doesn't start
+/// or restart the gateway
+async fn ensure_managed(state: &AppState) -> Result<(), String> {
+    if lifecycle(state).await == GatewayLifecycle::Attached {
+        return Err("gateway lifecycle is attached, start and stop it by hand".to_string());
+    }
+    Ok(())
+}
+
+/// Switch between managing the gateway process and leaving it alone,
+/// persisting the mode
+///
+/// Going `attached`, the sidecars this instance runs are released: they keep
+/// running but are no longer tracked, restarted or stopped on exit, and the
+/// connection stays up as a plain one. Going `managed`, a local gateway the
+/// connection points at is adopted as the active persona's sidecar: its
+/// process is found by the port it listens on and tracked (with a PID file)
+/// as if the app had launched it. A remote gateway is left as it is. Refused
+/// while the gateway is starting or restarting, which would leave the
+/// connection on the other side of the switch.
+pub async fn set_lifecycle(state: &AppState, mode: GatewayLifecycle) -> Result<(), String> {
+    if lifecycle(state).await == mode {
+        return Ok(());
+    }
+    match &*state.gateway_state.read().await {
+        GatewayState::Starting { .. } | GatewayState::Restarting { .. } => {
+            return Err("gateway is starting, switch once it's up".to_string());
+        }
+        _ => {}
+    }
+
+    // Adopt first, so a gateway that can't be adopted leaves the mode as it was
+    if mode == GatewayLifecycle::Managed {
+        adopt_gateway(state).await?;
+    }
+    {
+        let mut settings = state.settings.write().await;
+        settings.gateway_lifecycle = mode;
+        settings.save(&state.data_dir)?;
+    }
+    if mode == GatewayLifecycle::Attached {
+        release_sidecars(state).await;
+    }
+
+    tracing::info!(mode = ?mode, "gateway lifecycle changed");
+    Ok(())
+}
+
+/// Stop tracking every sidecar without stopping it, keeping the connection
+/// up as a plain one
+async fn release_sidecars(state: &AppState) {
+    let released: Vec<(String, Sidecar)> = state.sidecars.write().await.drain().collect();
+    for (persona, sidecar) in &released {
+        let pid = sidecar.process.id();
+        tracing::info!(persona, pid, "releasing gateway sidecar");
+        remove_pid_file(&state.data_dir, persona, &state.instance_id);
+    }
+    // Dropping the handles leaves the processes running
+    drop(released);
+
+    let current = state.gateway_state.read().await.clone();
+    if let GatewayState::Connected {
+        url,
+        is_sidecar: true,
+    } = current
+    {
+        state
+            .set_gateway_state(GatewayState::Connected {
+                url,
+                is_sidecar: false,
+            })
+            .await;
+    }
+}
+
+/// Adopt the local gateway the connection points at as the active persona's
+/// sidecar
+async fn adopt_gateway(state: &AppState) -> Result<(), String> {
+    let url = match &*state.gateway_state.read().await {
+        GatewayState::Connected {
+            url,
+            is_sidecar: false,
+        } => url.clone(),
+        _ => return Ok(()),
+    };
+    let Some(port) = local_port(&url) else {
+        tracing::info!(url = %url, "gateway is not a local sidecar, leaving it unmanaged");
+        return Ok(());
+    };
+
+    let persona = state.active_sidecar.read().await.clone();
+    ensure_persona_owned(state, &persona).await?;
+    if state.sidecars.read().await.contains_key(&persona) {
+        return Err(format!(
+            "a sidecar is already running for persona `{persona}`"
+        ));
+    }
+    let pid = listener_pid(port)
+        .ok_or_else(|| format!("could not find the gateway process listening on port {port}"))?;
+
+    tracing::info!(url = %url, pid, persona, "adopting gateway as sidecar");
+    let sidecar = Sidecar {
+        process: SidecarProcess::Adopted(pid),
+        port,
+        started_ms: logs::now_ms(),
+    };
+    let url = sidecar.url();
+    state
+        .sidecars
+        .write()
+        .await
+        .insert(persona.clone(), sidecar);
+    write_pid_file(&state.data_dir, &persona, pid, &state.instance_id);
+    state
+        .set_gateway_state(GatewayState::Connected {
+            url,
+            is_sidecar: true,
+        })
+        .await;
+    Ok(())
+}
+
+/// Port of a plain-HTTP gateway URL on this machine, as sidecars are served
+fn local_port(url: &str) -> Option<u16> {
+    let parsed = reqwest::Url::parse(url).ok()?;
+    if parsed.scheme() != "http" {
+        return None;
+    }
+
+    let host = parsed.host_str()?;
+    let bare = host.trim_start_matches('[').trim_end_matches(']');
+    let local = bare == "localhost" || bare.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+    local.then(|| parsed.port_or_known_default()).flatten()
+}
+
+/// Probe gateway to check if it's running
+pub async fn probe_gateway(url: &str) -> bool {
+    canonical_url(url).await.is_ok()
+}
+
+/// Probe gateway using a preconfigured client (e.g. one carrying auth headers)
+pub async fn probe_with_client(client: &reqwest::Client, url: &str) -> bool {
+    probe_canonical(client, url, PROBE_TIMEOUT).await.is_ok()
+}
+
+/// Probe gateway and return its canonical URL
+///
+/// If `/health` was redirected (e.g. by a reverse proxy upgrading to https),
+/// the URL it ended up at is returned so callers can go there directly.
+pub async fn canonical_url(url: &str) -> Result<String, String> {
+    probe_canonical(&default_client(), url, PROBE_TIMEOUT).await
+}
+
+/// Probe gateway with a given client and timeout, returning its canonical URL
+///
+/// Probes with `HEAD /health` to spare the gateway sending a body, falling
+/// back to `GET` for gateways that answer 405. Which one works is remembered
+/// per gateway, so later probes go straight to it.
+pub async fn probe_canonical(
+    client: &reqwest::Client,
+    url: &str,
+    timeout: Duration,
+) -> Result<String, String> {
+    let health_url = format!("{url}/health");
+    let send = |method: Method| client.request(method, &health_url).timeout(timeout).send();
+    let connect_error = |e: reqwest::Error| {
+        if e.is_redirect() {
+            format!("gateway at {url} redirected to a different host, refusing to follow")
+        } else if pinning::is_pin_mismatch(&e) {
+            format!("certificate pin mismatch: gateway at {url} presented another certificate")
+        } else if is_tls_version_error(&e) {
+            format!("TLS version too low: gateway at {url} doesn't support the minimum TLS version")
+        } else {
+            format!("failed to connect to gateway at {url}")
+        }
+    };
+
+    let method = health_method(url);
+    let mut resp = send(method.clone()).await.map_err(connect_error)?;
+    if method == Method::HEAD {
+        if resp.status() == StatusCode::METHOD_NOT_ALLOWED {
+            tracing::debug!(url = %url, "gateway doesn't allow HEAD /health, probing with GET");
+            remember_health_method(url, Method::GET);
+            resp = send(Method::GET).await.map_err(connect_error)?;
+        } else if resp.status().is_success() {
+            remember_health_method(url, Method::HEAD);
+        }
+    }
+
+    if !resp.status().is_success() {
+        return Err(format!("gateway at {url} returned {}", resp.status()));
+    }
+
+    let canonical = resp
+        .url()
+        .as_str()
+        .strip_suffix("/health")
+        .unwrap_or(url)
+        .trim_end_matches('/')
+        .to_string();
+
+    if canonical != url {
+        tracing::info!(from = %url, to = %canonical, "gateway redirected to canonical URL");
+    }
+
+    Ok(canonical)
+}
+
+/// Health probe method known to work per gateway URL
+static HEALTH_METHODS: LazyLock<Mutex<HashMap<String, Method>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
 
-        if probe_gateway(&url).await {
-            tracing::info!(url = %url, "connected to existing gateway");
-            *state.gateway_state.write().await = GatewayState::Connected {
-                url,
-                is_sidecar: false,
-            };
-            return;
-        }
+/// Gateways remembered before the method cache starts over
+const MAX_HEALTH_METHODS: usize = 64;
+
+/// Method to probe `url` with (`HEAD` until it turns out not to work)
+fn health_method(url: &str) -> Method {
+    HEALTH_METHODS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(url)
+        .cloned()
+        .unwrap_or(Method::HEAD)
+}
+
+fn remember_health_method(url: &str, method: Method) {
+    let mut methods = HEALTH_METHODS.lock().unwrap_or_else(|e| e.into_inner());
+    if methods.len() >= MAX_HEALTH_METHODS && !methods.contains_key(url) {
+        methods.clear();
     }
+    methods.insert(url.to_string(), method);
+}
 
-    // No existing gateway, try to start sidecar
-    tracing::info!("no existing gateway found, attempting to start sidecar");
-    if let Err(e) = start_sidecar(&state).await {
-        tracing::warn!(error = %e, "failed to start sidecar gateway");
-        *state.gateway_state.write().await = GatewayState::Failed {
-            error: e.to_string(),
+/// Redirects allowed for gateway requests
+///
+/// Only redirects that stay on the same host are followed, optionally
+/// upgrading http to https. Cross-host redirects and https downgrades are
+/// rejected so a proxy can't bounce gateway traffic (and tokens) elsewhere.
+fn redirect_policy() -> reqwest::redirect::Policy {
+    const MAX_REDIRECTS: usize = 5;
+
+    reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+
+        let Some(origin) = attempt.previous().first() else {
+            return attempt.follow();
         };
-    }
+
+        if is_allowed_redirect(origin, attempt.url()) {
+            attempt.follow()
+        } else {
+            let error = format!("refusing redirect from {origin} to {}", attempt.url());
+            attempt.error(error)
+        }
+    })
 }
 
-/// Start the gateway as a sidecar process
-pub async fn start_sidecar(state: &AppState) -> Result<(), String> {
-    *state.gateway_state.write().await = GatewayState::Starting;
+/// Whether a redirect stays on the same host (with at most an https upgrade)
+fn is_allowed_redirect(from: &reqwest::Url, to: &reqwest::Url) -> bool {
+    let same_host = match (from.host_str(), to.host_str()) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        _ => false,
+    };
+    let scheme_ok =
+        from.scheme() == to.scheme() || (from.scheme() == "http" && to.scheme() == "https");
 
-    // Find the gateway binary
-    let gateway_path = find_gateway_binary()?;
-    tracing::info!(path = %gateway_path.display(), "starting gateway sidecar");
+    same_host && scheme_ok
+}
 
-    // Start the process
-    let child = Command::new(&gateway_path)
-        .args(["--persona", "orin"])
-        .env("BEACON_API_PORT", "18790")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("failed to start gateway: {e}"))?;
+/// Client builder with the settings every gateway client shares
+fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder()
+        .redirect(redirect_policy())
+        .user_agent(default_user_agent())
+        .min_tls_version(TlsVersion::default().to_reqwest());
+    pool::apply(builder)
+}
 
-    let pid = child.id();
-    tracing::info!(pid, "gateway process started");
+/// Oldest TLS version a gateway connection may use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TlsVersion {
+    #[default]
+    #[serde(rename = "1.2")]
+    Tls12,
 
-    // Store the process handle
-    *state.sidecar_process.write().await = Some(child);
+    #[serde(rename = "1.3")]
+    Tls13,
+}
 
-    // Wait for gateway to be ready
-    let url = "http://localhost:18790".to_string();
-    let ready = wait_for_gateway(&url, GATEWAY_STARTUP_TIMEOUT).await;
+impl TlsVersion {
+    fn to_reqwest(self) -> reqwest::tls::Version {
+        match self {
+            Self::Tls12 => reqwest::tls::Version::TLS_1_2,
+            Self::Tls13 => reqwest::tls::Version::TLS_1_3,
+        }
+    }
+}
 
-    if ready {
-        tracing::info!(url = %url, "gateway sidecar ready");
-        *state.gateway_state.write().await = GatewayState::Connected {
-            url,
-            is_sidecar: true,
-        };
-        Ok(())
-    } else {
-        // Gateway failed to start, clean up
-        stop_sidecar(state).await;
-        *state.gateway_state.write().await = GatewayState::Failed {
-            error: "gateway failed to start within timeout".to_string(),
-        };
-        Err("gateway failed to start within timeout".to_string())
+impl std::fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tls12 => f.write_str("TLS 1.2"),
+            Self::Tls13 => f.write_str("TLS 1.3"),
+        }
     }
 }
 
-/// Stop the sidecar process
-pub async fn stop_sidecar(state: &AppState) {
-    let mut process = state.sidecar_process.write().await;
-    if let Some(mut child) = process.take() {
-        tracing::info!("stopping gateway sidecar");
+/// Whether `error` is a TLS handshake failing because the two sides have no
+/// protocol version in common, rather than any other handshake failure
+///
+/// TLS backends only say so in their messages (OpenSSL, rustls, Secure
+/// Transport and SChannel each in their own words), so those are matched.
+pub fn is_tls_version_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    const MARKERS: &[&str] = &[
+        "protocol version",
+        "protocolversion",
+        "unsupported protocol",
+        "no protocols available",
+        "do not possess a common algorithm",
+    ];
 
-        // Try graceful shutdown first (SIGTERM on Unix)
-        #[cfg(unix)]
-        {
-            let _ = Command::new("kill")
-                .args(["-TERM", &child.id().to_string()])
-                .status();
-            tokio::time::sleep(Duration::from_secs(2)).await;
+    let mut source = Some(error);
+    while let Some(e) = source {
+        let message = e.to_string().to_lowercase();
+        if MARKERS.iter().any(|m| message.contains(m)) {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// User-Agent sent to gateways unless a profile overrides it
+pub fn default_user_agent() -> String {
+    format!(
+        "beacon-app/{} ({}; {})",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
+
+/// Gateway client without auth headers
+pub fn default_client() -> reqwest::Client {
+    client_builder().build().unwrap_or_default()
+}
+
+/// Replace the live client with a fresh one for the active profile (the
+/// default client if none), dropping its pooled connections
+pub async fn rebuild_client(state: &AppState) -> Result<(), String> {
+    let active_profile = state.active_profile.read().await.clone();
+    let client = match active_profile.and_then(|name| profiles::find(&state.data_dir, &name)) {
+        Some(profile) => {
+            let token = storage::get(&profiles::token_key(&profile.name))?;
+            profiles::client(&profile, token.as_deref())?
         }
+        None => default_client(),
+    };
+    *state.client.write().await = client;
+    Ok(())
+}
+
+/// Normalize a user-entered gateway URL
+///
+/// Adds `http://` when no scheme is given and strips trailing slashes. The
+/// host is passed through untouched: bare hostnames such as Tailscale
+/// MagicDNS names (`my-server`) are as valid as IP literals.
+pub fn normalize_gateway_url(url: &str) -> Result<String, String> {
+    let trimmed = url.trim().trim_end_matches('/');
+    let normalized = if trimmed.contains("://") {
+        trimmed.to_string()
+    } else {
+        format!("http://{trimmed}")
+    };
 
-        // Force kill if still running
-        let _ = child.kill();
-        let _ = child.wait();
+    let parsed = reqwest::Url::parse(&normalized)
+        .map_err(|e| format!("invalid gateway URL `{url}`: {e}"))?;
 
-        tracing::info!("gateway sidecar stopped");
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!(
+            "unsupported gateway URL scheme `{}`",
+            parsed.scheme()
+        ));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(format!("gateway URL `{url}` has no host"));
     }
 
-    *state.gateway_state.write().await = GatewayState::Disconnected;
+    Ok(normalized)
 }
 
-/// Probe gateway to check if it's running
-pub async fn probe_gateway(url: &str) -> bool {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(2))
+/// Resolve the host of a gateway URL through the system resolver
+///
+/// Fails with a clear message when the name yields no address, which for a
+/// MagicDNS name usually means the tunnel is down or the short name isn't
+/// searchable; the full `<name>.<tailnet>.ts.net` name is the fallback.
+pub async fn resolve_gateway_host(url: &str) -> Result<Vec<SocketAddr>, String> {
+    let parsed =
+        reqwest::Url::parse(url).map_err(|e| format!("invalid gateway URL `{url}`: {e}"))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("gateway URL `{url}` has no host"))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = parsed.port_or_known_default().unwrap_or(80);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map(Iterator::collect)
+        .unwrap_or_default();
+
+    if addrs.is_empty() {
+        return Err(format!(
+            "could not resolve gateway host `{host}`; for a Tailscale MagicDNS name, \
+             check Tailscale is connected or use the full `{host}.<tailnet>.ts.net` name"
+        ));
+    }
+
+    Ok(addrs)
+}
+
+/// Build the HTTP client used for gateway requests
+///
+/// When a token is given it is attached as a bearer `Authorization` header
+/// to every request made with the client. Extra headers are attached the same
+/// way, and all values are marked sensitive so they never show up in debug
+/// output. A CA bundle, if given, is trusted in addition to the system roots.
+/// A local address pins outgoing connections to that interface, and a
+/// User-Agent replaces [`default_user_agent`]. Handshakes below
+/// `min_tls_version` are refused; TLS 1.3 only is enforced with rustls, since
+/// the platform TLS libraries can't be limited to it. A pinned certificate
+/// fingerprint replaces certificate verification (and the CA bundle) with
+/// [`pinning`].
+pub fn build_client(
+    token: Option<&str>,
+    extra_headers: &BTreeMap<String, String>,
+    ca_bundle: Option<&std::path::Path>,
+    local_address: Option<IpAddr>,
+    user_agent: Option<&str>,
+    min_tls_version: TlsVersion,
+    pinned_cert_sha256: Option<&str>,
+) -> Result<reqwest::Client, String> {
+    let mut headers = reqwest::header::HeaderMap::new();
+
+    for (name, value) in extra_headers {
+        let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("invalid header name `{name}`"))?;
+        let mut value = reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| format!("header `{name}` contains invalid characters"))?;
+        value.set_sensitive(true);
+        headers.insert(name, value);
+    }
+
+    if let Some(token) = token {
+        let mut value = reqwest::header::HeaderValue::from_str(&format!("Bearer {token}"))
+            .map_err(|_| "gateway token contains invalid characters".to_string())?;
+        value.set_sensitive(true);
+        headers.insert(reqwest::header::AUTHORIZATION, value);
+    }
+
+    let mut builder = client_builder().default_headers(headers);
+
+    if let Some(user_agent) = user_agent {
+        check_user_agent(user_agent)?;
+        builder = builder.user_agent(user_agent);
+    }
+
+    if let Some(path) = ca_bundle {
+        let pem = std::fs::read(path)
+            .map_err(|e| format!("failed to read CA bundle {}: {e}", path.display()))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("invalid CA bundle {}: {e}", path.display()))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if let Some(addr) = local_address {
+        check_local_address(addr)?;
+        builder = builder.local_address(addr);
+    }
+
+    if let Some(pin) = pinned_cert_sha256 {
+        builder = builder.use_preconfigured_tls(pinning::client_config(pin, min_tls_version)?);
+    } else {
+        if min_tls_version == TlsVersion::Tls13 {
+            builder = builder.use_rustls_tls();
+        }
+        builder = builder.min_tls_version(min_tls_version.to_reqwest());
+    }
+
+    builder
         .build()
-        .ok();
+        .map_err(|e| format!("failed to build http client: {e}"))
+}
 
-    let Some(client) = client else {
-        return false;
-    };
+/// Check that `addr` is assigned to one of this machine's interfaces
+///
+/// Binding a throwaway socket is the portable way to ask the OS; it fails
+/// with "address not available" for addresses no interface has.
+pub fn check_local_address(addr: IpAddr) -> Result<(), String> {
+    std::net::UdpSocket::bind(SocketAddr::new(addr, 0))
+        .map(drop)
+        .map_err(|e| format!("{addr} is not an address of any network interface: {e}"))
+}
 
-    let health_url = format!("{url}/health");
-    match client.get(&health_url).send().await {
-        Ok(resp) => resp.status().is_success(),
-        Err(_) => false,
+/// Check that `user_agent` can be sent as a User-Agent header
+pub fn check_user_agent(user_agent: &str) -> Result<(), String> {
+    if user_agent.trim().is_empty() {
+        return Err("User-Agent must not be empty".to_string());
     }
+    reqwest::header::HeaderValue::from_str(user_agent)
+        .map(drop)
+        .map_err(|_| "User-Agent contains invalid characters".to_string())
 }
 
 /// Wait for gateway to become ready
@@ -141,15 +1645,48 @@ async fn wait_for_gateway(url: &str, timeout: Duration) -> bool {
     false
 }
 
+/// Where the gateway binary was found
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinarySource {
+    /// `BEACON_GATEWAY_PATH`
+    Env,
+
+    /// Shipped with the app (next to it, or in its Resources)
+    Bundled,
+
+    /// Downloaded during onboarding
+    Downloaded,
+
+    /// Found on the system `PATH`
+    SystemPath,
+
+    /// A local development build
+    DevBuild,
+}
+
+/// A resolved gateway binary
+#[derive(Debug, Clone, Serialize)]
+pub struct BinarySourceInfo {
+    pub path: std::path::PathBuf,
+    pub source: BinarySource,
+}
+
+impl BinarySourceInfo {
+    fn new(path: std::path::PathBuf, source: BinarySource) -> Self {
+        Self { path, source }
+    }
+}
+
 /// Find the gateway binary
-fn find_gateway_binary() -> Result<std::path::PathBuf, String> {
+pub fn find_gateway_binary(data_dir: &std::path::Path) -> Result<BinarySourceInfo, String> {
     // Check common locations
 
     // 1. Environment variable
     if let Ok(path) = std::env::var("BEACON_GATEWAY_PATH") {
         let p = std::path::PathBuf::from(path);
         if p.exists() {
-            return Ok(p);
+            return Ok(BinarySourceInfo::new(p, BinarySource::Env));
         }
     }
 
@@ -166,13 +1703,22 @@ fn find_gateway_binary() -> Result<std::path::PathBuf, String> {
 
             for candidate in &candidates {
                 if candidate.exists() {
-                    return Ok(candidate.clone());
+                    return Ok(BinarySourceInfo::new(
+                        candidate.clone(),
+                        BinarySource::Bundled,
+                    ));
                 }
             }
         }
     }
 
-    // 3. System PATH
+    // 3. Downloaded during onboarding
+    let downloaded = download::binary_path(data_dir);
+    if downloaded.exists() {
+        return Ok(BinarySourceInfo::new(downloaded, BinarySource::Downloaded));
+    }
+
+    // 4. System PATH
     if let Ok(output) = std::process::Command::new("which")
         .arg("beacon")
         .output()
@@ -180,12 +1726,15 @@ fn find_gateway_binary() -> Result<std::path::PathBuf, String> {
         if output.status.success() {
             let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
             if !path.is_empty() {
-                return Ok(std::path::PathBuf::from(path));
+                return Ok(BinarySourceInfo::new(
+                    std::path::PathBuf::from(path),
+                    BinarySource::SystemPath,
+                ));
             }
         }
     }
 
-    // 4. Development build location
+    // 5. Development build location
     let dev_paths = [
         "../../beacon-gateway/target/debug/beacon",
         "../../beacon-gateway/target/release/beacon",
@@ -196,53 +1745,680 @@ fn find_gateway_binary() -> Result<std::path::PathBuf, String> {
     for path in &dev_paths {
         let p = std::path::PathBuf::from(path);
         if p.exists() {
-            return Ok(p);
+            return Ok(BinarySourceInfo::new(p, BinarySource::DevBuild));
         }
     }
 
     Err("beacon-gateway binary not found".to_string())
 }
 
+/// Sidecar restarts allowed within [`RESTART_WINDOW`] before giving up
+const MAX_RESTARTS: usize = 3;
+
+/// Window the restart budget applies to
+const RESTART_WINDOW: Duration = Duration::from_secs(300);
+
+/// Progress through the sidecar restart budget, also the `gateway-retry`
+/// event payload (`null` once the attempt is over)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RetryBudget {
+    /// This attempt's number within the current window, from 1
+    pub attempt: u32,
+
+    /// Attempts allowed within the window
+    pub max: u32,
+
+    /// Time until the attempt starts (ms)
+    pub next_delay_ms: u64,
+}
+
+/// Payload of the `gateway-unresponsive` event
+#[derive(Debug, Clone, Serialize)]
+pub struct UnresponsiveEvent {
+    /// Consecutive failed health checks that triggered the restart
+    pub failures: u32,
+
+    /// Whether a restart will be attempted (false once the breaker trips)
+    pub restarting: bool,
+}
+
+/// Circuit breaker for sidecar restarts
+///
+/// A gateway that keeps dying or hanging right after starting is not going to
+/// fix itself, so after too many restarts in a short window we stop trying
+/// and leave it failed for the user to look at.
+#[derive(Default)]
+struct RestartBreaker {
+    restarts: VecDeque<tokio::time::Instant>,
+}
+
+impl RestartBreaker {
+    /// Record a restart attempt after `delay`, returning `None` if the
+    /// budget is spent
+    fn try_restart(&mut self, delay: Duration) -> Option<RetryBudget> {
+        let now = tokio::time::Instant::now();
+        while self
+            .restarts
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > RESTART_WINDOW)
+        {
+            self.restarts.pop_front();
+        }
+
+        if self.restarts.len() >= MAX_RESTARTS {
+            return None;
+        }
+        self.restarts.push_back(now);
+
+        Some(RetryBudget {
+            attempt: self.restarts.len() as u32,
+            max: MAX_RESTARTS as u32,
+            next_delay_ms: delay.as_millis() as u64,
+        })
+    }
+}
+
 /// Health check loop for sidecar monitoring
-#[allow(dead_code)]
-pub async fn monitor_sidecar(state: Arc<AppState>) {
+///
+/// Restarts the sidecar when it exits, and when it stays alive but stops
+/// answering `/health` for `sidecar_hung_threshold` checks in a row. With
+/// the lifecycle attached, the connected gateway is checked the same way but
+/// only reported on, never restarted.
+pub async fn monitor_sidecar(app: AppHandle, state: Arc<AppState>) {
     const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 
+    let start = tokio::time::Instant::now() + phase_offset(HEALTH_CHECK_INTERVAL);
+    let mut ticker = tokio::time::interval_at(start, HEALTH_CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let mut failures = 0u32;
+    let mut breaker = RestartBreaker::default();
+
     loop {
-        tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+        ticker.tick().await;
 
         let current_state = state.gateway_state.read().await.clone();
-        if let GatewayState::Connected { url, is_sidecar: true } = current_state {
-            if !probe_gateway(&url).await {
-                tracing::warn!("gateway sidecar health check failed");
-
-                // Check if process is still running
-                let mut process = state.sidecar_process.write().await;
-                if let Some(ref mut child) = *process {
-                    match child.try_wait() {
-                        Ok(Some(status)) => {
-                            tracing::error!(status = ?status, "gateway sidecar exited");
-                            *process = None;
-                            drop(process);
-
-                            *state.gateway_state.write().await = GatewayState::Failed {
-                                error: format!("gateway exited with status: {status:?}"),
-                            };
-
-                            // Attempt restart
-                            tokio::time::sleep(Duration::from_secs(1)).await;
-                            let _ = start_sidecar(&state).await;
-                        }
-                        Ok(None) => {
-                            // Process still running, just a temporary health check failure
-                            tracing::debug!("gateway process running but health check failed");
-                        }
-                        Err(e) => {
-                            tracing::error!(error = %e, "failed to check process status");
-                        }
-                    }
+        let offline = state.settings.read().await.offline_mode;
+        let GatewayState::Connected { url, is_sidecar } = current_state else {
+            failures = 0;
+            continue;
+        };
+        let attached = !is_sidecar && lifecycle(&state).await == GatewayLifecycle::Attached;
+        if offline || !(is_sidecar || attached) {
+            failures = 0;
+            continue;
+        }
+
+        let started = tokio::time::Instant::now();
+        let healthy = probe_gateway(&url).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        state.metrics.record_latency(&url, latency_ms, healthy);
+
+        if healthy {
+            failures = 0;
+            if state.reconnect.settle() {
+                let _ = app.emit("gateway-stable", state.reconnect.state());
+            }
+            continue;
+        }
+
+        failures += 1;
+        if attached {
+            watch_attached(&app, &state, failures).await;
+            continue;
+        }
+        tracing::warn!(failures, "gateway sidecar health check failed");
+
+        // Check if process is still running
+        let persona = state.active_sidecar.read().await.clone();
+        let mut sidecars = state.sidecars.write().await;
+        let Some(process) = sidecars
+            .get_mut(&persona)
+            .map(|sidecar| &mut sidecar.process)
+        else {
+            continue;
+        };
+
+        let reason = match process.try_wait() {
+            Ok(Some(status)) => {
+                tracing::error!(status = %status, persona, "gateway sidecar exited");
+                sidecars.remove(&persona);
+                remove_pid_file(&state.data_dir, &persona, &state.instance_id);
+                let error = format!("gateway exited with status: {status}");
+                state.recent_errors.record(ErrorKind::Crash, &error);
+                error
+            }
+            Ok(None) => {
+                let threshold = state.settings.read().await.sidecar_hung_threshold.max(1);
+                if failures < threshold {
+                    // Process still running, maybe a temporary health check failure
+                    tracing::debug!("gateway process running but health check failed");
+                    continue;
+                }
+
+                tracing::error!(
+                    failures,
+                    "gateway sidecar is running but unresponsive, killing it"
+                );
+                // Killed outside the lock, as reaping it can take a while
+                let hung = sidecars.remove(&persona);
+                drop(sidecars);
+                if let Some(mut sidecar) = hung {
+                    kill_process(&mut sidecar.process).await;
+                }
+                remove_pid_file(&state.data_dir, &persona, &state.instance_id);
+
+                let backoff = note_failure(&app, &state);
+                let retry = breaker.try_restart(backoff.delay);
+                if backoff.notify {
+                    let _ = app.emit(
+                        "gateway-unresponsive",
+                        UnresponsiveEvent {
+                            failures,
+                            restarting: retry.is_some(),
+                        },
+                    );
+                }
+
+                let error = format!("gateway stopped responding after {failures} health checks");
+                state.recent_errors.record(ErrorKind::Hang, &error);
+                failures = 0;
+                restart_or_fail(&app, &state, error, retry).await;
+                continue;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to check process status");
+                continue;
+            }
+        };
+        drop(sidecars);
+
+        failures = 0;
+        let backoff = note_failure(&app, &state);
+        let retry = breaker.try_restart(backoff.delay);
+        restart_or_fail(&app, &state, reason, retry).await;
+    }
+}
+
+/// Report an attached gateway failing health checks, once it has failed
+/// `sidecar_hung_threshold` in a row; it's left running either way
+async fn watch_attached(app: &AppHandle, state: &AppState, failures: u32) {
+    tracing::warn!(failures, "attached gateway health check failed");
+    let threshold = state.settings.read().await.sidecar_hung_threshold.max(1);
+    if failures != threshold {
+        return;
+    }
+
+    let error = format!("attached gateway stopped responding after {failures} health checks");
+    tracing::error!("{error}");
+    state.recent_errors.record(ErrorKind::Hang, &error);
+    let _ = app.emit(
+        "gateway-unresponsive",
+        UnresponsiveEvent {
+            failures,
+            restarting: false,
+        },
+    );
+}
+
+/// Delay before restarting a background sidecar
+const BACKGROUND_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Payload of the `sidecar-failed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct SidecarFailedEvent {
+    pub persona: String,
+    pub error: String,
+
+    /// Whether a restart will be attempted (false once its breaker trips)
+    pub restarting: bool,
+}
+
+/// Health check loop for the sidecars the connection doesn't point at
+///
+/// Each one is watched on its own, the way [`monitor_sidecar`] watches the
+/// active one: restarted on its port when it exits, or when it stays alive
+/// but stops answering `/health` for `sidecar_hung_threshold` checks in a
+/// row, under its own restart breaker. The gateway state is never touched;
+/// failures are reported as `sidecar-failed` events.
+pub async fn monitor_background_sidecars(app: AppHandle, state: Arc<AppState>) {
+    const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+    let start = tokio::time::Instant::now() + phase_offset(HEALTH_CHECK_INTERVAL);
+    let mut ticker = tokio::time::interval_at(start, HEALTH_CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let mut failures: HashMap<String, u32> = HashMap::new();
+    let mut breakers: HashMap<String, RestartBreaker> = HashMap::new();
+
+    loop {
+        ticker.tick().await;
+        if state.settings.read().await.offline_mode {
+            failures.clear();
+            continue;
+        }
+
+        // Sidecars still starting are left to the start that launched them
+        let startup_timeout = startup::timeout(&state.settings.read().await);
+        let startup_ms = startup_timeout.as_millis() as u64;
+        let background: Vec<SidecarInfo> = list_sidecars(&state)
+            .await
+            .into_iter()
+            .filter(|sidecar| !sidecar.active)
+            .filter(|sidecar| logs::now_ms().saturating_sub(sidecar.started_ms) >= startup_ms)
+            .collect();
+        failures.retain(|persona, _| background.iter().any(|s| s.persona == *persona));
+
+        for sidecar in background {
+            let started = tokio::time::Instant::now();
+            let healthy = probe_gateway(&sidecar.url).await;
+            let latency = started.elapsed().as_millis() as u64;
+            state.metrics.record_latency(&sidecar.url, latency, healthy);
+            if healthy {
+                failures.remove(&sidecar.persona);
+                continue;
+            }
+
+            let count = failures.entry(sidecar.persona.clone()).or_default();
+            *count += 1;
+            tracing::warn!(
+                failures = *count,
+                persona = %sidecar.persona,
+                "background gateway sidecar health check failed"
+            );
+
+            let Some((kind, error)) = reap_background(&state, &sidecar.persona, *count).await
+            else {
+                continue;
+            };
+            failures.remove(&sidecar.persona);
+            state.recent_errors.record(kind, &error);
+
+            let retry = breakers
+                .entry(sidecar.persona.clone())
+                .or_default()
+                .try_restart(BACKGROUND_RESTART_DELAY);
+            let _ = app.emit(
+                "sidecar-failed",
+                SidecarFailedEvent {
+                    persona: sidecar.persona.clone(),
+                    error,
+                    restarting: retry.is_some(),
+                },
+            );
+
+            let Some(retry) = retry else {
+                tracing::error!(
+                    persona = %sidecar.persona,
+                    "background sidecar restarted too often, giving up"
+                );
+                continue;
+            };
+            let state = state.clone();
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(retry.next_delay_ms)).await;
+                let (persona, port) = (sidecar.persona, Some(sidecar.port));
+                if let Err(e) = start_sidecar_for(&state, &persona, port, false).await {
+                    tracing::error!(error = %e, persona, "failed to restart background sidecar");
                 }
+            });
+        }
+    }
+}
+
+/// Clear out a background sidecar that exited, or that has failed enough
+/// health checks to count as hung (killing it), returning why
+async fn reap_background(
+    state: &AppState,
+    persona: &str,
+    failures: u32,
+) -> Option<(ErrorKind, String)> {
+    let mut sidecars = state.sidecars.write().await;
+    let process = &mut sidecars.get_mut(persona)?.process;
+
+    let (reason, hung) = match process.try_wait() {
+        Ok(Some(status)) => {
+            tracing::error!(status = %status, persona, "background gateway sidecar exited");
+            let error = format!("{persona} sidecar exited with status: {status}");
+            ((ErrorKind::Crash, error), false)
+        }
+        Ok(None) => {
+            let threshold = state.settings.read().await.sidecar_hung_threshold.max(1);
+            if failures < threshold {
+                return None;
+            }
+            tracing::error!(
+                failures,
+                persona,
+                "background gateway sidecar is unresponsive, killing it"
+            );
+            let error =
+                format!("{persona} sidecar stopped responding after {failures} health checks");
+            ((ErrorKind::Hang, error), true)
+        }
+        Err(e) => {
+            tracing::error!(error = %e, persona, "failed to check process status");
+            return None;
+        }
+    };
+
+    // Killed outside the lock, as reaping it can take a while
+    let removed = sidecars.remove(persona);
+    drop(sidecars);
+    if let Some(mut sidecar) = removed.filter(|_| hung) {
+        kill_process(&mut sidecar.process).await;
+    }
+    remove_pid_file(&state.data_dir, persona, &state.instance_id);
+    Some(reason)
+}
+
+/// Record a sidecar failure for the adaptive backoff, announcing when the
+/// gateway turns unstable
+fn note_failure(app: &AppHandle, state: &AppState) -> reconnect::Failure {
+    let failure = state.reconnect.record_failure();
+    if failure.became_unstable {
+        let _ = app.emit("gateway-unstable", state.reconnect.state());
+    }
+    failure
+}
+
+/// Mark the sidecar failed and, unless the breaker tripped, start it again
+///
+/// The attempt is published in [`AppState::retry`] and as `gateway-retry`
+/// events while it runs.
+async fn restart_or_fail(
+    app: &AppHandle,
+    state: &AppState,
+    error: String,
+    retry: Option<RetryBudget>,
+) {
+    let Some(retry) = retry else {
+        tracing::error!("gateway sidecar restarted too often, giving up");
+        state
+            .set_gateway_state(GatewayState::Failed {
+                error: format!("{error} (restarted too often, not retrying)"),
+            })
+            .await;
+        return;
+    };
+
+    state
+        .set_gateway_state(GatewayState::Failed { error })
+        .await;
+
+    *state.retry.write().await = Some(retry);
+    let _ = app.emit("gateway-retry", Some(retry));
+
+    // Attempt restart
+    tokio::time::sleep(Duration::from_millis(retry.next_delay_ms)).await;
+    if let Err(e) = start_sidecar(state).await {
+        tracing::error!(
+            error = %e,
+            attempt = retry.attempt,
+            max = retry.max,
+            "failed to restart gateway sidecar"
+        );
+    }
+
+    *state.retry.write().await = None;
+    let _ = app.emit("gateway-retry", None::<RetryBudget>);
+}
+
+/// Downtime assumed for an announced restart that doesn't say how long
+const DEFAULT_RESTART_DOWNTIME: Duration = Duration::from_secs(30);
+
+/// Longest announced downtime honored, so a bogus value can't mask a crash
+const MAX_RESTART_DOWNTIME: Duration = Duration::from_secs(300);
+
+/// How often to check for a restarting gateway coming back
+const RESTART_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Treat the connected gateway's coming downtime as expected
+///
+/// Moves to `Restarting` and waits in the background for the gateway to
+/// come back (see `handle_gateway_shutdown`). The sidecar monitor only acts
+/// on a connected sidecar, so it and its restart breaker stay out of it.
+pub async fn expect_restart(
+    state: Arc<AppState>,
+    restarting: bool,
+    downtime: Option<Duration>,
+    reason: Option<String>,
+) -> Result<(), String> {
+    let current = state.gateway_state.read().await.clone();
+    let (url, is_sidecar) = match current {
+        GatewayState::Connected { url, is_sidecar }
+        | GatewayState::Restarting {
+            url, is_sidecar, ..
+        } => (url, is_sidecar),
+        _ => return Err("not connected to a gateway".to_string()),
+    };
+
+    let downtime = downtime
+        .unwrap_or(DEFAULT_RESTART_DOWNTIME)
+        .min(MAX_RESTART_DOWNTIME);
+    let kind = if restarting { "restart" } else { "shutdown" };
+    let reason = match reason {
+        Some(reason) => format!("expected {kind}: {reason}"),
+        None => format!("expected {kind}"),
+    };
+    tracing::info!(url = %url, downtime_ms = downtime.as_millis() as u64, "{reason}");
+
+    let until = std::time::Instant::now() + downtime;
+    state
+        .set_gateway_state(GatewayState::Restarting {
+            url: url.clone(),
+            is_sidecar,
+            reason,
+            until,
+        })
+        .await;
+
+    tauri::async_runtime::spawn(await_restart(state, url, is_sidecar, restarting, until));
+    Ok(())
+}
+
+/// Reconnect to a restarting gateway once it answers again
+///
+/// A sidecar that exits after announcing a shutdown rather than a restart
+/// is left stopped, and the connection disconnected. Gives up quietly if
+/// the state moves on meanwhile (e.g. the user disconnects or switches
+/// gateways).
+async fn await_restart(
+    state: Arc<AppState>,
+    url: String,
+    is_sidecar: bool,
+    restarting: bool,
+    until: std::time::Instant,
+) {
+    let still_restarting = |s: &GatewayState| match s {
+        GatewayState::Restarting {
+            url: u, until: t, ..
+        } => *u == url && *t == until,
+        _ => false,
+    };
+
+    while std::time::Instant::now() < until {
+        tokio::time::sleep(RESTART_POLL_INTERVAL).await;
+        if !still_restarting(&state.gateway_state.read().await) {
+            return;
+        }
+
+        let client = state.client.read().await.clone();
+        if probe_with_client(&client, &url).await {
+            tracing::info!(url = %url, "gateway back after expected restart");
+            state
+                .set_gateway_state(GatewayState::Connected { url, is_sidecar })
+                .await;
+            return;
+        }
+
+        if is_sidecar && sidecar_exited(&state).await {
+            if !restarting {
+                tracing::info!("gateway sidecar shut down as announced");
+                state.set_gateway_state(GatewayState::Disconnected).await;
+                return;
             }
+
+            // Our own sidecar went down as announced; bring it back without
+            // counting it against the crash restart budget
+            tracing::info!("gateway sidecar exited for expected restart, starting it again");
+            if let Err(e) = start_sidecar(&state).await {
+                tracing::error!(error = %e, "failed to start gateway sidecar after expected restart");
+            }
+            return;
         }
     }
+
+    if !still_restarting(&state.gateway_state.read().await) {
+        return;
+    }
+    if restarting {
+        let error = "gateway did not come back after an expected restart".to_string();
+        tracing::warn!(url = %url, "{error}");
+        state.recent_errors.record(ErrorKind::Probe, &error);
+        state
+            .set_gateway_state(GatewayState::Failed { error })
+            .await;
+    } else {
+        tracing::info!(url = %url, "gateway shut down as announced");
+        state.set_gateway_state(GatewayState::Disconnected).await;
+    }
+}
+
+/// Whether the active sidecar process has exited, clearing its handle if so
+async fn sidecar_exited(state: &AppState) -> bool {
+    let persona = state.active_sidecar.read().await.clone();
+    let mut sidecars = state.sidecars.write().await;
+    let exited = match sidecars.get_mut(&persona) {
+        Some(sidecar) => matches!(sidecar.process.try_wait(), Ok(Some(_))),
+        None => true,
+    };
+
+    if exited {
+        sidecars.remove(&persona);
+        remove_pid_file(&state.data_dir, &persona, &state.instance_id);
+    }
+    exited
+}
+
+/// Random phase offset within `interval`
+///
+/// Periodic checks for different connections start at different offsets so
+/// their timers spread across the interval instead of firing together. Only
+/// the phase changes; each check still runs once per `interval`.
+pub fn phase_offset(interval: Duration) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+
+    // RandomState is randomly keyed, which is plenty for scheduling jitter
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+
+    let millis = interval.as_millis().max(1) as u64;
+    Duration::from_millis(hasher.finish() % millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_passes_hostnames_through() {
+        assert_eq!(
+            normalize_gateway_url("http://my-server:18790").unwrap(),
+            "http://my-server:18790"
+        );
+        assert_eq!(
+            normalize_gateway_url("my-server.tail1234.ts.net:18790/").unwrap(),
+            "http://my-server.tail1234.ts.net:18790"
+        );
+        assert_eq!(
+            normalize_gateway_url("https://beacon.ts.net").unwrap(),
+            "https://beacon.ts.net"
+        );
+    }
+
+    #[test]
+    fn normalize_rejects_other_schemes() {
+        assert!(normalize_gateway_url("ftp://my-server").is_err());
+    }
+
+    #[test]
+    fn working_dir_defaults_to_binary_dir() {
+        let binary = std::path::Path::new("/opt/beacon/bin/beacon-gateway");
+        assert_eq!(
+            working_dir(binary, None, None),
+            Some(std::path::PathBuf::from("/opt/beacon/bin"))
+        );
+    }
+
+    #[test]
+    fn working_dir_prefers_env_then_setting() {
+        let binary = std::path::Path::new("/opt/beacon/bin/beacon-gateway");
+        let env = Some(std::path::PathBuf::from("/srv/env"));
+        let configured = Some(std::path::PathBuf::from("/srv/configured"));
+        assert_eq!(working_dir(binary, env.clone(), configured.clone()), env);
+        assert_eq!(working_dir(binary, None, configured.clone()), configured);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stop_kills_a_sidecar_ignoring_sigterm() {
+        let child = Command::new("sh")
+            .args(["-c", "trap '' TERM; sleep 60"])
+            .spawn()
+            .unwrap();
+        let mut process = SidecarProcess::Spawned(child);
+        // Let the shell install its trap before it's asked to stop
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let started = std::time::Instant::now();
+        terminate(&mut process, "test").await;
+        assert!(started.elapsed() < SIDECAR_STOP_GRACE + Duration::from_secs(1));
+        assert!(process.try_wait().unwrap().is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn force_stop_reaps_within_kill_wait() {
+        let child = Command::new("sleep").arg("60").spawn().unwrap();
+        let mut process = SidecarProcess::Spawned(child);
+
+        let started = std::time::Instant::now();
+        kill_process(&mut process).await;
+        assert!(started.elapsed() < SIDECAR_KILL_WAIT);
+        assert!(process.try_wait().unwrap().is_some());
+    }
+
+    fn url(url: &str) -> reqwest::Url {
+        reqwest::Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn redirect_allows_same_host_https_upgrade() {
+        assert!(is_allowed_redirect(
+            &url("http://gateway.local:18790/health"),
+            &url("https://gateway.local:18790/health"),
+        ));
+    }
+
+    #[test]
+    fn redirect_rejects_other_host() {
+        assert!(!is_allowed_redirect(
+            &url("http://gateway.local/health"),
+            &url("https://elsewhere.example/health"),
+        ));
+    }
+
+    #[test]
+    fn redirect_rejects_https_downgrade() {
+        assert!(!is_allowed_redirect(
+            &url("https://gateway.local/health"),
+            &url("http://gateway.local/health"),
+        ));
+    }
 }
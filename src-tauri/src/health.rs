@@ -0,0 +1,125 @@
+//! Gateway health details
+//!
+//! `/health` may return a body with subsystem statuses, or nothing but a
+//! bare 200. Known fields are picked out leniently (a few spellings each) so
+//! the UI gets structured values either way.
+
+use std::time::{Duration, Instant};
+
+use reqwest::Method;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::features::Feature;
+use crate::{api, AppState};
+
+/// How long a health detail is reused before asking the gateway again
+const CACHE_TTL: Duration = Duration::from_secs(2);
+
+/// Parsed `/health` response
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthDetail {
+    /// Overall status reported by the gateway (`ok` for a bare 200)
+    pub status: String,
+
+    /// Whether the gateway returned any details beyond its status code
+    pub detailed: bool,
+
+    /// Model state (e.g. `loaded`, `loading`, `not_loaded`)
+    pub model_state: Option<String>,
+
+    /// Requests waiting to be processed
+    pub queue_depth: Option<u64>,
+
+    /// Memory used by the gateway process (bytes)
+    pub memory_bytes: Option<u64>,
+
+    /// Warnings reported by the gateway
+    pub warnings: Vec<String>,
+
+    /// When this was fetched (ms since Unix epoch)
+    pub fetched_at_ms: u64,
+}
+
+/// Fetch health details, reusing a recent result for the same gateway
+pub async fn detail(state: &AppState) -> Result<HealthDetail, String> {
+    let url = api::base_url(state).await?;
+
+    if let Some((cached_url, fetched, detail)) = &*state.health_cache.read().await {
+        if *cached_url == url && fetched.elapsed() < CACHE_TTL {
+            state.cache_counters.hit();
+            return Ok(detail.clone());
+        }
+    }
+    state.cache_counters.miss();
+
+    let resp = api::request(state, Method::GET, "/health", None).await?;
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(format!("gateway returned {status}: {body}"));
+    }
+
+    let detail = parse(&body);
+    if state.features.is_enabled(Feature::Cache) {
+        *state.health_cache.write().await = Some((url, Instant::now(), detail.clone()));
+    }
+    Ok(detail)
+}
+
+/// Parse a health body, falling back to a bare "ok" for non-JSON bodies
+fn parse(body: &str) -> HealthDetail {
+    let value = serde_json::from_str::<Value>(body).unwrap_or(Value::Null);
+    let first = |pointers: &[&str]| pointers.iter().find_map(|p| value.pointer(p));
+
+    let status = first(&["/status", "/state"])
+        .and_then(Value::as_str)
+        .unwrap_or("ok")
+        .to_string();
+
+    let model_state = match first(&["/model/state", "/model/status", "/model_state", "/model"]) {
+        Some(Value::String(state)) => Some(state.clone()),
+        _ => match first(&["/model_loaded", "/model/loaded"]) {
+            Some(Value::Bool(true)) => Some("loaded".to_string()),
+            Some(Value::Bool(false)) => Some("not_loaded".to_string()),
+            _ => None,
+        },
+    };
+
+    let queue_depth =
+        first(&["/queue_depth", "/queue/depth", "/queue/pending"]).and_then(Value::as_u64);
+
+    let memory_bytes = first(&[
+        "/memory_bytes",
+        "/memory/rss_bytes",
+        "/memory/used_bytes",
+        "/memory/rss",
+    ])
+    .and_then(Value::as_u64);
+
+    let warnings = first(&["/warnings"])
+        .and_then(Value::as_array)
+        .map(|warnings| {
+            warnings
+                .iter()
+                .filter_map(|w| match w {
+                    Value::String(s) => Some(s.clone()),
+                    Value::Object(o) => {
+                        o.get("message").and_then(Value::as_str).map(str::to_string)
+                    }
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    HealthDetail {
+        status,
+        detailed: value.as_object().is_some_and(|o| !o.is_empty()),
+        model_state,
+        queue_depth,
+        memory_bytes,
+        warnings,
+        fetched_at_ms: crate::logs::now_ms(),
+    }
+}
@@ -0,0 +1,322 @@
+//! Long-running gateway operations
+//!
+//! Some gateway operations answer with a job ID instead of a result, and
+//! finish later. Started through `start_async_operation`, such a job is
+//! polled at the gateway's `/jobs/{id}` while connected to the gateway that
+//! runs it, and every change of its status is emitted as a `job-update`
+//! event. Jobs still in flight are kept in `jobs.json` in the data
+//! directory, so polling picks up again after a reconnect or a relaunch.
+//! Finished jobs are kept in memory for an hour for `get_async_operation`,
+//! then dropped.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+
+use crate::{api, auth, gateway, logs, settings, AppState};
+
+/// In-flight jobs file name (relative to data dir)
+const JOBS_FILE: &str = "jobs.json";
+
+/// Gateway endpoint reporting a job's status, followed by its ID
+const JOBS_PATH: &str = "/jobs";
+
+/// How often in-flight jobs are polled
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long a finished job is kept after it finished (ms)
+const FINISHED_TTL_MS: u64 = 60 * 60 * 1000;
+
+/// Where a job is at
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Accepted by the gateway, not started yet
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobState {
+    fn finished(self) -> bool {
+        matches!(self, Self::Done | Self::Failed)
+    }
+
+    /// State from a status the gateway reports, e.g. `queued` or `completed`
+    fn parse(status: &str) -> Option<Self> {
+        match status.to_ascii_lowercase().as_str() {
+            "pending" | "queued" | "accepted" => Some(Self::Pending),
+            "running" | "in_progress" | "processing" | "started" => Some(Self::Running),
+            "done" | "completed" | "complete" | "succeeded" | "success" => Some(Self::Done),
+            "failed" | "error" | "cancelled" | "canceled" => Some(Self::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// A gateway job, also the `job-update` event payload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobStatus {
+    /// Job ID the gateway handed out
+    pub id: String,
+
+    /// Gateway the job runs on
+    pub url: String,
+
+    /// Endpoint that started it
+    pub path: String,
+
+    pub state: JobState,
+
+    /// Job result, once done
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+
+    /// Why the job failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// When the job was started (ms since Unix epoch)
+    pub started_at_ms: u64,
+
+    /// When its status last changed (ms since Unix epoch)
+    pub updated_at_ms: u64,
+}
+
+/// Jobs started this session or resumed from disk, by gateway URL and ID
+///
+/// Gateways hand out IDs independently, so one is only unique together with
+/// the gateway's URL.
+#[derive(Default)]
+pub struct Jobs {
+    jobs: Mutex<HashMap<(String, String), JobStatus>>,
+}
+
+impl Jobs {
+    /// Jobs left in flight by an earlier run
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(JOBS_FILE);
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+
+        let jobs: Vec<JobStatus> = serde_json::from_str(&contents).unwrap_or_else(|e| {
+            tracing::warn!(error = %e, path = %path.display(), "failed to parse jobs");
+            Vec::new()
+        });
+        if !jobs.is_empty() {
+            tracing::info!(count = jobs.len(), "resuming in-flight gateway jobs");
+        }
+        Self {
+            jobs: Mutex::new(jobs.into_iter().map(|job| (key(&job), job)).collect()),
+        }
+    }
+
+    /// Job `id` of the gateway at `url`, or of any gateway if unset
+    pub fn get(&self, url: Option<&str>, id: &str) -> Option<JobStatus> {
+        let jobs = self.lock();
+        match url {
+            Some(url) => jobs.get(&(url.to_string(), id.to_string())).cloned(),
+            None => jobs.values().find(|job| job.id == id).cloned(),
+        }
+    }
+
+    fn insert(&self, data_dir: &Path, job: JobStatus) {
+        let mut jobs = self.lock();
+        jobs.insert(key(&job), job);
+        save(data_dir, &jobs);
+    }
+
+    /// IDs of the jobs still in flight on the gateway at `url`
+    fn in_flight(&self, url: &str) -> Vec<String> {
+        self.lock()
+            .values()
+            .filter(|job| job.url == url && !job.state.finished())
+            .map(|job| job.id.clone())
+            .collect()
+    }
+
+    /// Apply a polled status, returning the job if it changed
+    fn update(&self, data_dir: &Path, url: &str, id: &str, polled: Polled) -> Option<JobStatus> {
+        let mut jobs = self.lock();
+        let job = jobs.get_mut(&(url.to_string(), id.to_string()))?;
+        if job.state == polled.state && job.result == polled.result && job.error == polled.error {
+            return None;
+        }
+
+        job.state = polled.state;
+        job.result = polled.result;
+        job.error = polled.error;
+        job.updated_at_ms = logs::now_ms();
+        let job = job.clone();
+        if job.state.finished() {
+            save(data_dir, &jobs);
+        }
+        Some(job)
+    }
+
+    /// Drop jobs that finished more than the TTL ago
+    fn prune(&self) {
+        let cutoff = logs::now_ms().saturating_sub(FINISHED_TTL_MS);
+        self.lock()
+            .retain(|_, job| !job.state.finished() || job.updated_at_ms > cutoff);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(String, String), JobStatus>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn key(job: &JobStatus) -> (String, String) {
+    (job.url.clone(), job.id.clone())
+}
+
+/// Persist the jobs still in flight
+fn save(data_dir: &Path, jobs: &HashMap<(String, String), JobStatus>) {
+    let in_flight: Vec<&JobStatus> = jobs.values().filter(|j| !j.state.finished()).collect();
+    let written = serde_json::to_string_pretty(&in_flight)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| {
+            settings::write_atomic(&data_dir.join(JOBS_FILE), contents.as_bytes())
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = written {
+        tracing::warn!(error = %e, "failed to save in-flight jobs");
+    }
+}
+
+/// Status of a job as the gateway reports it
+struct Polled {
+    state: JobState,
+    result: Option<Value>,
+    error: Option<String>,
+}
+
+/// Start an operation at `path`, returning the job ID the gateway answers
+/// with
+pub async fn start(
+    app: &AppHandle,
+    state: &AppState,
+    path: &str,
+    body: Option<&Value>,
+) -> Result<String, String> {
+    let url = api::base_url(state).await?;
+    let resp = auth::request(app, state, Method::POST, path, body, None).await?;
+    let status = resp.status();
+    let text = resp.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(format!("gateway returned {status}: {text}"));
+    }
+
+    let value: Value =
+        serde_json::from_str(&text).map_err(|e| format!("gateway returned invalid JSON: {e}"))?;
+    let id = ["/job_id", "/id", "/job/id"]
+        .iter()
+        .find_map(|p| match value.pointer(p)? {
+            Value::String(id) => Some(id.clone()),
+            Value::Number(id) => Some(id.to_string()),
+            _ => None,
+        })
+        .ok_or("gateway didn't return a job ID")?;
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':'))
+    {
+        return Err(format!("gateway returned an invalid job ID `{id}`"));
+    }
+
+    let polled = parse(&value);
+    let now = logs::now_ms();
+    let job = JobStatus {
+        id: id.clone(),
+        url,
+        path: path.to_string(),
+        state: polled.as_ref().map_or(JobState::Pending, |p| p.state),
+        result: polled.as_ref().and_then(|p| p.result.clone()),
+        error: polled.and_then(|p| p.error),
+        started_at_ms: now,
+        updated_at_ms: now,
+    };
+    tracing::info!(id = %job.id, path, "gateway job started");
+    let _ = app.emit("job-update", &job);
+    state.jobs.insert(&state.data_dir, job);
+    Ok(id)
+}
+
+/// Poll in-flight jobs for the lifetime of the app
+pub async fn run(app: AppHandle, state: Arc<AppState>) {
+    let start = tokio::time::Instant::now() + gateway::phase_offset(POLL_INTERVAL);
+    let mut ticker = tokio::time::interval_at(start, POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        state.jobs.prune();
+
+        // Jobs on other gateways wait until they're connected to again
+        let Ok(url) = api::base_url(&state).await else {
+            continue;
+        };
+        for id in state.jobs.in_flight(&url) {
+            let polled = match poll(&app, &state, &id).await {
+                Ok(polled) => polled,
+                Err(e) => {
+                    tracing::debug!(id, error = %e, "gateway job poll failed");
+                    continue;
+                }
+            };
+            if let Some(job) = state.jobs.update(&state.data_dir, &url, &id, polled) {
+                tracing::info!(id, state = ?job.state, "gateway job updated");
+                let _ = app.emit("job-update", &job);
+            }
+        }
+    }
+}
+
+async fn poll(app: &AppHandle, state: &AppState, id: &str) -> Result<Polled, String> {
+    let path = format!("{JOBS_PATH}/{id}");
+    let resp = auth::request(app, state, Method::GET, &path, None, None).await?;
+    match resp.status() {
+        StatusCode::NOT_FOUND | StatusCode::GONE => Ok(Polled {
+            state: JobState::Failed,
+            result: None,
+            error: Some("job no longer exists on the gateway".to_string()),
+        }),
+        status if status.is_success() => {
+            let value: Value = api::json(resp).await?;
+            parse(&value).ok_or_else(|| "gateway job status has no known state".to_string())
+        }
+        status => Err(format!("gateway returned {status} for the job")),
+    }
+}
+
+/// Status from a job body: `status` or `state`, with `result` and `error`
+/// (a message or an object with one)
+fn parse(value: &Value) -> Option<Polled> {
+    let state = ["/status", "/state", "/job/status"]
+        .iter()
+        .find_map(|p| value.pointer(p).and_then(Value::as_str))
+        .and_then(JobState::parse)?;
+    let error = match value.get("error") {
+        Some(Value::String(message)) => Some(message.clone()),
+        Some(error @ Value::Object(_)) => error
+            .get("message")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .or_else(|| Some(error.to_string())),
+        _ => None,
+    };
+    Some(Polled {
+        state,
+        result: value.get("result").filter(|v| !v.is_null()).cloned(),
+        error: error.or_else(|| (state == JobState::Failed).then(|| "job failed".to_string())),
+    })
+}
@@ -6,22 +6,138 @@
 //! - An external daemon (user-managed)
 //! - A remote server (via mDNS discovery or manual URL)
 
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use directories::BaseDirs;
-use tauri::Manager;
-use tokio::sync::RwLock;
+use tauri::{Emitter, Manager, RunEvent, WindowEvent};
+use tokio::sync::{watch, Notify, RwLock};
 
+mod allowlist;
+mod api;
+mod auth;
+mod cache;
+mod capabilities;
+mod clock;
 mod commands;
+mod config_blob;
+mod decision;
+mod diagnostics;
+mod discovery;
+mod download;
+mod env_profiles;
+mod errors;
+mod event_channel;
+mod export;
+mod features;
 mod gateway;
+mod health;
+mod jobs;
+mod load;
+mod logs;
+mod manifest;
+mod metrics;
+mod metrics_stream;
+mod models;
+mod opener;
+mod pairing;
+mod permissions;
+mod personas;
+mod pinning;
+mod pool;
+mod profiles;
+mod protocol;
+mod proxy;
+mod reachability;
+mod reconnect;
+mod recording;
+mod request_size;
+mod schedule;
+mod settings;
+mod snapshot;
+mod startup;
+mod storage;
+mod throttle;
+mod timing;
+#[cfg(desktop)]
+mod tray;
+mod validation;
+mod verify;
+#[cfg(desktop)]
+mod wake;
+
+use cache::CacheCounters;
+use capabilities::FeatureSupport;
+use clock::SkewResult;
+use decision::DecisionLog;
+use discovery::DiscoveryCache;
+use errors::ErrorLog;
+use event_channel::EventChannelResult;
+use features::FeatureSet;
+use health::HealthDetail;
+use jobs::Jobs;
+use load::GatewayLoad;
+use logs::GatewayLog;
+use manifest::ManifestCheck;
+use metrics::Metrics;
+use models::ModelInfo;
+use pairing::PairingAttempts;
+use personas::PersonaProfile;
+use pool::PoolTracker;
+use protocol::NegotiatedProtocol;
+use proxy::{RequestLimiter, StreamRegistry};
+use reconnect::ReconnectTracker;
+use recording::EventRecorder;
+use request_size::RequestLimit;
+use settings::Settings;
+use throttle::Throttle;
 
 use commands::{
     // Gateway management
-    get_gateway_status, start_gateway, stop_gateway,
+    await_gateway_ready, disconnect_gateway, download_gateway_binary, force_stop_gateway,
+    get_connection_decision, get_feature_support, get_gateway_binary_source,
+    get_gateway_health_detail, get_gateway_schema, get_gateway_status, connect_with_env_profile,
+    get_next_scheduled_restart, get_reconnect_state, get_sidecar_ownership, get_startup_estimate,
+    handle_gateway_shutdown, list_sidecars, set_gateway_lifecycle, start_gateway,
+    start_persona_sidecar, stop_gateway, stop_sidecar_by_persona, switch_gateway, switch_sidecar,
+    verify_gateway_binary,
+    // Proxy
+    cancel_active_request, clear_response_cache, get_async_operation, get_bandwidth_limits,
+    get_cache_stats, get_connection_pool_stats, get_gateway_load, get_max_request_size,
+    get_negotiated_protocol, get_request_queue_stats, list_active_requests, proxy_request,
+    proxy_stream,
+    replay_event_recording, set_bandwidth_limits, set_connection_pool, set_proxy_allowlist,
+    set_request_tracing, start_async_operation, start_event_recording, stop_event_recording,
+    // Gateway logs
+    export_gateway_log, get_gateway_logs, set_gateway_log_level, start_gateway_log_stream,
+    stop_gateway_log_stream,
+    // Discovery
+    clear_discovery_cache, refresh_gateway_metadata, rescan_gateways,
+    // Models
+    get_active_model, get_available_models, set_active_model,
+    // Personas
+    get_persona_profile,
+    // Profile commands
+    apply_gateway_config_blob, connect_by_fingerprint, connect_profile, delete_profile,
+    get_gateway_cert_fingerprint, list_profiles, pair_with_code, rotate_gateway_token,
+    save_profile, set_profile_cert_pin, set_profile_min_tls_version, set_profile_user_agent,
+    // Settings commands
+    get_settings, is_offline_mode, repair_settings, set_close_to_tray, set_discovery_enabled,
+    set_offline_mode, update_settings,
+    // Permissions
+    get_permission_states, request_permission,
+    // Opener
+    open_external, reveal_gateway_binary,
     // Storage commands
-    get_secure_storage, set_secure_storage,
+    get_secure_storage, migrate_secure_storage, set_secure_storage, test_secure_storage,
+    // Diagnostics
+    check_clock_skew, export_diagnostics, export_metrics_csv, export_state_snapshot,
+    diagnose_connection, get_bundled_gateway_manifest, get_diagnostics, get_enabled_features,
+    get_recent_errors, import_state_snapshot, ping_gateway_host, start_metrics_stream,
+    stop_metrics_stream, test_event_channel, validate_connection,
 };
 
 /// Gateway connection state
@@ -31,31 +147,212 @@ pub enum GatewayState {
     Disconnected,
 
     /// Starting the sidecar process
-    Starting,
+    Starting {
+        phase: StartupPhase,
+        started: Instant,
+    },
 
     /// Connected to gateway at URL
     Connected { url: String, is_sidecar: bool },
 
+    /// Gateway announced it is restarting or shutting down; the downtime is
+    /// expected until `until`, so it isn't treated as a crash
+    Restarting {
+        url: String,
+        is_sidecar: bool,
+        reason: String,
+        until: Instant,
+    },
+
     /// Connection failed
     Failed { error: String },
 }
 
+/// Step of sidecar startup in progress
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupPhase {
+    /// Looking for the gateway binary
+    LocatingBinary,
+
+    /// Launching the gateway process
+    Spawning,
+
+    /// Process running, waiting for `/health` to answer
+    WaitingForHealth,
+}
+
+impl StartupPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LocatingBinary => "locating_binary",
+            Self::Spawning => "spawning",
+            Self::WaitingForHealth => "waiting_for_health",
+        }
+    }
+}
+
 /// Application state shared across IPC commands
 pub struct AppState {
     /// Current gateway connection state
     pub gateway_state: RwLock<GatewayState>,
 
+    /// Publishes every gateway state change, for waiting on a state
+    pub state_changes: watch::Sender<GatewayState>,
+
     /// Gateway URL (configured or discovered)
     pub gateway_url: RwLock<Option<String>>,
 
-    /// Sidecar process handle (if running as sidecar)
-    pub sidecar_process: RwLock<Option<Child>>,
+    /// Sidecar processes this instance launched, keyed by persona
+    pub sidecars: RwLock<HashMap<String, gateway::Sidecar>>,
+
+    /// Persona of the sidecar the connection points at (or would, once
+    /// started)
+    pub active_sidecar: RwLock<String>,
+
+    /// Captured sidecar output
+    pub gateway_logs: Arc<GatewayLog>,
+
+    /// Recent connection, sidecar and auth failures
+    pub recent_errors: ErrorLog,
+
+    /// Latency samples, connection events and request counts
+    pub metrics: Metrics,
+
+    /// Model passed to the sidecar at launch (if overridden)
+    pub sidecar_model: RwLock<Option<String>>,
+
+    /// Last known active model on the connected gateway
+    pub active_model: RwLock<Option<ModelInfo>>,
+
+    /// HTTP client for gateway requests (carries the active profile's token)
+    pub client: RwLock<reqwest::Client>,
+
+    /// Connection reuse of gateway requests
+    pub pool_tracker: PoolTracker,
+
+    /// Active streaming requests
+    pub streams: Arc<StreamRegistry>,
+
+    /// Bounds concurrent proxied requests
+    pub request_limiter: RequestLimiter,
+
+    /// Last polled gateway load, which tightens the request limiter
+    pub gateway_load: RwLock<Option<GatewayLoad>>,
+
+    /// Paces request bodies sent to the gateway (see `upload_limit_bps`)
+    pub upload_throttle: Arc<Throttle>,
+
+    /// Paces proxied responses read from the gateway (see `download_limit_bps`)
+    pub download_throttle: Arc<Throttle>,
+
+    /// Records proxied stream events while a recording runs
+    pub recorder: Arc<EventRecorder>,
+
+    /// Name of the connected profile (if connected via a profile)
+    pub active_profile: RwLock<Option<String>>,
+
+    /// Persistent app settings
+    pub settings: RwLock<Settings>,
+
+    /// `close_to_tray` of the settings, for window events, which can't wait
+    /// on the settings lock
+    pub close_to_tray: AtomicBool,
+
+    /// Cancels the in-progress gateway rescan (if any)
+    pub rescan_cancel: RwLock<Option<Arc<Notify>>>,
+
+    /// Stops the running metrics stream (if any)
+    pub metrics_stream: RwLock<Option<Arc<Notify>>>,
+
+    /// Gateways found by recent discovery scans
+    pub discovery_cache: DiscoveryCache,
+
+    /// Wrong pairing codes per gateway, to limit guessing
+    pub pairing_attempts: PairingAttempts,
+
+    /// Gateway API schema, keyed by the gateway version it was fetched from
+    pub schema_cache: RwLock<Option<(String, serde_json::Value)>>,
+
+    /// Last health detail, with the gateway URL and time it was fetched
+    pub health_cache: RwLock<Option<(String, Instant, HealthDetail)>>,
+
+    /// Feature support of the gateway at the URL, until the next connect
+    pub feature_support_cache: RwLock<Option<(String, FeatureSupport)>>,
+
+    /// Request size limit of the connected gateway, until the next connect
+    pub request_limit: RwLock<Option<RequestLimit>>,
+
+    /// Protocol in use with the connected gateway, until the next connect
+    pub protocol: RwLock<Option<NegotiatedProtocol>>,
+
+    /// Active persona's profile, with the gateway URL it came from
+    pub persona_profile: RwLock<Option<(String, PersonaProfile)>>,
+
+    /// Last clock skew check against the gateway
+    pub clock_skew: RwLock<Option<SkewResult>>,
+
+    /// Bundled gateway binary checked against its manifest at startup
+    pub manifest_check: RwLock<Option<ManifestCheck>>,
+
+    /// Last event channel test against the gateway
+    pub event_channel: RwLock<Option<EventChannelResult>>,
+
+    /// Hits and misses of the response caches
+    pub cache_counters: CacheCounters,
+
+    /// Sidecar restart in progress, if any
+    pub retry: RwLock<Option<gateway::RetryBudget>>,
+
+    /// Recent gateway failures, driving the adaptive restart backoff
+    pub reconnect: ReconnectTracker,
+
+    /// What the latest `auto_connect` tried, and how each attempt went
+    pub connection_decision: RwLock<DecisionLog>,
+
+    /// Next scheduled sidecar restart (ms since Unix epoch)
+    pub next_scheduled_restart: RwLock<Option<i64>>,
+
+    /// Long-running gateway jobs, polled until they finish
+    pub jobs: Jobs,
 
     /// Data directory for app storage
     pub data_dir: PathBuf,
+
+    /// Unique ID of this app instance, marking the sidecars it launches
+    pub instance_id: String,
+
+    /// Optional subsystems enabled for this launch
+    pub features: FeatureSet,
+
+    /// Launched in safe mode (auto-connect skipped)
+    pub safe_mode: bool,
 }
 
 impl AppState {
+    /// Update the gateway connection state, recording the transition
+    ///
+    /// Response caches are cleared on every new connection, since the
+    /// gateway (or its model) may have changed.
+    pub async fn set_gateway_state(&self, new_state: GatewayState) {
+        let connected = {
+            let mut current = self.gateway_state.write().await;
+            let changed = *current != new_state;
+            if changed {
+                self.metrics.record_state(&new_state);
+            }
+            self.state_changes.send_replace(new_state.clone());
+            *current = new_state;
+            changed && matches!(&*current, GatewayState::Connected { .. })
+        };
+
+        if connected {
+            cache::clear(self).await;
+            *self.request_limit.write().await = None;
+            *self.protocol.write().await = None;
+        }
+    }
+
     /// Check if connected to a gateway
     pub async fn is_connected(&self) -> bool {
         matches!(&*self.gateway_state.read().await, GatewayState::Connected { .. })
@@ -70,6 +367,21 @@ impl AppState {
     }
 }
 
+/// Check whether the app was launched in safe mode
+///
+/// Safe mode is a recovery escape hatch: it skips auto-connect entirely so a
+/// gateway that crashes the app during startup can't lock the user out.
+/// Enabled with `--safe-mode` or `BEACON_SAFE_MODE=1`.
+fn is_safe_mode() -> bool {
+    if std::env::args().any(|arg| arg == "--safe-mode") {
+        return true;
+    }
+
+    std::env::var("BEACON_SAFE_MODE")
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tracing_subscriber::fmt()
@@ -85,17 +397,69 @@ pub fn run() {
         .unwrap_or_else(|| PathBuf::from(".local/share/omni/beacon"));
     std::fs::create_dir_all(&data_dir).ok();
 
-    tracing::info!(data_dir = %data_dir.display(), "app starting");
+    let safe_mode = is_safe_mode();
+    let features = FeatureSet::from_env();
+
+    tracing::info!(
+        data_dir = %data_dir.display(),
+        safe_mode,
+        features = ?features.enabled(),
+        "app starting"
+    );
 
     // Default gateway URL (local gateway)
     let default_gateway_url = std::env::var("BEACON_GATEWAY_URL")
         .unwrap_or_else(|_| "http://localhost:18790".to_string());
 
+    let (settings, settings_recovery) = Settings::load(&data_dir);
+    pool::set_config(settings.pool());
+    let offline_mode = settings.offline_mode;
+
     let state = Arc::new(AppState {
         gateway_state: RwLock::new(GatewayState::Disconnected),
+        state_changes: watch::Sender::new(GatewayState::Disconnected),
         gateway_url: RwLock::new(Some(default_gateway_url)),
-        sidecar_process: RwLock::new(None),
+        sidecars: RwLock::new(HashMap::new()),
+        active_sidecar: RwLock::new(gateway::DEFAULT_PERSONA.to_string()),
+        gateway_logs: Arc::new(GatewayLog::default()),
+        recent_errors: ErrorLog::default(),
+        metrics: Metrics::default(),
+        sidecar_model: RwLock::new(None),
+        active_model: RwLock::new(None),
+        client: RwLock::new(gateway::default_client()),
+        pool_tracker: PoolTracker::default(),
+        streams: Arc::new(StreamRegistry::default()),
+        request_limiter: RequestLimiter::new(settings.max_concurrent_requests),
+        gateway_load: RwLock::new(None),
+        upload_throttle: Arc::new(Throttle::new(settings.upload_limit_bps)),
+        download_throttle: Arc::new(Throttle::new(settings.download_limit_bps)),
+        recorder: Arc::new(EventRecorder::default()),
+        active_profile: RwLock::new(None),
+        close_to_tray: AtomicBool::new(settings.close_to_tray),
+        settings: RwLock::new(settings),
+        rescan_cancel: RwLock::new(None),
+        metrics_stream: RwLock::new(None),
+        discovery_cache: DiscoveryCache::default(),
+        pairing_attempts: PairingAttempts::default(),
+        schema_cache: RwLock::new(None),
+        health_cache: RwLock::new(None),
+        feature_support_cache: RwLock::new(None),
+        request_limit: RwLock::new(None),
+        protocol: RwLock::new(None),
+        persona_profile: RwLock::new(None),
+        clock_skew: RwLock::new(None),
+        manifest_check: RwLock::new(None),
+        event_channel: RwLock::new(None),
+        cache_counters: CacheCounters::default(),
+        retry: RwLock::new(None),
+        reconnect: ReconnectTracker::default(),
+        connection_decision: RwLock::new(DecisionLog::default()),
+        next_scheduled_restart: RwLock::new(None),
+        jobs: Jobs::load(&data_dir),
         data_dir,
+        instance_id: format!("{}-{}", std::process::id(), logs::now_ms()),
+        features,
+        safe_mode,
     });
 
     #[allow(unused_mut)]
@@ -115,11 +479,65 @@ pub fn run() {
     builder
         .manage(state.clone())
         .setup(move |app| {
+            // Tray icon (desktop only), the way back when closed to tray
+            #[cfg(desktop)]
+            tray::init(app)?;
+
+            logs::spawn_flusher(app.handle().clone(), state.gateway_logs.clone());
+            tauri::async_runtime::spawn(gateway::monitor_sidecar(
+                app.handle().clone(),
+                state.clone(),
+            ));
+            tauri::async_runtime::spawn(gateway::monitor_background_sidecars(
+                app.handle().clone(),
+                state.clone(),
+            ));
+            tauri::async_runtime::spawn(schedule::run(app.handle().clone(), state.clone()));
+            tauri::async_runtime::spawn(load::run(app.handle().clone(), state.clone()));
+            tauri::async_runtime::spawn(jobs::run(app.handle().clone(), state.clone()));
+            tauri::async_runtime::spawn(manifest::verify_at_startup(
+                app.handle().clone(),
+                state.clone(),
+            ));
+            tauri::async_runtime::spawn(personas::watch_profile(
+                app.handle().clone(),
+                state.clone(),
+            ));
+            #[cfg(desktop)]
+            tauri::async_runtime::spawn(wake::run(app.handle().clone(), state.clone()));
+
             // Show window
             if let Some(window) = app.get_webview_window("main") {
                 let _ = window.show();
             }
 
+            // First run with the keychain: anything only kept in memory by
+            // earlier builds is gone, so ask for missing tokens again
+            match storage::migrate(&state.data_dir) {
+                Ok(migration) if migration.first_run && !migration.missing_tokens.is_empty() => {
+                    let _ = app.emit("secure-storage-reenter", &migration);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!(error = %e, "secure storage migration failed"),
+            }
+
+            // Settings were reset from a corrupt file, which `repair_settings`
+            // can still salvage
+            if let Some(recovery) = &settings_recovery {
+                let _ = app.emit("settings-recovered", recovery);
+            }
+
+            // In safe mode, stay disconnected and let the user decide via the UI
+            if state.safe_mode {
+                tracing::warn!("safe mode active, skipping gateway auto-connect");
+                return Ok(());
+            }
+
+            if offline_mode {
+                tracing::info!("offline mode active, skipping gateway auto-connect");
+                return Ok(());
+            }
+
             // Try to connect to gateway or start sidecar
             let state_clone = state.clone();
             tauri::async_runtime::spawn(async move {
@@ -128,15 +546,137 @@ pub fn run() {
 
             Ok(())
         })
+        .on_window_event(|window, event| {
+            // With close_to_tray, closing hides the window and keeps the
+            // process (and sidecar) running
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                let state = window.state::<Arc<AppState>>();
+                if cfg!(desktop) && state.close_to_tray.load(Ordering::Relaxed) {
+                    api.prevent_close();
+                    let _ = window.hide();
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             // Gateway management
             get_gateway_status,
+            await_gateway_ready,
             start_gateway,
+            connect_with_env_profile,
             stop_gateway,
+            set_gateway_lifecycle,
+            force_stop_gateway,
+            disconnect_gateway,
+            switch_gateway,
+            start_persona_sidecar,
+            list_sidecars,
+            switch_sidecar,
+            stop_sidecar_by_persona,
+            get_gateway_schema,
+            get_feature_support,
+            get_gateway_health_detail,
+            download_gateway_binary,
+            get_gateway_binary_source,
+            verify_gateway_binary,
+            get_next_scheduled_restart,
+            get_startup_estimate,
+            get_sidecar_ownership,
+            get_reconnect_state,
+            get_connection_decision,
+            handle_gateway_shutdown,
+            // Proxy
+            proxy_request,
+            proxy_stream,
+            start_async_operation,
+            get_async_operation,
+            list_active_requests,
+            cancel_active_request,
+            get_request_queue_stats,
+            get_gateway_load,
+            get_cache_stats,
+            clear_response_cache,
+            set_bandwidth_limits,
+            get_bandwidth_limits,
+            start_event_recording,
+            stop_event_recording,
+            replay_event_recording,
+            set_connection_pool,
+            set_request_tracing,
+            set_proxy_allowlist,
+            get_max_request_size,
+            get_negotiated_protocol,
+            get_connection_pool_stats,
+            // Gateway logs
+            get_gateway_logs,
+            export_gateway_log,
+            set_gateway_log_level,
+            start_gateway_log_stream,
+            stop_gateway_log_stream,
+            // Discovery
+            rescan_gateways,
+            clear_discovery_cache,
+            refresh_gateway_metadata,
+            // Models
+            get_available_models,
+            get_active_model,
+            set_active_model,
+            get_persona_profile,
+            // Profiles
+            list_profiles,
+            save_profile,
+            delete_profile,
+            connect_profile,
+            connect_by_fingerprint,
+            pair_with_code,
+            rotate_gateway_token,
+            set_profile_user_agent,
+            set_profile_min_tls_version,
+            set_profile_cert_pin,
+            get_gateway_cert_fingerprint,
+            apply_gateway_config_blob,
+            // Settings
+            get_settings,
+            repair_settings,
+            update_settings,
+            set_offline_mode,
+            is_offline_mode,
+            set_close_to_tray,
+            set_discovery_enabled,
+            // Permissions
+            get_permission_states,
+            request_permission,
+            // Opener
+            open_external,
+            reveal_gateway_binary,
             // Secure storage
             get_secure_storage,
             set_secure_storage,
+            test_secure_storage,
+            migrate_secure_storage,
+            // Diagnostics
+            get_diagnostics,
+            get_bundled_gateway_manifest,
+            check_clock_skew,
+            test_event_channel,
+            export_diagnostics,
+            get_enabled_features,
+            ping_gateway_host,
+            diagnose_connection,
+            validate_connection,
+            get_recent_errors,
+            export_metrics_csv,
+            start_metrics_stream,
+            stop_metrics_stream,
+            export_state_snapshot,
+            import_state_snapshot,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Stop the sidecars on the way out so they don't outlive the app
+            if let RunEvent::Exit = event {
+                let state = app.state::<Arc<AppState>>();
+                tauri::async_runtime::block_on(gateway::stop_all_sidecars(&state));
+            }
+        });
 }
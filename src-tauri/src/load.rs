@@ -0,0 +1,129 @@
+//! Gateway load and request backpressure
+//!
+//! A busy shared gateway reports how many requests it has queued in its
+//! `/health` detail. That depth is polled while connected, turned into a
+//! pressure level and published as `gateway-load` events. Under pressure the
+//! request limiter is tightened below `max_concurrent_requests`, so an eager
+//! client backs off instead of piling more onto a backed-up gateway; it opens
+//! up again once the queue drains. Gateways that don't report a queue depth
+//! are never throttled.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::{gateway, health, logs, AppState};
+
+/// How often the gateway's queue depth is checked
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Queue depth from which pressure is elevated
+const ELEVATED_DEPTH: u64 = 3;
+
+/// Queue depth from which pressure is high
+const HIGH_DEPTH: u64 = 10;
+
+/// How backed up the gateway is
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pressure {
+    /// Gateway doesn't report a queue depth
+    Unknown,
+    Low,
+    Elevated,
+    High,
+}
+
+impl Pressure {
+    fn from_depth(depth: Option<u64>) -> Self {
+        match depth {
+            None => Self::Unknown,
+            Some(depth) if depth >= HIGH_DEPTH => Self::High,
+            Some(depth) if depth >= ELEVATED_DEPTH => Self::Elevated,
+            Some(_) => Self::Low,
+        }
+    }
+
+    /// Concurrency allowed under this pressure, out of `configured`
+    fn limit(self, configured: usize) -> usize {
+        let limit = match self {
+            Self::Unknown | Self::Low => configured,
+            Self::Elevated => configured / 2,
+            Self::High => configured / 4,
+        };
+        limit.max(1)
+    }
+}
+
+/// Gateway load, also the `gateway-load` event payload
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GatewayLoad {
+    /// Requests queued on the gateway, if it reports them
+    pub queue_depth: Option<u64>,
+
+    pub pressure: Pressure,
+
+    /// Proxied requests currently allowed in flight at once
+    pub request_limit: usize,
+
+    /// `max_concurrent_requests` from settings
+    pub configured_limit: usize,
+
+    /// When the depth was read (ms since Unix epoch)
+    pub checked_at_ms: u64,
+}
+
+/// Poll gateway load for the lifetime of the app
+pub async fn run(app: AppHandle, state: Arc<AppState>) {
+    let start = tokio::time::Instant::now() + gateway::phase_offset(POLL_INTERVAL);
+    let mut ticker = tokio::time::interval_at(start, POLL_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+
+        let configured = state.settings.read().await.max_concurrent_requests;
+        let queue_depth = if state.is_connected().await {
+            match health::detail(&state).await {
+                Ok(detail) => detail.queue_depth,
+                Err(e) => {
+                    tracing::debug!(error = %e, "gateway load check failed");
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+
+        let pressure = Pressure::from_depth(queue_depth);
+        let request_limit = pressure.limit(configured);
+        state.request_limiter.set_limit(request_limit);
+
+        let load = GatewayLoad {
+            queue_depth,
+            pressure,
+            request_limit,
+            configured_limit: configured,
+            checked_at_ms: logs::now_ms(),
+        };
+
+        let mut current = state.gateway_load.write().await;
+        let changed = current.as_ref().is_none_or(|previous| {
+            previous.queue_depth != load.queue_depth || previous.request_limit != load.request_limit
+        });
+        if changed {
+            if current.as_ref().map(|l| l.pressure) != Some(pressure) {
+                tracing::info!(
+                    ?pressure,
+                    queue_depth,
+                    request_limit,
+                    "gateway load changed"
+                );
+            }
+            let _ = app.emit("gateway-load", &load);
+        }
+        *current = Some(load);
+    }
+}
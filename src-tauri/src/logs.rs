@@ -0,0 +1,196 @@
+//! Sidecar log capture
+//!
+//! The sidecar's stdout/stderr are read line by line into a bounded ring
+//! buffer (for `get_gateway_logs` snapshots). When live streaming is on, new
+//! lines are also batched and emitted as `gateway-log-line` events.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::export::ExportFile;
+
+/// Maximum lines kept in the ring buffer
+const MAX_LINES: usize = 1000;
+
+/// How often batched lines are flushed to the frontend
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Log level detected from a line's text
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// Level name as understood by the gateway's `BEACON_LOG`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+
+    /// Detect the level of a log line (`Info` when none is found)
+    fn detect(line: &str) -> Self {
+        let upper = line.to_ascii_uppercase();
+        let has = |token: &str| {
+            upper
+                .split(|c: char| !c.is_ascii_alphabetic())
+                .any(|w| w == token)
+        };
+
+        if has("ERROR") {
+            Self::Error
+        } else if has("WARN") || has("WARNING") {
+            Self::Warn
+        } else if has("DEBUG") {
+            Self::Debug
+        } else if has("TRACE") {
+            Self::Trace
+        } else {
+            Self::Info
+        }
+    }
+}
+
+/// A captured sidecar log line
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    /// Capture time (ms since Unix epoch)
+    pub timestamp_ms: u64,
+
+    /// Source stream (`stdout` or `stderr`)
+    pub stream: &'static str,
+
+    pub level: LogLevel,
+    pub line: String,
+}
+
+/// Sidecar log buffer, shared with the reader threads
+#[derive(Default)]
+pub struct GatewayLog {
+    lines: Mutex<VecDeque<LogLine>>,
+    pending: Mutex<Vec<LogLine>>,
+    streaming: AtomicBool,
+}
+
+impl GatewayLog {
+    /// Most recent lines, oldest first
+    pub fn recent(&self, limit: usize) -> Vec<LogLine> {
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        let skip = lines.len().saturating_sub(limit);
+        lines.iter().skip(skip).cloned().collect()
+    }
+
+    /// Enable or disable live `gateway-log-line` events
+    pub fn set_streaming(&self, enabled: bool) {
+        self.streaming.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clear();
+        }
+    }
+
+    /// Write all buffered lines into `dir` (gzip-compressed if `compress`),
+    /// returning the file's path
+    pub fn export(&self, dir: &Path, compress: bool) -> Result<PathBuf, String> {
+        let name = format!("gateway-{}.log", now_ms());
+        let (path, mut file) = ExportFile::create(dir, &name, compress)?;
+
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        for entry in lines.iter() {
+            writeln!(
+                file,
+                "{} {} {} {}",
+                entry.timestamp_ms,
+                entry.stream,
+                entry.level.as_str(),
+                entry.line
+            )
+            .map_err(|e| format!("failed to write gateway log: {e}"))?;
+        }
+        drop(lines);
+
+        file.finish()?;
+        Ok(path)
+    }
+
+    fn push(&self, stream: &'static str, line: String) {
+        let entry = LogLine {
+            timestamp_ms: now_ms(),
+            stream,
+            level: LogLevel::detect(&line),
+            line,
+        };
+
+        if self.streaming.load(Ordering::Relaxed) {
+            self.pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(entry.clone());
+        }
+
+        let mut lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() == MAX_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(entry);
+    }
+
+    fn take_pending(&self) -> Vec<LogLine> {
+        std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Read a sidecar output stream into the log on a background thread
+pub fn capture(log: Arc<GatewayLog>, stream: &'static str, reader: impl Read + Send + 'static) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            log.push(stream, line);
+        }
+    });
+}
+
+/// Periodically emit batched lines while streaming is enabled
+///
+/// Batching keeps a chatty gateway from flooding IPC with one event per line.
+pub fn spawn_flusher(app: AppHandle, log: Arc<GatewayLog>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(FLUSH_INTERVAL).await;
+
+            let batch = log.take_pending();
+            if !batch.is_empty() {
+                let _ = app.emit("gateway-log-line", &batch);
+            }
+        }
+    });
+}
+
+/// Current time in ms since the Unix epoch
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,148 @@
+//! Version manifest of the bundled gateway binary
+//!
+//! Packaged builds ship a `gateway-manifest.json` next to the sidecar,
+//! recording the version, SHA-256 and build date of the gateway binary they
+//! were built with. It answers "which gateway did this build ship with?" for
+//! support. At startup the bundled binary is hashed and checked against it,
+//! and a mismatch (a tampered or mis-packaged app) is warned about with a
+//! `gateway-manifest-mismatch` event.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::{download, AppState};
+
+/// Manifest file name, next to the bundled binary
+const MANIFEST_FILE: &str = "gateway-manifest.json";
+
+/// Names the bundled binary may have, as looked for by `find_gateway_binary`
+const BINARY_NAMES: &[&str] = &["beacon-gateway", "beacon"];
+
+/// Bundled gateway manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+
+    /// Hex SHA-256 of the binary
+    pub sha256: String,
+
+    /// When the binary was built, as recorded by the build
+    pub build_date: Option<String>,
+}
+
+/// The bundled binary checked against its manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestCheck {
+    pub manifest: Manifest,
+
+    /// Binary the manifest describes, if it's there
+    pub binary: Option<PathBuf>,
+
+    /// SHA-256 of the binary, if it could be read
+    pub actual_sha256: Option<String>,
+
+    /// Whether the binary is the one in the manifest
+    pub matches: bool,
+
+    pub error: Option<String>,
+}
+
+/// Read the bundled manifest, if the app ships one
+pub fn bundled() -> Result<Option<Manifest>, String> {
+    locate().map(|path| read(&path)).transpose()
+}
+
+/// Hash the bundled binary and compare it with the manifest
+///
+/// Reads the whole binary, so this is run off the async runtime.
+pub fn check() -> Result<Option<ManifestCheck>, String> {
+    let Some(path) = locate() else {
+        return Ok(None);
+    };
+    let manifest = read(&path)?;
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let Some(binary) = BINARY_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|p| p.exists())
+    else {
+        return Ok(Some(ManifestCheck {
+            manifest,
+            binary: None,
+            actual_sha256: None,
+            matches: false,
+            error: Some(format!("no gateway binary next to {}", path.display())),
+        }));
+    };
+
+    let (actual_sha256, error) = match download::sha256_file(&binary) {
+        Ok(sha256) => (Some(sha256), None),
+        Err(e) => (None, Some(e)),
+    };
+    let matches = actual_sha256
+        .as_deref()
+        .is_some_and(|actual| actual.eq_ignore_ascii_case(manifest.sha256.trim()));
+    Ok(Some(ManifestCheck {
+        manifest,
+        binary: Some(binary),
+        actual_sha256,
+        matches,
+        error,
+    }))
+}
+
+/// Check the bundled binary at startup, keeping the result for diagnostics
+pub async fn verify_at_startup(app: AppHandle, state: Arc<AppState>) {
+    let checked = tokio::task::spawn_blocking(check)
+        .await
+        .unwrap_or_else(|e| Err(format!("manifest check panicked: {e}")));
+
+    let check = match checked {
+        Ok(Some(check)) => check,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to read the bundled gateway manifest");
+            return;
+        }
+    };
+
+    if check.matches {
+        tracing::info!(
+            version = %check.manifest.version,
+            "bundled gateway binary matches its manifest"
+        );
+    } else {
+        tracing::warn!(
+            expected = %check.manifest.sha256,
+            actual = ?check.actual_sha256,
+            binary = ?check.binary,
+            error = ?check.error,
+            "bundled gateway binary doesn't match its manifest"
+        );
+        let _ = app.emit("gateway-manifest-mismatch", &check);
+    }
+    *state.manifest_check.write().await = Some(check);
+}
+
+/// Manifest path, in the places a bundled binary is looked for
+fn locate() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let dir = exe.parent()?;
+    [
+        dir.join(MANIFEST_FILE),
+        dir.join("../Resources").join(MANIFEST_FILE),
+    ]
+    .into_iter()
+    .find(|path| path.exists())
+}
+
+fn read(path: &Path) -> Result<Manifest, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| format!("invalid gateway manifest {}: {e}", path.display()))
+}
@@ -0,0 +1,251 @@
+//! Connection metrics
+//!
+//! Health-check latency samples, connection state transitions and proxied
+//! request counts, kept in bounded rings for the lifetime of the session and
+//! exportable as CSV for charting connection stability externally.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{logs, GatewayState};
+
+/// Maximum latency samples kept
+const MAX_SAMPLES: usize = 10_000;
+
+/// Maximum connection events kept
+const MAX_EVENTS: usize = 1000;
+
+/// A timed health check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencySample {
+    pub timestamp_ms: u64,
+    pub url: String,
+
+    /// Round-trip time of the check (ms)
+    pub latency_ms: u64,
+
+    /// Whether the gateway answered healthy
+    pub ok: bool,
+}
+
+/// A gateway connection state transition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionEvent {
+    pub timestamp_ms: u64,
+
+    /// New state (`connected`, `disconnected`, `starting`, `restarting`,
+    /// `failed`); `restarting` marks expected downtime, `failed` a crash
+    pub state: String,
+
+    /// URL when connected, error when failed, phase when starting
+    pub detail: Option<String>,
+}
+
+/// Copy of all session metrics, as captured in a state snapshot
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub samples: Vec<LatencySample>,
+    pub events: Vec<ConnectionEvent>,
+    pub requests_ok: u64,
+    pub requests_failed: u64,
+}
+
+/// Session metrics, shared across the app
+#[derive(Default)]
+pub struct Metrics {
+    samples: Mutex<VecDeque<LatencySample>>,
+    events: Mutex<VecDeque<ConnectionEvent>>,
+    requests_ok: AtomicU64,
+    requests_failed: AtomicU64,
+}
+
+impl Metrics {
+    /// Record a health check
+    pub fn record_latency(&self, url: &str, latency_ms: u64, ok: bool) {
+        push_bounded(
+            &self.samples,
+            MAX_SAMPLES,
+            LatencySample {
+                timestamp_ms: logs::now_ms(),
+                url: url.to_string(),
+                latency_ms,
+                ok,
+            },
+        );
+    }
+
+    /// Record a connection state transition
+    pub fn record_state(&self, state: &GatewayState) {
+        let (state, detail) = match state {
+            GatewayState::Disconnected => ("disconnected", None),
+            GatewayState::Starting { phase, .. } => ("starting", Some(phase.as_str().to_string())),
+            GatewayState::Connected { url, .. } => ("connected", Some(url.clone())),
+            GatewayState::Restarting { reason, .. } => ("restarting", Some(reason.clone())),
+            GatewayState::Failed { error } => ("failed", Some(error.clone())),
+        };
+
+        push_bounded(
+            &self.events,
+            MAX_EVENTS,
+            ConnectionEvent {
+                timestamp_ms: logs::now_ms(),
+                state: state.to_string(),
+                detail,
+            },
+        );
+    }
+
+    /// Count a finished proxied request
+    pub fn record_request(&self, ok: bool) {
+        let counter = if ok {
+            &self.requests_ok
+        } else {
+            &self.requests_failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Proxied requests finished so far, as (succeeded, failed)
+    pub fn request_counts(&self) -> (u64, u64) {
+        (
+            self.requests_ok.load(Ordering::Relaxed),
+            self.requests_failed.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Newest health check of `url`
+    pub fn latest_sample(&self, url: &str) -> Option<LatencySample> {
+        lock(&self.samples)
+            .iter()
+            .rev()
+            .find(|sample| sample.url == url)
+            .cloned()
+    }
+
+    /// When the current connection was made, if the latest transition was
+    /// to connected (ms since Unix epoch)
+    pub fn connected_since_ms(&self) -> Option<u64> {
+        lock(&self.events)
+            .back()
+            .filter(|event| event.state == "connected")
+            .map(|event| event.timestamp_ms)
+    }
+
+    /// Copy out all metrics
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            samples: lock(&self.samples).iter().cloned().collect(),
+            events: lock(&self.events).iter().cloned().collect(),
+            requests_ok: self.requests_ok.load(Ordering::Relaxed),
+            requests_failed: self.requests_failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Replace all metrics with a snapshot (the newest entries if it holds
+    /// more than the rings keep)
+    pub fn restore(&self, snapshot: MetricsSnapshot) {
+        let samples = snapshot.samples.len().saturating_sub(MAX_SAMPLES);
+        *lock(&self.samples) = snapshot.samples.into_iter().skip(samples).collect();
+
+        let events = snapshot.events.len().saturating_sub(MAX_EVENTS);
+        *lock(&self.events) = snapshot.events.into_iter().skip(events).collect();
+
+        self.requests_ok
+            .store(snapshot.requests_ok, Ordering::Relaxed);
+        self.requests_failed
+            .store(snapshot.requests_failed, Ordering::Relaxed);
+    }
+
+    /// Write all metrics as CSV into `dir`, returning the file's path
+    pub fn export_csv(&self, dir: &Path) -> Result<PathBuf, String> {
+        let now = logs::now_ms();
+        let mut csv = String::new();
+
+        // Header comments describe the capture so the file stands on its own
+        let _ = writeln!(
+            csv,
+            "# beacon-app connection metrics, exported at {now} (ms since Unix epoch)"
+        );
+        let _ = writeln!(
+            csv,
+            "# latency: health-check round trips (value = ms, detail = url; ok = healthy)"
+        );
+        let _ = writeln!(
+            csv,
+            "# event: connection state changes (value = new state, detail = url, error or phase)"
+        );
+        let _ = writeln!(
+            csv,
+            "# requests: proxied request totals at export time (value = count)"
+        );
+        let _ = writeln!(
+            csv,
+            "# at most {MAX_SAMPLES} latency samples and {MAX_EVENTS} events are retained"
+        );
+        csv.push_str("timestamp_ms,kind,value,ok,detail\n");
+
+        for s in self
+            .samples
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                csv,
+                "{},latency,{},{},{}",
+                s.timestamp_ms,
+                s.latency_ms,
+                s.ok,
+                field(&s.url)
+            );
+        }
+
+        for e in self.events.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            let _ = writeln!(
+                csv,
+                "{},event,{},,{}",
+                e.timestamp_ms,
+                e.state,
+                field(e.detail.as_deref().unwrap_or_default())
+            );
+        }
+
+        let ok = self.requests_ok.load(Ordering::Relaxed);
+        let failed = self.requests_failed.load(Ordering::Relaxed);
+        let _ = writeln!(csv, "{now},requests,{ok},true,");
+        let _ = writeln!(csv, "{now},requests,{failed},false,");
+
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+        let path = dir.join(format!("metrics-{now}.csv"));
+        std::fs::write(&path, csv).map_err(|e| format!("failed to write metrics: {e}"))?;
+
+        Ok(path)
+    }
+}
+
+fn lock<T>(ring: &Mutex<VecDeque<T>>) -> std::sync::MutexGuard<'_, VecDeque<T>> {
+    ring.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn push_bounded<T>(ring: &Mutex<VecDeque<T>>, max: usize, item: T) {
+    let mut ring = lock(ring);
+    if ring.len() == max {
+        ring.pop_front();
+    }
+    ring.push_back(item);
+}
+
+/// Quote a CSV field if it needs it
+fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
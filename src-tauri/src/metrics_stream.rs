@@ -0,0 +1,199 @@
+//! Live gateway metrics feed
+//!
+//! While a stream runs, the connection is sampled every interval and each
+//! sample is emitted as a `gateway-metrics` event for charting: health-check
+//! latency, connection uptime, proxied request counts, CPU and memory of the
+//! sidecars, and bandwidth through the proxy. Sampling is kept cheap. A
+//! health check the sidecar monitor made within the interval is reused
+//! instead of probing again, and only the sidecar processes are refreshed in
+//! sysinfo, for CPU and memory alone. The stream ends on its own once the
+//! gateway disconnects.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Notify;
+
+use crate::{gateway, logs, AppState, GatewayState};
+
+/// Shortest sampling interval accepted (ms)
+pub const MIN_INTERVAL_MS: u64 = 250;
+
+/// Longest sampling interval accepted (ms)
+pub const MAX_INTERVAL_MS: u64 = 60_000;
+
+/// Check a sampling interval
+pub fn check_interval(interval_ms: u64) -> Result<(), String> {
+    if (MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&interval_ms) {
+        Ok(())
+    } else {
+        Err(format!(
+            "metrics interval must be {MIN_INTERVAL_MS}-{MAX_INTERVAL_MS}ms (got {interval_ms})"
+        ))
+    }
+}
+
+/// One sample, the `gateway-metrics` event payload
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsTick {
+    pub timestamp_ms: u64,
+    pub url: String,
+
+    /// Round-trip time of the latest health check (ms)
+    pub latency_ms: u64,
+
+    /// Whether the latest health check answered healthy
+    pub healthy: bool,
+
+    /// Time since the connection was made (ms)
+    pub uptime_ms: Option<u64>,
+
+    /// Proxied requests finished this session
+    pub requests_ok: u64,
+    pub requests_failed: u64,
+
+    /// Proxied traffic since the previous sample (bytes/sec)
+    pub upload_bps: u64,
+    pub download_bps: u64,
+
+    /// Resource use of each sidecar this instance runs
+    pub sidecars: Vec<ProcessUsage>,
+}
+
+/// Resource use of a sidecar process
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessUsage {
+    pub persona: String,
+    pub pid: u32,
+
+    /// CPU use since the previous sample, 100 per core (0 on the first)
+    pub cpu_percent: f32,
+
+    /// Resident memory (bytes)
+    pub memory_bytes: u64,
+}
+
+/// Sample every `interval` and emit `gateway-metrics` until `stop` is
+/// notified or the gateway disconnects
+pub async fn run(app: AppHandle, state: Arc<AppState>, interval: Duration, stop: Arc<Notify>) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let mut system = System::new();
+    let mut transferred = (
+        state.upload_throttle.transferred(),
+        state.download_throttle.transferred(),
+    );
+    let mut last_sample = tokio::time::Instant::now();
+
+    tracing::info!(
+        interval_ms = interval.as_millis() as u64,
+        "metrics stream started"
+    );
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            () = stop.notified() => break,
+        }
+
+        let url = match &*state.gateway_state.read().await {
+            GatewayState::Connected { url, .. } | GatewayState::Restarting { url, .. } => {
+                url.clone()
+            }
+            _ => {
+                tracing::info!("gateway disconnected, ending metrics stream");
+                break;
+            }
+        };
+
+        let (latency_ms, healthy) = latency(&state, &url, interval).await;
+        let (requests_ok, requests_failed) = state.metrics.request_counts();
+
+        let elapsed = last_sample.elapsed().as_secs_f64().max(0.001);
+        last_sample = tokio::time::Instant::now();
+        let now = (
+            state.upload_throttle.transferred(),
+            state.download_throttle.transferred(),
+        );
+        let rate = |bytes: u64| (bytes as f64 / elapsed) as u64;
+        let upload_bps = rate(now.0.saturating_sub(transferred.0));
+        let download_bps = rate(now.1.saturating_sub(transferred.1));
+        transferred = now;
+
+        let timestamp_ms = logs::now_ms();
+        let tick = MetricsTick {
+            timestamp_ms,
+            url,
+            latency_ms,
+            healthy,
+            uptime_ms: state
+                .metrics
+                .connected_since_ms()
+                .map(|since| timestamp_ms.saturating_sub(since)),
+            requests_ok,
+            requests_failed,
+            upload_bps,
+            download_bps,
+            sidecars: sidecar_usage(&state, &mut system).await,
+        };
+        let _ = app.emit("gateway-metrics", &tick);
+    }
+
+    // Leave the slot to a newer stream that replaced this one
+    let mut current = state.metrics_stream.write().await;
+    if current.as_ref().is_some_and(|c| Arc::ptr_eq(c, &stop)) {
+        *current = None;
+    }
+    tracing::info!("metrics stream stopped");
+}
+
+/// Latest health check of `url`, probing only if none is fresher than
+/// `interval`
+async fn latency(state: &AppState, url: &str, interval: Duration) -> (u64, bool) {
+    let fresh_ms = logs::now_ms().saturating_sub(interval.as_millis() as u64);
+    if let Some(sample) = state
+        .metrics
+        .latest_sample(url)
+        .filter(|sample| sample.timestamp_ms >= fresh_ms)
+    {
+        return (sample.latency_ms, sample.ok);
+    }
+
+    let client = state.client.read().await.clone();
+    let started = tokio::time::Instant::now();
+    let healthy = gateway::probe_with_client(&client, url).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    state.metrics.record_latency(url, latency_ms, healthy);
+    (latency_ms, healthy)
+}
+
+/// CPU and memory of the sidecars, refreshing just their processes
+async fn sidecar_usage(state: &AppState, system: &mut System) -> Vec<ProcessUsage> {
+    let sidecars = gateway::list_sidecars(state).await;
+    if sidecars.is_empty() {
+        return Vec::new();
+    }
+
+    let pids: Vec<Pid> = sidecars.iter().map(|s| Pid::from_u32(s.pid)).collect();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&pids),
+        true,
+        ProcessRefreshKind::nothing().with_cpu().with_memory(),
+    );
+
+    sidecars
+        .into_iter()
+        .filter_map(|sidecar| {
+            let process = system.process(Pid::from_u32(sidecar.pid))?;
+            Some(ProcessUsage {
+                persona: sidecar.persona,
+                pid: sidecar.pid,
+                cpu_percent: process.cpu_usage(),
+                memory_bytes: process.memory(),
+            })
+        })
+        .collect()
+}
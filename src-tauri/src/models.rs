@@ -0,0 +1,93 @@
+//! Gateway model selection
+//!
+//! The model is the LLM the gateway runs with, independent of the persona.
+//! Remote gateways switch models at runtime; a sidecar that can't is
+//! restarted with the model passed through its environment instead.
+
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{api, cache, gateway, AppState, GatewayState};
+
+/// A model the gateway can run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+
+    #[serde(default)]
+    pub provider: Option<String>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Model list, either bare or wrapped in `{ "models": [...] }`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ModelList {
+    Wrapped { models: Vec<ModelInfo> },
+    Bare(Vec<ModelInfo>),
+}
+
+/// The subset of `/status` describing the active model
+#[derive(Deserialize)]
+struct StatusModel {
+    model: Option<ModelInfo>,
+}
+
+/// List the models the connected gateway offers
+pub async fn available(state: &AppState) -> Result<Vec<ModelInfo>, String> {
+    Ok(match api::get::<ModelList>(state, "/models").await? {
+        ModelList::Wrapped { models } | ModelList::Bare(models) => models,
+    })
+}
+
+/// Fetch the model the connected gateway is running
+pub async fn active(state: &AppState) -> Result<Option<ModelInfo>, String> {
+    let status: StatusModel = api::get(state, "/status").await?;
+    *state.active_model.write().await = status.model.clone();
+    Ok(status.model)
+}
+
+/// Switch the connected gateway to `model_id`
+///
+/// Fails for models the gateway doesn't list. If the gateway has no runtime
+/// switch endpoint and we manage it as a sidecar, it's restarted with the
+/// model set through `BEACON_MODEL`.
+pub async fn set_active(state: &AppState, model_id: &str) -> Result<ModelInfo, String> {
+    let models = available(state).await?;
+    let Some(model) = models.iter().find(|m| m.id == model_id).cloned() else {
+        let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        return Err(format!(
+            "unsupported model `{model_id}` (available: {})",
+            ids.join(", ")
+        ));
+    };
+
+    let path = format!("/models/{model_id}/activate");
+    let resp = api::request(state, Method::POST, &path, None).await?;
+
+    let is_sidecar = matches!(
+        &*state.gateway_state.read().await,
+        GatewayState::Connected {
+            is_sidecar: true,
+            ..
+        }
+    );
+    let unsupported = matches!(
+        resp.status(),
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+    );
+
+    if unsupported && is_sidecar {
+        tracing::info!(model = %model_id, "restarting sidecar to switch model");
+        *state.sidecar_model.write().await = Some(model_id.to_string());
+        gateway::restart_sidecar(state).await?;
+    } else {
+        api::json::<serde_json::Value>(resp).await?;
+    }
+
+    *state.active_model.write().await = Some(model.clone());
+    cache::clear(state).await;
+    Ok(model)
+}
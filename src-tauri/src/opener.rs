@@ -0,0 +1,132 @@
+//! Guarded opening of external links and files
+//!
+//! Links in assistant output are untrusted, so nothing is handed to the OS
+//! opener without passing this allowlist: URLs must use an allowed scheme and
+//! local files must live inside the app's data directory.
+
+use std::path::{Path, PathBuf};
+
+/// A validated target, safe to open
+#[derive(Debug)]
+pub enum OpenTarget {
+    Url(String),
+    Path(PathBuf),
+}
+
+/// Validate a link or file path against the allowlist
+pub fn validate(
+    target: &str,
+    allowed_schemes: &[String],
+    data_dir: &Path,
+) -> Result<OpenTarget, String> {
+    let target = target.trim();
+
+    // Single-letter "schemes" are Windows drive letters, not URLs
+    let url = reqwest::Url::parse(target)
+        .ok()
+        .filter(|url| url.scheme().len() > 1);
+
+    let path = match url {
+        Some(url) if url.scheme() == "file" => url
+            .to_file_path()
+            .map_err(|()| format!("invalid file URL `{target}`"))?,
+        Some(url) => {
+            let scheme = url.scheme();
+            if !allowed_schemes
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+            {
+                return Err(format!("opening `{scheme}:` links is not allowed"));
+            }
+            return Ok(OpenTarget::Url(url.to_string()));
+        }
+        None => PathBuf::from(target),
+    };
+
+    // Canonicalize so `..` segments and symlinks can't escape the data dir
+    let path = path
+        .canonicalize()
+        .map_err(|e| format!("cannot open `{target}`: {e}"))?;
+    let data_dir = data_dir
+        .canonicalize()
+        .map_err(|e| format!("cannot resolve data directory: {e}"))?;
+
+    if !path.starts_with(&data_dir) {
+        return Err(format!(
+            "opening `{}` is not allowed, only files in the app data directory can be opened",
+            path.display()
+        ));
+    }
+
+    Ok(OpenTarget::Path(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A data directory holding `inside.txt`, next to a directory holding
+    /// `outside.txt`
+    fn dirs(name: &str) -> (PathBuf, PathBuf) {
+        let base =
+            std::env::temp_dir().join(format!("beacon-opener-{}-{name}", std::process::id()));
+        let (data, outside) = (base.join("data"), base.join("outside"));
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(data.join("inside.txt"), "").unwrap();
+        std::fs::write(outside.join("outside.txt"), "").unwrap();
+        (data, outside)
+    }
+
+    fn schemes(schemes: &[&str]) -> Vec<String> {
+        schemes.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn disallowed_schemes_are_refused() {
+        let data = std::env::temp_dir();
+        let allowed = schemes(&["https"]);
+        assert!(validate("javascript:alert(1)", &allowed, &data).is_err());
+        assert!(validate("ftp://example.com/file", &allowed, &data).is_err());
+    }
+
+    #[test]
+    fn schemes_match_in_any_case() {
+        let data = std::env::temp_dir();
+        let target = validate("HTTPS://example.com/a", &schemes(&["https"]), &data).unwrap();
+        assert!(matches!(target, OpenTarget::Url(url) if url == "https://example.com/a"));
+        let target = validate("https://example.com/a", &schemes(&["HTTPS"]), &data);
+        assert!(matches!(target, Ok(OpenTarget::Url(_))));
+    }
+
+    #[test]
+    fn drive_letters_are_paths_not_schemes() {
+        let error =
+            validate(r"C:\missing\file.txt", &schemes(&[]), &std::env::temp_dir()).unwrap_err();
+        assert!(error.starts_with("cannot open"), "{error}");
+    }
+
+    #[test]
+    fn files_must_be_in_the_data_dir() {
+        let (data, outside) = dirs("file-url");
+        let inside = reqwest::Url::from_file_path(data.join("inside.txt")).unwrap();
+        assert!(matches!(
+            validate(inside.as_str(), &[], &data),
+            Ok(OpenTarget::Path(_))
+        ));
+
+        let escaped = reqwest::Url::from_file_path(outside.join("outside.txt")).unwrap();
+        let error = validate(escaped.as_str(), &[], &data).unwrap_err();
+        assert!(error.contains("not allowed"), "{error}");
+        std::fs::remove_dir_all(data.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn dot_segments_cant_escape_the_data_dir() {
+        let (data, _) = dirs("dot-dot");
+        let target = data.join("..").join("outside").join("outside.txt");
+        let error = validate(target.to_str().unwrap(), &[], &data).unwrap_err();
+        assert!(error.contains("not allowed"), "{error}");
+        std::fs::remove_dir_all(data.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+//! Pairing with a gateway by short code
+//!
+//! The device running the gateway shows a 6-digit code; entering it here
+//! exchanges it at the gateway's `/api/pair` endpoint for an auth token. A
+//! wrong code and an expired one are reported differently, so the UI can
+//! tell the user to retype it or to ask for a new one.
+//!
+//! Six digits are only a million guesses, so failed attempts are also
+//! limited on this side, per gateway, on top of whatever the gateway does.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::gateway;
+
+/// Digits in a pairing code
+const CODE_LEN: usize = 6;
+
+/// Failed attempts allowed per gateway before pairing with it is locked
+const MAX_ATTEMPTS: u32 = 5;
+
+/// How long pairing stays locked after too many failed attempts
+const LOCKOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Token (and identity) handed out in exchange for a valid code
+#[derive(Deserialize)]
+pub struct Paired {
+    #[serde(alias = "access_token")]
+    pub token: String,
+
+    /// Gateway name, suggested as the profile name
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Gateway device ID
+    #[serde(default)]
+    pub device_id: Option<String>,
+}
+
+/// Why the gateway refused a code
+enum Refused {
+    Invalid,
+    Expired,
+}
+
+/// Failed and in-flight attempts per gateway URL
+#[derive(Default)]
+pub struct PairingAttempts {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+struct Entry {
+    /// Wrong codes since the lockout last lapsed
+    failures: u32,
+
+    /// Attempts sent and not answered yet
+    pending: u32,
+
+    /// When the last wrong code was counted
+    last: Instant,
+}
+
+impl PairingAttempts {
+    /// Reserve an attempt at pairing with `url`, failing if it's locked
+    /// after too many wrong codes
+    ///
+    /// Attempts still waiting for an answer count against the limit, so
+    /// concurrent ones can't get past it.
+    fn reserve<'a>(&'a self, url: &'a str) -> Result<Attempt<'a>, String> {
+        let mut entries = self.lock();
+        let entry = entries.entry(url.to_string()).or_insert(Entry {
+            failures: 0,
+            pending: 0,
+            last: Instant::now(),
+        });
+
+        let since_failure = entry.last.elapsed();
+        if since_failure >= LOCKOUT {
+            entry.failures = 0;
+        }
+        if entry.failures >= MAX_ATTEMPTS {
+            let wait = LOCKOUT.saturating_sub(since_failure).as_secs().max(1);
+            return Err(format!(
+                "too many wrong pairing codes, try again in {wait}s"
+            ));
+        }
+        if entry.failures + entry.pending >= MAX_ATTEMPTS {
+            return Err("other pairing attempts are still being checked, try again".to_string());
+        }
+
+        entry.pending += 1;
+        Ok(Attempt {
+            attempts: self,
+            url,
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A reserved pairing attempt, given back once dropped unless it turned
+/// out to be a wrong code
+struct Attempt<'a> {
+    attempts: &'a PairingAttempts,
+    url: &'a str,
+}
+
+impl Attempt<'_> {
+    /// Count the attempt as a wrong code, returning how many attempts are
+    /// left
+    fn wrong(self) -> u32 {
+        let mut entries = self.attempts.lock();
+        let Some(entry) = entries.get_mut(self.url) else {
+            return MAX_ATTEMPTS;
+        };
+        entry.failures += 1;
+        entry.last = Instant::now();
+        MAX_ATTEMPTS.saturating_sub(entry.failures)
+    }
+
+    /// Clear the wrong codes counted against the gateway
+    fn succeeded(self) {
+        if let Some(entry) = self.attempts.lock().get_mut(self.url) {
+            entry.failures = 0;
+        }
+    }
+}
+
+impl Drop for Attempt<'_> {
+    fn drop(&mut self) {
+        let mut entries = self.attempts.lock();
+        let Some(entry) = entries.get_mut(self.url) else {
+            return;
+        };
+        entry.pending = entry.pending.saturating_sub(1);
+        if entry.pending == 0 && entry.failures == 0 {
+            entries.remove(self.url);
+        }
+    }
+}
+
+/// Check that a pairing code is 6 digits (spaces and dashes are ignored)
+pub fn normalize_code(code: &str) -> Result<String, String> {
+    let digits: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect();
+    if digits.len() != CODE_LEN || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("pairing code must be {CODE_LEN} digits"));
+    }
+    Ok(digits)
+}
+
+/// Exchange a pairing code for a token from the gateway at `url`
+pub async fn pair(attempts: &PairingAttempts, url: &str, code: &str) -> Result<Paired, String> {
+    let code = normalize_code(code)?;
+    let attempt = attempts.reserve(url)?;
+
+    let resp = gateway::default_client()
+        .post(format!("{url}/api/pair"))
+        .json(&serde_json::json!({ "code": code }))
+        .timeout(gateway::PROBE_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("failed to reach gateway at {url}: {e}"))?;
+
+    let status = resp.status();
+    if status.is_success() {
+        attempt.succeeded();
+        return resp
+            .json()
+            .await
+            .map_err(|e| format!("invalid pairing response: {e}"));
+    }
+
+    let body = resp.text().await.unwrap_or_default();
+    match refusal(status, &body) {
+        Some(Refused::Expired) => {
+            Err("pairing code expired, show a new code on the gateway and try again".to_string())
+        }
+        Some(Refused::Invalid) => match attempt.wrong() {
+            0 => Err(format!(
+                "wrong pairing code, pairing locked for {}s",
+                LOCKOUT.as_secs()
+            )),
+            left => Err(format!("wrong pairing code ({left} attempts left)")),
+        },
+        None if status == StatusCode::NOT_FOUND => {
+            Err("gateway does not support pairing codes".to_string())
+        }
+        None if status == StatusCode::TOO_MANY_REQUESTS => {
+            Err("gateway is refusing pairing attempts for now, try again later".to_string())
+        }
+        None => Err(format!("pairing failed, gateway returned {status}: {body}")),
+    }
+}
+
+/// Whether an error response is a rejection of the code itself
+fn refusal(status: StatusCode, body: &str) -> Option<Refused> {
+    if status == StatusCode::GONE || body.to_ascii_lowercase().contains("expired") {
+        return Some(Refused::Expired);
+    }
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Some(Refused::Invalid)
+        }
+        _ => None,
+    }
+}
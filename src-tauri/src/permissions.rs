@@ -0,0 +1,126 @@
+//! Platform permission states
+//!
+//! Geolocation, notifications and the camera (for barcode scanning) each sit
+//! behind a plugin with its own permission API. This gathers them behind one
+//! status type so the UI can show and request them in one place.
+//!
+//! Desktop has no geolocation (the plugin is a stub there) or barcode
+//! scanner, and grants notifications without asking, so it reports those as
+//! `unavailable` / `granted`.
+
+use serde::{Deserialize, Serialize};
+use tauri::plugin::PermissionState;
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+#[cfg(mobile)]
+use tauri_plugin_geolocation::{GeolocationExt, PermissionType};
+
+/// Status of a single permission
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionStatus {
+    Granted,
+    Denied,
+
+    /// Not decided yet, asking will show the platform prompt
+    Prompt,
+
+    /// The capability doesn't exist on this platform
+    Unavailable,
+}
+
+impl From<PermissionState> for PermissionStatus {
+    fn from(state: PermissionState) -> Self {
+        match state {
+            PermissionState::Granted => Self::Granted,
+            PermissionState::Denied => Self::Denied,
+            PermissionState::Prompt | PermissionState::PromptWithRationale => Self::Prompt,
+        }
+    }
+}
+
+/// A capability gated by a platform permission
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionKind {
+    Geolocation,
+    Notifications,
+    Camera,
+}
+
+/// Current status of every permission the app uses
+#[derive(Debug, Clone, Serialize)]
+pub struct PermissionStates {
+    pub geolocation: PermissionStatus,
+    pub notifications: PermissionStatus,
+    pub camera: PermissionStatus,
+}
+
+/// Query every permission without prompting
+pub fn states(app: &AppHandle) -> Result<PermissionStates, String> {
+    Ok(PermissionStates {
+        geolocation: geolocation(app, false)?,
+        notifications: notifications(app, false)?,
+        camera: camera(),
+    })
+}
+
+/// Show the platform prompt for `kind` (if not already decided), returning
+/// the resulting status
+pub fn request(app: &AppHandle, kind: PermissionKind) -> Result<PermissionStatus, String> {
+    match kind {
+        PermissionKind::Geolocation => geolocation(app, true),
+        PermissionKind::Notifications => notifications(app, true),
+        PermissionKind::Camera => match camera() {
+            PermissionStatus::Unavailable => Ok(PermissionStatus::Unavailable),
+            // The scanner plugin only exposes its permission calls to the
+            // webview, and asks on first scan anyway
+            _ => Err("camera permission is requested by the barcode scanner".to_string()),
+        },
+    }
+}
+
+fn notifications(app: &AppHandle, prompt: bool) -> Result<PermissionStatus, String> {
+    let notification = app.notification();
+    let state = if prompt {
+        notification.request_permission()
+    } else {
+        notification.permission_state()
+    };
+
+    state
+        .map(PermissionStatus::from)
+        .map_err(|e| format!("failed to get notification permission: {e}"))
+}
+
+#[cfg(mobile)]
+fn geolocation(app: &AppHandle, prompt: bool) -> Result<PermissionStatus, String> {
+    let geolocation = app.geolocation();
+    let status = if prompt {
+        geolocation.request_permissions(Some(vec![PermissionType::Location]))
+    } else {
+        geolocation.check_permissions()
+    };
+
+    status
+        .map(|s| s.location.into())
+        .map_err(|e| format!("failed to get location permission: {e}"))
+}
+
+#[cfg(desktop)]
+fn geolocation(_app: &AppHandle, _prompt: bool) -> Result<PermissionStatus, String> {
+    Ok(PermissionStatus::Unavailable)
+}
+
+/// Camera status as far as the app can tell without the webview
+///
+/// The barcode scanner plugin doesn't expose its permission check to Rust,
+/// so on mobile this only says the camera exists and will be asked for.
+fn camera() -> PermissionStatus {
+    if cfg!(mobile) {
+        PermissionStatus::Prompt
+    } else {
+        PermissionStatus::Unavailable
+    }
+}
@@ -0,0 +1,220 @@
+//! Gateway persona selection
+//!
+//! Mirrors the frontend's persona calls (`/personas`, `/personas/{id}/activate`)
+//! for the cases where the app itself has to pick one, like applying a
+//! profile's default on connect.
+//!
+//! The active persona's metadata (display name, accent color, greeting) is
+//! also turned into a [`PersonaProfile`] the UI can theme itself with, and
+//! published as `persona-profile-changed` events on connect and whenever a
+//! persona switch is noticed.
+
+use std::sync::Arc;
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+
+use crate::features::Feature;
+use crate::{api, cache, AppState, GatewayState};
+
+/// Name shown when the gateway has no persona metadata
+const DEFAULT_DISPLAY_NAME: &str = "Beacon";
+
+/// Accent color used when the gateway has none (or an invalid one)
+const DEFAULT_COLOR: &str = "#6366f1";
+
+/// Greeting used when the gateway has none
+const DEFAULT_GREETING: &str = "Hi! How can I help?";
+
+/// A persona the gateway offers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersonaInfo {
+    pub id: String,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// `/personas` response
+#[derive(Deserialize)]
+struct PersonaList {
+    personas: Vec<PersonaInfo>,
+
+    #[serde(default)]
+    active_id: Option<String>,
+}
+
+/// List the personas the connected gateway offers, with the active one's ID
+pub async fn available(state: &AppState) -> Result<(Vec<PersonaInfo>, Option<String>), String> {
+    let list: PersonaList = api::get(state, "/personas").await?;
+    Ok((list.personas, list.active_id))
+}
+
+/// Switch the connected gateway to `persona_id`
+///
+/// Fails for personas the gateway doesn't list.
+pub async fn activate(state: &AppState, persona_id: &str) -> Result<(), String> {
+    let (personas, active_id) = available(state).await?;
+
+    if !personas.iter().any(|p| p.id == persona_id) {
+        let ids: Vec<&str> = personas.iter().map(|p| p.id.as_str()).collect();
+        return Err(format!(
+            "unknown persona `{persona_id}` (available: {})",
+            ids.join(", ")
+        ));
+    }
+    if active_id.as_deref() == Some(persona_id) {
+        return Ok(());
+    }
+
+    let path = format!("/personas/{persona_id}/activate");
+    let resp = api::request(state, Method::POST, &path, None).await?;
+    api::json::<serde_json::Value>(resp).await?;
+    cache::clear(state).await;
+
+    tracing::info!(persona = %persona_id, "persona activated");
+    Ok(())
+}
+
+/// What the UI needs to present the active persona
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PersonaProfile {
+    /// Active persona (`None` when the gateway reports none)
+    pub id: Option<String>,
+
+    pub display_name: String,
+
+    /// Accent color, as `#rgb`, `#rrggbb` or `#rrggbbaa`
+    pub color: String,
+
+    pub description: Option<String>,
+    pub greeting: String,
+
+    /// Whether any of this came from the gateway rather than defaults
+    pub from_gateway: bool,
+}
+
+impl Default for PersonaProfile {
+    fn default() -> Self {
+        Self {
+            id: None,
+            display_name: DEFAULT_DISPLAY_NAME.to_string(),
+            color: DEFAULT_COLOR.to_string(),
+            description: None,
+            greeting: DEFAULT_GREETING.to_string(),
+            from_gateway: false,
+        }
+    }
+}
+
+impl PersonaProfile {
+    /// Build a profile from persona metadata, earlier sources winning
+    fn from_metadata(id: String, sources: &[&Value]) -> Self {
+        let field = |keys: &[&str]| {
+            sources.iter().find_map(|source| {
+                keys.iter()
+                    .find_map(|key| source.pointer(key))
+                    .and_then(Value::as_str)
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string)
+            })
+        };
+
+        let display_name = field(&["/display_name", "/name"]);
+        let color = field(&["/color", "/accent_color", "/theme/color"]).filter(|c| is_color(c));
+        let description = field(&["/description"]);
+        let greeting = field(&["/greeting", "/default_greeting", "/welcome_message"]);
+
+        Self {
+            from_gateway: display_name.is_some()
+                || color.is_some()
+                || description.is_some()
+                || greeting.is_some(),
+            display_name: display_name.unwrap_or_else(|| id.clone()),
+            color: color.unwrap_or_else(|| DEFAULT_COLOR.to_string()),
+            description,
+            greeting: greeting.unwrap_or_else(|| DEFAULT_GREETING.to_string()),
+            id: Some(id),
+        }
+    }
+}
+
+/// Whether `color` is a hex color safe to hand to CSS
+fn is_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    })
+}
+
+/// Profile of the active persona, emitting `persona-profile-changed` when it
+/// differs from the last one seen
+///
+/// The persona list is always checked, to notice switches made by the
+/// frontend; the persona's own metadata is cached until it changes.
+pub async fn current_profile(app: &AppHandle, state: &AppState) -> Result<PersonaProfile, String> {
+    let url = api::base_url(state).await?;
+    let list: Value = api::get(state, "/personas").await?;
+    let active_id = list.get("active_id").and_then(Value::as_str);
+
+    let previous = state.persona_profile.read().await.clone();
+    let cached = previous
+        .as_ref()
+        .filter(|(cached_url, profile)| *cached_url == url && profile.id.as_deref() == active_id);
+    if let Some((_, profile)) = cached.filter(|_| state.features.is_enabled(Feature::Cache)) {
+        state.cache_counters.hit();
+        return Ok(profile.clone());
+    }
+    state.cache_counters.miss();
+
+    let profile = match active_id {
+        Some(id) => {
+            let entry = list
+                .get("personas")
+                .and_then(Value::as_array)
+                .and_then(|personas| {
+                    personas
+                        .iter()
+                        .find(|p| p.get("id").and_then(Value::as_str) == Some(id))
+                });
+            let detail = detail(state, id).await;
+            let sources: Vec<&Value> = detail.iter().chain(entry).collect();
+            PersonaProfile::from_metadata(id.to_string(), &sources)
+        }
+        None => PersonaProfile::default(),
+    };
+
+    if previous.as_ref().map(|(_, p)| p) != Some(&profile) {
+        tracing::info!(persona = ?profile.id, "persona profile changed");
+        let _ = app.emit("persona-profile-changed", &profile);
+    }
+    *state.persona_profile.write().await = Some((url, profile.clone()));
+    Ok(profile)
+}
+
+/// `/personas/{id}`, for gateways that keep metadata out of the list
+async fn detail(state: &AppState, persona_id: &str) -> Option<Value> {
+    let path = format!("/personas/{persona_id}");
+    let resp = api::request(state, Method::GET, &path, None).await.ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    resp.json().await.ok()
+}
+
+/// Publish the persona profile each time a gateway connects
+pub async fn watch_profile(app: AppHandle, state: Arc<AppState>) {
+    let mut changes = state.state_changes.subscribe();
+
+    while changes.changed().await.is_ok() {
+        let connected = matches!(*changes.borrow_and_update(), GatewayState::Connected { .. });
+        if !connected {
+            continue;
+        }
+        if let Err(e) = current_profile(&app, &state).await {
+            tracing::debug!(error = %e, "failed to fetch persona profile");
+        }
+    }
+}
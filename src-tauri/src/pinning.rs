@@ -0,0 +1,254 @@
+//! Certificate pinning for self-hosted gateways
+//!
+//! A gateway with a self-signed certificate can be trusted by pinning the
+//! SHA-256 fingerprint of its certificate instead of adding a CA. A pinned
+//! connection accepts exactly that certificate, whoever issued it and
+//! whatever name it carries, and fails on any other with a "certificate pin
+//! mismatch" error. Handshake signatures are still checked against the
+//! pinned certificate's key, so the gateway must hold its private key.
+//!
+//! Pinned clients use rustls, configured here, instead of the platform TLS
+//! library.
+
+use std::sync::{Arc, Mutex};
+
+use reqwest::redirect::Policy;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme, SupportedProtocolVersion};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::gateway::{self, TlsVersion};
+
+/// Start of the error a pinned handshake fails with on another certificate
+const PIN_MISMATCH: &str = "certificate pin mismatch";
+
+/// Fingerprint of the certificate a gateway presents, as captured for pinning
+#[derive(Debug, Clone, Serialize)]
+pub struct CertFingerprint {
+    pub url: String,
+
+    /// Hex SHA-256 of the certificate (DER), the value to pin
+    pub sha256: String,
+
+    /// Certificates in the chain the gateway presented (1 if self-signed)
+    pub chain_len: usize,
+}
+
+/// Parse a pin: 64 hex digits, optionally colon-separated, in either case
+pub fn parse_pin(pin: &str) -> Result<[u8; 32], String> {
+    let digits: String = pin.trim().chars().filter(|c| *c != ':').collect();
+    if digits.len() != 64 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("certificate pin must be a SHA-256 fingerprint (64 hex digits)".to_string());
+    }
+
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16)
+            .map_err(|e| format!("invalid certificate pin: {e}"))?;
+    }
+    Ok(bytes)
+}
+
+/// A pin in its stored form, lowercase hex without separators
+pub fn normalize_pin(pin: &str) -> Result<String, String> {
+    parse_pin(pin).map(|bytes| hex(&bytes))
+}
+
+/// TLS config for a client accepting only the certificate pinned by `pin`
+pub fn client_config(pin: &str, min_version: TlsVersion) -> Result<rustls::ClientConfig, String> {
+    let verifier = Arc::new(PinVerifier::new(Some(parse_pin(pin)?)));
+    config(verifier, min_version)
+}
+
+/// Connect to `url` and read the fingerprint of the certificate it presents
+///
+/// The certificate is not verified, this being how a pin is captured in the
+/// first place, so no credentials are sent.
+pub async fn fetch_fingerprint(url: &str) -> Result<CertFingerprint, String> {
+    let url = gateway::normalize_gateway_url(url)?;
+    if !url.starts_with("https://") {
+        return Err(format!(
+            "{url} doesn't use TLS, there is no certificate to pin"
+        ));
+    }
+
+    let verifier = Arc::new(PinVerifier::new(None));
+    let client = reqwest::Client::builder()
+        .use_preconfigured_tls(config(verifier.clone(), TlsVersion::Tls12)?)
+        .redirect(Policy::none())
+        .build()
+        .map_err(|e| format!("failed to build http client: {e}"))?;
+
+    // Only the handshake matters, whatever the gateway answers
+    let sent = client
+        .head(&url)
+        .timeout(gateway::PROBE_TIMEOUT)
+        .send()
+        .await;
+    let seen = verifier
+        .seen
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    let Some((fingerprint, chain_len)) = seen else {
+        return Err(match sent {
+            Err(e) => format!("failed to connect to {url}: {e}"),
+            Ok(_) => format!("{url} presented no certificate"),
+        });
+    };
+
+    let sha256 = hex(&fingerprint);
+    tracing::info!(url = %url, sha256 = %sha256, chain_len, "read gateway certificate fingerprint");
+    Ok(CertFingerprint {
+        url,
+        sha256,
+        chain_len,
+    })
+}
+
+/// Whether `error` is a pinned handshake refusing the gateway's certificate
+pub fn is_pin_mismatch(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(e) = source {
+        if e.to_string().contains(PIN_MISMATCH) {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+fn config(
+    verifier: Arc<PinVerifier>,
+    min_version: TlsVersion,
+) -> Result<rustls::ClientConfig, String> {
+    let versions: &[&SupportedProtocolVersion] = match min_version {
+        TlsVersion::Tls12 => &[&rustls::version::TLS13, &rustls::version::TLS12],
+        TlsVersion::Tls13 => &[&rustls::version::TLS13],
+    };
+
+    let config = rustls::ClientConfig::builder_with_provider(verifier.provider.clone())
+        .with_protocol_versions(versions)
+        .map_err(|e| format!("failed to configure TLS: {e}"))?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+    Ok(config)
+}
+
+/// Accepts the certificate matching `pin`, or any without a pin, and records
+/// the certificate presented
+#[derive(Debug)]
+struct PinVerifier {
+    pin: Option<[u8; 32]>,
+    provider: Arc<CryptoProvider>,
+
+    /// Fingerprint and chain length of the last certificate presented
+    seen: Mutex<Option<(Vec<u8>, usize)>>,
+}
+
+impl PinVerifier {
+    fn new(pin: Option<[u8; 32]>) -> Self {
+        Self {
+            pin,
+            provider: Arc::new(crypto::ring::default_provider()),
+            seen: Mutex::new(None),
+        }
+    }
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = Sha256::digest(end_entity.as_ref()).to_vec();
+        let mismatch = self.pin.is_some_and(|pin| fingerprint != pin);
+        let presented = hex(&fingerprint);
+        *self.seen.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some((fingerprint, intermediates.len() + 1));
+
+        if mismatch {
+            tracing::warn!(presented = %presented, "gateway certificate doesn't match the pin");
+            return Err(rustls::Error::General(format!(
+                "{PIN_MISMATCH}: gateway presented {presented}"
+            )));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.provider.signature_verification_algorithms;
+        crypto::verify_tls12_signature(message, cert, dss, algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.provider.signature_verification_algorithms;
+        crypto::verify_tls13_signature(message, cert, dss, algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIN: &str = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+
+    #[test]
+    fn pins_parse_in_either_case() {
+        let bytes = parse_pin(PIN).unwrap();
+        assert_eq!(bytes[..4], [0x00, 0x11, 0x22, 0x33]);
+        assert_eq!(parse_pin(&PIN.to_ascii_uppercase()).unwrap(), bytes);
+        assert_eq!(
+            normalize_pin(&format!("  {}  ", PIN.to_ascii_uppercase())).unwrap(),
+            PIN
+        );
+    }
+
+    #[test]
+    fn colon_separated_pins_normalize() {
+        let colons = PIN
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| std::str::from_utf8(pair).unwrap().to_ascii_uppercase())
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(normalize_pin(&colons).unwrap(), PIN);
+    }
+
+    #[test]
+    fn malformed_pins_are_refused() {
+        assert!(parse_pin("").is_err());
+        assert!(parse_pin(&PIN[..62]).is_err());
+        assert!(parse_pin(&format!("{PIN}00")).is_err());
+        assert!(parse_pin(&PIN.replace('a', "g")).is_err());
+        assert!(parse_pin(&PIN.replacen("00", "é", 1)).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+//! Connection pool of the gateway clients
+//!
+//! Every gateway client keeps idle connections around for reuse, which saves
+//! a TCP (and TLS) handshake per request to a remote gateway. How many and
+//! for how long is configurable for heavy-usage setups; the values apply to
+//! clients built from then on.
+//!
+//! reqwest doesn't expose its pool, so reuse is estimated from the outside:
+//! each response carries the local address of the connection it came over,
+//! and requests seen on an already-known address were served by a reused
+//! connection.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use hyper_util::client::legacy::connect::HttpInfo;
+use serde::Serialize;
+
+/// Longest idle timeout accepted (s)
+const MAX_IDLE_TIMEOUT_SECS: u64 = 3600;
+
+/// Connections remembered for reuse estimates before starting over
+const MAX_TRACKED_CONNECTIONS: usize = 4096;
+
+/// Pool limits applied to new gateway clients
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PoolConfig {
+    /// Idle connections kept per host (0 disables reuse)
+    pub max_idle_per_host: usize,
+
+    /// How long an idle connection is kept (s)
+    pub idle_timeout_secs: u64,
+}
+
+impl PoolConfig {
+    /// Check that the limits make sense
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_IDLE_TIMEOUT_SECS).contains(&self.idle_timeout_secs) {
+            return Err(format!(
+                "pool idle timeout must be 1 to {MAX_IDLE_TIMEOUT_SECS}s (got {})",
+                self.idle_timeout_secs
+            ));
+        }
+        Ok(())
+    }
+}
+
+static CONFIG: RwLock<PoolConfig> = RwLock::new(PoolConfig {
+    max_idle_per_host: 8,
+    idle_timeout_secs: 90,
+});
+
+/// Pool limits new clients are built with
+pub fn config() -> PoolConfig {
+    *CONFIG.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Change the pool limits, returning whether they changed
+pub fn set_config(config: PoolConfig) -> bool {
+    let mut current = CONFIG.write().unwrap_or_else(|e| e.into_inner());
+    let changed = *current != config;
+    *current = config;
+    changed
+}
+
+/// Apply the pool limits to a client builder
+pub fn apply(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    let config = config();
+    builder
+        .pool_max_idle_per_host(config.max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(config.idle_timeout_secs))
+}
+
+/// Estimated pool usage
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    #[serde(flatten)]
+    pub config: PoolConfig,
+
+    /// Gateway requests observed (those sent through the shared client)
+    pub requests: u64,
+
+    /// Distinct connections those requests went over (approximate)
+    pub connections: u64,
+
+    /// Requests served by a connection an earlier request opened
+    pub reused_requests: u64,
+}
+
+/// Counts requests and the connections they used
+#[derive(Default)]
+pub struct PoolTracker {
+    requests: AtomicU64,
+    connections: AtomicU64,
+    seen: Mutex<HashSet<SocketAddr>>,
+}
+
+impl PoolTracker {
+    /// Note the connection a response came over
+    pub fn observe(&self, resp: &reqwest::Response) {
+        let Some(info) = resp.extensions().get::<HttpInfo>() else {
+            return;
+        };
+        self.requests.fetch_add(1, Ordering::Relaxed);
+
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        if seen.len() >= MAX_TRACKED_CONNECTIONS {
+            seen.clear();
+        }
+        if seen.insert(info.local_addr()) {
+            self.connections.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Current estimates
+    pub fn stats(&self) -> PoolStats {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let connections = self.connections.load(Ordering::Relaxed);
+        PoolStats {
+            config: config(),
+            requests,
+            connections,
+            reused_requests: requests.saturating_sub(connections),
+        }
+    }
+}
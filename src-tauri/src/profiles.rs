@@ -0,0 +1,217 @@
+//! Gateway connection profiles
+//!
+//! A profile is a named gateway the user connects to. Profiles are persisted
+//! to `profiles.json` in the data directory; their auth tokens never touch
+//! disk and live in secure storage instead.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gateway::TlsVersion;
+use crate::settings;
+
+/// Profiles file name (relative to data dir)
+const PROFILES_FILE: &str = "profiles.json";
+
+/// Longest accepted profile name
+const MAX_NAME_LEN: usize = 64;
+
+/// A saved gateway connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GatewayProfile {
+    /// Unique profile name
+    pub name: String,
+
+    /// Gateway URL
+    pub url: String,
+
+    /// PEM CA bundle used to verify the gateway's certificate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Local address to send gateway traffic from (OS routing if unset)
+    ///
+    /// Pins the connection to one interface on multi-homed machines, e.g. to
+    /// keep it on the LAN or on a VPN in split-tunnel setups.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_address: Option<IpAddr>,
+
+    /// Persona to activate after connecting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_persona: Option<String>,
+
+    /// Model to activate after connecting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_model: Option<String>,
+
+    /// User-Agent sent to this gateway (the app's default if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+
+    /// Device ID of the gateway, used to find it again if its address changes
+    ///
+    /// Captured on the first successful connect when the gateway reports one.
+    /// Profiles without it connect by URL only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+
+    /// Endpoint returning a fresh token once the current one expires
+    ///
+    /// Without one, an expired token is replaced by re-reading the token in
+    /// secure storage, for setups that rotate it there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_refresh_url: Option<String>,
+
+    /// Oldest TLS version the gateway connection may use
+    #[serde(default)]
+    pub min_tls_version: TlsVersion,
+
+    /// SHA-256 fingerprint of the gateway's certificate, trusted instead of
+    /// verifying it (for self-signed certificates)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_cert_sha256: Option<String>,
+}
+
+/// Check a profile name is usable: not blank, not too long, and free of
+/// control characters
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("profile name is empty".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(format!(
+            "profile name is too long (max {MAX_NAME_LEN} characters)"
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("profile name may not contain control characters".to_string());
+    }
+    Ok(())
+}
+
+impl GatewayProfile {
+    /// Profile for `name` at `url` with nothing else set
+    pub fn new(name: String, url: String) -> Self {
+        Self {
+            name,
+            url,
+            ca_bundle: None,
+            local_address: None,
+            default_persona: None,
+            default_model: None,
+            user_agent: None,
+            fingerprint: None,
+            token_refresh_url: None,
+            min_tls_version: TlsVersion::default(),
+            pinned_cert_sha256: None,
+        }
+    }
+
+    /// This new profile, replacing `existing`, with what it doesn't set
+    /// carried over
+    ///
+    /// Interface pinning, defaults, User-Agent, token refresh and TLS
+    /// version are the user's choices and always kept. The CA bundle, the
+    /// fingerprint and the pinned certificate describe the gateway, so
+    /// they're kept only while the profile still points at the same one
+    /// (same scheme, host and port).
+    pub fn merged_with(self, existing: Option<GatewayProfile>) -> Self {
+        let Some(existing) = existing else {
+            return self;
+        };
+        let origin = |url: &str| reqwest::Url::parse(url).ok().map(|url| url.origin());
+        let same = origin(&self.url).is_some() && origin(&self.url) == origin(&existing.url);
+
+        Self {
+            ca_bundle: self.ca_bundle.or(existing.ca_bundle.filter(|_| same)),
+            fingerprint: self.fingerprint.or(existing.fingerprint.filter(|_| same)),
+            pinned_cert_sha256: self
+                .pinned_cert_sha256
+                .or(existing.pinned_cert_sha256.filter(|_| same)),
+            local_address: self.local_address.or(existing.local_address),
+            default_persona: self.default_persona.or(existing.default_persona),
+            default_model: self.default_model.or(existing.default_model),
+            user_agent: self.user_agent.or(existing.user_agent),
+            token_refresh_url: self.token_refresh_url.or(existing.token_refresh_url),
+            min_tls_version: existing.min_tls_version,
+            name: self.name,
+            url: self.url,
+        }
+    }
+}
+
+/// Secure storage key holding a profile's auth token
+pub fn token_key(profile_name: &str) -> String {
+    format!("gateway-token:{profile_name}")
+}
+
+/// Secure storage key holding a profile's extra request headers (as JSON)
+pub fn headers_key(profile_name: &str) -> String {
+    format!("gateway-headers:{profile_name}")
+}
+
+/// Extra request headers of a profile
+///
+/// Read back from secure storage since they may carry credentials (e.g. an
+/// access proxy's service token).
+pub fn headers(profile: &GatewayProfile) -> Result<BTreeMap<String, String>, String> {
+    match crate::storage::get(&headers_key(&profile.name))? {
+        Some(json) => {
+            serde_json::from_str(&json).map_err(|e| format!("failed to parse stored headers: {e}"))
+        }
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Build an HTTP client for a profile
+pub fn client(profile: &GatewayProfile, token: Option<&str>) -> Result<reqwest::Client, String> {
+    let headers = headers(profile)?;
+
+    crate::gateway::build_client(
+        token,
+        &headers,
+        profile.ca_bundle.as_deref(),
+        profile.local_address,
+        profile.user_agent.as_deref(),
+        profile.min_tls_version,
+        profile.pinned_cert_sha256.as_deref(),
+    )
+}
+
+/// Load all saved profiles (empty if none saved yet)
+pub fn load(data_dir: &Path) -> Vec<GatewayProfile> {
+    let path = data_dir.join(PROFILES_FILE);
+
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, path = %path.display(), "failed to parse profiles");
+        Vec::new()
+    })
+}
+
+/// Persist all profiles
+pub fn save(data_dir: &Path, profiles: &[GatewayProfile]) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(profiles)
+        .map_err(|e| format!("failed to serialize profiles: {e}"))?;
+
+    settings::write_atomic(&data_dir.join(PROFILES_FILE), contents.as_bytes())
+        .map_err(|e| format!("failed to write profiles: {e}"))
+}
+
+/// Find a profile by name
+pub fn find(data_dir: &Path, name: &str) -> Option<GatewayProfile> {
+    load(data_dir).into_iter().find(|p| p.name == name)
+}
+
+/// Find the profile of the gateway with this fingerprint (device ID)
+pub fn find_by_fingerprint(data_dir: &Path, fingerprint: &str) -> Option<GatewayProfile> {
+    load(data_dir)
+        .into_iter()
+        .find(|p| p.fingerprint.as_deref() == Some(fingerprint))
+}
@@ -0,0 +1,287 @@
+//! Protocol version negotiation with the gateway
+//!
+//! The app speaks protocol [`CURRENT`]; a gateway reports the newest version
+//! it speaks as `protocol_version` at `/version` or in its `/capabilities`.
+//! Gateways reporting neither (or failing to answer) are taken to predate
+//! versioning and speak protocol 1. The
+//! lower of the two is used for the connection, and proxied requests are
+//! adapted to it: endpoints added later are sent to their legacy path, and
+//! body parameters the gateway doesn't know are left out, so a newer
+//! feature degrades instead of failing on an older gateway. Running below
+//! [`CURRENT`] is announced with a `protocol-downgraded` event, so the UI
+//! can hide what the gateway can't do.
+
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+
+use crate::{api, AppState};
+
+/// Protocol version this app speaks
+pub const CURRENT: u32 = 2;
+
+/// Version of gateways that don't report one
+const LEGACY: u32 = 1;
+
+/// Something a protocol version added, undone for older gateways
+struct Change {
+    /// Version that added it
+    since: u32,
+
+    /// Requests it applies to, by path prefix
+    path: &'static str,
+
+    /// Prefix the path had before, if the endpoint moved
+    legacy_path: Option<&'static str>,
+
+    /// Body parameters added along with it
+    params: &'static [&'static str],
+
+    /// What's missing without it, as reported to the UI
+    feature: &'static str,
+}
+
+/// Changes by version, oldest first
+const CHANGES: &[Change] = &[
+    Change {
+        since: 2,
+        path: "/api/v2/",
+        legacy_path: Some("/api/"),
+        params: &[],
+        feature: "versioned_api",
+    },
+    Change {
+        since: 2,
+        path: "/api/chat",
+        legacy_path: None,
+        params: &["response_format", "stream_options"],
+        feature: "structured_output",
+    },
+    Change {
+        since: 2,
+        path: "/api/personas",
+        legacy_path: None,
+        params: &["memory_scope"],
+        feature: "persona_memory_scope",
+    },
+];
+
+/// Where the gateway's version came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolSource {
+    /// The gateway's `/version`
+    Version,
+
+    /// The gateway's `/capabilities`
+    Capabilities,
+
+    /// Reported by neither, so assumed to be the legacy protocol
+    Assumed,
+}
+
+/// Protocol in use with a gateway, also the `protocol-downgraded` payload
+#[derive(Debug, Clone, Serialize)]
+pub struct NegotiatedProtocol {
+    pub url: String,
+
+    /// Version the app speaks
+    pub app_version: u32,
+
+    /// Newest version the gateway speaks
+    pub gateway_version: u32,
+    pub source: ProtocolSource,
+
+    /// Version requests are adapted to
+    pub negotiated: u32,
+
+    /// Whether that's older than the app's
+    pub downgraded: bool,
+
+    /// Features unavailable at the negotiated version
+    pub unsupported: Vec<String>,
+}
+
+impl NegotiatedProtocol {
+    fn new(url: String, gateway_version: u32, source: ProtocolSource) -> Self {
+        let negotiated = gateway_version.min(CURRENT);
+        let unsupported = CHANGES
+            .iter()
+            .filter(|change| change.since > negotiated)
+            .map(|change| change.feature.to_string())
+            .collect();
+        Self {
+            url,
+            app_version: CURRENT,
+            gateway_version,
+            source,
+            negotiated,
+            downgraded: negotiated < CURRENT,
+            unsupported,
+        }
+    }
+}
+
+/// Protocol in use with the connected gateway, negotiated on first use
+/// after each connect
+pub async fn negotiate(app: &AppHandle, state: &AppState) -> Result<NegotiatedProtocol, String> {
+    let url = api::base_url(state).await?;
+    if let Some(protocol) = &*state.protocol.read().await {
+        if protocol.url == url {
+            return Ok(protocol.clone());
+        }
+    }
+
+    let (gateway_version, source) = match reported(state, "/version").await? {
+        Some(version) => (version, ProtocolSource::Version),
+        None => match reported(state, "/capabilities").await? {
+            Some(version) => (version, ProtocolSource::Capabilities),
+            None => (LEGACY, ProtocolSource::Assumed),
+        },
+    };
+    let protocol = NegotiatedProtocol::new(url, gateway_version, source);
+
+    if protocol.downgraded {
+        tracing::warn!(
+            gateway = protocol.gateway_version,
+            app = CURRENT,
+            unsupported = ?protocol.unsupported,
+            "gateway speaks an older protocol, running downgraded"
+        );
+        let _ = app.emit("protocol-downgraded", &protocol);
+    } else {
+        tracing::debug!(?protocol, "negotiated gateway protocol");
+    }
+    *state.protocol.write().await = Some(protocol.clone());
+    Ok(protocol)
+}
+
+/// Adapt a proxied request to the protocol in use with the gateway
+///
+/// Left as is if the protocol can't be negotiated; the gateway will say
+/// what it makes of it.
+pub async fn adapt(
+    app: &AppHandle,
+    state: &AppState,
+    path: String,
+    body: Option<Value>,
+) -> (String, Option<Value>) {
+    let negotiated = match negotiate(app, state).await {
+        Ok(protocol) => protocol.negotiated,
+        Err(e) => {
+            tracing::debug!(error = %e, "could not negotiate the gateway protocol");
+            return (path, body);
+        }
+    };
+    downgrade(negotiated, path, body)
+}
+
+/// Undo the changes newer than `negotiated` in a request
+fn downgrade(
+    negotiated: u32,
+    mut path: String,
+    mut body: Option<Value>,
+) -> (String, Option<Value>) {
+    for change in CHANGES.iter().filter(|change| change.since > negotiated) {
+        let prefix = change.path.trim_end_matches('/');
+        let Some(rest) = path
+            .strip_prefix(prefix)
+            .filter(|rest| rest.is_empty() || rest.starts_with(['/', '?']))
+        else {
+            continue;
+        };
+
+        if let Some(Value::Object(fields)) = &mut body {
+            for param in change.params {
+                if fields.remove(*param).is_some() {
+                    tracing::debug!(path = %path, param, "dropped a parameter the gateway lacks");
+                }
+            }
+        }
+        if let Some(legacy) = change.legacy_path {
+            let moved = format!("{}{rest}", legacy.trim_end_matches('/'));
+            tracing::debug!(from = %path, to = %moved, "sending to the legacy endpoint");
+            path = moved;
+        }
+    }
+    (path, body)
+}
+
+/// Protocol version the gateway reports at `path`, if it reports one
+///
+/// An error status counts as not reporting one, so a gateway failing there
+/// settles on the legacy protocol instead of being asked before every
+/// request; only an unreachable gateway is an error.
+async fn reported(state: &AppState, path: &str) -> Result<Option<u32>, String> {
+    let resp = api::request(state, Method::GET, path, None).await?;
+    match resp.status() {
+        status if status.is_success() => {
+            let body = resp.text().await.unwrap_or_default();
+            let value = serde_json::from_str::<Value>(&body).unwrap_or(Value::Null);
+            Ok(version(&value))
+        }
+        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => Ok(None),
+        status => {
+            tracing::debug!(%status, path, "gateway failed to report its protocol version");
+            Ok(None)
+        }
+    }
+}
+
+/// `protocol_version` at the top level or under `protocol`, as a number or
+/// a string like `"2"` or `"2.1"` (the minor is ignored)
+fn version(value: &Value) -> Option<u32> {
+    [
+        "/protocol_version",
+        "/protocol/version",
+        "/capabilities/protocol_version",
+    ]
+    .iter()
+    .find_map(|p| match value.pointer(p)? {
+        Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+        Value::String(s) => s
+            .trim()
+            .trim_start_matches('v')
+            .split('.')
+            .next()?
+            .parse()
+            .ok(),
+        _ => None,
+    })
+    .filter(|&version| version >= LEGACY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downgrade_moves_to_the_legacy_path() {
+        let (path, _) = downgrade(LEGACY, "/api/v2/models?x=1".to_string(), None);
+        assert_eq!(path, "/api/models?x=1");
+        let (path, _) = downgrade(LEGACY, "/api/v2".to_string(), None);
+        assert_eq!(path, "/api");
+        let (path, _) = downgrade(CURRENT, "/api/v2/models".to_string(), None);
+        assert_eq!(path, "/api/v2/models");
+    }
+
+    #[test]
+    fn downgrade_matches_whole_segments() {
+        let (_, body) = downgrade(
+            LEGACY,
+            "/api/chatbots".to_string(),
+            Some(body_with_format()),
+        );
+        assert!(body.unwrap().get("response_format").is_some());
+
+        let (_, body) = downgrade(LEGACY, "/api/chat".to_string(), Some(body_with_format()));
+        let body = body.unwrap();
+        assert!(body.get("response_format").is_none());
+        assert_eq!(body["model"], "m");
+    }
+
+    fn body_with_format() -> Value {
+        serde_json::json!({ "response_format": "json", "model": "m" })
+    }
+}
@@ -0,0 +1,563 @@
+//! Proxied gateway requests
+//!
+//! Long-running gateway responses (e.g. token streams) are pumped chunk by
+//! chunk to the frontend as `proxy-stream-chunk` events, each followed by a
+//! single `proxy-stream-end`. Active streams, and buffered requests, are
+//! tracked so a disconnect can either abort them right away or let them
+//! drain first, and so they can be listed and cancelled one by one.
+//!
+//! All proxied requests, streams included, also go through a
+//! [`RequestLimiter`]. A local sidecar is often single-threaded and shares the
+//! machine with everything else, so a burst of requests (a UI bug, a flurry
+//! of tool calls) queues here instead of flooding it. Requests that opt in
+//! can also wait here while the gateway is still starting or reconnecting,
+//! instead of failing straight away.
+//!
+//! Streams are read at the download bandwidth limit (see [`Throttle`]), and
+//! their events go through the [`EventRecorder`] so they can be recorded.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::Method;
+use serde::Serialize;
+use tauri::AppHandle;
+use tokio::sync::{watch, Notify, OwnedSemaphorePermit, Semaphore};
+
+use crate::logs;
+use crate::recording::EventRecorder;
+use crate::throttle::Throttle;
+use crate::GatewayState;
+
+/// Payload of `proxy-stream-chunk` events
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamChunk {
+    pub stream_id: u64,
+    pub data: String,
+}
+
+/// How a stream ended
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamOutcome {
+    /// Gateway finished the response
+    Completed,
+
+    /// Aborted by the app (abort-now disconnect or drain timeout)
+    Cancelled,
+
+    /// Reading the response failed
+    Failed,
+}
+
+/// Payload of `proxy-stream-end` events
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamEnd {
+    pub stream_id: u64,
+    pub outcome: StreamOutcome,
+    pub error: Option<String>,
+}
+
+/// Result of draining active streams
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DrainResult {
+    /// Streams that finished on their own within the grace period
+    pub completed: usize,
+
+    /// Streams still running at the deadline, which were aborted
+    pub cancelled: usize,
+}
+
+/// Queueing metrics for proxied requests
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueStats {
+    /// Configured concurrency limit
+    pub max_concurrent: usize,
+
+    /// Requests (and streams) currently holding a slot
+    pub in_flight: usize,
+
+    /// Requests waiting for a slot
+    pub queued: usize,
+
+    /// Requests waiting for the gateway to become ready
+    pub waiting_for_gateway: usize,
+
+    /// Requests admitted since launch
+    pub total_requests: u64,
+
+    /// Requests that had to wait for a slot
+    pub waited_requests: u64,
+
+    /// Total time spent waiting for a slot (ms)
+    pub total_wait_ms: u64,
+
+    /// Longest single wait for a slot (ms)
+    pub max_wait_ms: u64,
+}
+
+/// Bounds how many proxied requests are in flight at once
+pub struct RequestLimiter {
+    semaphore: Arc<Semaphore>,
+    limit: Mutex<usize>,
+    in_flight: Arc<AtomicUsize>,
+    queued: AtomicUsize,
+    waiting_for_gateway: AtomicUsize,
+    total_requests: AtomicU64,
+    waited_requests: AtomicU64,
+    total_wait_ms: AtomicU64,
+    max_wait_ms: AtomicU64,
+}
+
+impl RequestLimiter {
+    /// Limiter admitting `limit` requests at once (at least one)
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit: Mutex::new(limit),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            queued: AtomicUsize::new(0),
+            waiting_for_gateway: AtomicUsize::new(0),
+            total_requests: AtomicU64::new(0),
+            waited_requests: AtomicU64::new(0),
+            total_wait_ms: AtomicU64::new(0),
+            max_wait_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait for a slot
+    ///
+    /// The slot is released when the returned [`Slot`] is dropped, so a
+    /// request or stream that is cancelled frees it immediately.
+    pub async fn acquire(&self) -> Result<Slot, String> {
+        let started = Instant::now();
+
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        let permit = self.semaphore.clone().acquire_owned().await;
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+
+        let permit = permit.map_err(|_| "request limiter closed".to_string())?;
+
+        let waited_ms = started.elapsed().as_millis() as u64;
+        self.total_requests.fetch_add(1, Ordering::Relaxed);
+        if waited_ms > 0 {
+            self.waited_requests.fetch_add(1, Ordering::Relaxed);
+            self.total_wait_ms.fetch_add(waited_ms, Ordering::Relaxed);
+            self.max_wait_ms.fetch_max(waited_ms, Ordering::Relaxed);
+        }
+
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        Ok(Slot {
+            _permit: permit,
+            in_flight: self.in_flight.clone(),
+        })
+    }
+
+    /// Wait up to `timeout` for the gateway to connect
+    ///
+    /// At most `max_waiting` requests wait at once; past that, and if the
+    /// gateway isn't connected in time, the request fails.
+    pub async fn wait_until_ready(
+        &self,
+        mut changes: watch::Receiver<GatewayState>,
+        max_waiting: usize,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        if self.waiting_for_gateway.fetch_add(1, Ordering::Relaxed) >= max_waiting {
+            self.waiting_for_gateway.fetch_sub(1, Ordering::Relaxed);
+            return Err(format!(
+                "too many requests waiting for the gateway (at most {max_waiting})"
+            ));
+        }
+        // Counted down however the wait ends, cancellation included
+        let _waiting = Waiting(&self.waiting_for_gateway);
+
+        let connected = |s: &GatewayState| matches!(s, GatewayState::Connected { .. });
+        match tokio::time::timeout(timeout, changes.wait_for(connected)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(_)) | Err(_) => Err(format!(
+                "gateway did not become ready within {}ms",
+                timeout.as_millis()
+            )),
+        }
+    }
+
+    /// Change the concurrency limit
+    ///
+    /// Raising it admits queued requests right away. Lowering it takes effect
+    /// as in-flight requests finish; nothing already running is interrupted.
+    pub fn set_limit(&self, limit: usize) {
+        let limit = limit.max(1);
+        let mut current = self.limit.lock().unwrap_or_else(|e| e.into_inner());
+
+        if limit > *current {
+            self.semaphore.add_permits(limit - *current);
+        } else if limit < *current {
+            let excess = (*current - limit) as u32;
+            let semaphore = self.semaphore.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Ok(permits) = semaphore.acquire_many_owned(excess).await {
+                    permits.forget();
+                }
+            });
+        }
+
+        *current = limit;
+    }
+
+    /// Current queueing metrics
+    pub fn stats(&self) -> QueueStats {
+        let max_concurrent = *self.limit.lock().unwrap_or_else(|e| e.into_inner());
+
+        QueueStats {
+            max_concurrent,
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            waiting_for_gateway: self.waiting_for_gateway.load(Ordering::Relaxed),
+            total_requests: self.total_requests.load(Ordering::Relaxed),
+            waited_requests: self.waited_requests.load(Ordering::Relaxed),
+            total_wait_ms: self.total_wait_ms.load(Ordering::Relaxed),
+            max_wait_ms: self.max_wait_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A request's limiter slot, counted as in flight until dropped
+///
+/// Counted separately rather than from the semaphore's free permits, which
+/// also drop while a lowered limit is still claiming its excess permits.
+pub struct Slot {
+    _permit: OwnedSemaphorePermit,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts a request waiting for the gateway until dropped
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// How a proxied request's response is delivered
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestKind {
+    /// `proxy_request`, returned whole
+    Buffered,
+
+    /// `proxy_stream`, emitted chunk by chunk
+    Stream,
+}
+
+/// An in-flight proxied request, as listed by `list_active_requests`
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveRequest {
+    /// Request ID (the stream ID for streams)
+    pub id: u64,
+
+    pub kind: RequestKind,
+    pub method: String,
+    pub path: String,
+
+    /// When the request started (ms since Unix epoch)
+    pub started_at_ms: u64,
+
+    pub elapsed_ms: u64,
+
+    /// Response bytes received so far
+    pub bytes: u64,
+}
+
+/// Bookkeeping for an active request
+struct Tracked {
+    cancel: Arc<Notify>,
+    kind: RequestKind,
+    method: Method,
+    path: String,
+    started_at_ms: u64,
+    started: Instant,
+    bytes: Arc<AtomicU64>,
+}
+
+/// An active request's registration, removed again on drop
+pub struct Registration {
+    pub id: u64,
+    pub cancel: Arc<Notify>,
+
+    /// Response bytes received so far, for the request to count up
+    pub bytes: Arc<AtomicU64>,
+
+    registry: Arc<StreamRegistry>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.registry.unregister(self.id);
+    }
+}
+
+/// Active streams and requests, shared between IPC commands and the tasks
+/// serving them
+pub struct StreamRegistry {
+    next_id: AtomicU64,
+    accepting: AtomicBool,
+    active: Mutex<HashMap<u64, Tracked>>,
+    count: watch::Sender<usize>,
+}
+
+impl Default for StreamRegistry {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            accepting: AtomicBool::new(true),
+            active: Mutex::new(HashMap::new()),
+            count: watch::Sender::new(0),
+        }
+    }
+}
+
+impl StreamRegistry {
+    /// Register a new request, failing while a disconnect is in progress
+    pub fn register(
+        self: &Arc<Self>,
+        kind: RequestKind,
+        method: &Method,
+        path: &str,
+    ) -> Result<Registration, String> {
+        if !self.is_accepting() {
+            return Err("gateway is disconnecting, not accepting new requests".to_string());
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = Arc::new(Notify::new());
+        let bytes = Arc::new(AtomicU64::new(0));
+
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        active.insert(
+            id,
+            Tracked {
+                cancel: cancel.clone(),
+                kind,
+                method: method.clone(),
+                path: path.to_string(),
+                started_at_ms: logs::now_ms(),
+                started: Instant::now(),
+                bytes: bytes.clone(),
+            },
+        );
+        self.count.send_replace(active.len());
+
+        Ok(Registration {
+            id,
+            cancel,
+            bytes,
+            registry: self.clone(),
+        })
+    }
+
+    fn unregister(&self, id: u64) {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        active.remove(&id);
+        self.count.send_replace(active.len());
+    }
+
+    /// Whether new streams are currently accepted
+    pub fn is_accepting(&self) -> bool {
+        self.accepting.load(Ordering::SeqCst)
+    }
+
+    /// Number of streams and requests currently running
+    pub fn active(&self) -> usize {
+        *self.count.borrow()
+    }
+
+    /// Running streams and requests, oldest first
+    pub fn list(&self) -> Vec<ActiveRequest> {
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        let mut requests: Vec<ActiveRequest> = active
+            .iter()
+            .map(|(id, tracked)| ActiveRequest {
+                id: *id,
+                kind: tracked.kind,
+                method: tracked.method.to_string(),
+                path: tracked.path.clone(),
+                started_at_ms: tracked.started_at_ms,
+                elapsed_ms: tracked.started.elapsed().as_millis() as u64,
+                bytes: tracked.bytes.load(Ordering::Relaxed),
+            })
+            .collect();
+        requests.sort_by_key(|r| r.id);
+        requests
+    }
+
+    /// Cancel one stream or request, returning whether it was running
+    pub fn cancel(&self, id: u64) -> bool {
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        match active.get(&id) {
+            Some(tracked) => {
+                tracked.cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop accepting new streams (until [`Self::reopen`])
+    pub fn close(&self) {
+        self.accepting.store(false, Ordering::SeqCst);
+    }
+
+    /// Accept new streams again
+    pub fn reopen(&self) {
+        self.accepting.store(true, Ordering::SeqCst);
+    }
+
+    /// Cancel every active stream and request, returning how many were
+    /// cancelled
+    pub fn abort_all(&self) -> usize {
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        for tracked in active.values() {
+            // notify_one stores a permit, so a stream between reads still sees it
+            tracked.cancel.notify_one();
+        }
+        active.len()
+    }
+
+    /// Let active streams and requests finish for up to `grace`, then abort
+    /// the rest
+    ///
+    /// New streams must already be refused (see [`Self::close`]), otherwise
+    /// this may never see the count reach zero.
+    pub async fn drain(&self, grace: Duration) -> DrainResult {
+        let started = self.active();
+        let mut count = self.count.subscribe();
+
+        let _ = tokio::time::timeout(grace, count.wait_for(|n| *n == 0)).await;
+
+        let cancelled = self.abort_all();
+        if cancelled > 0 {
+            // Give cancelled streams a moment to emit their end events
+            let _ = tokio::time::timeout(Duration::from_secs(1), count.wait_for(|n| *n == 0)).await;
+        }
+
+        DrainResult {
+            completed: started.saturating_sub(cancelled),
+            cancelled,
+        }
+    }
+}
+
+/// Start pumping a gateway response to the frontend, returning its stream ID
+///
+/// `permit` is the stream's request-limiter slot and `registration` its
+/// entry in the [`StreamRegistry`], both held until the stream ends. Each
+/// chunk is emitted as soon as it arrives; `throttle` only delays reading
+/// the next one, so events keep flowing at the limited byte rate.
+pub fn spawn(
+    app: AppHandle,
+    registration: Registration,
+    permit: Slot,
+    throttle: Arc<Throttle>,
+    recorder: Arc<EventRecorder>,
+    mut resp: reqwest::Response,
+) -> u64 {
+    let stream_id = registration.id;
+
+    tauri::async_runtime::spawn(async move {
+        let _permit = permit;
+        let cancel = registration.cancel.clone();
+
+        // Bytes of a UTF-8 sequence split across chunks, held for the next one
+        let mut partial = Vec::new();
+
+        let (outcome, error) = loop {
+            tokio::select! {
+                chunk = resp.chunk() => match chunk {
+                    Ok(Some(bytes)) => {
+                        registration.bytes.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                        partial.extend_from_slice(&bytes);
+                        let complete = match std::str::from_utf8(&partial) {
+                            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                            _ => partial.len(),
+                        };
+                        let data = String::from_utf8_lossy(&partial[..complete]).into_owned();
+                        partial.drain(..complete);
+
+                        recorder.emit(&app, "proxy-stream-chunk", StreamChunk { stream_id, data });
+
+                        tokio::select! {
+                            () = throttle.consume(bytes.len()) => {}
+                            () = cancel.notified() => break (StreamOutcome::Cancelled, None),
+                        }
+                    }
+                    Ok(None) => break (StreamOutcome::Completed, None),
+                    Err(e) => break (StreamOutcome::Failed, Some(e.to_string())),
+                },
+                () = cancel.notified() => break (StreamOutcome::Cancelled, None),
+            }
+        };
+
+        tracing::debug!(stream_id, ?outcome, "proxy stream ended");
+        recorder.emit(
+            &app,
+            "proxy-stream-end",
+            StreamEnd {
+                stream_id,
+                outcome,
+                error,
+            },
+        );
+        drop(registration);
+    });
+
+    stream_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn slots_count_in_flight_until_dropped() {
+        let limiter = RequestLimiter::new(2);
+        let first = limiter.acquire().await.unwrap();
+        let second = limiter.acquire().await.unwrap();
+        assert_eq!(limiter.stats().in_flight, 2);
+
+        drop(first);
+        assert_eq!(limiter.stats().in_flight, 1);
+        drop(second);
+
+        let stats = limiter.stats();
+        assert_eq!(stats.in_flight, 0);
+        assert_eq!(stats.total_requests, 2);
+    }
+
+    #[tokio::test]
+    async fn lowering_the_limit_leaves_in_flight_alone() {
+        let limiter = RequestLimiter::new(4);
+        let mut slots = Vec::new();
+        for _ in 0..3 {
+            slots.push(limiter.acquire().await.unwrap());
+        }
+
+        // The excess permits are still being claimed while these run
+        limiter.set_limit(1);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let stats = limiter.stats();
+        assert_eq!(stats.max_concurrent, 1);
+        assert_eq!(stats.in_flight, 3);
+    }
+}
@@ -0,0 +1,139 @@
+//! Network-level reachability checks
+//!
+//! Resolves a gateway's host and opens a raw TCP connection to its port,
+//! timing each step. Unlike the `/health` probe this never speaks HTTP, so
+//! it tells DNS trouble and blocked ports apart from a gateway that is
+//! reachable but unhealthy.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+use serde::Serialize;
+use tokio::net::TcpStream;
+
+/// Timeout for resolving the host
+const DNS_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Timeout for each TCP connection attempt
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Outcome of a reachability check
+#[derive(Debug, Clone, Serialize)]
+pub struct ReachabilityResult {
+    pub host: String,
+    pub port: u16,
+
+    /// Whether the host resolved to at least one address (always true for IP
+    /// literals)
+    pub dns_resolved: bool,
+
+    /// Addresses the host resolved to
+    pub addresses: Vec<IpAddr>,
+
+    /// Time spent resolving (ms, None for IP literals)
+    pub dns_ms: Option<u64>,
+
+    /// Whether a TCP connection to the port succeeded
+    pub tcp_connected: bool,
+
+    /// Address the TCP connection was made to
+    pub connected_address: Option<SocketAddr>,
+
+    /// Time spent connecting, across all attempts (ms)
+    pub tcp_ms: Option<u64>,
+
+    /// What went wrong, for the first step that failed
+    pub error: Option<String>,
+}
+
+/// Resolve `url`'s host and try a TCP connection to its port
+///
+/// Resolved addresses are tried in order until one accepts.
+pub async fn check(url: &str) -> Result<ReachabilityResult, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("invalid gateway URL `{url}`: {e}"))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| format!("no port in gateway URL `{url}`"))?;
+
+    let mut result = ReachabilityResult {
+        host: String::new(),
+        port,
+        dns_resolved: false,
+        addresses: Vec::new(),
+        dns_ms: None,
+        tcp_connected: false,
+        connected_address: None,
+        tcp_ms: None,
+        error: None,
+    };
+
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("no host in gateway URL `{url}`"))?;
+    // IPv6 literals keep their brackets in URLs
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    result.host = host.to_string();
+
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        result.dns_resolved = true;
+        result.addresses.push(ip);
+    } else {
+        let started = Instant::now();
+        let lookup = tokio::time::timeout(DNS_TIMEOUT, tokio::net::lookup_host((host, port)));
+        let resolved = lookup.await;
+        result.dns_ms = Some(started.elapsed().as_millis() as u64);
+
+        match resolved {
+            Ok(Ok(addrs)) => {
+                for addr in addrs {
+                    if !result.addresses.contains(&addr.ip()) {
+                        result.addresses.push(addr.ip());
+                    }
+                }
+                result.dns_resolved = !result.addresses.is_empty();
+                if !result.dns_resolved {
+                    result.error = Some(format!("{host} resolved to no addresses"));
+                }
+            }
+            Ok(Err(e)) => result.error = Some(format!("failed to resolve {host}: {e}")),
+            Err(_) => {
+                result.error = Some(format!(
+                    "resolving {host} timed out after {}s",
+                    DNS_TIMEOUT.as_secs()
+                ));
+            }
+        }
+    }
+
+    if !result.dns_resolved {
+        return Ok(result);
+    }
+
+    let started = Instant::now();
+    let mut last_error = None;
+    for ip in &result.addresses {
+        let addr = SocketAddr::new(*ip, port);
+        match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => {
+                result.tcp_connected = true;
+                result.connected_address = Some(addr);
+                break;
+            }
+            Ok(Err(e)) => last_error = Some(format!("connecting to {addr} failed: {e}")),
+            Err(_) => {
+                last_error = Some(format!(
+                    "connecting to {addr} timed out after {}s",
+                    CONNECT_TIMEOUT.as_secs()
+                ));
+            }
+        }
+    }
+    result.tcp_ms = Some(started.elapsed().as_millis() as u64);
+
+    if !result.tcp_connected {
+        result.error = last_error;
+    }
+
+    Ok(result)
+}
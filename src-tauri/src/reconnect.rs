@@ -0,0 +1,193 @@
+//! Adaptive reconnect backoff for a flapping gateway
+//!
+//! Each sidecar crash or hang that leads to a restart is recorded here. The
+//! restart delay doubles with every failure that comes within
+//! [`STABLE_AFTER`] of the previous one, up to a ceiling. Past
+//! [`UNSTABLE_FAILURES`] a minute the gateway counts as unstable. The
+//! ceiling is then raised so restarts back off much further, and the
+//! per-failure `gateway-unresponsive` notifications are swallowed in favor
+//! of a single `gateway-unstable`. After [`STABLE_AFTER`] without a failure,
+//! normal backoff is restored and `gateway-stable` is emitted.
+//!
+//! The sidecar restart breaker still applies on top, and gives up on a
+//! sidecar that can't stay up at all.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::logs;
+
+/// Window failure density is measured over
+const DENSITY_WINDOW: Duration = Duration::from_secs(60);
+
+/// Failures within the window from which the gateway is unstable
+const UNSTABLE_FAILURES: usize = 3;
+
+/// Failure-free time after which an unstable gateway is stable again
+const STABLE_AFTER: Duration = Duration::from_secs(120);
+
+/// Delay before the first restart
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest restart delay while stable
+const BACKOFF_CEILING: Duration = Duration::from_secs(30);
+
+/// Longest restart delay while unstable
+const UNSTABLE_BACKOFF_CEILING: Duration = Duration::from_secs(300);
+
+/// Reconnect backoff, as returned by `get_reconnect_state` and in
+/// `gateway-unstable` / `gateway-stable` events
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconnectState {
+    /// Failures in the last minute
+    pub failures_per_minute: usize,
+
+    pub unstable: bool,
+
+    /// When the gateway became unstable (ms since Unix epoch)
+    pub unstable_since_ms: Option<u64>,
+
+    /// Delay before the most recent restart (ms)
+    pub backoff_ms: u64,
+
+    /// Longest delay restarts currently back off to (ms)
+    pub backoff_ceiling_ms: u64,
+
+    /// Failure notifications swallowed since the gateway became unstable
+    pub suppressed_notifications: u32,
+}
+
+/// What to do about a failure
+pub struct Failure {
+    /// Delay before restarting
+    pub delay: Duration,
+
+    /// Whether to notify about this failure (false while unstable)
+    pub notify: bool,
+
+    /// Whether this failure made the gateway unstable
+    pub became_unstable: bool,
+}
+
+#[derive(Default)]
+struct Inner {
+    failures: VecDeque<Instant>,
+
+    /// Last failure, kept past the density window to judge stability
+    last_failure: Option<Instant>,
+
+    /// Failures in a row, each within [`STABLE_AFTER`] of the previous one
+    streak: u32,
+
+    unstable_since_ms: Option<u64>,
+    backoff: Duration,
+    suppressed: u32,
+}
+
+impl Inner {
+    fn prune(&mut self, now: Instant) {
+        while self
+            .failures
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > DENSITY_WINDOW)
+        {
+            self.failures.pop_front();
+        }
+    }
+
+    fn ceiling(&self) -> Duration {
+        if self.unstable_since_ms.is_some() {
+            UNSTABLE_BACKOFF_CEILING
+        } else {
+            BACKOFF_CEILING
+        }
+    }
+}
+
+/// Failure history of the gateway
+#[derive(Default)]
+pub struct ReconnectTracker {
+    inner: Mutex<Inner>,
+}
+
+impl ReconnectTracker {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record a failure, returning how long to back off
+    pub fn record_failure(&self) -> Failure {
+        let now = Instant::now();
+        let mut inner = self.lock();
+        inner.prune(now);
+        inner.failures.push_back(now);
+        let in_streak = inner
+            .last_failure
+            .is_some_and(|t| now.duration_since(t) < STABLE_AFTER);
+        inner.streak = if in_streak { inner.streak + 1 } else { 1 };
+        inner.last_failure = Some(now);
+
+        let became_unstable =
+            inner.unstable_since_ms.is_none() && inner.failures.len() >= UNSTABLE_FAILURES;
+        if became_unstable {
+            inner.unstable_since_ms = Some(logs::now_ms());
+            inner.suppressed = 0;
+            tracing::warn!(
+                failures = inner.failures.len(),
+                "gateway is unstable, backing off further"
+            );
+        }
+
+        let notify = inner.unstable_since_ms.is_none();
+        if !notify && !became_unstable {
+            inner.suppressed += 1;
+        }
+
+        let doublings = (inner.streak - 1).min(16);
+        inner.backoff = (BASE_DELAY * 2u32.pow(doublings)).min(inner.ceiling());
+
+        Failure {
+            delay: inner.backoff,
+            notify,
+            became_unstable,
+        }
+    }
+
+    /// Restore normal backoff once the gateway has stayed up long enough,
+    /// returning whether it just became stable
+    pub fn settle(&self) -> bool {
+        let mut inner = self.lock();
+        let quiet = inner
+            .last_failure
+            .is_none_or(|t| t.elapsed() >= STABLE_AFTER);
+        if !quiet || inner.unstable_since_ms.is_none() {
+            return false;
+        }
+        tracing::info!(
+            suppressed = inner.suppressed,
+            "gateway is stable again, restoring normal backoff"
+        );
+        inner.unstable_since_ms = None;
+        inner.backoff = BASE_DELAY;
+        inner.streak = 0;
+        true
+    }
+
+    /// Current backoff state
+    pub fn state(&self) -> ReconnectState {
+        let mut inner = self.lock();
+        inner.prune(Instant::now());
+        ReconnectState {
+            failures_per_minute: inner.failures.len(),
+            unstable: inner.unstable_since_ms.is_some(),
+            unstable_since_ms: inner.unstable_since_ms,
+            backoff_ms: inner.backoff.as_millis() as u64,
+            backoff_ceiling_ms: inner.ceiling().as_millis() as u64,
+            suppressed_notifications: inner.suppressed,
+        }
+    }
+}
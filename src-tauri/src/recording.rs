@@ -0,0 +1,328 @@
+//! Recording and replaying gateway stream events
+//!
+//! While a recording runs, every proxied stream event sent to the frontend
+//! (`proxy-stream-chunk`, `proxy-stream-end`) is also appended to a
+//! newline-delimited JSON file with its time offset. Replaying the file
+//! re-emits the same events, so a UI bug seen against a live gateway can be
+//! reproduced against the recorded data. Events the frontend receives from
+//! the gateway directly don't pass through here and aren't recorded.
+//!
+//! Credentials are redacted before anything is written (best effort: stream
+//! chunks are scanned line by line for SSE `data:` JSON), and a recording
+//! stops by itself once the file reaches [`MAX_RECORDING_BYTES`].
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+
+use crate::logs;
+
+/// Largest recording file; recording stops once it is reached
+pub const MAX_RECORDING_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Events that are recorded, and the only ones a replay will emit
+const RECORDED_EVENTS: &[&str] = &["proxy-stream-chunk", "proxy-stream-end"];
+
+/// Keys whose values are replaced before recording
+const SENSITIVE_KEYS: &[&str] = &[
+    "authorization",
+    "password",
+    "secret",
+    "client_secret",
+    "api_key",
+    "apikey",
+    "cookie",
+    "set-cookie",
+];
+
+const REDACTED: &str = "[redacted]";
+
+/// One line of a recording file
+#[derive(Serialize, Deserialize)]
+struct RecordedEvent {
+    /// Time since the recording started (ms)
+    offset_ms: u64,
+
+    /// When the event was emitted (ms since Unix epoch)
+    timestamp_ms: u64,
+
+    event: String,
+    payload: Value,
+}
+
+/// A finished (or running) recording
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingSummary {
+    pub path: PathBuf,
+    pub events: u64,
+    pub bytes: u64,
+
+    /// Whether recording stopped because the file reached its size cap
+    pub truncated: bool,
+}
+
+/// Result of replaying a recording
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplaySummary {
+    /// Events re-emitted
+    pub events: u64,
+
+    /// Lines skipped (unparseable, or not a recordable event)
+    pub skipped: u64,
+
+    pub elapsed_ms: u64,
+}
+
+struct Recording {
+    writer: BufWriter<File>,
+    started: Instant,
+    summary: RecordingSummary,
+}
+
+/// The active recording, if any
+#[derive(Default)]
+pub struct EventRecorder {
+    active: Mutex<Option<Recording>>,
+}
+
+impl EventRecorder {
+    /// Start recording into `path`, replacing any recording in progress
+    pub fn start(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+        }
+        let file =
+            File::create(path).map_err(|e| format!("failed to create {}: {e}", path.display()))?;
+
+        let recording = Recording {
+            writer: BufWriter::new(file),
+            started: Instant::now(),
+            summary: RecordingSummary {
+                path: path.to_path_buf(),
+                events: 0,
+                bytes: 0,
+                truncated: false,
+            },
+        };
+
+        let previous = self.lock().replace(recording);
+        if let Some(previous) = previous {
+            finish(previous);
+        }
+        tracing::info!(path = %path.display(), "started event recording");
+        Ok(())
+    }
+
+    /// Stop recording, returning what was recorded
+    pub fn stop(&self) -> Option<RecordingSummary> {
+        let recording = self.lock().take()?;
+        let summary = finish(recording);
+        tracing::info!(
+            path = %summary.path.display(),
+            events = summary.events,
+            "stopped event recording"
+        );
+        Some(summary)
+    }
+
+    /// Emit an event to the frontend, recording it if a recording is running
+    pub fn emit<S: Serialize + Clone>(&self, app: &AppHandle, event: &str, payload: S) {
+        self.record(event, &payload);
+        let _ = app.emit(event, payload);
+    }
+
+    fn record<S: Serialize>(&self, event: &str, payload: &S) {
+        let mut active = self.lock();
+        let Some(recording) = active.as_mut() else {
+            return;
+        };
+
+        let Ok(mut payload) = serde_json::to_value(payload) else {
+            return;
+        };
+        redact(&mut payload);
+
+        let line = RecordedEvent {
+            offset_ms: recording.started.elapsed().as_millis() as u64,
+            timestamp_ms: logs::now_ms(),
+            event: event.to_string(),
+            payload,
+        };
+        let Ok(mut line) = serde_json::to_vec(&line) else {
+            return;
+        };
+        line.push(b'\n');
+
+        let summary = &mut recording.summary;
+        if summary.bytes + line.len() as u64 > MAX_RECORDING_BYTES {
+            let path = summary.path.display();
+            tracing::warn!(path = %path, "event recording reached its size cap, stopping");
+            let mut recording = active.take().expect("recording is active");
+            recording.summary.truncated = true;
+            finish(recording);
+            return;
+        }
+
+        if let Err(e) = recording.writer.write_all(&line) {
+            tracing::warn!(error = %e, "failed to write event recording, stopping");
+            finish(active.take().expect("recording is active"));
+            return;
+        }
+        summary.events += 1;
+        summary.bytes += line.len() as u64;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Recording>> {
+        self.active.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn finish(mut recording: Recording) -> RecordingSummary {
+    if let Err(e) = recording.writer.flush() {
+        tracing::warn!(error = %e, "failed to flush event recording");
+    }
+    recording.summary
+}
+
+/// Resolve the path of a recording, keeping it inside `exports`
+///
+/// A relative `path` is taken from `exports`. Anything that resolves to
+/// outside it, through `..` or a symlink too, is refused, so the webview
+/// can't have arbitrary files overwritten or read back.
+pub fn confine(exports: &Path, path: &Path) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(exports)
+        .map_err(|e| format!("failed to create {}: {e}", exports.display()))?;
+    let exports = exports
+        .canonicalize()
+        .map_err(|e| format!("cannot resolve exports directory: {e}"))?;
+
+    let path = exports.join(path);
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("`{}` is not a file path", path.display()))?;
+
+    // A file that exists is resolved whole, so a symlink to elsewhere fails
+    let resolved = match path.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(_) => path
+            .parent()
+            .unwrap_or(&exports)
+            .canonicalize()
+            .map_err(|e| format!("cannot use `{}`: {e}", path.display()))?
+            .join(name),
+    };
+    if !resolved.starts_with(&exports) {
+        return Err(format!(
+            "`{}` is not allowed, recordings must be in {}",
+            path.display(),
+            exports.display()
+        ));
+    }
+    Ok(resolved)
+}
+
+/// Re-emit the events of a recording
+///
+/// With `realtime`, events keep their recorded spacing; otherwise they are
+/// sent as fast as possible.
+pub async fn replay(app: &AppHandle, path: &Path, realtime: bool) -> Result<ReplaySummary, String> {
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+
+    let started = Instant::now();
+    let mut events = 0;
+    let mut skipped = 0;
+
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let recorded = match serde_json::from_str::<RecordedEvent>(&line) {
+            Ok(recorded) if RECORDED_EVENTS.contains(&recorded.event.as_str()) => recorded,
+            _ => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        if realtime {
+            let due = Duration::from_millis(recorded.offset_ms);
+            tokio::time::sleep(due.saturating_sub(started.elapsed())).await;
+        }
+
+        let _ = app.emit(&recorded.event, recorded.payload);
+        events += 1;
+    }
+
+    tracing::info!(path = %path.display(), events, skipped, "replayed event recording");
+    Ok(ReplaySummary {
+        events,
+        skipped,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Replace credentials anywhere in a payload
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_sensitive(key) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        Value::String(text) => {
+            if let Some(redacted) = redact_sse(text) {
+                *text = redacted;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Redact JSON carried in SSE `data:` lines, if any line needed it
+fn redact_sse(text: &str) -> Option<String> {
+    let mut changed = false;
+    let lines: Vec<String> = text
+        .split('\n')
+        .map(|line| {
+            let Some(data) = line.strip_prefix("data:") else {
+                return line.to_string();
+            };
+            let Ok(mut json) = serde_json::from_str::<Value>(data.trim()) else {
+                return line.to_string();
+            };
+
+            let before = json.clone();
+            redact(&mut json);
+            if json == before {
+                return line.to_string();
+            }
+            changed = true;
+            format!("data: {json}")
+        })
+        .collect();
+
+    changed.then(|| lines.join("\n"))
+}
+
+/// Whether a key holds a credential (`token`, `*_token`, `password`, ...)
+///
+/// Token *counts* (`max_tokens`, `prompt_tokens`) are kept, since they're
+/// what usage bugs are usually about.
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key == "token" || key.ends_with("_token") || SENSITIVE_KEYS.contains(&key.as_str())
+}
@@ -0,0 +1,120 @@
+//! Client-side check of the gateway's request size limit
+//!
+//! A gateway may cap request bodies, and a body over the cap used to be sent
+//! in full only to come back as an opaque 413. The cap is read from
+//! `max_request_bytes` in the gateway's `/capabilities`, or learned from a
+//! 413 (the limit it states, or else the size it turned down), and kept for
+//! the connection. Bodies over it are refused with a `request too large`
+//! error before anything is sent.
+
+use reqwest::Method;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{api, capabilities, AppState};
+
+/// Where the limit came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitSource {
+    /// Advertised in the gateway's `/capabilities`
+    Capabilities,
+
+    /// Learned from a request the gateway turned down with 413
+    Rejected,
+}
+
+/// Request size limit of a gateway
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestLimit {
+    pub url: String,
+
+    /// Largest body the gateway accepts (bytes, no limit known if unset)
+    pub max_bytes: Option<u64>,
+    pub source: Option<LimitSource>,
+}
+
+/// Request size limit of the connected gateway, read from its capabilities
+/// unless already known for this connection
+pub async fn limit(state: &AppState) -> Result<RequestLimit, String> {
+    let url = api::base_url(state).await?;
+    if let Some(limit) = &*state.request_limit.read().await {
+        if limit.url == url {
+            return Ok(limit.clone());
+        }
+    }
+
+    let resp = api::request(state, Method::GET, "/capabilities", None).await?;
+    let max_bytes = match resp.status() {
+        status if status.is_success() => {
+            let body = resp.text().await.unwrap_or_default();
+            serde_json::from_str::<Value>(&body)
+                .ok()
+                .as_ref()
+                .and_then(capabilities::max_request_bytes)
+        }
+        _ => None,
+    };
+
+    let limit = RequestLimit {
+        url,
+        max_bytes,
+        source: max_bytes.map(|_| LimitSource::Capabilities),
+    };
+    tracing::debug!(?limit, "gateway request size limit");
+    *state.request_limit.write().await = Some(limit.clone());
+    Ok(limit)
+}
+
+/// Fail with a `request too large` error if `body` is over the gateway's
+/// limit, returning its size
+///
+/// A limit that can't be read isn't enforced; the gateway still is.
+pub async fn check(state: &AppState, body: Option<&Value>) -> Result<u64, String> {
+    let Some(body) = body else {
+        return Ok(0);
+    };
+    let size = serde_json::to_vec(body)
+        .map_err(|e| format!("failed to serialize request body: {e}"))?
+        .len() as u64;
+
+    let max_bytes = match limit(state).await {
+        Ok(limit) => limit.max_bytes,
+        Err(e) => {
+            tracing::debug!(error = %e, "could not read the gateway request size limit");
+            None
+        }
+    };
+    match max_bytes {
+        Some(max) if size > max => {
+            tracing::warn!(size, max, "request body over the gateway limit, not sent");
+            Err(too_large(size, max))
+        }
+        _ => Ok(size),
+    }
+}
+
+/// Learn the limit from a 413 response to a body of `size` bytes, returning
+/// the error to report
+pub async fn rejected(state: &AppState, resp: reqwest::Response, size: u64) -> String {
+    let url = api::base_url(state).await.unwrap_or_default();
+    let body = resp.text().await.unwrap_or_default();
+    let stated = serde_json::from_str::<Value>(&body)
+        .ok()
+        .as_ref()
+        .and_then(capabilities::max_request_bytes);
+
+    // Without a stated limit, anything this size or larger is known to fail
+    let max = stated.unwrap_or(size.saturating_sub(1));
+    tracing::warn!(size, max, "gateway rejected a request as too large");
+    *state.request_limit.write().await = Some(RequestLimit {
+        url,
+        max_bytes: Some(max),
+        source: Some(LimitSource::Rejected),
+    });
+    too_large(size, max)
+}
+
+fn too_large(size: u64, max: u64) -> String {
+    format!("request too large: body is {size} bytes, the gateway accepts at most {max}")
+}
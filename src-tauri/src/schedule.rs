@@ -0,0 +1,290 @@
+//! Scheduled sidecar restarts
+//!
+//! Long-running sidecars can be restarted on a schedule (every N hours, or
+//! daily at a local time) to clear slow leaks. A restart waits for active
+//! streams to finish, up to [`MAX_DEFER`], and never touches external
+//! gateways the app doesn't manage.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::{gateway, AppState, GatewayState};
+
+/// How often the scheduler wakes up to check the clock and settings
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often a deferred restart checks whether the gateway became idle
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest a restart waits for active streams before going ahead anyway
+const MAX_DEFER: Duration = Duration::from_secs(30 * 60);
+
+/// Longest restart interval accepted (a year)
+const MAX_INTERVAL_HOURS: u32 = 24 * 365;
+
+/// When to restart the sidecar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ScheduledRestart {
+    /// Every `hours` hours after the previous restart (or app start)
+    Interval { hours: u32 },
+
+    /// Every day at `time` (`HH:MM`, local time)
+    Daily { time: String },
+}
+
+impl ScheduledRestart {
+    /// Check the schedule is usable
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Interval { hours: 0 } => {
+                Err("restart interval must be at least 1 hour".to_string())
+            }
+            Self::Interval { hours } if *hours > MAX_INTERVAL_HOURS => Err(format!(
+                "restart interval must be at most {MAX_INTERVAL_HOURS} hours (got {hours})"
+            )),
+            Self::Interval { .. } => Ok(()),
+            Self::Daily { time } => parse_time(time).map(drop),
+        }
+    }
+
+    /// Next restart time after `now`, counting intervals from `anchor`
+    fn next_after(&self, now: DateTime<Local>, anchor: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Self::Interval { hours } => {
+                let next = anchor.checked_add_signed(TimeDelta::hours(i64::from(*hours)))?;
+                Some(next.max(now))
+            }
+            Self::Daily { time } => {
+                let time = parse_time(time).ok()?;
+                (0..=1).find_map(|days| {
+                    let date = now.date_naive() + TimeDelta::days(days);
+                    date.and_time(time)
+                        .and_local_timezone(Local)
+                        .earliest()
+                        .filter(|at| *at > now)
+                })
+            }
+        }
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .map_err(|_| format!("invalid restart time `{time}`, expected HH:MM"))
+}
+
+/// Stage of a scheduled restart, reported in `scheduled-restart` events
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPhase {
+    /// Restart time reached, waiting for active streams to finish
+    Deferred,
+
+    /// Sidecar is being restarted
+    Started,
+
+    /// Sidecar is back up
+    Completed,
+
+    /// Restart failed
+    Failed,
+}
+
+/// Payload of `scheduled-restart` events
+#[derive(Debug, Clone, Serialize)]
+pub struct RestartEvent {
+    pub phase: RestartPhase,
+    pub error: Option<String>,
+}
+
+/// Run the restart scheduler for the lifetime of the app
+pub async fn run(app: AppHandle, state: Arc<AppState>) {
+    let mut anchor = Local::now();
+    let mut current: Option<ScheduledRestart> = None;
+
+    loop {
+        let schedule = state.settings.read().await.scheduled_restart.clone();
+
+        // Restart the interval count when the schedule changes
+        if schedule != current {
+            anchor = Local::now();
+            current = schedule.clone();
+        }
+
+        let now = Local::now();
+        let next = schedule.as_ref().and_then(|s| s.next_after(now, anchor));
+        *state.next_scheduled_restart.write().await = next.map(|at| at.timestamp_millis());
+
+        let Some(next) = next else {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            continue;
+        };
+
+        let until = (next - now).to_std().unwrap_or_default();
+        if until > CHECK_INTERVAL {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            continue;
+        }
+        tokio::time::sleep(until).await;
+
+        anchor = Local::now();
+        if is_managed_sidecar(&state).await {
+            restart_when_idle(&app, &state).await;
+        } else {
+            tracing::debug!("scheduled restart skipped, no managed sidecar running");
+        }
+
+        // Don't fire twice for the same daily slot
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn is_managed_sidecar(state: &AppState) -> bool {
+    matches!(
+        &*state.gateway_state.read().await,
+        GatewayState::Connected {
+            is_sidecar: true,
+            ..
+        }
+    )
+}
+
+/// Wait (bounded) for active streams to finish, then restart the sidecar
+async fn restart_when_idle(app: &AppHandle, state: &AppState) {
+    let emit = |phase, error| {
+        let _ = app.emit("scheduled-restart", RestartEvent { phase, error });
+    };
+
+    if state.streams.active() > 0 {
+        tracing::info!(
+            active = state.streams.active(),
+            "scheduled restart deferred until idle"
+        );
+        emit(RestartPhase::Deferred, None);
+
+        let deadline = tokio::time::Instant::now() + MAX_DEFER;
+        while state.streams.active() > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+        }
+
+        // The sidecar may have gone away while we waited
+        if !is_managed_sidecar(state).await {
+            return;
+        }
+    }
+
+    tracing::info!("performing scheduled gateway restart");
+    emit(RestartPhase::Started, None);
+
+    match gateway::restart_sidecar(state).await {
+        Ok(()) => emit(RestartPhase::Completed, None),
+        Err(e) => {
+            tracing::error!(error = %e, "scheduled gateway restart failed");
+            emit(RestartPhase::Failed, Some(e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(y, m, d, h, min, 0)
+            .earliest()
+            .unwrap()
+    }
+
+    fn daily(time: &str) -> ScheduledRestart {
+        ScheduledRestart::Daily {
+            time: time.to_string(),
+        }
+    }
+
+    #[test]
+    fn interval_bounds() {
+        assert!(ScheduledRestart::Interval { hours: 0 }.validate().is_err());
+        assert!(ScheduledRestart::Interval { hours: 1 }.validate().is_ok());
+        let max = ScheduledRestart::Interval {
+            hours: MAX_INTERVAL_HOURS,
+        };
+        assert!(max.validate().is_ok());
+        let over = ScheduledRestart::Interval {
+            hours: MAX_INTERVAL_HOURS + 1,
+        };
+        assert!(over.validate().is_err());
+    }
+
+    #[test]
+    fn daily_time_must_be_hh_mm() {
+        assert!(daily("04:30").validate().is_ok());
+        assert!(daily(" 04:30 ").validate().is_ok());
+        assert!(daily("4:30pm").validate().is_err());
+        assert!(daily("25:00").validate().is_err());
+        assert!(daily("").validate().is_err());
+    }
+
+    #[test]
+    fn interval_counts_from_the_anchor() {
+        let anchor = local(2026, 1, 15, 8, 0);
+        let every_6h = ScheduledRestart::Interval { hours: 6 };
+
+        let next = every_6h.next_after(anchor, anchor);
+        assert_eq!(next, Some(local(2026, 1, 15, 14, 0)));
+
+        // An overdue restart happens now rather than in the past
+        let late = local(2026, 1, 15, 20, 0);
+        assert_eq!(every_6h.next_after(late, anchor), Some(late));
+    }
+
+    #[test]
+    fn interval_overflow_means_no_restart() {
+        let anchor = DateTime::<chrono::Utc>::MAX_UTC.with_timezone(&Local);
+        let schedule = ScheduledRestart::Interval { hours: 1 };
+        assert_eq!(schedule.next_after(anchor, anchor), None);
+    }
+
+    #[test]
+    fn daily_slot_later_today() {
+        let now = local(2026, 1, 15, 3, 0);
+        assert_eq!(
+            daily("04:30").next_after(now, now),
+            Some(local(2026, 1, 15, 4, 30))
+        );
+    }
+
+    #[test]
+    fn daily_slot_passed_rolls_over_midnight() {
+        let now = local(2026, 1, 15, 23, 50);
+        assert_eq!(
+            daily("00:10").next_after(now, now),
+            Some(local(2026, 1, 16, 0, 10))
+        );
+
+        // The slot itself counts as passed
+        let at_slot = local(2026, 1, 15, 4, 30);
+        assert_eq!(
+            daily("04:30").next_after(at_slot, at_slot),
+            Some(local(2026, 1, 16, 4, 30))
+        );
+    }
+
+    #[test]
+    fn daily_slot_on_dst_change_still_fires() {
+        // US and EU spring-forward nights, the slot may not exist locally
+        for (m, d) in [(3, 8), (3, 29)] {
+            let now = local(2026, m, d, 0, 0);
+            let next = daily("02:30").next_after(now, now).unwrap();
+            assert!(next > now);
+            assert!(next - now < TimeDelta::days(2));
+        }
+    }
+}
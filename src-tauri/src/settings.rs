@@ -0,0 +1,472 @@
+//! Persistent app settings
+//!
+//! Stored as `settings.json` in the data directory. Missing or unknown fields
+//! fall back to defaults so older settings files keep loading.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::allowlist::AllowedEndpoint;
+use crate::env_profiles::EnvProfile;
+use crate::gateway::GatewayLifecycle;
+use crate::logs::{self, LogLevel};
+use crate::pool::PoolConfig;
+use crate::schedule::ScheduledRestart;
+
+/// Settings file name (relative to data dir)
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Prefix of a corrupt settings file set aside, followed by a timestamp
+const CORRUPT_PREFIX: &str = "settings.json.corrupt-";
+
+/// App settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Last external gateway successfully connected to
+    pub saved_gateway: Option<SavedGateway>,
+
+    /// Hide to the tray instead of quitting when the window is closed (desktop)
+    pub close_to_tray: bool,
+
+    /// Base URL gateway release artifacts are downloaded from
+    pub gateway_download_url: Option<String>,
+
+    /// URL schemes `open_external` may hand to the OS
+    pub allowed_open_schemes: Vec<String>,
+
+    /// Consecutive failed health checks before a running sidecar is
+    /// considered hung and restarted (checks run every 5s)
+    pub sidecar_hung_threshold: u32,
+
+    /// How long a draining disconnect lets active streams finish (ms)
+    pub drain_grace_ms: u64,
+
+    /// Gateway log verbosity (gateway default if unset)
+    pub gateway_log_level: Option<LogLevel>,
+
+    /// Most proxied requests (streams included) in flight at once; more are
+    /// queued, which keeps a resource-constrained sidecar from being flooded
+    pub max_concurrent_requests: usize,
+
+    /// Proxied requests that may wait at once for a starting or
+    /// reconnecting gateway (see `queue_until_ready`)
+    pub max_queued_until_ready: usize,
+
+    /// How long such a request waits before failing (ms)
+    pub queue_until_ready_timeout_ms: u64,
+
+    /// Working directory for the sidecar (the binary's directory if unset)
+    pub sidecar_working_dir: Option<PathBuf>,
+
+    /// Whether the app manages the gateway process or leaves a hand-started
+    /// one alone (see `set_gateway_lifecycle`)
+    pub gateway_lifecycle: GatewayLifecycle,
+
+    /// Don't reach out to any gateway until turned off again
+    pub offline_mode: bool,
+
+    /// Restart the sidecar on a schedule (never if unset)
+    pub scheduled_restart: Option<ScheduledRestart>,
+
+    /// How long a discovered gateway stays listed after it was last seen (s)
+    pub discovery_ttl_secs: u64,
+
+    /// Look for the saved gateway over mDNS when connecting at startup
+    ///
+    /// Off for networks where multicast is noisy or blocked; explicit scans
+    /// still work.
+    pub discovery_on_startup: bool,
+
+    /// Cap on gateway upload bandwidth (bytes/sec, unlimited if unset)
+    pub upload_limit_bps: Option<u64>,
+
+    /// Cap on gateway download bandwidth (bytes/sec, unlimited if unset)
+    pub download_limit_bps: Option<u64>,
+
+    /// Idle connections kept open per gateway host for reuse (0 disables
+    /// reuse)
+    pub pool_max_idle_per_host: usize,
+
+    /// How long an idle gateway connection is kept open (s)
+    pub pool_idle_timeout_secs: u64,
+
+    /// Named sets of environment variables for the sidecar
+    pub env_profiles: BTreeMap<String, EnvProfile>,
+
+    /// Env profile applied when the sidecar starts (none if unset)
+    pub env_profile: Option<String>,
+
+    /// Tag proxied requests with an `X-Request-Id` and log each one, so
+    /// they can be matched up with the gateway's logs
+    pub request_tracing: bool,
+
+    /// Endpoints proxied requests are restricted to (all allowed if unset)
+    pub proxy_allowlist: Option<Vec<AllowedEndpoint>>,
+
+    /// Sampling interval of `start_metrics_stream` when it isn't given one
+    /// (ms)
+    pub metrics_stream_interval_ms: u64,
+
+    /// Durations of the latest successful sidecar startups (ms), oldest
+    /// first, sizing the startup timeout (recorded by the app; replacing
+    /// the settings keeps them)
+    pub startup_samples_ms: Vec<u64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            saved_gateway: None,
+            close_to_tray: false,
+            gateway_download_url: None,
+            allowed_open_schemes: vec!["https".to_string(), "mailto".to_string()],
+            sidecar_hung_threshold: 6,
+            drain_grace_ms: 5000,
+            gateway_log_level: None,
+            max_concurrent_requests: 8,
+            max_queued_until_ready: 32,
+            queue_until_ready_timeout_ms: 30_000,
+            sidecar_working_dir: None,
+            gateway_lifecycle: GatewayLifecycle::Managed,
+            offline_mode: false,
+            scheduled_restart: None,
+            discovery_ttl_secs: 60,
+            discovery_on_startup: true,
+            upload_limit_bps: None,
+            download_limit_bps: None,
+            pool_max_idle_per_host: 8,
+            pool_idle_timeout_secs: 90,
+            env_profiles: BTreeMap::new(),
+            env_profile: None,
+            request_tracing: false,
+            proxy_allowlist: None,
+            metrics_stream_interval_ms: 1000,
+            startup_samples_ms: Vec::new(),
+        }
+    }
+}
+
+/// A remembered gateway connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedGateway {
+    /// Gateway URL at the time it was last reached
+    pub url: String,
+
+    /// Gateway device ID, used to recognize it again if its address changes
+    pub device_id: Option<String>,
+}
+
+impl Settings {
+    /// Connection pool limits for gateway clients
+    pub fn pool(&self) -> PoolConfig {
+        PoolConfig {
+            max_idle_per_host: self.pool_max_idle_per_host,
+            idle_timeout_secs: self.pool_idle_timeout_secs,
+        }
+    }
+
+    /// Load settings from the data directory (defaults if missing)
+    ///
+    /// A file that doesn't parse is moved aside to
+    /// `settings.json.corrupt-<timestamp>` and defaults are used, so the
+    /// next save doesn't overwrite it; `repair_settings` can salvage it.
+    pub fn load(data_dir: &Path) -> (Self, Option<SettingsRecovery>) {
+        let path = data_dir.join(SETTINGS_FILE);
+
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return (Self::default(), None);
+        };
+
+        match serde_json::from_str(&contents) {
+            Ok(settings) => (settings, None),
+            Err(e) => {
+                tracing::warn!(error = %e, path = %path.display(), "failed to parse settings");
+                let recovery = set_aside(data_dir, e.to_string());
+                (Self::default(), recovery)
+            }
+        }
+    }
+
+    /// Persist settings to the data directory
+    pub fn save(&self, data_dir: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("failed to serialize settings: {e}"))?;
+
+        write_atomic(&data_dir.join(SETTINGS_FILE), contents.as_bytes())
+            .map_err(|e| format!("failed to write settings: {e}"))
+    }
+}
+
+/// Write a file through a temporary one next to it, so a crash mid-write
+/// leaves the previous contents instead of a truncated file
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let written = std::fs::File::create(&tmp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    let renamed = written.and_then(|()| std::fs::rename(&tmp, path));
+    if renamed.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    renamed
+}
+
+/// A corrupt settings file set aside at startup, the `settings-recovered`
+/// event payload
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingsRecovery {
+    /// Where the corrupt file was moved
+    pub backup_path: PathBuf,
+
+    /// Why it didn't parse
+    pub error: String,
+}
+
+/// Settings salvaged by `repair_settings`
+#[derive(Debug, Clone, Serialize)]
+pub struct Salvage {
+    /// File salvaged from (None if there was nothing to repair)
+    pub source: Option<PathBuf>,
+
+    /// Top-level fields kept from the file
+    pub salvaged: Vec<String>,
+
+    /// Top-level fields found but unusable, left at their defaults
+    pub dropped: Vec<String>,
+
+    pub settings: Settings,
+}
+
+/// Move a corrupt settings file aside
+fn set_aside(data_dir: &Path, error: String) -> Option<SettingsRecovery> {
+    let backup_path = data_dir.join(format!("{CORRUPT_PREFIX}{}", logs::now_ms()));
+    match std::fs::rename(data_dir.join(SETTINGS_FILE), &backup_path) {
+        Ok(()) => {
+            tracing::warn!(
+                backup = %backup_path.display(),
+                "corrupt settings set aside, using defaults"
+            );
+            Some(SettingsRecovery { backup_path, error })
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "failed to set aside corrupt settings");
+            None
+        }
+    }
+}
+
+/// Salvage what can be saved of a corrupt settings file
+///
+/// Salvages `settings.json` itself if it doesn't parse (setting it aside
+/// first), else the newest file set aside at startup. Each top-level field is
+/// kept if it's valid on its own, so one bad field costs only that field. A
+/// truncated file keeps the fields before the point it was cut off.
+pub fn salvage(data_dir: &Path) -> Result<Salvage, String> {
+    let path = data_dir.join(SETTINGS_FILE);
+    let current = std::fs::read_to_string(&path).ok();
+    let current_broken = current
+        .as_deref()
+        .is_some_and(|contents| serde_json::from_str::<Settings>(contents).is_err());
+
+    let source = if current_broken {
+        let error = "corrupt settings found while repairing".to_string();
+        set_aside(data_dir, error)
+            .map(|recovery| recovery.backup_path)
+            .ok_or_else(|| "failed to set aside the corrupt settings file".to_string())?
+    } else {
+        match newest_backup(data_dir) {
+            Some(backup) => backup,
+            None => {
+                let settings = current
+                    .and_then(|contents| serde_json::from_str(&contents).ok())
+                    .unwrap_or_default();
+                return Ok(Salvage {
+                    source: None,
+                    salvaged: Vec::new(),
+                    dropped: Vec::new(),
+                    settings,
+                });
+            }
+        }
+    };
+
+    let contents = std::fs::read_to_string(&source)
+        .map_err(|e| format!("failed to read {}: {e}", source.display()))?;
+    let fields = match serde_json::from_str::<Value>(&contents) {
+        Ok(Value::Object(fields)) => fields,
+        Ok(_) => Map::new(),
+        Err(_) => leading_fields(&contents),
+    };
+
+    // Fields are tried one at a time on top of the defaults
+    let mut merged = match serde_json::to_value(Settings::default()) {
+        Ok(Value::Object(defaults)) => defaults,
+        _ => return Err("failed to serialize default settings".to_string()),
+    };
+    let (mut salvaged, mut dropped) = (Vec::new(), Vec::new());
+    for (name, value) in fields {
+        let previous = merged.insert(name.clone(), value);
+        if serde_json::from_value::<Settings>(Value::Object(merged.clone())).is_ok() {
+            salvaged.push(name);
+        } else {
+            match previous {
+                Some(previous) => merged.insert(name.clone(), previous),
+                None => merged.remove(&name),
+            };
+            dropped.push(name);
+        }
+    }
+
+    let settings = serde_json::from_value(Value::Object(merged))
+        .map_err(|e| format!("failed to rebuild settings: {e}"))?;
+    tracing::info!(
+        source = %source.display(),
+        ?salvaged,
+        ?dropped,
+        "salvaged corrupt settings"
+    );
+    Ok(Salvage {
+        source: Some(source),
+        salvaged,
+        dropped,
+        settings,
+    })
+}
+
+/// Rename a salvaged file so it isn't salvaged again over newer settings
+pub fn mark_repaired(source: &Path) {
+    let mut repaired = source.as_os_str().to_owned();
+    repaired.push(".repaired");
+    if let Err(e) = std::fs::rename(source, &repaired) {
+        tracing::warn!(
+            error = %e,
+            path = %source.display(),
+            "failed to mark settings repaired"
+        );
+    }
+}
+
+/// Newest corrupt settings file set aside in `data_dir`
+fn newest_backup(data_dir: &Path) -> Option<PathBuf> {
+    std::fs::read_dir(data_dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let stamp: u64 = name.to_str()?.strip_prefix(CORRUPT_PREFIX)?.parse().ok()?;
+            Some((stamp, entry.path()))
+        })
+        .max_by_key(|(stamp, _)| *stamp)
+        .map(|(_, path)| path)
+}
+
+/// Top-level fields of a JSON object, read up to the first point it stops
+/// parsing (e.g. where a partial write cut it off)
+fn leading_fields(contents: &str) -> Map<String, Value> {
+    let mut fields = Map::new();
+    let Some(mut rest) = contents.trim_start().strip_prefix('{') else {
+        return fields;
+    };
+
+    loop {
+        let Some((key, after)) = next_value(rest) else {
+            break;
+        };
+        let (Value::String(key), Some(after)) = (key, after.trim_start().strip_prefix(':')) else {
+            break;
+        };
+        let Some((value, after)) = next_value(after) else {
+            break;
+        };
+        fields.insert(key, value);
+
+        match after.trim_start().strip_prefix(',') {
+            Some(after) => rest = after,
+            None => break,
+        }
+    }
+    fields
+}
+
+/// Parse the JSON value at the start of `text`, returning it and the rest
+fn next_value(text: &str) -> Option<(Value, &str)> {
+    let mut values = serde_json::Deserializer::from_str(text).into_iter::<Value>();
+    let value = values.next()?.ok()?;
+    Some((value, &text[values.byte_offset()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Salvage `contents` as a corrupt `settings.json` in a fresh directory,
+    /// returning the result and what the file set aside holds
+    fn salvage_file(name: &str, contents: &str) -> (Salvage, String) {
+        let dir =
+            std::env::temp_dir().join(format!("beacon-salvage-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(SETTINGS_FILE), contents).unwrap();
+
+        let result = salvage(&dir).unwrap();
+        assert!(!dir.join(SETTINGS_FILE).exists());
+        let kept = std::fs::read_to_string(result.source.as_ref().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        (result, kept)
+    }
+
+    fn sorted(mut fields: Vec<String>) -> Vec<String> {
+        fields.sort();
+        fields
+    }
+
+    #[test]
+    fn truncated_file_keeps_the_fields_before_the_cut() {
+        let contents = r#"{"close_to_tray": true, "offline_mode": true, "env_profile": "sta"#;
+        let (result, kept) = salvage_file("truncated", contents);
+        assert_eq!(sorted(result.salvaged), ["close_to_tray", "offline_mode"]);
+        assert!(result.dropped.is_empty());
+        assert!(result.settings.close_to_tray);
+        assert!(result.settings.offline_mode);
+        assert_eq!(result.settings.env_profile, None);
+        assert_eq!(kept, contents);
+    }
+
+    #[test]
+    fn wrong_type_drops_only_that_field() {
+        let contents = r#"{"close_to_tray": "yes", "offline_mode": true}"#;
+        let (result, kept) = salvage_file("wrong-type", contents);
+        assert_eq!(result.salvaged, ["offline_mode"]);
+        assert_eq!(result.dropped, ["close_to_tray"]);
+        assert!(!result.settings.close_to_tray);
+        assert!(result.settings.offline_mode);
+        assert_eq!(kept, contents);
+    }
+
+    #[test]
+    fn non_object_root_salvages_nothing() {
+        let (result, kept) = salvage_file("array", "[1, 2]");
+        assert!(result.salvaged.is_empty());
+        assert!(result.dropped.is_empty());
+        assert!(!result.settings.offline_mode);
+        assert_eq!(kept, "[1, 2]");
+    }
+
+    #[test]
+    fn empty_file_salvages_nothing() {
+        let (result, kept) = salvage_file("empty", "");
+        assert!(result.salvaged.is_empty());
+        assert!(result.dropped.is_empty());
+        assert!(!result.settings.close_to_tray);
+        assert_eq!(kept, "");
+    }
+}
@@ -0,0 +1,287 @@
+//! Full app state snapshots
+//!
+//! A snapshot bundles settings, profiles, connection history and metrics into
+//! a single JSON file, so QA and support can reproduce a user's exact state.
+//! Profile tokens and headers are only included when the user supplies a
+//! passphrase; they are then sealed with AES-256-GCM under a key derived from
+//! it with PBKDF2, and never written in the clear.
+
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+use crate::logs;
+use crate::metrics::MetricsSnapshot;
+use crate::profiles::{self, GatewayProfile};
+use crate::settings::Settings;
+use crate::storage;
+
+/// Snapshot format version, bumped on incompatible changes
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// PBKDF2-HMAC-SHA256 rounds for deriving the secrets key
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// Salt length for key derivation (bytes)
+const SALT_LEN: usize = 16;
+
+/// A captured copy of the app's state
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Snapshot format version
+    pub version: u32,
+
+    /// App version the snapshot was taken with
+    pub app_version: String,
+
+    /// When the snapshot was taken (ms since Unix epoch)
+    pub created_at_ms: u64,
+
+    pub settings: Settings,
+    pub profiles: Vec<GatewayProfile>,
+
+    /// Connection events, latency samples and request counts
+    pub metrics: MetricsSnapshot,
+
+    /// Profile tokens and headers, only present when exported with a passphrase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets: Option<SealedSecrets>,
+}
+
+/// Secure storage entries encrypted under a passphrase
+#[derive(Serialize, Deserialize)]
+pub struct SealedSecrets {
+    /// Key derivation salt (base64)
+    pub salt: String,
+
+    /// AES-GCM nonce (base64)
+    pub nonce: String,
+
+    /// Encrypted JSON map of storage key to value, with its tag (base64)
+    pub ciphertext: String,
+}
+
+/// Result of restoring a snapshot
+#[derive(Debug, Serialize)]
+pub struct SnapshotImport {
+    /// App version the snapshot was taken with
+    pub app_version: String,
+
+    /// When the snapshot was taken (ms since Unix epoch)
+    pub created_at_ms: u64,
+
+    /// Profiles restored
+    pub profiles: usize,
+
+    /// Whether tokens and headers were restored too
+    pub secrets_restored: bool,
+}
+
+/// Capture the current state
+///
+/// Secrets of every profile are sealed under `passphrase` if one is given and
+/// left out entirely otherwise.
+pub fn capture(
+    settings: Settings,
+    metrics: MetricsSnapshot,
+    data_dir: &Path,
+    passphrase: Option<&str>,
+) -> Result<Snapshot, String> {
+    let profiles = profiles::load(data_dir);
+
+    let secrets = match passphrase {
+        Some(passphrase) => {
+            let mut entries = BTreeMap::new();
+            for profile in &profiles {
+                for key in [
+                    profiles::token_key(&profile.name),
+                    profiles::headers_key(&profile.name),
+                ] {
+                    if let Some(value) = storage::get(&key)? {
+                        entries.insert(key, value);
+                    }
+                }
+            }
+
+            let sealed = seal(&entries, passphrase);
+            for value in entries.values_mut() {
+                value.zeroize();
+            }
+            Some(sealed?)
+        }
+        None => None,
+    };
+
+    Ok(Snapshot {
+        version: SNAPSHOT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at_ms: logs::now_ms(),
+        settings,
+        profiles,
+        metrics,
+        secrets,
+    })
+}
+
+/// Write a snapshot into `dir`, returning the file's path
+pub fn write(snapshot: &Snapshot, dir: &Path) -> Result<PathBuf, String> {
+    let contents = serde_json::to_string_pretty(snapshot)
+        .map_err(|e| format!("failed to serialize snapshot: {e}"))?;
+
+    std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    let path = dir.join(format!("state-{}.json", snapshot.created_at_ms));
+    std::fs::write(&path, contents).map_err(|e| format!("failed to write snapshot: {e}"))?;
+
+    Ok(path)
+}
+
+/// Read a snapshot and check that this app version can restore it
+pub fn read(path: &Path) -> Result<Snapshot, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read snapshot {}: {e}", path.display()))?;
+    let snapshot: Snapshot =
+        serde_json::from_str(&contents).map_err(|e| format!("invalid snapshot: {e}"))?;
+
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(format!(
+            "snapshot format {} is not supported (expected {SNAPSHOT_VERSION}, taken with app {})",
+            snapshot.version, snapshot.app_version
+        ));
+    }
+
+    Ok(snapshot)
+}
+
+/// Decrypt a snapshot's secrets, if it has any
+///
+/// Only the tokens and headers of the snapshot's own profiles are kept, so
+/// a crafted snapshot can't overwrite other secure storage entries.
+pub fn unseal(
+    snapshot: &Snapshot,
+    passphrase: Option<&str>,
+) -> Result<Option<BTreeMap<String, String>>, String> {
+    let mut secrets = match (&snapshot.secrets, passphrase) {
+        (Some(sealed), Some(passphrase)) => open(sealed, passphrase)?,
+        (Some(_), None) => {
+            return Err("snapshot contains encrypted secrets; a passphrase is required".to_string())
+        }
+        (None, _) => return Ok(None),
+    };
+
+    secrets.retain(|key, value| {
+        let known = snapshot.profiles.iter().any(|profile| {
+            *key == profiles::token_key(&profile.name)
+                || *key == profiles::headers_key(&profile.name)
+        });
+        if !known {
+            tracing::warn!(key, "ignoring a snapshot secret of no restored profile");
+            value.zeroize();
+        }
+        known
+    });
+    Ok(Some(secrets))
+}
+
+/// Restore a snapshot's profiles, and its secrets from [`unseal`], to disk
+/// and secure storage
+///
+/// Settings and metrics are left for the caller, which owns the live copies.
+pub fn restore(
+    snapshot: &Snapshot,
+    data_dir: &Path,
+    secrets: Option<BTreeMap<String, String>>,
+) -> Result<bool, String> {
+    // Drop secrets of the profiles being replaced so none leak across
+    for profile in profiles::load(data_dir) {
+        storage::remove(&profiles::token_key(&profile.name))?;
+        storage::remove(&profiles::headers_key(&profile.name))?;
+    }
+    profiles::save(data_dir, &snapshot.profiles)?;
+
+    let restored = secrets.is_some();
+    if let Some(mut secrets) = secrets {
+        for (key, value) in &mut secrets {
+            if let Some(mut previous) = storage::set(key, std::mem::take(value))? {
+                previous.zeroize();
+            }
+        }
+    }
+
+    Ok(restored)
+}
+
+/// Derive the secrets key from a passphrase
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey, String> {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PBKDF2_ITERATIONS).expect("iterations are nonzero"),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+
+    let unbound = UnboundKey::new(&AES_256_GCM, &key);
+    key.zeroize();
+    unbound
+        .map(LessSafeKey::new)
+        .map_err(|_| "failed to derive snapshot key".to_string())
+}
+
+/// Encrypt secure storage entries under a passphrase
+fn seal(entries: &BTreeMap<String, String>, passphrase: &str) -> Result<SealedSecrets, String> {
+    if passphrase.is_empty() {
+        return Err("snapshot passphrase must not be empty".to_string());
+    }
+
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|()| rng.fill(&mut nonce))
+        .map_err(|_| "failed to generate snapshot salt".to_string())?;
+
+    let key = derive_key(passphrase, &salt)?;
+    let mut data =
+        serde_json::to_vec(entries).map_err(|e| format!("failed to serialize secrets: {e}"))?;
+    let sealed =
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data);
+    if sealed.is_err() {
+        data.zeroize();
+        return Err("failed to encrypt snapshot secrets".to_string());
+    }
+
+    Ok(SealedSecrets {
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(&data),
+    })
+}
+
+/// Decrypt sealed secrets, failing on a wrong passphrase or tampered data
+fn open(sealed: &SealedSecrets, passphrase: &str) -> Result<BTreeMap<String, String>, String> {
+    let invalid = |_| "snapshot secrets are corrupt".to_string();
+    let salt = STANDARD.decode(&sealed.salt).map_err(invalid)?;
+    let nonce = STANDARD.decode(&sealed.nonce).map_err(invalid)?;
+    let mut data = STANDARD.decode(&sealed.ciphertext).map_err(invalid)?;
+
+    let nonce = Nonce::try_assume_unique_for_key(&nonce)
+        .map_err(|_| "snapshot secrets are corrupt".to_string())?;
+    let key = derive_key(passphrase, &salt)?;
+    let plaintext = key
+        .open_in_place(nonce, Aad::empty(), &mut data)
+        .map_err(|_| "wrong passphrase or corrupt snapshot secrets".to_string())?;
+
+    let entries = serde_json::from_slice(plaintext)
+        .map_err(|e| format!("failed to parse snapshot secrets: {e}"));
+    data.zeroize();
+    entries
+}
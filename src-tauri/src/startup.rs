@@ -0,0 +1,116 @@
+//! Sidecar startup time on this machine
+//!
+//! How long the gateway takes to come up depends heavily on the hardware,
+//! so successful sidecar startups are timed and the most recent kept in
+//! `startup_samples_ms` in settings. Their median is the startup estimate
+//! the UI sizes its progress bar by, and a few times it is the startup
+//! timeout; until there are enough samples, the default timeout applies.
+
+use std::time::Duration;
+
+use crate::gateway::GATEWAY_STARTUP_TIMEOUT;
+use crate::settings::Settings;
+use crate::AppState;
+
+/// Startups kept for the estimate
+const MAX_SAMPLES: usize = 10;
+
+/// Startups needed before the estimate is trusted
+const MIN_SAMPLES: usize = 3;
+
+/// Timeout as a multiple of the estimate, for slower-than-usual startups
+const SAFETY_FACTOR: u32 = 3;
+
+/// Bounds of the adaptive startup timeout
+const MIN_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Median of the recent startups, once there are enough of them
+pub fn estimate(settings: &Settings) -> Option<Duration> {
+    let mut samples = settings.startup_samples_ms.clone();
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+    samples.sort_unstable();
+    let mid = samples.len() / 2;
+    let median = if samples.len() % 2 == 0 {
+        (samples[mid - 1] + samples[mid]) / 2
+    } else {
+        samples[mid]
+    };
+    Some(Duration::from_millis(median))
+}
+
+/// How long a sidecar gets to become healthy
+pub fn timeout(settings: &Settings) -> Duration {
+    estimate(settings).map_or(GATEWAY_STARTUP_TIMEOUT, |estimate| {
+        (estimate * SAFETY_FACTOR).clamp(MIN_TIMEOUT, MAX_TIMEOUT)
+    })
+}
+
+/// Add a successful startup to the samples
+pub async fn record(state: &AppState, took: Duration) {
+    let mut settings = state.settings.write().await;
+    let samples = &mut settings.startup_samples_ms;
+    samples.push(took.as_millis() as u64);
+    if samples.len() > MAX_SAMPLES {
+        let excess = samples.len() - MAX_SAMPLES;
+        samples.drain(..excess);
+    }
+
+    let estimate_ms = estimate(&settings).map(|e| e.as_millis() as u64);
+    tracing::debug!(
+        took_ms = took.as_millis() as u64,
+        estimate_ms,
+        "recorded sidecar startup time"
+    );
+    if let Err(e) = settings.save(&state.data_dir) {
+        tracing::warn!(error = %e, "failed to save sidecar startup time");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_samples(samples: &[u64]) -> Settings {
+        Settings {
+            startup_samples_ms: samples.to_vec(),
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn too_few_samples_use_the_default() {
+        let settings = with_samples(&[1_000, 2_000]);
+        assert_eq!(estimate(&settings), None);
+        assert_eq!(timeout(&settings), GATEWAY_STARTUP_TIMEOUT);
+    }
+
+    #[test]
+    fn median_of_odd_samples_is_the_middle_one() {
+        let settings = with_samples(&[9_000, 1_000, 3_000]);
+        assert_eq!(estimate(&settings), Some(Duration::from_millis(3_000)));
+    }
+
+    #[test]
+    fn median_of_even_samples_is_the_mean_of_the_middle_two() {
+        let settings = with_samples(&[4_000, 1_000, 2_000, 30_000]);
+        assert_eq!(estimate(&settings), Some(Duration::from_millis(3_000)));
+    }
+
+    #[test]
+    fn timeout_is_a_multiple_of_the_estimate() {
+        let settings = with_samples(&[4_000, 4_000, 4_000]);
+        assert_eq!(timeout(&settings), Duration::from_secs(12));
+    }
+
+    #[test]
+    fn timeout_is_clamped() {
+        assert_eq!(timeout(&with_samples(&[100, 100, 100])), MIN_TIMEOUT);
+        assert_eq!(
+            timeout(&with_samples(&[60_000, 60_000, 60_000])),
+            MAX_TIMEOUT
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Secure storage for secrets (device identity, gateway tokens)
+//!
+//! On desktop, secrets go to the platform keychain (macOS Keychain, Windows
+//! Credential Manager, Secret Service on Linux). Where no keychain is
+//! available, and for writes the keychain refuses, they are kept in memory
+//! for the session instead; [`migrate`] moves those into the keychain.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+use serde::Serialize;
+use zeroize::Zeroize;
+
+use crate::{logs, profiles};
+
+/// Reserved key used by the storage round-trip test
+const TEST_KEY: &str = "__beacon_storage_test__";
+
+/// Marker file (relative to data dir) recording the keychain migration
+const MIGRATION_MARKER: &str = "secure-storage-migrated";
+
+/// Where secrets are kept
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// Process memory, lost on restart
+    Memory,
+
+    /// The platform keychain
+    Keychain,
+}
+
+impl Backend {
+    /// Whether values survive an app restart
+    pub fn is_persistent(self) -> bool {
+        match self {
+            Self::Memory => false,
+            Self::Keychain => true,
+        }
+    }
+}
+
+static BACKEND: LazyLock<Backend> = LazyLock::new(|| match keychain::check() {
+    Ok(()) => Backend::Keychain,
+    Err(e) => {
+        tracing::warn!(error = %e, "keychain unavailable, keeping secrets in memory");
+        Backend::Memory
+    }
+});
+
+/// Backend currently in use
+pub fn backend() -> Backend {
+    *BACKEND
+}
+
+/// Values not (yet) in the keychain
+static SECURE_STORAGE: LazyLock<Mutex<HashMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn memory() -> Result<std::sync::MutexGuard<'static, HashMap<String, String>>, String> {
+    SECURE_STORAGE
+        .lock()
+        .map_err(|e| format!("storage lock failed: {e}"))
+}
+
+/// Get a value from secure storage
+pub fn get(key: &str) -> Result<Option<String>, String> {
+    if let Some(value) = memory()?.get(key) {
+        return Ok(Some(value.clone()));
+    }
+    match backend() {
+        Backend::Memory => Ok(None),
+        Backend::Keychain => keychain::get(key),
+    }
+}
+
+/// Set a value in secure storage, returning the previous value
+///
+/// A value the keychain refuses is kept in memory, so the session still
+/// works; `migrate_secure_storage` retries moving it.
+pub fn set(key: &str, value: String) -> Result<Option<String>, String> {
+    if backend() == Backend::Memory {
+        return Ok(memory()?.insert(key.to_string(), value));
+    }
+
+    let previous = match memory()?.remove(key) {
+        Some(previous) => Some(previous),
+        None => keychain::get(key)?,
+    };
+    if let Err(e) = keychain::set(key, &value) {
+        tracing::warn!(key, error = %e, "keychain write failed, keeping value in memory");
+        memory()?.insert(key.to_string(), value);
+    }
+    Ok(previous)
+}
+
+/// Remove a value from secure storage, wiping it from memory
+pub fn remove(key: &str) -> Result<(), String> {
+    if let Some(mut value) = memory()?.remove(key) {
+        value.zeroize();
+    }
+    match backend() {
+        Backend::Memory => Ok(()),
+        Backend::Keychain => keychain::remove(key),
+    }
+}
+
+/// Result of moving in-memory secrets into the keychain
+#[derive(Debug, Serialize)]
+pub struct MigrationResult {
+    pub backend: Backend,
+
+    /// Keys moved from memory into the keychain
+    pub migrated: Vec<String>,
+
+    /// Keys the keychain refused, still in memory
+    pub failed: Vec<String>,
+
+    /// Whether this is the first run with the keychain on this install
+    pub first_run: bool,
+
+    /// Profiles with no token in secure storage, which may need it
+    /// re-entered after the move to the keychain
+    pub missing_tokens: Vec<String>,
+}
+
+/// Move in-memory secrets into the keychain and record that it was done
+///
+/// Safe to call any number of times: entries already moved are gone from
+/// memory, and the marker only changes `first_run`. Earlier builds kept
+/// secrets in memory alone and never wrote them to disk, so there is no
+/// dump to import; profiles whose token is gone are listed instead so the
+/// user can be asked for them again.
+pub fn migrate(data_dir: &Path) -> Result<MigrationResult, String> {
+    let backend = backend();
+    let mut result = MigrationResult {
+        backend,
+        migrated: Vec::new(),
+        failed: Vec::new(),
+        first_run: false,
+        missing_tokens: Vec::new(),
+    };
+    if backend != Backend::Keychain {
+        return Ok(result);
+    }
+
+    let entries: Vec<(String, String)> = memory()?.drain().collect();
+    for (key, mut value) in entries {
+        match keychain::set(&key, &value) {
+            Ok(()) => {
+                value.zeroize();
+                result.migrated.push(key);
+            }
+            Err(e) => {
+                tracing::warn!(key, error = %e, "failed to move secret into the keychain");
+                memory()?.insert(key.clone(), value);
+                result.failed.push(key);
+            }
+        }
+    }
+
+    for profile in profiles::load(data_dir) {
+        if get(&profiles::token_key(&profile.name))?.is_none() {
+            result.missing_tokens.push(profile.name);
+        }
+    }
+
+    let marker = data_dir.join(MIGRATION_MARKER);
+    if !marker.exists() {
+        result.first_run = true;
+        std::fs::write(&marker, logs::now_ms().to_string())
+            .map_err(|e| format!("failed to write {}: {e}", marker.display()))?;
+    }
+
+    tracing::info!(
+        migrated = result.migrated.len(),
+        failed = result.failed.len(),
+        first_run = result.first_run,
+        "secure storage migration"
+    );
+    Ok(result)
+}
+
+/// The platform keychain
+#[cfg(desktop)]
+mod keychain {
+    use keyring::{Entry, Error};
+
+    /// Service name secrets are filed under
+    const SERVICE: &str = "dev.omni.beacon";
+
+    fn entry(key: &str) -> Result<Entry, String> {
+        Entry::new(SERVICE, key).map_err(|e| format!("keychain entry failed: {e}"))
+    }
+
+    /// Check that the keychain can be reached
+    pub fn check() -> Result<(), String> {
+        match entry(super::TEST_KEY)?.get_password() {
+            Ok(_) | Err(Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn get(key: &str) -> Result<Option<String>, String> {
+        match entry(key)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("keychain read failed: {e}")),
+        }
+    }
+
+    pub fn set(key: &str, value: &str) -> Result<(), String> {
+        entry(key)?
+            .set_password(value)
+            .map_err(|e| format!("keychain write failed: {e}"))
+    }
+
+    pub fn remove(key: &str) -> Result<(), String> {
+        match entry(key)?.delete_credential() {
+            Ok(()) | Err(Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("keychain delete failed: {e}")),
+        }
+    }
+}
+
+/// No keychain on mobile; secrets stay in memory
+#[cfg(not(desktop))]
+mod keychain {
+    const UNAVAILABLE: &str = "no keychain on this platform";
+
+    pub fn check() -> Result<(), String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn get(_key: &str) -> Result<Option<String>, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn set(_key: &str, _value: &str) -> Result<(), String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn remove(_key: &str) -> Result<(), String> {
+        Err(UNAVAILABLE.to_string())
+    }
+}
+
+/// Result of a secure storage round-trip test
+#[derive(Debug, Serialize)]
+pub struct StorageTestResult {
+    /// Write, read-back and delete all succeeded
+    pub success: bool,
+
+    /// Backend the test ran against
+    pub backend: Backend,
+
+    /// Whether the backend keeps values across restarts
+    pub persistent: bool,
+
+    /// Value read back matched the value written
+    pub value_survived: bool,
+
+    /// First error encountered, if any
+    pub error: Option<String>,
+}
+
+/// Write, read back and delete a value under a reserved key
+///
+/// The test key is always removed, even when an earlier step fails.
+pub fn round_trip_test() -> StorageTestResult {
+    let backend = backend();
+    let expected = format!("beacon-storage-test-{}", std::process::id());
+
+    let read_back = set(TEST_KEY, expected.clone()).and_then(|_| get(TEST_KEY));
+    let removed = remove(TEST_KEY);
+
+    let value_survived = matches!(&read_back, Ok(Some(v)) if *v == expected);
+    let error = match (read_back, removed) {
+        (Err(e), _) | (_, Err(e)) => Some(e),
+        (Ok(None), _) => Some("value missing after write".to_string()),
+        (Ok(Some(_)), _) if !value_survived => Some("value changed after write".to_string()),
+        _ => None,
+    };
+
+    StorageTestResult {
+        success: error.is_none(),
+        backend,
+        persistent: backend.is_persistent(),
+        value_survived,
+        error,
+    }
+}
@@ -0,0 +1,179 @@
+//! Bandwidth limits for gateway traffic
+//!
+//! On shared or metered connections the app can be capped to a number of
+//! bytes per second in each direction. A [`Throttle`] is shared by every
+//! request going that way, so the cap holds for the app as a whole rather
+//! than per request. Bodies are paced chunk by chunk instead of being
+//! delayed up front, which keeps streamed responses (SSE) flowing: each
+//! chunk is passed on as soon as it arrives, and the next read waits until
+//! the budget allows it.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Slowest limit accepted (bytes/sec); anything lower stalls requests for minutes
+pub const MIN_LIMIT_BPS: u64 = 1024;
+
+/// Size of the chunks a paced upload is split into
+const UPLOAD_CHUNK: usize = 16 * 1024;
+
+/// Unused budget that may build up while idle, sent as a burst
+const MAX_BURST: Duration = Duration::from_millis(250);
+
+/// Check a configured limit (unset means unlimited)
+pub fn check_limit(limit_bps: Option<u64>) -> Result<(), String> {
+    match limit_bps {
+        Some(limit) if limit < MIN_LIMIT_BPS => Err(format!(
+            "bandwidth limit must be at least {MIN_LIMIT_BPS} bytes/sec (got {limit})"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Paces bytes in one direction to a rate limit
+pub struct Throttle {
+    /// Limit in bytes/sec, 0 for unlimited
+    limit_bps: AtomicU64,
+
+    /// When the bytes handed out so far will have been paid for
+    next_free: Mutex<Instant>,
+
+    /// Bytes passed so far, limited or not
+    transferred: AtomicU64,
+}
+
+impl Throttle {
+    /// Throttle limited to `limit_bps` (unlimited if unset)
+    pub fn new(limit_bps: Option<u64>) -> Self {
+        Self {
+            limit_bps: AtomicU64::new(limit_bps.unwrap_or(0)),
+            next_free: Mutex::new(Instant::now()),
+            transferred: AtomicU64::new(0),
+        }
+    }
+
+    /// Change the limit; takes effect from the next chunk
+    pub fn set_limit(&self, limit_bps: Option<u64>) {
+        self.limit_bps
+            .store(limit_bps.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Current limit (unlimited if unset)
+    pub fn limit(&self) -> Option<u64> {
+        Some(self.limit_bps.load(Ordering::Relaxed)).filter(|&limit| limit > 0)
+    }
+
+    /// Bytes passed so far
+    pub fn transferred(&self) -> u64 {
+        self.transferred.load(Ordering::Relaxed)
+    }
+
+    /// Count `bytes` sent without pacing them
+    pub fn count(&self, bytes: usize) {
+        self.transferred.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Wait until `bytes` more may pass
+    pub async fn consume(&self, bytes: usize) {
+        self.count(bytes);
+        let Some(limit) = self.limit() else {
+            return;
+        };
+
+        let wait = {
+            let mut next_free = self.next_free.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let earliest = now.checked_sub(MAX_BURST).unwrap_or(now);
+            let start = (*next_free).max(earliest);
+
+            *next_free = start + Duration::from_secs_f64(bytes as f64 / limit as f64);
+            next_free.saturating_duration_since(now)
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Request body sending `data` no faster than `throttle` allows
+pub fn body(data: Vec<u8>, throttle: Arc<Throttle>) -> reqwest::Body {
+    let chunks: VecDeque<Vec<u8>> = data.chunks(UPLOAD_CHUNK).map(<[u8]>::to_vec).collect();
+
+    let stream =
+        futures_util::stream::unfold((chunks, throttle), |(mut chunks, throttle)| async move {
+            let chunk = chunks.pop_front()?;
+            throttle.consume(chunk.len()).await;
+            Some((Ok::<_, std::io::Error>(chunk), (chunks, throttle)))
+        });
+    reqwest::Body::wrap_stream(stream)
+}
+
+/// Read a whole response body no faster than `throttle` allows, counting
+/// the bytes read in `progress`
+pub async fn read(
+    resp: &mut reqwest::Response,
+    throttle: &Throttle,
+    progress: &AtomicU64,
+) -> reqwest::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        progress.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        body.extend_from_slice(&chunk);
+        throttle.consume(chunk.len()).await;
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Time `consume(bytes)` takes
+    async fn consume_time(throttle: &Throttle, bytes: usize) -> Duration {
+        let started = Instant::now();
+        throttle.consume(bytes).await;
+        started.elapsed()
+    }
+
+    #[test]
+    fn limits_below_the_minimum_are_refused() {
+        assert!(check_limit(None).is_ok());
+        assert!(check_limit(Some(MIN_LIMIT_BPS)).is_ok());
+        assert!(check_limit(Some(MIN_LIMIT_BPS - 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn unlimited_passes_at_once_but_counts() {
+        let throttle = Throttle::new(None);
+        assert!(consume_time(&throttle, 10 * 1024 * 1024).await < Duration::from_millis(50));
+        throttle.count(5);
+        assert_eq!(throttle.transferred(), 10 * 1024 * 1024 + 5);
+    }
+
+    #[tokio::test]
+    async fn idle_budget_is_capped_at_the_burst() {
+        // 10 kB/s, so the burst is 2.5 kB
+        let throttle = Throttle::new(Some(10_000));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // One burst's worth goes through right away after idling
+        assert!(consume_time(&throttle, 2_500).await < Duration::from_millis(50));
+
+        // A second of idling still only buys one burst
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let took = consume_time(&throttle, 5_000).await;
+        assert!(took >= Duration::from_millis(200), "{took:?}");
+        assert!(took < Duration::from_millis(400), "{took:?}");
+    }
+
+    #[tokio::test]
+    async fn lifting_the_limit_stops_pacing() {
+        let throttle = Throttle::new(Some(MIN_LIMIT_BPS));
+        throttle.set_limit(None);
+        assert!(consume_time(&throttle, 1024 * 1024).await < Duration::from_millis(50));
+        assert_eq!(throttle.limit(), None);
+    }
+}
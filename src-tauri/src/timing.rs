@@ -0,0 +1,251 @@
+//! Timing breakdown of a single gateway probe
+//!
+//! Sends one `/health` request over a connection set up by hand, so each
+//! phase can be timed on its own: DNS resolution, TCP connect, TLS handshake,
+//! time to first byte, and the whole exchange. The request is plain HTTP/1.1
+//! with `Connection: close` and bypasses the shared client (no profile
+//! headers, custom CA bundle or pooled connections), so every phase really
+//! happens each time.
+
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_native_tls::native_tls;
+
+/// Timeout for each phase
+const PHASE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Endpoint probed
+const HEALTH_PATH: &str = "/health";
+
+/// Most of the response read; the rest of a larger body isn't waited for
+const MAX_RESPONSE: usize = 64 * 1024;
+
+/// Phase of a probe
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Dns,
+    Connect,
+    Tls,
+
+    /// Sending the request and waiting for the response to start
+    FirstByte,
+
+    /// Reading the rest of the response
+    Response,
+}
+
+/// Where a probe spent its time
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionTiming {
+    pub url: String,
+
+    /// Address the TCP connection was made to
+    pub address: Option<SocketAddr>,
+
+    /// Resolving the host (ms, None for IP literals)
+    pub dns_ms: Option<u64>,
+
+    /// Opening the TCP connection, across all addresses tried (ms)
+    pub connect_ms: Option<u64>,
+
+    /// TLS handshake (ms, None for plain HTTP)
+    pub tls_ms: Option<u64>,
+
+    /// From sending the request to the first byte of the response (ms)
+    pub ttfb_ms: Option<u64>,
+
+    /// The whole probe, up to the end of the response or the failure (ms)
+    pub total_ms: u64,
+
+    /// HTTP status of the `/health` response
+    pub status: Option<u16>,
+
+    /// Phase that failed, if any
+    pub failed_phase: Option<Phase>,
+
+    pub error: Option<String>,
+}
+
+/// A phase failure
+type Failure = (Phase, String);
+
+/// Probe `url`'s `/health`, timing each phase
+///
+/// Only an unusable URL is an error; a failed probe is reported in the
+/// timing, along with the phases it got through.
+pub async fn diagnose(url: &str) -> Result<ConnectionTiming, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("invalid gateway URL `{url}`: {e}"))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("no host in gateway URL `{url}`"))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| format!("no port in gateway URL `{url}`"))?;
+    let tls = match parsed.scheme() {
+        "https" => true,
+        "http" => false,
+        scheme => return Err(format!("unsupported URL scheme `{scheme}`")),
+    };
+
+    let mut timing = ConnectionTiming {
+        url: url.to_string(),
+        address: None,
+        dns_ms: None,
+        connect_ms: None,
+        tls_ms: None,
+        ttfb_ms: None,
+        total_ms: 0,
+        status: None,
+        failed_phase: None,
+        error: None,
+    };
+
+    let started = Instant::now();
+    let result = probe(&mut timing, host, port, tls).await;
+    timing.total_ms = elapsed_ms(started);
+
+    if let Err((phase, error)) = result {
+        timing.failed_phase = Some(phase);
+        timing.error = Some(error);
+    }
+    tracing::info!(?timing, "diagnosed gateway connection");
+    Ok(timing)
+}
+
+async fn probe(
+    timing: &mut ConnectionTiming,
+    host: &str,
+    port: u16,
+    tls: bool,
+) -> Result<(), Failure> {
+    // IPv6 literals keep their brackets in URLs
+    let bare = host.trim_start_matches('[').trim_end_matches(']');
+
+    let addresses: Vec<SocketAddr> = match bare.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => {
+            let started = Instant::now();
+            let resolved = run_phase(Phase::Dns, tokio::net::lookup_host((bare, port))).await;
+            timing.dns_ms = Some(elapsed_ms(started));
+            resolved?.collect()
+        }
+    };
+    if addresses.is_empty() {
+        return Err((Phase::Dns, format!("{bare} resolved to no addresses")));
+    }
+
+    // Addresses are tried in order until one accepts
+    let started = Instant::now();
+    let mut stream = None;
+    let mut last_error = None;
+    for addr in addresses {
+        match run_phase(Phase::Connect, TcpStream::connect(addr)).await {
+            Ok(connected) => {
+                timing.address = Some(addr);
+                stream = Some(connected);
+                break;
+            }
+            Err((phase, e)) => last_error = Some((phase, format!("{addr}: {e}"))),
+        }
+    }
+    timing.connect_ms = Some(elapsed_ms(started));
+    let Some(stream) = stream else {
+        return Err(last_error.unwrap_or((Phase::Connect, "nothing to connect to".to_string())));
+    };
+
+    if !tls {
+        return exchange(timing, stream, host, port).await;
+    }
+
+    let connector = native_tls::TlsConnector::new().map_err(|e| (Phase::Tls, e.to_string()))?;
+    let connector = tokio_native_tls::TlsConnector::from(connector);
+    let started = Instant::now();
+    let stream = run_phase(Phase::Tls, connector.connect(bare, stream)).await;
+    timing.tls_ms = Some(elapsed_ms(started));
+    exchange(timing, stream?, host, port).await
+}
+
+/// Send the request and read the response, timing the first byte
+async fn exchange(
+    timing: &mut ConnectionTiming,
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
+    host: &str,
+    port: u16,
+) -> Result<(), Failure> {
+    let request = format!(
+        "GET {HEALTH_PATH} HTTP/1.1\r\n\
+         Host: {host}:{port}\r\n\
+         Accept: */*\r\n\
+         Connection: close\r\n\r\n"
+    );
+    let mut chunk = [0u8; 8192];
+
+    let started = Instant::now();
+    let first = run_phase(Phase::FirstByte, async {
+        stream.write_all(request.as_bytes()).await?;
+        stream.read(&mut chunk).await
+    })
+    .await?;
+    timing.ttfb_ms = Some(elapsed_ms(started));
+    if first == 0 {
+        return Err((
+            Phase::FirstByte,
+            "connection closed without a response".to_string(),
+        ));
+    }
+
+    let mut response = chunk[..first].to_vec();
+    let rest = run_phase(Phase::Response, async {
+        while response.len() < MAX_RESPONSE {
+            match stream.read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(n) => response.extend_from_slice(&chunk[..n]),
+                // Servers often close TLS connections without a close_notify
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    })
+    .await;
+    timing.status = status(&response);
+    rest
+}
+
+/// Run one phase under [`PHASE_TIMEOUT`], tagging its error with the phase
+async fn run_phase<T, E: std::fmt::Display>(
+    phase: Phase,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, Failure> {
+    match tokio::time::timeout(PHASE_TIMEOUT, future).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err((phase, e.to_string())),
+        Err(_) => Err((
+            phase,
+            format!("timed out after {}s", PHASE_TIMEOUT.as_secs()),
+        )),
+    }
+}
+
+/// Status code from the response's status line
+fn status(response: &[u8]) -> Option<u16> {
+    let line = response.split(|b| *b == b'\n').next()?;
+    std::str::from_utf8(line)
+        .ok()?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis() as u64
+}
@@ -0,0 +1,267 @@
+//! End-to-end validation of a candidate gateway connection
+//!
+//! Runs every check a connection depends on, in order, for a config the
+//! user hasn't saved yet: the URL parses, the host accepts TCP connections,
+//! TLS verifies (with the given CA bundle), `/health` answers, the token is
+//! accepted, the gateway version is one this app supports, and the event
+//! channel carries push events. Clients are built just for the check; the
+//! active connection is never touched.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+use crate::gateway::{self, TlsVersion};
+use crate::{event_channel, pinning, reachability};
+
+/// Oldest gateway version this app works with
+const MIN_GATEWAY_VERSION: (u64, u64) = (0, 1);
+
+/// Endpoint used to check that the token is accepted
+const AUTH_CHECK_PATH: &str = "/status";
+
+/// Connection settings to validate
+#[derive(Deserialize)]
+pub struct ConnectionConfig {
+    pub url: String,
+    pub token: Option<String>,
+    pub ca_bundle: Option<PathBuf>,
+
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Health endpoint (`/health` if unset)
+    pub health_path: Option<String>,
+
+    /// Oldest TLS version the connection may use
+    #[serde(default)]
+    pub min_tls_version: TlsVersion,
+
+    /// Certificate fingerprint to trust instead of verifying the certificate
+    pub pinned_cert_sha256: Option<String>,
+}
+
+/// Result of validating a connection
+#[derive(Debug, Serialize)]
+pub struct ValidationReport {
+    /// Normalized gateway URL, if it parsed
+    pub url: Option<String>,
+
+    /// Whether every step passed
+    pub passed: bool,
+
+    /// Steps in the order they ran; validation stops at the first failure
+    pub steps: Vec<ValidationStep>,
+}
+
+/// A single validation step
+#[derive(Debug, Serialize)]
+pub struct ValidationStep {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Validate `config`, checking each step in turn
+pub async fn validate(mut config: ConnectionConfig) -> ValidationReport {
+    let mut steps = Vec::new();
+    let url = gateway::normalize_gateway_url(&config.url);
+    let passed = match &url {
+        Ok(url) => {
+            record(&mut steps, "url", Ok(url.clone()));
+            run_steps(&config, url, &mut steps).await
+        }
+        Err(e) => record(&mut steps, "url", Err(e.clone())),
+    };
+
+    if let Some(token) = &mut config.token {
+        token.zeroize();
+    }
+    tracing::info!(url = %config.url, passed, "validated gateway connection");
+    ValidationReport {
+        url: url.ok(),
+        passed,
+        steps,
+    }
+}
+
+async fn run_steps(config: &ConnectionConfig, url: &str, steps: &mut Vec<ValidationStep>) -> bool {
+    if !record(steps, "tcp", check_tcp(url).await) {
+        return false;
+    }
+
+    let build = |token: Option<&str>| {
+        gateway::build_client(
+            token,
+            &config.headers,
+            config.ca_bundle.as_deref(),
+            None,
+            None,
+            config.min_tls_version,
+            config.pinned_cert_sha256.as_deref(),
+        )
+    };
+    let (anonymous, authed) = match (build(None), build(config.token.as_deref())) {
+        (Ok(anonymous), Ok(authed)) => (anonymous, authed),
+        (Err(e), _) | (_, Err(e)) => return record(steps, "client", Err(e)),
+    };
+
+    let health_path = config.health_path.as_deref().unwrap_or("/health");
+    let has_token = config.token.is_some();
+    let min_tls = config.min_tls_version;
+    record(steps, "tls", check_tls(&anonymous, url, min_tls).await)
+        && record(steps, "health", health(&authed, url, health_path).await)
+        && record(steps, "auth", check_auth(&authed, url, has_token).await)
+        && record(steps, "version", check_version(&authed, url).await)
+        && record(steps, "events", check_events(config, url).await)
+}
+
+fn record(steps: &mut Vec<ValidationStep>, name: &str, result: Result<String, String>) -> bool {
+    let passed = result.is_ok();
+    steps.push(ValidationStep {
+        name: name.to_string(),
+        passed,
+        detail: result.unwrap_or_else(|e| e),
+    });
+    passed
+}
+
+/// Check that the host resolves and accepts a TCP connection
+async fn check_tcp(url: &str) -> Result<String, String> {
+    let result = reachability::check(url).await?;
+    match result.connected_address {
+        Some(addr) if result.tcp_connected => Ok(format!(
+            "connected to {addr} in {}ms",
+            result.tcp_ms.unwrap_or_default()
+        )),
+        _ => Err(result
+            .error
+            .unwrap_or_else(|| format!("{} is not reachable", result.host))),
+    }
+}
+
+/// Check that the TLS handshake succeeds, at `min_version` or newer, and the
+/// certificate verifies
+async fn check_tls(
+    client: &reqwest::Client,
+    url: &str,
+    min_version: TlsVersion,
+) -> Result<String, String> {
+    if !url.starts_with("https://") {
+        return Ok("plain HTTP, no TLS".to_string());
+    }
+
+    // Any HTTP response at all means the handshake went through
+    client
+        .head(url)
+        .timeout(gateway::PROBE_TIMEOUT)
+        .send()
+        .await
+        .map(|_| "certificate verified".to_string())
+        .map_err(|e| {
+            if pinning::is_pin_mismatch(&e) {
+                "certificate pin mismatch: gateway presented another certificate".to_string()
+            } else if gateway::is_tls_version_error(&e) {
+                format!("TLS version too low: gateway doesn't support {min_version} or newer")
+            } else {
+                format!("TLS handshake failed: {}", error_chain(&e))
+            }
+        })
+}
+
+async fn health(client: &reqwest::Client, url: &str, path: &str) -> Result<String, String> {
+    let started = std::time::Instant::now();
+    let resp = client
+        .get(format!("{url}{path}"))
+        .timeout(gateway::PROBE_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("{path} failed: {}", error_chain(&e)))?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(format!("{path} returned {status}"));
+    }
+    Ok(format!(
+        "{path} answered in {}ms",
+        started.elapsed().as_millis()
+    ))
+}
+
+/// Check that the gateway accepts the token (or needs none), at an
+/// endpoint that requires auth
+pub async fn check_auth(
+    client: &reqwest::Client,
+    url: &str,
+    has_token: bool,
+) -> Result<String, String> {
+    let resp = client
+        .get(format!("{url}{AUTH_CHECK_PATH}"))
+        .timeout(gateway::PROBE_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("auth check failed: {}", error_chain(&e)))?;
+
+    match (resp.status(), has_token) {
+        (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN, true) => {
+            Err("gateway rejected the token".to_string())
+        }
+        (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN, false) => {
+            Err("gateway requires a token".to_string())
+        }
+        (_, true) => Ok("token accepted".to_string()),
+        (_, false) => Ok("gateway accepts requests without a token".to_string()),
+    }
+}
+
+/// Check that the gateway is at least [`MIN_GATEWAY_VERSION`]
+async fn check_version(client: &reqwest::Client, url: &str) -> Result<String, String> {
+    let Some(version) = gateway::fetch_gateway_info(client, url)
+        .await
+        .and_then(|info| info.version)
+    else {
+        return Ok("gateway does not report its version".to_string());
+    };
+
+    let (min_major, min_minor) = MIN_GATEWAY_VERSION;
+    match gateway::parse_version(&version) {
+        Some(found) if found >= MIN_GATEWAY_VERSION => Ok(format!("version {version}")),
+        Some(_) => Err(format!(
+            "gateway version {version} is too old, {min_major}.{min_minor} or newer is needed"
+        )),
+        None => Err(format!("unrecognized gateway version `{version}`")),
+    }
+}
+
+/// Check that the event channel opens and answers a ping
+async fn check_events(config: &ConnectionConfig, url: &str) -> Result<String, String> {
+    let result = event_channel::test(
+        url,
+        config.token.as_deref(),
+        &config.headers,
+        config.ca_bundle.as_deref(),
+        config.min_tls_version,
+        config.pinned_cert_sha256.as_deref(),
+    )
+    .await?;
+
+    if result.events_work {
+        Ok(result.detail())
+    } else {
+        Err(result.detail())
+    }
+}
+
+/// An error with its causes, which carry the useful TLS details
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
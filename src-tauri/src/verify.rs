@@ -0,0 +1,240 @@
+//! Gateway binary verification
+//!
+//! Checks that a gateway binary (e.g. a fresh download) will actually run on
+//! this machine before it replaces the one in use: the file is executable
+//! and built for this architecture, answers `--version`, and optionally
+//! starts and turns healthy on a throwaway port. Nothing here touches the
+//! active connection or the running sidecar.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::process::Command;
+
+use crate::gateway;
+
+/// How long `--version` may take
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a test start may take to turn healthy
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Result of verifying a gateway binary
+#[derive(Debug, Serialize)]
+pub struct VerifyResult {
+    pub path: PathBuf,
+
+    /// Whether every step passed
+    pub passed: bool,
+
+    /// Steps in the order they ran; verification stops at the first failure
+    pub steps: Vec<VerifyStep>,
+}
+
+/// A single verification step
+#[derive(Debug, Serialize)]
+pub struct VerifyStep {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// Verify the binary at `path`, with a test start if `start` is set
+pub async fn verify(path: &Path, start: bool) -> VerifyResult {
+    let mut steps = Vec::new();
+    let passed = run_steps(path, start, &mut steps).await;
+
+    tracing::info!(path = %path.display(), passed, "verified gateway binary");
+    VerifyResult {
+        path: path.to_path_buf(),
+        passed,
+        steps,
+    }
+}
+
+/// Run each step in turn, returning whether all of them passed
+async fn run_steps(path: &Path, start: bool, steps: &mut Vec<VerifyStep>) -> bool {
+    record(steps, "executable", check_executable(path))
+        && record(steps, "architecture", check_arch(path))
+        && record(steps, "version", check_version(path).await)
+        && (!start || record(steps, "start", check_start(path).await))
+}
+
+fn record(steps: &mut Vec<VerifyStep>, name: &str, result: Result<String, String>) -> bool {
+    let passed = result.is_ok();
+    steps.push(VerifyStep {
+        name: name.to_string(),
+        passed,
+        detail: result.unwrap_or_else(|e| e),
+    });
+    passed
+}
+
+/// Check that `path` is a file this user may execute
+fn check_executable(path: &Path) -> Result<String, String> {
+    let metadata =
+        std::fs::metadata(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(format!("{} is not executable", path.display()));
+        }
+    }
+
+    Ok(format!("{} bytes", metadata.len()))
+}
+
+/// Check that the binary is built for the architecture the app runs on
+fn check_arch(path: &Path) -> Result<String, String> {
+    let mut header = Vec::with_capacity(4096);
+    std::fs::File::open(path)
+        .and_then(|file| file.take(4096).read_to_end(&mut header))
+        .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+
+    let expected = std::env::consts::ARCH;
+    match binary_arch(&header) {
+        Some("universal") => Ok("universal binary".to_string()),
+        Some(arch) if arch == expected => Ok(arch.to_string()),
+        Some(arch) => Err(format!("built for {arch}, this machine is {expected}")),
+        None => Err("not a recognized executable format".to_string()),
+    }
+}
+
+/// Architecture of an ELF, Mach-O or PE executable, from its header
+fn binary_arch(header: &[u8]) -> Option<&'static str> {
+    let u16_at = |at: usize, le: bool| -> Option<u16> {
+        let bytes = [*header.get(at)?, *header.get(at + 1)?];
+        Some(if le {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let u32_at = |at: usize| -> Option<u32> {
+        let bytes = header.get(at..at + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    };
+
+    match header.get(..4)? {
+        [0x7f, b'E', b'L', b'F'] => {
+            let le = *header.get(5)? == 1;
+            Some(match u16_at(18, le)? {
+                0x03 => "x86",
+                0x28 => "arm",
+                0x3e => "x86_64",
+                0xb7 => "aarch64",
+                0xf3 => "riscv64",
+                _ => "unknown",
+            })
+        }
+        [0xcf, 0xfa, 0xed, 0xfe] => Some(match u32_at(4)? {
+            0x0100_0007 => "x86_64",
+            0x0100_000c => "aarch64",
+            _ => "unknown",
+        }),
+        [0xca, 0xfe, 0xba, 0xbe] => Some("universal"),
+        [b'M', b'Z', ..] => {
+            let pe = u32_at(0x3c)? as usize;
+            if header.get(pe..pe + 4)? != b"PE\0\0" {
+                return None;
+            }
+            Some(match u16_at(pe + 4, true)? {
+                0x014c => "x86",
+                0x8664 => "x86_64",
+                0xaa64 => "aarch64",
+                _ => "unknown",
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Run `--version`, returning what it printed
+async fn check_version(path: &Path) -> Result<String, String> {
+    let output = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    let output = tokio::time::timeout(VERSION_TIMEOUT, output)
+        .await
+        .map_err(|_| "`--version` did not finish in time".to_string())?
+        .map_err(|e| format!("failed to run: {e}"))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(format!(
+            "`--version` exited with {}: {stderr}",
+            output.status
+        ));
+    }
+
+    Ok(stdout)
+}
+
+/// Start the binary on a free port in a scratch directory and wait for it
+/// to turn healthy, then stop it
+async fn check_start(path: &Path) -> Result<String, String> {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map_err(|e| format!("no free port for a test start: {e}"))?
+        .port();
+
+    let scratch = std::env::temp_dir().join(format!("beacon-gateway-verify-{port}"));
+    std::fs::create_dir_all(&scratch)
+        .map_err(|e| format!("failed to create {}: {e}", scratch.display()))?;
+
+    let result = start_and_probe(path, port, &scratch).await;
+
+    if let Err(e) = std::fs::remove_dir_all(&scratch) {
+        tracing::warn!(error = %e, dir = %scratch.display(), "failed to remove verify scratch dir");
+    }
+    result
+}
+
+async fn start_and_probe(path: &Path, port: u16, scratch: &Path) -> Result<String, String> {
+    let started = Instant::now();
+    let mut child = Command::new(path)
+        .args(["--persona", "orin"])
+        .env("BEACON_API_PORT", port.to_string())
+        .current_dir(scratch)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to start: {e}"))?;
+
+    let url = format!("http://127.0.0.1:{port}");
+    let mut result = Err(format!("not healthy within {}s", START_TIMEOUT.as_secs()));
+    while started.elapsed() < START_TIMEOUT {
+        if let Some(status) = child.try_wait().ok().flatten() {
+            result = Err(format!("exited during startup with {status}"));
+            break;
+        }
+        if gateway::probe_gateway(&url).await {
+            result = Ok(format!("healthy after {}ms", started.elapsed().as_millis()));
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    // kill() also waits, so no test process outlives the check
+    if child.try_wait().ok().flatten().is_none() {
+        if let Err(e) = child.kill().await {
+            tracing::warn!(error = %e, "failed to stop test gateway process");
+        }
+    }
+    result
+}
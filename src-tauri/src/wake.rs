@@ -0,0 +1,132 @@
+//! Re-checking the gateway after the system wakes from sleep
+//!
+//! Sleep kills sockets without closing them, so after a resume the app can
+//! look connected while every request hangs. There is no cross-platform
+//! power event in Tauri, so resume is detected from the clocks instead: a
+//! lightweight loop ticks every few seconds and a tick that comes far later
+//! than scheduled, by the wall clock (which keeps running during sleep) or
+//! the monotonic one (which does on some platforms), means the machine was
+//! suspended. A large forward change of the system clock looks the same and
+//! just triggers a harmless re-check.
+//!
+//! On resume, streams are cancelled, pooled connections are dropped, and the
+//! active gateway is probed until the network is back; an unreachable gateway
+//! is reconnected the way the app does at launch.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::{gateway, AppState, GatewayState};
+
+/// How often the clocks are compared
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Delay past the tick interval that counts as having slept
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// Probes of the active gateway after resume, while the network comes back
+const RESUME_PROBES: u32 = 5;
+
+/// Delay between those probes
+const RESUME_PROBE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Payload of the `system-resumed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ResumeEvent {
+    /// How long the app was asleep, roughly (ms)
+    pub slept_ms: u64,
+
+    /// Streams cancelled because their connections died
+    pub streams_cancelled: usize,
+}
+
+/// Watch for resumes for the lifetime of the app
+pub async fn run(app: AppHandle, state: Arc<AppState>) {
+    let mut ticker = tokio::time::interval(TICK_INTERVAL);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    ticker.tick().await;
+
+    let mut last_tick = Instant::now();
+    let mut last_wall = SystemTime::now();
+
+    loop {
+        ticker.tick().await;
+
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        let elapsed = now
+            .duration_since(last_tick)
+            .max(wall.duration_since(last_wall).unwrap_or_default());
+        last_tick = now;
+        last_wall = wall;
+
+        let Some(slept) = elapsed.checked_sub(TICK_INTERVAL) else {
+            continue;
+        };
+        if slept >= SLEEP_THRESHOLD {
+            on_resume(&app, &state, slept).await;
+        }
+    }
+}
+
+async fn on_resume(app: &AppHandle, state: &Arc<AppState>, slept: Duration) {
+    let streams_cancelled = state.streams.abort_all();
+    tracing::info!(
+        slept_secs = slept.as_secs(),
+        streams_cancelled,
+        "system resumed, re-checking gateway connection"
+    );
+    let _ = app.emit(
+        "system-resumed",
+        ResumeEvent {
+            slept_ms: slept.as_millis() as u64,
+            streams_cancelled,
+        },
+    );
+
+    if let Err(e) = gateway::rebuild_client(state).await {
+        tracing::warn!(error = %e, "failed to rebuild gateway client after resume");
+    }
+
+    if state.settings.read().await.offline_mode {
+        return;
+    }
+    let GatewayState::Connected { url, is_sidecar } = state.gateway_state.read().await.clone()
+    else {
+        return;
+    };
+
+    let client = state.client.read().await.clone();
+    for attempt in 1..=RESUME_PROBES {
+        if gateway::probe_with_client(&client, &url).await {
+            tracing::info!(url = %url, attempt, "gateway reachable after resume");
+            return;
+        }
+        tokio::time::sleep(RESUME_PROBE_INTERVAL).await;
+    }
+
+    // Something else (the user, the sidecar monitor) may have moved on meanwhile
+    let still_connected = matches!(
+        &*state.gateway_state.read().await,
+        GatewayState::Connected { url: current, .. } if *current == url
+    );
+    if !still_connected {
+        return;
+    }
+
+    tracing::warn!(url = %url, "gateway unreachable after resume, reconnecting");
+    if is_sidecar {
+        if let Err(e) = gateway::restart_sidecar(state).await {
+            tracing::error!(error = %e, "failed to restart gateway sidecar after resume");
+            state
+                .set_gateway_state(GatewayState::Failed { error: e })
+                .await;
+        }
+    } else {
+        state.set_gateway_state(GatewayState::Disconnected).await;
+        gateway::auto_connect(state.clone()).await;
+    }
+}
@@ -2,6 +2,8 @@ import { LogoLockup } from "@omnidotdev/thornberry/logo-lockup";
 import { Link, Outlet } from "@tanstack/react-router";
 import { Menu, X } from "lucide-react";
 import { useState } from "react";
+import OfflineBanner from "./OfflineBanner";
+import SafeModeBanner from "./SafeModeBanner";
 import Sidebar, { BeaconLogo } from "./Sidebar";
 
 function Layout() {
@@ -47,6 +49,9 @@ function Layout() {
           </button>
         </header>
 
+        <SafeModeBanner />
+        <OfflineBanner />
+
         <Outlet />
       </main>
     </div>
@@ -1,19 +1,42 @@
 import { memo } from "react";
-import ReactMarkdown from "react-markdown";
+import ReactMarkdown, { type Components } from "react-markdown";
 import rehypeHighlight from "rehype-highlight";
 import remarkGfm from "remark-gfm";
 
+import { isNative, openExternal } from "@/lib/platform";
+
 import type { PluggableList } from "unified";
 
 const REMARK_PLUGINS: PluggableList = [remarkGfm];
 const REHYPE_PLUGINS: PluggableList = [rehypeHighlight];
 
+// Route links through the native allowlist instead of letting the webview open them
+const COMPONENTS: Components = {
+  a: ({ node: _node, href, ...props }) => (
+    <a
+      {...props}
+      href={href}
+      target="_blank"
+      rel="noopener noreferrer"
+      onClick={(event) => {
+        if (!href || !isNative()) return;
+
+        event.preventDefault();
+        openExternal(href).catch((error) =>
+          console.warn("[markdown] blocked link:", error),
+        );
+      }}
+    />
+  ),
+};
+
 function Markdown({ content }: { content: string }) {
   return (
     <div className="markdown">
       <ReactMarkdown
         remarkPlugins={REMARK_PLUGINS}
         rehypePlugins={REHYPE_PLUGINS}
+        components={COMPONENTS}
       >
         {content}
       </ReactMarkdown>
@@ -0,0 +1,51 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { WifiOff } from "lucide-react";
+import { useEffect, useState } from "react";
+
+import { isNative } from "@/lib/platform";
+
+interface OfflineModeEvent {
+  enabled: boolean;
+}
+
+/**
+ * Banner shown while offline mode is on.
+ * No gateway is contacted until the user turns it off again.
+ */
+function OfflineBanner() {
+  const [offline, setOffline] = useState(false);
+
+  useEffect(() => {
+    if (!isNative()) return;
+
+    invoke<boolean>("is_offline_mode")
+      .then(setOffline)
+      .catch(() => setOffline(false));
+
+    const unlisten = listen<OfflineModeEvent>("offline-mode-changed", (event) =>
+      setOffline(event.payload.enabled),
+    );
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  if (!offline) return null;
+
+  return (
+    <div
+      role="status"
+      className="flex items-center gap-2 border-b border-primary/20 bg-primary/10 px-4 py-2 text-sm text-foreground"
+    >
+      <WifiOff size={16} className="shrink-0 text-primary" />
+      <span>
+        Offline mode is on. The app won't connect to a gateway until you turn it
+        off in settings.
+      </span>
+    </div>
+  );
+}
+
+export default OfflineBanner;
@@ -0,0 +1,42 @@
+import { invoke } from "@tauri-apps/api/core";
+import { ShieldAlert } from "lucide-react";
+import { useEffect, useState } from "react";
+
+import { isNative } from "@/lib/platform";
+
+interface GatewayStatus {
+  safe_mode: boolean;
+}
+
+/**
+ * Banner shown when the app was launched with `--safe-mode`.
+ * Auto-connect is skipped in this mode, so the user connects manually.
+ */
+function SafeModeBanner() {
+  const [safeMode, setSafeMode] = useState(false);
+
+  useEffect(() => {
+    if (!isNative()) return;
+
+    invoke<GatewayStatus>("get_gateway_status")
+      .then((status) => setSafeMode(status.safe_mode))
+      .catch(() => setSafeMode(false));
+  }, []);
+
+  if (!safeMode) return null;
+
+  return (
+    <div
+      role="status"
+      className="flex items-center gap-2 border-b border-primary/20 bg-primary/10 px-4 py-2 text-sm text-foreground"
+    >
+      <ShieldAlert size={16} className="shrink-0 text-primary" />
+      <span>
+        Safe mode is active. The gateway was not started automatically, connect
+        to one manually from settings.
+      </span>
+    </div>
+  );
+}
+
+export default SafeModeBanner;
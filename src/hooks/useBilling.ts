@@ -4,6 +4,7 @@ import { useRouteContext } from "@tanstack/react-router";
 import { isCloudDeployment } from "@/lib/api";
 import billingProvider from "@/lib/billing";
 import app from "@/lib/config/app.config";
+import { openExternal } from "@/lib/platform";
 
 const STALE_TIME_MS = 300_000; // 5 minutes
 const CREDIT_STALE_TIME_MS = 60_000; // 1 minute
@@ -141,7 +142,7 @@ export function useBillingPortal() {
       );
     },
     onSuccess: (url) => {
-      window.open(url, "_blank");
+      openExternal(url);
     },
   });
 }
@@ -76,6 +76,11 @@ interface IpcGatewayStatus {
   url: string | null;
   is_sidecar: boolean;
   error: string | null;
+  safe_mode: boolean;
+  log_level: "error" | "warn" | "info" | "debug" | "trace" | null;
+  retry: { attempt: number; max: number; next_delay_ms: number } | null;
+  startup_phase: "locating_binary" | "spawning" | "waiting_for_health" | null;
+  elapsed_ms: number | null;
 }
 
 // Resolve the gateway URL from Tauri state
@@ -28,3 +28,19 @@ export function getPlatform(): "desktop" | "mobile" | "web" {
 
   return "desktop";
 }
+
+/**
+ * Open a link outside the app
+ *
+ * On native, goes through the `open_external` command, which checks the target
+ * against an allowlist of schemes (links in assistant output are untrusted)
+ */
+export async function openExternal(target: string): Promise<void> {
+  if (!isNative()) {
+    window.open(target, "_blank", "noopener,noreferrer");
+    return;
+  }
+
+  const { invoke } = await import("@tauri-apps/api/core");
+  await invoke("open_external", { target });
+}